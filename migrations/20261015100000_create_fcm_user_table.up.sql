@@ -0,0 +1,12 @@
+CREATE TABLE fcm_user (
+    fb_user_id TEXT PRIMARY KEY NOT NULL,
+    fb_project_id TEXT NOT NULL,
+    anonymous BOOLEAN NOT NULL DEFAULT 0,
+    last_seen_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+INSERT INTO fcm_user (fb_user_id, fb_project_id, anonymous, last_seen_at, created_at)
+SELECT fb_user_id, fb_project_id, 0, MAX(updated_at), MIN(created_at)
+FROM fcm_schedule
+GROUP BY fb_user_id;
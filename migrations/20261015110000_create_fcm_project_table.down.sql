@@ -0,0 +1 @@
+DROP TABLE fcm_project;
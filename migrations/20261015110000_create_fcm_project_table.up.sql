@@ -0,0 +1,9 @@
+CREATE TABLE fcm_project (
+    fb_project_id TEXT PRIMARY KEY NOT NULL,
+    icon TEXT,
+    color TEXT,
+    click_action TEXT,
+    channel_id TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE fcm_execution_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    schedule_id INTEGER NOT NULL,
+    fb_user_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    dry_run BOOLEAN NOT NULL DEFAULT 0,
+    executed_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_execution_log_schedule_id ON fcm_execution_log (schedule_id);
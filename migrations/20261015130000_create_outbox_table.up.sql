@@ -0,0 +1,19 @@
+CREATE TABLE outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel TEXT NOT NULL,
+    target TEXT NOT NULL,
+    project_id TEXT NOT NULL,
+    schedule_id INTEGER,
+    fb_user_id TEXT,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    dry_run BOOLEAN NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+CREATE INDEX outbox_status_next_attempt_at ON outbox (status, next_attempt_at);
+CREATE INDEX outbox_target ON outbox (target, status);
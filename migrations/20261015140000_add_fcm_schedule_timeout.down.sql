@@ -0,0 +1,2 @@
+ALTER TABLE outbox DROP COLUMN timeout_seconds;
+ALTER TABLE fcm_schedule DROP COLUMN timeout_seconds;
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_schedule ADD COLUMN timeout_seconds INTEGER;
+ALTER TABLE outbox ADD COLUMN timeout_seconds INTEGER;
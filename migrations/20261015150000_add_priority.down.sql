@@ -0,0 +1,2 @@
+ALTER TABLE outbox DROP COLUMN priority;
+ALTER TABLE fcm_schedule DROP COLUMN priority;
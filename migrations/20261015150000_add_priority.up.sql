@@ -0,0 +1,2 @@
+ALTER TABLE fcm_schedule ADD COLUMN priority TEXT NOT NULL DEFAULT 'normal';
+ALTER TABLE outbox ADD COLUMN priority TEXT NOT NULL DEFAULT 'normal';
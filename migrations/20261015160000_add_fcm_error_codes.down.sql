@@ -0,0 +1,2 @@
+ALTER TABLE fcm_schedule DROP COLUMN disabled_reason;
+ALTER TABLE fcm_execution_log DROP COLUMN error_code;
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_execution_log ADD COLUMN error_code TEXT;
+ALTER TABLE fcm_schedule ADD COLUMN disabled_reason TEXT;
@@ -0,0 +1,3 @@
+DROP INDEX outbox_broadcast_id;
+ALTER TABLE outbox DROP COLUMN broadcast_id;
+DROP TABLE fcm_broadcast;
@@ -0,0 +1,16 @@
+CREATE TABLE fcm_broadcast (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    fb_project_id TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    priority TEXT NOT NULL DEFAULT 'normal',
+    status TEXT NOT NULL DEFAULT 'running',
+    total INTEGER NOT NULL DEFAULT 0,
+    enqueued INTEGER NOT NULL DEFAULT 0,
+    last_token TEXT,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+ALTER TABLE outbox ADD COLUMN broadcast_id INTEGER;
+
+CREATE INDEX outbox_broadcast_id ON outbox (broadcast_id, status);
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
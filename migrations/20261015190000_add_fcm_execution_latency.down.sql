@@ -0,0 +1,2 @@
+DROP INDEX fcm_execution_log_executed_at;
+ALTER TABLE fcm_execution_log DROP COLUMN latency_ms;
@@ -0,0 +1,3 @@
+ALTER TABLE fcm_execution_log ADD COLUMN latency_ms INTEGER;
+
+CREATE INDEX fcm_execution_log_executed_at ON fcm_execution_log (executed_at);
@@ -0,0 +1 @@
+DROP TABLE fcm_token_hint;
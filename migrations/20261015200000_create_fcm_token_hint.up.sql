@@ -0,0 +1,8 @@
+CREATE TABLE fcm_token_hint (
+    fb_user_id TEXT NOT NULL,
+    push_token TEXT NOT NULL,
+    replacement_token TEXT,
+    reason TEXT NOT NULL,
+    reported_at DATETIME NOT NULL,
+    PRIMARY KEY (fb_user_id, push_token)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_project DROP COLUMN allowed_link_hosts;
+ALTER TABLE fcm_project DROP COLUMN allowed_link_schemes;
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_project ADD COLUMN allowed_link_schemes TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE fcm_project ADD COLUMN allowed_link_hosts TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1 @@
+ALTER TABLE fcm_project DROP COLUMN require_approval;
@@ -0,0 +1 @@
+ALTER TABLE fcm_project ADD COLUMN require_approval BOOLEAN NOT NULL DEFAULT 0;
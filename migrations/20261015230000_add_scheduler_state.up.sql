@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS scheduler_state (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    paused BOOLEAN NOT NULL DEFAULT 0,
+    reason TEXT,
+    updated_at DATETIME NOT NULL
+);
+
+INSERT INTO scheduler_state (id, paused, updated_at) VALUES (1, 0, CURRENT_TIMESTAMP);
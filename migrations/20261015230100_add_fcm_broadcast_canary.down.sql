@@ -0,0 +1,4 @@
+ALTER TABLE fcm_broadcast DROP COLUMN canary_status;
+ALTER TABLE fcm_broadcast DROP COLUMN canary_max_error_rate;
+ALTER TABLE fcm_broadcast DROP COLUMN canary_delay_seconds;
+ALTER TABLE fcm_broadcast DROP COLUMN canary_size;
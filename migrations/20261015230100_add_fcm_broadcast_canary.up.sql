@@ -0,0 +1,4 @@
+ALTER TABLE fcm_broadcast ADD COLUMN canary_size INTEGER;
+ALTER TABLE fcm_broadcast ADD COLUMN canary_delay_seconds INTEGER NOT NULL DEFAULT 300;
+ALTER TABLE fcm_broadcast ADD COLUMN canary_max_error_rate INTEGER NOT NULL DEFAULT 10;
+ALTER TABLE fcm_broadcast ADD COLUMN canary_status TEXT;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS browser_usage (
+    user_id TEXT NOT NULL,
+    month TEXT NOT NULL,
+    tasks INTEGER NOT NULL DEFAULT 0,
+    browser_ms INTEGER NOT NULL DEFAULT 0,
+    updated_at DATETIME NOT NULL,
+    PRIMARY KEY (user_id, month)
+);
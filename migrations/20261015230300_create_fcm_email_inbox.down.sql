@@ -0,0 +1,2 @@
+DROP INDEX fcm_email_inbox_fb_user_id;
+DROP TABLE fcm_email_inbox;
@@ -0,0 +1,13 @@
+CREATE TABLE fcm_email_inbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    fb_user_id TEXT NOT NULL,
+    fb_project_id TEXT NOT NULL,
+    push_token TEXT NOT NULL,
+    address TEXT NOT NULL UNIQUE,
+    sender_filter TEXT,
+    subject_filter TEXT,
+    received INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_email_inbox_fb_user_id ON fcm_email_inbox (fb_user_id);
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_execution_log DROP COLUMN attempts;
+ALTER TABLE fcm_execution_log DROP COLUMN message_id;
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_execution_log ADD COLUMN message_id TEXT;
+ALTER TABLE fcm_execution_log ADD COLUMN attempts INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_schedule DROP COLUMN run_at;
+ALTER TABLE fcm_schedule DROP COLUMN schedule_type;
@@ -0,0 +1,2 @@
+ALTER TABLE fcm_schedule ADD COLUMN schedule_type TEXT NOT NULL DEFAULT 'recurring';
+ALTER TABLE fcm_schedule ADD COLUMN run_at DATETIME;
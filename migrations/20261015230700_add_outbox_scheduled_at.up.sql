@@ -0,0 +1 @@
+ALTER TABLE outbox ADD COLUMN scheduled_at DATETIME;
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN errored_at DATETIME;
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN failed_count INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+DROP INDEX fcm_execution_log_execution_key;
+DROP INDEX outbox_execution_key;
+ALTER TABLE fcm_execution_log DROP COLUMN execution_key;
+ALTER TABLE outbox DROP COLUMN execution_key;
+ALTER TABLE fcm_execution_log DROP COLUMN push_token;
+DROP INDEX fcm_schedule_tokens_push_token;
+DROP TABLE fcm_schedule_tokens;
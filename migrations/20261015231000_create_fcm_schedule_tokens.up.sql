@@ -0,0 +1,17 @@
+CREATE TABLE fcm_schedule_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    schedule_id INTEGER NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    push_token TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    UNIQUE (schedule_id, push_token)
+);
+
+CREATE INDEX fcm_schedule_tokens_push_token ON fcm_schedule_tokens (push_token);
+
+ALTER TABLE fcm_execution_log ADD COLUMN push_token TEXT;
+
+ALTER TABLE outbox ADD COLUMN execution_key TEXT;
+ALTER TABLE fcm_execution_log ADD COLUMN execution_key TEXT;
+
+CREATE INDEX outbox_execution_key ON outbox (execution_key);
+CREATE INDEX fcm_execution_log_execution_key ON fcm_execution_log (execution_key);
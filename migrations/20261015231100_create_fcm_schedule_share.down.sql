@@ -0,0 +1,3 @@
+DROP INDEX fcm_schedule_share_fb_user_id;
+DROP INDEX fcm_schedule_share_invitee;
+DROP TABLE fcm_schedule_share;
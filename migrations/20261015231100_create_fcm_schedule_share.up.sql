@@ -0,0 +1,13 @@
+CREATE TABLE fcm_schedule_share (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    schedule_id INTEGER NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    invitee TEXT NOT NULL,
+    role TEXT NOT NULL,
+    fb_user_id TEXT,
+    created_at DATETIME NOT NULL,
+    accepted_at DATETIME,
+    UNIQUE (schedule_id, invitee)
+);
+
+CREATE INDEX fcm_schedule_share_invitee ON fcm_schedule_share (invitee);
+CREATE INDEX fcm_schedule_share_fb_user_id ON fcm_schedule_share (fb_user_id);
@@ -0,0 +1,5 @@
+DROP INDEX fcm_schedule_organization_id;
+ALTER TABLE fcm_schedule DROP COLUMN organization_id;
+DROP INDEX fcm_organization_member_fb_user_id;
+DROP TABLE fcm_organization_member;
+DROP TABLE fcm_organization;
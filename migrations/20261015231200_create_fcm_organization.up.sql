@@ -0,0 +1,20 @@
+CREATE TABLE fcm_organization (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    fb_project_id TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE TABLE fcm_organization_member (
+    organization_id INTEGER NOT NULL REFERENCES fcm_organization (id) ON DELETE CASCADE,
+    fb_user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (organization_id, fb_user_id)
+);
+
+CREATE INDEX fcm_organization_member_fb_user_id ON fcm_organization_member (fb_user_id);
+
+ALTER TABLE fcm_schedule ADD COLUMN organization_id INTEGER REFERENCES fcm_organization (id) ON DELETE SET NULL;
+
+CREATE INDEX fcm_schedule_organization_id ON fcm_schedule (organization_id);
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN target_type TEXT NOT NULL DEFAULT 'token';
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN conditions TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,3 @@
+DROP INDEX fcm_schedule_followup_target_schedule_id;
+DROP INDEX fcm_schedule_followup_schedule_id;
+DROP TABLE fcm_schedule_followup;
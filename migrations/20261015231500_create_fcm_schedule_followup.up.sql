@@ -0,0 +1,13 @@
+CREATE TABLE fcm_schedule_followup (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    schedule_id INTEGER NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    outcome TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target_schedule_id INTEGER REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    url TEXT,
+    delay_minutes INTEGER,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_schedule_followup_schedule_id ON fcm_schedule_followup (schedule_id);
+CREATE INDEX fcm_schedule_followup_target_schedule_id ON fcm_schedule_followup (target_schedule_id);
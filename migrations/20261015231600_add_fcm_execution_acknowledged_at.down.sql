@@ -0,0 +1 @@
+ALTER TABLE fcm_execution_log DROP COLUMN acknowledged_at;
@@ -0,0 +1 @@
+ALTER TABLE fcm_execution_log ADD COLUMN acknowledged_at DATETIME;
@@ -0,0 +1,4 @@
+ALTER TABLE fcm_schedule DROP COLUMN remaining_executions;
+ALTER TABLE fcm_schedule DROP COLUMN execution_count;
+ALTER TABLE fcm_schedule DROP COLUMN max_executions;
+ALTER TABLE fcm_schedule DROP COLUMN expires_at;
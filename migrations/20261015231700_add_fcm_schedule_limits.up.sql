@@ -0,0 +1,4 @@
+ALTER TABLE fcm_schedule ADD COLUMN expires_at DATETIME;
+ALTER TABLE fcm_schedule ADD COLUMN max_executions INTEGER;
+ALTER TABLE fcm_schedule ADD COLUMN execution_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE fcm_schedule ADD COLUMN remaining_executions INTEGER GENERATED ALWAYS AS (MAX(max_executions - execution_count, 0)) VIRTUAL;
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule DROP COLUMN snoozed_until;
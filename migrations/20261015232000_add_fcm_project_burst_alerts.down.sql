@@ -0,0 +1,3 @@
+ALTER TABLE fcm_project DROP COLUMN paused_at;
+ALTER TABLE fcm_project DROP COLUMN pause_on_burst;
+ALTER TABLE fcm_project DROP COLUMN alert_webhook_url;
@@ -0,0 +1,3 @@
+ALTER TABLE fcm_project ADD COLUMN alert_webhook_url TEXT;
+ALTER TABLE fcm_project ADD COLUMN pause_on_burst BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE fcm_project ADD COLUMN paused_at DATETIME;
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,7 @@
+CREATE TABLE quota_warning (
+    user_id TEXT NOT NULL,
+    quota TEXT NOT NULL,
+    period TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (user_id, quota, period)
+);
@@ -0,0 +1,2 @@
+DROP TABLE fcm_impersonation_request;
+DROP TABLE fcm_impersonation;
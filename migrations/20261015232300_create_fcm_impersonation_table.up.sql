@@ -0,0 +1,22 @@
+CREATE TABLE fcm_impersonation (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    fb_user_id TEXT NOT NULL,
+    fb_project_id TEXT NOT NULL,
+    operator TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    expires_at DATETIME NOT NULL,
+    revoked_at DATETIME,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_impersonation_fb_user_id ON fcm_impersonation (fb_user_id);
+
+CREATE TABLE fcm_impersonation_request (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    impersonation_id INTEGER NOT NULL REFERENCES fcm_impersonation (id) ON DELETE CASCADE,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_impersonation_request_impersonation_id ON fcm_impersonation_request (impersonation_id);
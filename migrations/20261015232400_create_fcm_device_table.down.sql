@@ -0,0 +1,2 @@
+ALTER TABLE fcm_schedule DROP COLUMN local_time;
+DROP TABLE fcm_device;
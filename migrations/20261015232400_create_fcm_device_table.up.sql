@@ -0,0 +1,11 @@
+CREATE TABLE fcm_device (
+    push_token TEXT PRIMARY KEY NOT NULL,
+    fb_user_id TEXT NOT NULL REFERENCES fcm_user (fb_user_id) ON DELETE CASCADE,
+    timezone TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_device_fb_user_id ON fcm_device (fb_user_id);
+
+ALTER TABLE fcm_schedule ADD COLUMN local_time BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+DROP INDEX fcm_webhook_delivery_pending;
+DROP INDEX fcm_webhook_delivery_schedule_id;
+DROP TABLE fcm_webhook_delivery;
+DROP TABLE fcm_schedule_webhook;
@@ -0,0 +1,23 @@
+CREATE TABLE fcm_schedule_webhook (
+    schedule_id INTEGER PRIMARY KEY NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
+
+CREATE TABLE fcm_webhook_delivery (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    schedule_id INTEGER NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME NOT NULL,
+    delivered_at DATETIME,
+    failed_at DATETIME,
+    error TEXT,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX fcm_webhook_delivery_schedule_id ON fcm_webhook_delivery (schedule_id);
+CREATE INDEX fcm_webhook_delivery_pending ON fcm_webhook_delivery (next_attempt_at) WHERE delivered_at IS NULL AND failed_at IS NULL;
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN message TEXT NOT NULL DEFAULT '{}';
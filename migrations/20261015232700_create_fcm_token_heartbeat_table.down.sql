@@ -0,0 +1,2 @@
+DROP INDEX fcm_token_heartbeat_fb_user_id;
+DROP TABLE fcm_token_heartbeat;
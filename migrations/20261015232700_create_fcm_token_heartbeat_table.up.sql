@@ -0,0 +1,9 @@
+CREATE TABLE fcm_token_heartbeat (
+    push_token TEXT PRIMARY KEY NOT NULL,
+    fb_user_id TEXT NOT NULL REFERENCES fcm_user (fb_user_id) ON DELETE CASCADE,
+    first_seen_at DATETIME NOT NULL,
+    last_seen_at DATETIME NOT NULL,
+    hinted_at DATETIME
+);
+
+CREATE INDEX fcm_token_heartbeat_fb_user_id ON fcm_token_heartbeat (fb_user_id);
@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
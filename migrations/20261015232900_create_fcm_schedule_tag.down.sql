@@ -0,0 +1,3 @@
+DROP TRIGGER fcm_schedule_tag_update;
+DROP TRIGGER fcm_schedule_tag_insert;
+DROP TABLE fcm_schedule_tag;
@@ -0,0 +1,19 @@
+CREATE TABLE fcm_schedule_tag (
+    schedule_id INTEGER NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (schedule_id, tag)
+);
+
+CREATE INDEX fcm_schedule_tag_tag ON fcm_schedule_tag (tag, schedule_id);
+
+INSERT OR IGNORE INTO fcm_schedule_tag (schedule_id, tag)
+SELECT fcm_schedule.id, json_each.value FROM fcm_schedule, json_each(fcm_schedule.tags);
+
+CREATE TRIGGER fcm_schedule_tag_insert AFTER INSERT ON fcm_schedule BEGIN
+    INSERT OR IGNORE INTO fcm_schedule_tag (schedule_id, tag) SELECT new.id, value FROM json_each(new.tags);
+END;
+
+CREATE TRIGGER fcm_schedule_tag_update AFTER UPDATE OF tags ON fcm_schedule BEGIN
+    DELETE FROM fcm_schedule_tag WHERE schedule_id = new.id;
+    INSERT OR IGNORE INTO fcm_schedule_tag (schedule_id, tag) SELECT new.id, value FROM json_each(new.tags);
+END;
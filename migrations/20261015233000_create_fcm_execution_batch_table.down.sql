@@ -0,0 +1,2 @@
+DROP TABLE fcm_execution_batch_message;
+DROP TABLE fcm_execution_batch;
@@ -0,0 +1,20 @@
+CREATE TABLE fcm_execution_batch (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    schedule_id INTEGER NOT NULL REFERENCES fcm_schedule (id) ON DELETE CASCADE,
+    total INTEGER NOT NULL,
+    completed_at DATETIME,
+    created_at DATETIME NOT NULL
+);
+
+CREATE TABLE fcm_execution_batch_message (
+    outbox_id INTEGER PRIMARY KEY NOT NULL,
+    batch_id INTEGER NOT NULL REFERENCES fcm_execution_batch (id) ON DELETE CASCADE,
+    push_token TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    error_code TEXT,
+    error TEXT,
+    executed_at DATETIME
+);
+
+CREATE INDEX fcm_execution_batch_schedule_id ON fcm_execution_batch (schedule_id);
+CREATE INDEX fcm_execution_batch_message_batch_id ON fcm_execution_batch_message (batch_id);
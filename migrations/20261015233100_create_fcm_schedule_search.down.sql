@@ -0,0 +1,4 @@
+DROP TRIGGER fcm_schedule_search_update;
+DROP TRIGGER fcm_schedule_search_delete;
+DROP TRIGGER fcm_schedule_search_insert;
+DROP TABLE fcm_schedule_search;
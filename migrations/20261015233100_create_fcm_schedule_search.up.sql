@@ -0,0 +1,22 @@
+CREATE VIRTUAL TABLE fcm_schedule_search USING fts5 (
+    name,
+    payload,
+    message,
+    content = 'fcm_schedule',
+    content_rowid = 'id'
+);
+
+INSERT INTO fcm_schedule_search (fcm_schedule_search) VALUES ('rebuild');
+
+CREATE TRIGGER fcm_schedule_search_insert AFTER INSERT ON fcm_schedule BEGIN
+    INSERT INTO fcm_schedule_search (rowid, name, payload, message) VALUES (new.id, new.name, new.payload, new.message);
+END;
+
+CREATE TRIGGER fcm_schedule_search_delete AFTER DELETE ON fcm_schedule BEGIN
+    INSERT INTO fcm_schedule_search (fcm_schedule_search, rowid, name, payload, message) VALUES ('delete', old.id, old.name, old.payload, old.message);
+END;
+
+CREATE TRIGGER fcm_schedule_search_update AFTER UPDATE OF name, payload, message ON fcm_schedule BEGIN
+    INSERT INTO fcm_schedule_search (fcm_schedule_search, rowid, name, payload, message) VALUES ('delete', old.id, old.name, old.payload, old.message);
+    INSERT INTO fcm_schedule_search (rowid, name, payload, message) VALUES (new.id, new.name, new.payload, new.message);
+END;
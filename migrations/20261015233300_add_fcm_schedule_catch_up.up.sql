@@ -0,0 +1 @@
+ALTER TABLE fcm_schedule ADD COLUMN catch_up TEXT NOT NULL DEFAULT 'once';
@@ -0,0 +1,216 @@
+use lazy_static::lazy_static;
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response, Result as PoemResult};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    env,
+    fmt::Debug,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{
+    field::{Field, Visit},
+    info, warn, Event, Subscriber,
+};
+use tracing_subscriber::{filter::filter_fn, layer::Context, registry::LookupSpan, Layer};
+
+lazy_static! {
+    // requests taking longer are logged as warnings along with the slowest SQL statement that ran meanwhile
+    static ref SLOW_REQUEST_MS: u64 = env::var("ACCESS_LOG_SLOW_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1000);
+    static ref STATEMENTS: Mutex<VecDeque<Statement>> =
+        Mutex::new(VecDeque::with_capacity(STATEMENT_HISTORY));
+}
+
+// number of recently finished SQL statements kept to annotate slow requests
+const STATEMENT_HISTORY: usize = 256;
+// longest SQL text attached to a log line
+const MAX_SQL_CHARS: usize = 500;
+
+tokio::task_local! {
+    static USER_ID: RefCell<Option<String>>;
+}
+
+/// SQL statement reported by sqlx
+struct Statement {
+    finished_at: Instant,
+    elapsed: Duration,
+    sql: String,
+}
+
+/// Attach the authenticated user to the access log line of the current request
+pub fn set_user(user_id: &str) {
+    let _ = USER_ID.try_with(|user| *user.borrow_mut() = Some(user_id.to_string()));
+}
+
+/// User authenticated by the current request so far
+pub fn current_user() -> Option<String> {
+    USER_ID
+        .try_with(|user| user.borrow().clone())
+        .ok()
+        .flatten()
+}
+
+/// Records the SQL statements sqlx reports so slow requests can name the slowest one.
+///
+/// sqlite statements run on a connection worker thread rather than the request's
+/// task, so a request is matched with the statements that finished while it ran,
+/// which may include statements of concurrent requests and background jobs.
+pub fn sql_layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    SqlStatements.with_filter(filter_fn(|metadata| metadata.target() == "sqlx::query"))
+}
+
+struct SqlStatements;
+
+impl<S: Subscriber> Layer<S> for SqlStatements {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = StatementVisitor::default();
+        event.record(&mut visitor);
+
+        let elapsed = match visitor.elapsed_secs {
+            Some(elapsed) => Duration::from_secs_f64(elapsed.max(0.0)),
+            None => return,
+        };
+        // sqlx only attaches the formatted statement when the summary is truncated
+        let sql = match visitor.statement.map(|sql| sql.trim().to_string()) {
+            Some(sql) if !sql.is_empty() => sql,
+            _ => visitor.summary.unwrap_or_default(),
+        };
+
+        let mut statements = STATEMENTS.lock().unwrap();
+        if statements.len() == STATEMENT_HISTORY {
+            statements.pop_front();
+        }
+        statements.push_back(Statement {
+            finished_at: Instant::now(),
+            elapsed,
+            sql,
+        });
+    }
+}
+
+#[derive(Default)]
+struct StatementVisitor {
+    summary: Option<String>,
+    statement: Option<String>,
+    elapsed_secs: Option<f64>,
+}
+
+impl Visit for StatementVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" {
+            self.elapsed_secs = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = Some(value.to_string()),
+            "db.statement" => self.statement = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "summary" => self.summary = Some(format!("{:?}", value)),
+            "db.statement" => self.statement = Some(format!("{:?}", value)),
+            _ => {}
+        }
+    }
+}
+
+/// Slowest statement that finished after the request started
+fn slowest_statement(since: Instant) -> Option<(Duration, String)> {
+    STATEMENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|statement| statement.finished_at >= since)
+        .max_by_key(|statement| statement.elapsed)
+        .map(|statement| {
+            (
+                statement.elapsed,
+                statement.sql.chars().take(MAX_SQL_CHARS).collect(),
+            )
+        })
+}
+
+/// Logs the method, path, status, user and duration of every request. Requests
+/// slower than ACCESS_LOG_SLOW_MS are flagged as slow together with the slowest
+/// SQL statement observed while they ran.
+pub struct AccessLog;
+
+impl<E: Endpoint> Middleware<E> for AccessLog {
+    type Output = AccessLogEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        AccessLogEndpoint { ep }
+    }
+}
+
+pub struct AccessLogEndpoint<E> {
+    ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for AccessLogEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> PoemResult<Self::Output> {
+        let started = Instant::now();
+        let method = req.method().to_string();
+        let path = req.uri().path().to_string();
+
+        let (response, user_id) = USER_ID
+            .scope(RefCell::new(None), async {
+                let response = match self.ep.call(req).await {
+                    Ok(response) => response.into_response(),
+                    Err(e) => e.into_response(),
+                };
+                (response, USER_ID.with(|user| user.borrow().clone()))
+            })
+            .await;
+
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let status = response.status().as_u16();
+        let user_id = user_id.unwrap_or_default();
+
+        if duration_ms < *SLOW_REQUEST_MS {
+            info!(target: "access", %method, %path, status, %user_id, duration_ms, "request");
+            return Ok(response);
+        }
+
+        match slowest_statement(started) {
+            Some((elapsed, sql)) => warn!(
+                target: "access",
+                %method,
+                %path,
+                status,
+                %user_id,
+                duration_ms,
+                slow = true,
+                slowest_sql_ms = elapsed.as_millis() as u64,
+                slowest_sql = %sql,
+                "slow request"
+            ),
+            None => warn!(
+                target: "access",
+                %method,
+                %path,
+                status,
+                %user_id,
+                duration_ms,
+                slow = true,
+                "slow request"
+            ),
+        }
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,544 @@
+use super::model::{
+    ConfigReload, ConnectivityResult, EgressDestination, EgressReport, Report, SchedulerState,
+    SchedulerStatus, TaskStatus, UserOrder, UserSummary,
+};
+use super::report;
+use super::tasks::{self, SCHEDULER_TASK};
+use super::users;
+use crate::config;
+use crate::http;
+use crate::metrics::{self, MetricsReport};
+use crate::outbox::{self, Channel};
+use crate::utils::{
+    self, verify_apikey, ApiTags, JsonError, JsonSuccess, PageMeta, ResponseObject, SortDirection,
+};
+use chrono::{NaiveDate, Utc};
+use poem::{web::Data, Request};
+use poem_openapi::{param::Query, payload::PlainText, ApiResponse, OpenApi};
+use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub struct Admin;
+
+// sends are capped at 300 seconds, a message claimed for longer than this was abandoned
+const STALE_SENDING_SECS: i64 = 600;
+
+fn default_user_limit() -> i64 {
+    50
+}
+
+#[derive(ApiResponse)]
+enum CsvResponse {
+    /// Report rendered as CSV
+    #[oai(status = 200, content_type = "text/csv")]
+    Ok(PlainText<String>),
+}
+
+#[derive(ApiResponse)]
+enum LagResponse {
+    /// Delivery lag in seconds
+    #[oai(status = 200, content_type = "text/plain")]
+    Ok(PlainText<String>),
+}
+
+fn destinations() -> Vec<EgressDestination> {
+    [
+        (
+            "fcm",
+            "https://fcm.googleapis.com",
+            "Firebase cloud messaging delivery",
+        ),
+        (
+            "google-oauth",
+            "https://oauth2.googleapis.com",
+            "Service account token exchange",
+        ),
+        ("openai", "https://api.openai.com", "Audio transcription"),
+        (
+            "instagram",
+            "https://www.instagram.com",
+            "Instagram reel downloads",
+        ),
+        ("12ft", "https://12ft.io", "Paywall bypass proxy"),
+        (
+            "selenium",
+            utils::CHROME_DRIVER_ENDPOINT.as_str(),
+            "Browser automation",
+        ),
+    ]
+    .into_iter()
+    .map(|(name, url, purpose)| EgressDestination {
+        name: name.to_string(),
+        url: url.to_string(),
+        purpose: purpose.to_string(),
+    })
+    .collect()
+}
+
+async fn scheduler_state(pool: &SqlitePool) -> Result<SchedulerState, sqlx::Error> {
+    sqlx::query_as!(
+        SchedulerState,
+        r#"SELECT paused as "paused: bool", reason, updated_at FROM scheduler_state WHERE id = 1"#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+async fn public_ip(url: &str) -> Option<String> {
+    let request = http::CLIENT.get(url).timeout(Duration::from_secs(10));
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!(url = %url, error = ?e, "Failed to resolve public ip");
+            return None;
+        }
+    };
+
+    response.text().await.ok().map(|ip| ip.trim().to_string())
+}
+
+#[OpenApi(
+    prefix_path = "/admin/",
+    request_header(name = "API-Key", ty = "String", description = "Private API Key"),
+    tag = "ApiTags::Admin"
+)]
+impl Admin {
+    /// report the outbound ip addresses and external destinations of the server
+    #[oai(path = "/egress", method = "get", operation_id = "admin::get_egress")]
+    async fn get_egress(
+        &self,
+        req: &Request,
+    ) -> Result<JsonSuccess<EgressReport>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        Ok(ResponseObject::ok(EgressReport {
+            ipv4: public_ip("https://api.ipify.org").await,
+            ipv6: public_ip("https://api6.ipify.org").await,
+            destinations: destinations(),
+        }))
+    }
+
+    /// test the connectivity to an external destination
+    #[oai(
+        path = "/egress/test",
+        method = "post",
+        operation_id = "admin::test_egress"
+    )]
+    async fn test_egress(
+        &self,
+        req: &Request,
+        /// name of the destination to test
+        destination: Query<String>,
+    ) -> Result<JsonSuccess<ConnectivityResult>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let destination = match destinations().into_iter().find(|d| d.name == destination.0) {
+            Some(destination) => destination,
+            None => {
+                return Err(ResponseObject::not_found("Unknown destination"));
+            }
+        };
+
+        let started = Instant::now();
+        let response = http::CLIENT
+            .get(&destination.url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let (status, error) = match response {
+            Ok(response) => (Some(response.status().as_u16()), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        Ok(ResponseObject::ok(ConnectivityResult {
+            name: destination.name,
+            url: destination.url,
+            reachable: status.is_some(),
+            status,
+            latency_ms,
+            error,
+        }))
+    }
+
+    /// list the background tasks of the server and their last outcome
+    #[oai(path = "/tasks", method = "get", operation_id = "admin::list_tasks")]
+    async fn list_tasks(
+        &self,
+        req: &Request,
+    ) -> Result<JsonSuccess<Vec<TaskStatus>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        Ok(ResponseObject::ok(tasks::list()))
+    }
+
+    /// delivery latency histograms and SLO burn rates of every channel since the server started
+    #[oai(path = "/metrics", method = "get", operation_id = "admin::get_metrics")]
+    async fn get_metrics(
+        &self,
+        req: &Request,
+    ) -> Result<JsonSuccess<MetricsReport>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        Ok(ResponseObject::ok(metrics::report()))
+    }
+
+    /// aggregate deliveries, failures by error class, active users and send latency over a window
+    #[oai(path = "/reports", method = "get", operation_id = "admin::get_report")]
+    async fn get_report(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// first day of the window (UTC)
+        from: Query<NaiveDate>,
+        /// last day of the window (UTC), inclusive
+        to: Query<NaiveDate>,
+    ) -> Result<JsonSuccess<Report>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        if from.0 > to.0 {
+            return Err(ResponseObject::bad_request("from must not be after to"));
+        }
+
+        match report::build_report(pool.0, from.0, to.0).await {
+            Ok(report) => Ok(ResponseObject::ok(report)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// same as /reports rendered as CSV
+    #[oai(
+        path = "/reports.csv",
+        method = "get",
+        operation_id = "admin::get_report_csv"
+    )]
+    async fn get_report_csv(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// first day of the window (UTC)
+        from: Query<NaiveDate>,
+        /// last day of the window (UTC), inclusive
+        to: Query<NaiveDate>,
+    ) -> Result<CsvResponse, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        if from.0 > to.0 {
+            return Err(ResponseObject::bad_request("from must not be after to"));
+        }
+
+        let report = match report::build_report(pool.0, from.0, to.0).await {
+            Ok(report) => report,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        match report::to_csv(&report) {
+            Ok(csv) => Ok(CsvResponse::Ok(PlainText(csv))),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// look up users of the messaging API with the schedules and tokens they own
+    #[allow(clippy::too_many_arguments)]
+    #[oai(path = "/users", method = "get", operation_id = "admin::list_users")]
+    async fn list_users(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// only return users whose user id or project id contains the text, ignoring case
+        search: Query<Option<String>>,
+        /// only return users of the Firebase project
+        fb_project_id: Query<Option<String>>,
+        /// field to sort the users by
+        #[oai(default)]
+        order_by: Query<UserOrder>,
+        /// sort direction
+        #[oai(default)]
+        order: Query<SortDirection>,
+        /// maximum number of users to return
+        #[oai(
+            default = "default_user_limit",
+            validator(minimum(value = "1"), maximum(value = "200"))
+        )]
+        limit: Query<i64>,
+        /// number of users to skip
+        #[oai(default, validator(minimum(value = "0")))]
+        offset: Query<i64>,
+    ) -> Result<JsonSuccess<Vec<UserSummary>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let search = search.0.filter(|search| !search.is_empty());
+
+        let total =
+            match users::count_users(pool.0, search.as_deref(), fb_project_id.0.as_deref()).await {
+                Ok(total) => total,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+        let users = users::list_users(
+            pool.0,
+            search.as_deref(),
+            fb_project_id.0.as_deref(),
+            order_by.0,
+            order.0 == SortDirection::Desc,
+            limit.0,
+            offset.0,
+        )
+        .await;
+
+        match users {
+            Ok(users) => Ok(ResponseObject::ok_with_meta(
+                users,
+                PageMeta {
+                    total,
+                    limit: Some(limit.0),
+                    offset: offset.0,
+                },
+            )),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// state of the global pause switch of outbound sends
+    #[oai(
+        path = "/scheduler",
+        method = "get",
+        operation_id = "admin::get_scheduler_state"
+    )]
+    async fn get_scheduler_state(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<SchedulerState>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        match scheduler_state(pool.0).await {
+            Ok(state) => Ok(ResponseObject::ok(state)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// health of the schedule dispatcher: last tick, queue depth, in flight and stale sends, and lag
+    #[oai(
+        path = "/scheduler/status",
+        method = "get",
+        operation_id = "admin::get_scheduler_status"
+    )]
+    async fn get_scheduler_status(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<SchedulerStatus>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let stale_before = current_time - chrono::Duration::seconds(STALE_SENDING_SECS);
+        let queue = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(status = 'pending' AND next_attempt_at <= ?1), 0) as "queue_depth!: i64",
+                COALESCE(SUM(status = 'pending' AND next_attempt_at > ?1), 0) as "deferred!: i64",
+                COALESCE(SUM(status = 'sending'), 0) as "in_flight!: i64",
+                COALESCE(SUM(status = 'sending' AND updated_at < ?2), 0) as "stale!: i64"
+            FROM outbox WHERE status IN ('pending', 'sending')"#,
+            current_time,
+            stale_before
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let queue = match queue {
+            Ok(queue) => queue,
+            Err(e) => return Err(ResponseObject::internal_server_error(e)),
+        };
+
+        let overdue_schedules = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule
+            WHERE disabled_reason IS NULL AND next_execution < datetime('now', '-2 minutes')"#
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let overdue_schedules = match overdue_schedules {
+            Ok(count) => count,
+            Err(e) => return Err(ResponseObject::internal_server_error(e)),
+        };
+
+        let since = current_time - chrono::Duration::hours(1);
+        let lag = sqlx::query!(
+            r#"SELECT
+                AVG((julianday(updated_at) - julianday(COALESCE(scheduled_at, created_at))) * 86400) as "avg: f64",
+                MAX((julianday(updated_at) - julianday(COALESCE(scheduled_at, created_at))) * 86400) as "max: f64"
+            FROM outbox WHERE status = 'delivered' AND schedule_id IS NOT NULL AND updated_at >= ?"#,
+            since
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let lag = match lag {
+            Ok(lag) => lag,
+            Err(e) => return Err(ResponseObject::internal_server_error(e)),
+        };
+
+        let lag_seconds = match outbox::lag(pool.0, Channel::Fcm).await {
+            Ok(lag) => lag,
+            Err(e) => return Err(ResponseObject::internal_server_error(e)),
+        };
+
+        let tick = tasks::get(SCHEDULER_TASK);
+        Ok(ResponseObject::ok(SchedulerStatus {
+            paused: outbox::is_paused(),
+            last_tick_at: tick.as_ref().and_then(|tick| tick.last_run_at),
+            last_tick_duration_ms: tick.as_ref().and_then(|tick| tick.last_duration_ms),
+            queue_depth: queue.queue_depth,
+            deferred: queue.deferred,
+            in_flight: queue.in_flight,
+            stale: queue.stale,
+            overdue_schedules,
+            lag_seconds,
+            lag_avg_seconds: lag.avg,
+            lag_max_seconds: lag.max,
+        }))
+    }
+
+    /// seconds the oldest due message has been waiting for a delivery worker, as a bare
+    /// number for autoscalers (e.g. a KEDA metrics-api or HPA external metric). Reports 0
+    /// while sends are paused so a pause doesn't scale workers up
+    #[oai(
+        path = "/scheduler/lag",
+        method = "get",
+        operation_id = "admin::get_scheduler_lag"
+    )]
+    async fn get_scheduler_lag(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<LagResponse, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        if outbox::is_paused() {
+            return Ok(LagResponse::Ok(PlainText("0".to_string())));
+        }
+
+        match outbox::lag(pool.0, Channel::Fcm).await {
+            Ok(lag) => {
+                metrics::record_lag(Channel::Fcm, lag);
+                Ok(LagResponse::Ok(PlainText(lag.to_string())))
+            }
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// halt every outbound send until resumed, persisted across restarts
+    #[oai(
+        path = "/scheduler/pause",
+        method = "post",
+        operation_id = "admin::pause_scheduler"
+    )]
+    async fn pause_scheduler(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// why sends are paused, e.g. an incident reference
+        reason: Query<Option<String>>,
+    ) -> Result<JsonSuccess<SchedulerState>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        if let Err(e) = outbox::set_paused(pool.0, true, reason.0.as_deref()).await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+        warn!(reason = ?reason.0, "Outbound sends paused");
+
+        match scheduler_state(pool.0).await {
+            Ok(state) => Ok(ResponseObject::ok(state)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// resume outbound sends, messages queued while paused are delivered
+    #[oai(
+        path = "/scheduler/resume",
+        method = "post",
+        operation_id = "admin::resume_scheduler"
+    )]
+    async fn resume_scheduler(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<SchedulerState>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        if let Err(e) = outbox::set_paused(pool.0, false, None).await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+        info!("Outbound sends resumed");
+
+        match scheduler_state(pool.0).await {
+            Ok(state) => Ok(ResponseObject::ok(state)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// reload the .env file, service accounts and claim policies without restarting (same as SIGHUP)
+    #[oai(
+        path = "/config/reload",
+        method = "post",
+        operation_id = "admin::reload_config"
+    )]
+    async fn reload_config(
+        &self,
+        req: &Request,
+    ) -> Result<JsonSuccess<Vec<ConfigReload>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let results = config::reload()
+            .await
+            .into_iter()
+            .map(|(name, result)| match result {
+                Ok(summary) => ConfigReload {
+                    name,
+                    result: Some(summary),
+                    error: None,
+                },
+                Err(e) => ConfigReload {
+                    name,
+                    result: None,
+                    error: Some(e),
+                },
+            })
+            .collect();
+
+        Ok(ResponseObject::ok(results))
+    }
+}
@@ -0,0 +1,68 @@
+use super::tasks;
+use chrono::Utc;
+use cron_parser::parse;
+use lazy_static::lazy_static;
+use sqlx::SqlitePool;
+use std::{env, time::Instant};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const TASK_NAME: &str = "db-maintenance";
+
+lazy_static! {
+    // defaults to every day at 03:00 UTC
+    static ref MAINTENANCE_CRON: String =
+        env::var("DB_MAINTENANCE_CRON").unwrap_or("0 3 * * *".to_string());
+}
+
+/// Periodically optimize the SQLite database so the single file stays healthy
+pub async fn run_db_maintenance(pool: SqlitePool) {
+    loop {
+        let next = match parse(&MAINTENANCE_CRON, &Utc::now()) {
+            Ok(next) => next,
+            Err(e) => {
+                error!(cron = %*MAINTENANCE_CRON, error = ?e, "Invalid DB_MAINTENANCE_CRON, database maintenance disabled");
+                return;
+            }
+        };
+        tasks::register(TASK_NAME, &MAINTENANCE_CRON, Some(next.naive_utc()));
+
+        if let Ok(wait) = (next - Utc::now()).to_std() {
+            sleep(wait).await;
+        }
+
+        let started = Instant::now();
+        let result = optimize(&pool).await.map_err(|e| e.to_string());
+        let duration = started.elapsed();
+
+        match &result {
+            Ok(summary) => {
+                info!(duration_ms = duration.as_millis() as u64, summary = %summary, "Database maintenance completed")
+            }
+            Err(e) => error!(error = %e, "Database maintenance failed"),
+        }
+        tasks::record(TASK_NAME, duration, result);
+    }
+}
+
+async fn optimize(pool: &SqlitePool) -> Result<String, sqlx::Error> {
+    let freelist_before: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await?;
+
+    sqlx::query("PRAGMA optimize").execute(pool).await?;
+    // only reclaims pages when the database uses auto_vacuum = INCREMENTAL
+    sqlx::query("PRAGMA incremental_vacuum")
+        .execute(pool)
+        .await?;
+    sqlx::query("ANALYZE").execute(pool).await?;
+
+    let freelist_after: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(format!(
+        "optimized and analyzed, free pages {} -> {}",
+        freelist_before, freelist_after
+    ))
+}
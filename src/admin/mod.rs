@@ -0,0 +1,17 @@
+use crate::utils::READ_ONLY;
+use sqlx::SqlitePool;
+
+mod handler;
+mod maintenance;
+mod model;
+mod report;
+pub mod tasks;
+mod users;
+
+pub async fn admin_api(pool: SqlitePool) -> handler::Admin {
+    if !*READ_ONLY {
+        tokio::spawn(maintenance::run_db_maintenance(pool));
+    }
+
+    handler::Admin
+}
@@ -0,0 +1,190 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use poem_openapi::{Enum, Object};
+use serde::Serialize;
+
+/// External destination the server connects to
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct EgressDestination {
+    /// Name of the destination
+    pub name: String,
+    /// Base URL of the destination
+    pub url: String,
+    /// Why the server connects to the destination
+    pub purpose: String,
+}
+
+/// Outbound network report
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct EgressReport {
+    /// Public IPv4 address used for outbound connections
+    pub ipv4: Option<String>,
+    /// Public IPv6 address used for outbound connections
+    pub ipv6: Option<String>,
+    /// Destinations the server will connect to
+    pub destinations: Vec<EgressDestination>,
+}
+
+/// Result of a connectivity test
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct ConnectivityResult {
+    /// Name of the destination
+    pub name: String,
+    /// Base URL of the destination
+    pub url: String,
+    /// Whether a response was received
+    pub reachable: bool,
+    /// HTTP status returned by the destination
+    pub status: Option<u16>,
+    /// Time taken to receive the response in milliseconds
+    pub latency_ms: u64,
+    /// Error returned while connecting
+    pub error: Option<String>,
+}
+
+/// Background task run by the server
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct TaskStatus {
+    /// Name of the task
+    pub name: String,
+    /// Cron pattern the task runs on
+    pub schedule: String,
+    /// Next time the task will run
+    pub next_run_at: Option<NaiveDateTime>,
+    /// Last time the task ran
+    pub last_run_at: Option<NaiveDateTime>,
+    /// Time taken by the last run in milliseconds
+    pub last_duration_ms: Option<u64>,
+    /// Summary of the last successful run
+    pub last_result: Option<String>,
+    /// Error of the last failed run
+    pub last_error: Option<String>,
+}
+
+/// Number of failed sends with the same error class
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct ErrorCount {
+    /// Error class, e.g. invalid_token or quota_exceeded
+    pub error_code: String,
+    /// Number of failed sends
+    pub count: i64,
+}
+
+/// Delivery statistics of scheduled messages over a time window
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct Report {
+    /// First day of the window (UTC)
+    pub from: NaiveDate,
+    /// Last day of the window (UTC), inclusive
+    pub to: NaiveDate,
+    /// Number of executions
+    pub sends: i64,
+    /// Number of successful executions
+    pub successes: i64,
+    /// Number of failed or timed out executions
+    pub failures: i64,
+    /// Failed executions grouped by error class
+    pub failures_by_error: Vec<ErrorCount>,
+    /// Number of users with at least one execution
+    pub active_users: i64,
+    /// 95th percentile of the send latency in milliseconds, dry runs excluded
+    pub p95_latency_ms: Option<i64>,
+}
+
+/// State of the global pause switch of outbound sends
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct SchedulerState {
+    /// Whether outbound sends are halted
+    pub paused: bool,
+    /// Why sends were paused
+    pub reason: Option<String>,
+    /// Last time the switch was changed
+    pub updated_at: NaiveDateTime,
+}
+
+/// Health of the schedule dispatcher and the outbox it fills
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct SchedulerStatus {
+    /// Whether outbound sends are halted
+    pub paused: bool,
+    /// Last time due schedules were enqueued
+    pub last_tick_at: Option<NaiveDateTime>,
+    /// Time taken by the last tick in milliseconds
+    pub last_tick_duration_ms: Option<u64>,
+    /// Messages due and waiting for a delivery worker
+    pub queue_depth: i64,
+    /// Messages waiting for a retry or a delayed send time
+    pub deferred: i64,
+    /// Messages being sent right now
+    pub in_flight: i64,
+    /// Messages claimed longer ago than any send may take, e.g. by a worker that crashed
+    pub stale: i64,
+    /// Enabled schedules whose next execution passed more than two minutes ago
+    pub overdue_schedules: i64,
+    /// Seconds the oldest due message has been waiting for a delivery worker
+    pub lag_seconds: i64,
+    /// Average seconds between the time a schedule was due and its delivery over the last hour
+    pub lag_avg_seconds: Option<f64>,
+    /// Longest time between a schedule being due and its delivery over the last hour
+    pub lag_max_seconds: Option<f64>,
+}
+
+/// Outcome of reloading a piece of configuration
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct ConfigReload {
+    /// Name of the configuration, e.g. service_accounts
+    pub name: String,
+    /// Summary of the loaded configuration
+    pub result: Option<String>,
+    /// Error returned while reloading, the previous configuration stays in use
+    pub error: Option<String>,
+}
+
+/// Field to sort the user directory by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum UserOrder {
+    /// most recent of the last request and the last execution
+    #[default]
+    LastActiveAt,
+    CreatedAt,
+    Schedules,
+    Tokens,
+    FbUserId,
+}
+
+impl UserOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserOrder::LastActiveAt => "last_active_at",
+            UserOrder::CreatedAt => "created_at",
+            UserOrder::Schedules => "schedules",
+            UserOrder::Tokens => "tokens",
+            UserOrder::FbUserId => "fb_user_id",
+        }
+    }
+}
+
+/// User of the messaging API and what they own
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct UserSummary {
+    /// Firebase user id
+    pub fb_user_id: String,
+    /// Firebase project the user signed in to
+    pub fb_project_id: String,
+    /// Whether the user signed in anonymously
+    pub anonymous: bool,
+    /// Number of schedules owned by the user
+    pub schedules: i64,
+    /// Number of enabled schedules owned by the user
+    pub active_schedules: i64,
+    /// Number of distinct push tokens targeted by the user's schedules
+    pub tokens: i64,
+    /// Last time the user called the API
+    pub last_seen_at: NaiveDateTime,
+    /// Last time a schedule of the user was executed
+    pub last_execution_at: Option<NaiveDateTime>,
+    /// Most recent of `last_seen_at` and `last_execution_at`
+    pub last_active_at: NaiveDateTime,
+    /// First time the user called the API
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,103 @@
+use super::model::{ErrorCount, Report};
+use chrono::{Duration, NaiveDate};
+use sqlx::SqlitePool;
+
+/// Aggregate the execution log between the start of `from` and the end of `to`
+pub async fn build_report(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Report, sqlx::Error> {
+    let start = from.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let end = (to + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default();
+
+    let totals = sqlx::query!(
+        r#"SELECT COUNT(*) as "sends!: i64",
+            COALESCE(SUM(CASE WHEN status = 'success' THEN 1 ELSE 0 END), 0) as "successes!: i64",
+            COUNT(DISTINCT fb_user_id) as "active_users!: i64"
+        FROM fcm_execution_log
+        WHERE executed_at >= ? AND executed_at < ? AND status NOT IN ('retrying', 'skipped') AND dry_run = 0"#,
+        start,
+        end
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let failures_by_error = sqlx::query_as!(
+        ErrorCount,
+        r#"SELECT COALESCE(error_code, 'unknown') as "error_code!: String", COUNT(*) as "count!: i64"
+        FROM fcm_execution_log
+        WHERE executed_at >= ? AND executed_at < ? AND status NOT IN ('success', 'retrying', 'skipped') AND dry_run = 0
+        GROUP BY COALESCE(error_code, 'unknown')
+        ORDER BY COUNT(*) DESC"#,
+        start,
+        end
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // nearest-rank percentile: skip the fastest 95% of the measured sends
+    let p95_latency_ms = sqlx::query_scalar!(
+        r#"SELECT latency_ms as "latency_ms!: i64"
+        FROM fcm_execution_log
+        WHERE executed_at >= ?1 AND executed_at < ?2 AND latency_ms IS NOT NULL AND status NOT IN ('retrying', 'skipped') AND dry_run = 0
+        ORDER BY latency_ms
+        LIMIT 1 OFFSET (
+            SELECT MAX((COUNT(*) * 95 + 99) / 100 - 1, 0)
+            FROM fcm_execution_log
+            WHERE executed_at >= ?1 AND executed_at < ?2 AND latency_ms IS NOT NULL AND status NOT IN ('retrying', 'skipped') AND dry_run = 0
+        )"#,
+        start,
+        end
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(Report {
+        from,
+        to,
+        sends: totals.sends,
+        successes: totals.successes,
+        failures: totals.sends - totals.successes,
+        failures_by_error,
+        active_users: totals.active_users,
+        p95_latency_ms,
+    })
+}
+
+/// Render the report as `metric,value` rows
+pub fn to_csv(report: &Report) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(["metric", "value"])?;
+
+    let mut rows = vec![
+        ("from".to_string(), report.from.to_string()),
+        ("to".to_string(), report.to.to_string()),
+        ("sends".to_string(), report.sends.to_string()),
+        ("successes".to_string(), report.successes.to_string()),
+        ("failures".to_string(), report.failures.to_string()),
+    ];
+    for failure in &report.failures_by_error {
+        rows.push((
+            format!("failures.{}", failure.error_code),
+            failure.count.to_string(),
+        ));
+    }
+    rows.push(("active_users".to_string(), report.active_users.to_string()));
+    rows.push((
+        "p95_latency_ms".to_string(),
+        report
+            .p95_latency_ms
+            .map(|latency| latency.to_string())
+            .unwrap_or_default(),
+    ));
+
+    for (metric, value) in rows {
+        writer.write_record([metric, value])?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
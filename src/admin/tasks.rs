@@ -0,0 +1,53 @@
+use super::model::TaskStatus;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use std::{collections::BTreeMap, sync::Mutex, time::Duration};
+
+/// Task the dispatcher of due FCM schedules records its ticks under
+pub const SCHEDULER_TASK: &str = "fcm-scheduler";
+
+lazy_static! {
+    static ref TASKS: Mutex<BTreeMap<String, TaskStatus>> = Mutex::new(BTreeMap::new());
+}
+
+/// Add a background task to the registry, or update when it runs next
+pub fn register(name: &str, schedule: &str, next_run_at: Option<NaiveDateTime>) {
+    let mut tasks = TASKS.lock().unwrap();
+    let task = tasks.entry(name.to_string()).or_insert_with(|| TaskStatus {
+        name: name.to_string(),
+        schedule: schedule.to_string(),
+        next_run_at: None,
+        last_run_at: None,
+        last_duration_ms: None,
+        last_result: None,
+        last_error: None,
+    });
+    task.schedule = schedule.to_string();
+    task.next_run_at = next_run_at;
+}
+
+/// Record the outcome of a task run
+pub fn record(name: &str, duration: Duration, result: Result<String, String>) {
+    let mut tasks = TASKS.lock().unwrap();
+    if let Some(task) = tasks.get_mut(name) {
+        task.last_run_at = Some(Utc::now().naive_utc());
+        task.last_duration_ms = Some(duration.as_millis() as u64);
+        match result {
+            Ok(summary) => {
+                task.last_result = Some(summary);
+                task.last_error = None;
+            }
+            Err(e) => task.last_error = Some(e),
+        }
+    }
+}
+
+/// Snapshot of a single task
+pub fn get(name: &str) -> Option<TaskStatus> {
+    TASKS.lock().unwrap().get(name).cloned()
+}
+
+/// Snapshot of every registered task
+pub fn list() -> Vec<TaskStatus> {
+    TASKS.lock().unwrap().values().cloned().collect()
+}
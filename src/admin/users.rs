@@ -0,0 +1,103 @@
+use super::model::{UserOrder, UserSummary};
+use chrono::NaiveDateTime;
+use sqlx::SqlitePool;
+
+/// Number of users matching the search and project filters
+pub async fn count_users(
+    pool: &SqlitePool,
+    search: Option<&str>,
+    fb_project_id: Option<&str>,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM fcm_user
+        WHERE (?1 IS NULL OR instr(lower(fb_user_id), lower(?1)) > 0 OR instr(lower(fb_project_id), lower(?1)) > 0)
+        AND (?2 IS NULL OR fb_project_id = ?2)"#,
+        search,
+        fb_project_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Page of users with the schedules and tokens they own
+pub async fn list_users(
+    pool: &SqlitePool,
+    search: Option<&str>,
+    fb_project_id: Option<&str>,
+    order_by: UserOrder,
+    descending: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<UserSummary>, sqlx::Error> {
+    // a token is counted once per user however many schedules target it
+    let order_by = order_by.as_str();
+    sqlx::query_as!(
+        UserSummary,
+        r#"WITH schedules AS (
+            SELECT fb_user_id, COUNT(*) as schedules, SUM(disabled_reason IS NULL) as active_schedules
+            FROM fcm_schedule GROUP BY fb_user_id
+        ), tokens AS (
+            SELECT fb_user_id, COUNT(DISTINCT push_token) as tokens FROM (
+                SELECT fb_user_id, push_token FROM fcm_schedule WHERE target_type = 'token'
+                UNION
+                SELECT fcm_schedule.fb_user_id, fcm_schedule_tokens.push_token
+                FROM fcm_schedule_tokens JOIN fcm_schedule ON fcm_schedule.id = fcm_schedule_tokens.schedule_id
+            ) GROUP BY fb_user_id
+        ), executions AS (
+            SELECT fb_user_id, MAX(executed_at) as executed_at FROM fcm_execution_log WHERE dry_run = 0 GROUP BY fb_user_id
+        ), users AS (
+            SELECT
+                fcm_user.fb_user_id,
+                fcm_user.fb_project_id,
+                fcm_user.anonymous,
+                COALESCE(schedules.schedules, 0) as schedules,
+                COALESCE(schedules.active_schedules, 0) as active_schedules,
+                COALESCE(tokens.tokens, 0) as tokens,
+                fcm_user.last_seen_at,
+                executions.executed_at as last_execution_at,
+                MAX(fcm_user.last_seen_at, COALESCE(executions.executed_at, fcm_user.last_seen_at)) as last_active_at,
+                fcm_user.created_at
+            FROM fcm_user
+            LEFT JOIN schedules ON schedules.fb_user_id = fcm_user.fb_user_id
+            LEFT JOIN tokens ON tokens.fb_user_id = fcm_user.fb_user_id
+            LEFT JOIN executions ON executions.fb_user_id = fcm_user.fb_user_id
+            WHERE (?1 IS NULL OR instr(lower(fcm_user.fb_user_id), lower(?1)) > 0 OR instr(lower(fcm_user.fb_project_id), lower(?1)) > 0)
+            AND (?2 IS NULL OR fcm_user.fb_project_id = ?2)
+        )
+        SELECT
+            fb_user_id as "fb_user_id!: String",
+            fb_project_id as "fb_project_id!: String",
+            anonymous as "anonymous!: bool",
+            schedules as "schedules!: i64",
+            active_schedules as "active_schedules!: i64",
+            tokens as "tokens!: i64",
+            last_seen_at as "last_seen_at!: NaiveDateTime",
+            last_execution_at as "last_execution_at: NaiveDateTime",
+            last_active_at as "last_active_at!: NaiveDateTime",
+            created_at as "created_at!: NaiveDateTime"
+        FROM users
+        ORDER BY
+            CASE WHEN ?3 THEN NULL ELSE CASE ?4
+                WHEN 'created_at' THEN created_at
+                WHEN 'schedules' THEN schedules
+                WHEN 'tokens' THEN tokens
+                WHEN 'fb_user_id' THEN fb_user_id
+                ELSE last_active_at END END ASC,
+            CASE WHEN ?3 THEN CASE ?4
+                WHEN 'created_at' THEN created_at
+                WHEN 'schedules' THEN schedules
+                WHEN 'tokens' THEN tokens
+                WHEN 'fb_user_id' THEN fb_user_id
+                ELSE last_active_at END END DESC,
+            fb_user_id ASC
+        LIMIT ?5 OFFSET ?6"#,
+        search,
+        fb_project_id,
+        descending,
+        order_by,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
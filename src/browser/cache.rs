@@ -0,0 +1,105 @@
+use super::navigation::Navigation;
+use crate::kv;
+use lazy_static::lazy_static;
+use poem::{http::header::CACHE_CONTROL, Request};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+lazy_static! {
+    // seconds a rendered result is reused for identical requests (0 disables the cache)
+    static ref TTL_SECS: u64 = env::var("BROWSER_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    static ref CACHE: Mutex<HashMap<String, (Instant, Value)>> = Mutex::new(HashMap::new());
+}
+
+// results kept at once, the oldest is dropped to make room
+const MAX_ENTRIES: usize = 100;
+
+/// Cache key of a request, the url already includes the paywall bypass
+pub fn key(endpoint: &str, url: &str, delay: u64, navigation: &Navigation) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        endpoint,
+        delay,
+        navigation.fingerprint(),
+        url
+    )
+}
+
+/// Result of an identical request rendered within the TTL. Requests sent with
+/// `Cache-Control: no-cache` are always rendered again.
+pub async fn get<T: DeserializeOwned>(req: &Request, key: &str) -> Option<T> {
+    if *TTL_SECS == 0 {
+        return None;
+    }
+
+    let no_cache = req
+        .headers()
+        .get(CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("no-cache"));
+    if no_cache {
+        return None;
+    }
+
+    // results rendered by any instance are shared through redis when it's configured
+    if kv::enabled() {
+        let value = kv::get(&format!("browser-cache:{}", key)).await?;
+        debug!(key = %key, "Serving cached browser result from redis");
+        return serde_json::from_str(&value).ok();
+    }
+
+    let mut cache = CACHE.lock().unwrap();
+    let value = match cache.get(key) {
+        Some((cached_at, _)) if cached_at.elapsed() > Duration::from_secs(*TTL_SECS) => {
+            cache.remove(key);
+            return None;
+        }
+        Some((_, value)) => value.clone(),
+        None => return None,
+    };
+
+    debug!(key = %key, "Serving cached browser result");
+    serde_json::from_value(value).ok()
+}
+
+/// Remember the result of a request
+pub async fn put<T: Serialize>(key: String, result: &T) {
+    if *TTL_SECS == 0 {
+        return;
+    }
+
+    let value = match serde_json::to_value(result) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    if kv::enabled() {
+        if let Ok(value) = serde_json::to_string(&value) {
+            let ttl = Duration::from_secs(*TTL_SECS);
+            kv::set(&format!("browser-cache:{}", key), &value, ttl).await;
+        }
+        return;
+    }
+
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= MAX_ENTRIES && !cache.contains_key(&key) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|(_, (cached_at, _))| *cached_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(key, (Instant::now(), value));
+}
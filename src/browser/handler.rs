@@ -1,27 +1,75 @@
-use std::{thread, time::Duration, process};
-
-use super::model::Image;
+use std::{collections::HashMap, env, thread, time::{Duration, Instant}, process};
+
+use super::cache;
+use super::model::{BrowserUsage, Image, OcrResult};
+use super::navigation::Navigation;
+use super::ocr;
+use super::politeness;
+use super::usage;
 use crate::utils::{verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
 use base64::{engine::general_purpose, Engine as _};
-use poem::{Request, Result};
+use lazy_static::lazy_static;
+use poem::{web::Data, Request, Result};
 use poem_openapi::{param::Query, OpenApi};
 use thirtyfour::prelude::*;
+use sqlx::SqlitePool;
 use tokio::sync::{Mutex, MutexGuard};
 use tracing::{error, info};
+use url::Url;
+
+lazy_static! {
+    // warm tabs older than this are closed and opened again to shed stale state
+    static ref WARM_TAB_TTL_SECS: u64 = env::var("BROWSER_WARM_TAB_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(900);
+}
+
+// sites kept warm at once, requests for other sites fall back to a fresh tab
+const MAX_WARM_TABS: usize = 5;
+
+struct WarmTab {
+    handle: WindowHandle,
+    opened_at: Instant,
+}
 
 pub struct Selenium {
     driver: Mutex<WebDriver>,
+    // tabs left open per site for warm requests, only used while holding the driver
+    warm_tabs: std::sync::Mutex<HashMap<String, WarmTab>>,
+}
+
+fn site_of(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_string)
 }
 
 #[OpenApi(
     prefix_path = "/browser/",
     request_header(name = "API-Key", ty = "String", description = "Private API Key",),
+    request_header(
+        name = "User-Id",
+        ty = "Option<String>",
+        description = "User the browser usage is attributed to and metered against"
+    ),
+    request_header(
+        name = "Browser-Headers",
+        ty = "Option<String>",
+        description = "JSON object of extra headers the browser sends with every request of the page"
+    ),
+    request_header(
+        name = "Browser-Auth",
+        ty = "Option<String>",
+        description = "Basic auth credentials of the page in <code>username:password</code> format"
+    ),
     tag = "ApiTags::Selenium"
 )]
 impl Selenium {
     // create new instance
     pub fn new(driver: Mutex<WebDriver>) -> Self {
-        Selenium { driver }
+        Selenium {
+            driver,
+            warm_tabs: std::sync::Mutex::new(HashMap::new()),
+        }
     }
 
     /// get rendered html
@@ -29,15 +77,16 @@ impl Selenium {
     async fn get_html(
         &self,
         req: &Request,
+        pool: Data<&SqlitePool>,
         /// url of the page to render
         url: Query<String>,
         /// delay in milliseconds wait for the page to be fully loaded
         delay: Query<u64>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
+        /// reuse a tab kept open on the same site, its cookies and cache make repeated runs faster
+        warm: Query<Option<bool>>,
     ) -> Result<JsonSuccess<String>, JsonError<String>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -45,12 +94,43 @@ impl Selenium {
             }
         }
 
+        let navigation = match Navigation::from_request(req) {
+            Ok(navigation) => navigation,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = politeness::check(&url.0).await {
+            return Err(ResponseObject::forbidden(e));
+        }
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/{}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let cache_key = cache::key("html", &url, delay.0, &navigation);
+        if let Some(html) = cache::get(req, &cache_key).await {
+            return Ok(ResponseObject::ok(html));
+        }
+
+        let user_id = usage::user_of(req);
+        match usage::current(pool.0, &user_id).await {
+            Ok(usage) => {
+                if let Err(e) = usage::check(&usage) {
+                    return Err(ResponseObject::forbidden(e));
+                }
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+        politeness::pace(&url).await;
+        let driver = self.driver.lock().await;
+        let _meter = usage::Meter::start(pool.0, &user_id);
+
+        let driver = match self.setup_driver(&driver, url.as_str(), warm.0.unwrap_or(false), &navigation).await {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -71,7 +151,7 @@ impl Selenium {
             }
         };
 
-        match self.cleanup_driver(driver, url.as_str()).await {
+        match self.release_driver(driver, url.as_str(), warm.0.unwrap_or(false)).await {
             Ok(_) => (),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to cleanup driver");
@@ -81,6 +161,7 @@ impl Selenium {
             }
         }
 
+        cache::put(cache_key, &html).await;
         return Ok(ResponseObject::ok(html));
     }
 
@@ -89,15 +170,16 @@ impl Selenium {
     async fn get_text(
         &self,
         req: &Request,
+        pool: Data<&SqlitePool>,
         /// url of the page to render
         url: Query<String>,
         /// delay in milliseconds wait for the page to be fully loaded
         delay: Query<u64>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
+        /// reuse a tab kept open on the same site, its cookies and cache make repeated runs faster
+        warm: Query<Option<bool>>,
     ) -> Result<JsonSuccess<String>, JsonError<String>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -105,12 +187,43 @@ impl Selenium {
             }
         }
 
+        let navigation = match Navigation::from_request(req) {
+            Ok(navigation) => navigation,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = politeness::check(&url.0).await {
+            return Err(ResponseObject::forbidden(e));
+        }
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let cache_key = cache::key("text", &url, delay.0, &navigation);
+        if let Some(text) = cache::get(req, &cache_key).await {
+            return Ok(ResponseObject::ok(text));
+        }
+
+        let user_id = usage::user_of(req);
+        match usage::current(pool.0, &user_id).await {
+            Ok(usage) => {
+                if let Err(e) = usage::check(&usage) {
+                    return Err(ResponseObject::forbidden(e));
+                }
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+        politeness::pace(&url).await;
+        let driver = self.driver.lock().await;
+        let _meter = usage::Meter::start(pool.0, &user_id);
+
+        let driver = match self.setup_driver(&driver, url.as_str(), warm.0.unwrap_or(false), &navigation).await {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -141,7 +254,7 @@ impl Selenium {
             }
         };
 
-        match self.cleanup_driver(driver, url.as_str()).await {
+        match self.release_driver(driver, url.as_str(), warm.0.unwrap_or(false)).await {
             Ok(_) => (),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to cleanup driver");
@@ -151,6 +264,7 @@ impl Selenium {
             }
         }
 
+        cache::put(cache_key, &text).await;
         return Ok(ResponseObject::ok(text));
     }
 
@@ -163,15 +277,16 @@ impl Selenium {
     async fn get_screenshot(
         &self,
         req: &Request,
+        pool: Data<&SqlitePool>,
         /// url of the page to render
         url: Query<String>,
         /// delay in milliseconds wait for the page to be fully loaded
         delay: Query<u64>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
+        /// reuse a tab kept open on the same site, its cookies and cache make repeated runs faster
+        warm: Query<Option<bool>>,
     ) -> Result<JsonSuccess<String>, JsonError<Option<String>>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -179,12 +294,43 @@ impl Selenium {
             }
         }
 
+        let navigation = match Navigation::from_request(req) {
+            Ok(navigation) => navigation,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = politeness::check(&url.0).await {
+            return Err(ResponseObject::forbidden(e));
+        }
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let cache_key = cache::key("screenshot", &url, delay.0, &navigation);
+        if let Some(screenshot) = cache::get(req, &cache_key).await {
+            return Ok(ResponseObject::ok(screenshot));
+        }
+
+        let user_id = usage::user_of(req);
+        match usage::current(pool.0, &user_id).await {
+            Ok(usage) => {
+                if let Err(e) = usage::check(&usage) {
+                    return Err(ResponseObject::forbidden(e));
+                }
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+        politeness::pace(&url).await;
+        let driver = self.driver.lock().await;
+        let _meter = usage::Meter::start(pool.0, &user_id);
+
+        let driver = match self.setup_driver(&driver, url.as_str(), warm.0.unwrap_or(false), &navigation).await {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -206,7 +352,7 @@ impl Selenium {
         };
         let b64 = general_purpose::STANDARD.encode(screenshot);
 
-        match self.cleanup_driver(driver, url.as_str()).await {
+        match self.release_driver(driver, url.as_str(), warm.0.unwrap_or(false)).await {
             Ok(_) => (),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to cleanup driver");
@@ -216,7 +362,116 @@ impl Selenium {
             }
         }
 
-        return Ok(ResponseObject::ok(format!("data:image/png;base64,{}", b64)));
+        let screenshot = format!("data:image/png;base64,{}", b64);
+        cache::put(cache_key, &screenshot).await;
+        return Ok(ResponseObject::ok(screenshot));
+    }
+
+    /// recognize the text of a screenshot of the rendered page, e.g. values drawn on a canvas
+    #[oai(path = "/ocr/", method = "get", operation_id = "browser::get_ocr")]
+    async fn get_ocr(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// url of the page to render
+        url: Query<String>,
+        /// delay in milliseconds wait for the page to be fully loaded
+        delay: Query<u64>,
+        /// whether to try to bypass paywall
+        bypass_paywall: Query<bool>,
+        /// reuse a tab kept open on the same site, its cookies and cache make repeated runs faster
+        warm: Query<Option<bool>>,
+    ) -> Result<JsonSuccess<OcrResult>, JsonError<String>> {
+        match verify_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        let navigation = match Navigation::from_request(req) {
+            Ok(navigation) => navigation,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = politeness::check(&url.0).await {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        let mut url = url.0;
+        if bypass_paywall.0 {
+            url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
+        }
+
+        let cache_key = cache::key("ocr", &url, delay.0, &navigation);
+        if let Some(result) = cache::get(req, &cache_key).await {
+            return Ok(ResponseObject::ok(result));
+        }
+
+        let user_id = usage::user_of(req);
+        match usage::current(pool.0, &user_id).await {
+            Ok(usage) => {
+                if let Err(e) = usage::check(&usage) {
+                    return Err(ResponseObject::forbidden(e));
+                }
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+        politeness::pace(&url).await;
+
+        // the browser is released before recognizing the text
+        let screenshot = {
+            let driver = self.driver.lock().await;
+            let _meter = usage::Meter::start(pool.0, &user_id);
+
+            let driver = match self
+                .setup_driver(&driver, url.as_str(), warm.0.unwrap_or(false), &navigation)
+                .await
+            {
+                Ok(d) => d,
+                Err(e) => {
+                    error!(url=?*url, error=?e, "Failed to setup driver");
+                    return Err(ResponseObject::internal_server_error(
+                        "Failed to setup driver",
+                    ));
+                }
+            };
+            tokio::time::sleep(tokio::time::Duration::from_millis(delay.0)).await;
+
+            let screenshot = match driver.screenshot_as_png().await {
+                Ok(s) => s,
+                Err(e) => {
+                    error!(url=?*url, error=?e, "Failed to get the screenshot of the page");
+                    return Err(ResponseObject::internal_server_error(
+                        "Failed to get the screenshot of the page",
+                    ));
+                }
+            };
+
+            if let Err(e) = self
+                .release_driver(driver, url.as_str(), warm.0.unwrap_or(false))
+                .await
+            {
+                error!(url=?*url, error=?e, "Failed to cleanup driver");
+                return Err(ResponseObject::internal_server_error(
+                    "Failed to cleanup driver",
+                ));
+            }
+
+            screenshot
+        };
+
+        match ocr::recognize(&screenshot).await {
+            Ok(result) => {
+                cache::put(cache_key, &result).await;
+                Ok(ResponseObject::ok(result))
+            }
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
     }
 
     /// get list of images in the rendered page
@@ -228,15 +483,16 @@ impl Selenium {
     async fn get_images(
         &self,
         req: &Request,
+        pool: Data<&SqlitePool>,
         /// url of the page to render
         url: Query<String>,
         /// delay in milliseconds wait for the page to be fully loaded
         delay: Query<u64>,
         /// whether to try to bypass paywall
         bypass_paywall: Query<bool>,
+        /// reuse a tab kept open on the same site, its cookies and cache make repeated runs faster
+        warm: Query<Option<bool>>,
     ) -> Result<JsonSuccess<Vec<Image>>, JsonError<String>> {
-        let driver = self.driver.lock().await;
-
         match verify_apikey(req).await {
             Ok(_) => (),
             Err(e) => {
@@ -244,12 +500,43 @@ impl Selenium {
             }
         }
 
+        let navigation = match Navigation::from_request(req) {
+            Ok(navigation) => navigation,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        if let Err(e) = politeness::check(&url.0).await {
+            return Err(ResponseObject::forbidden(e));
+        }
+
         let mut url = url.0;
         if bypass_paywall.0 {
             url = format!("https://12ft.io/api/proxy?ref=&q={}", url);
         }
 
-        let driver = match self.setup_driver(&driver, url.as_str()).await {
+        let cache_key = cache::key("images", &url, delay.0, &navigation);
+        if let Some(images) = cache::get(req, &cache_key).await {
+            return Ok(ResponseObject::ok(images));
+        }
+
+        let user_id = usage::user_of(req);
+        match usage::current(pool.0, &user_id).await {
+            Ok(usage) => {
+                if let Err(e) = usage::check(&usage) {
+                    return Err(ResponseObject::forbidden(e));
+                }
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+        politeness::pace(&url).await;
+        let driver = self.driver.lock().await;
+        let _meter = usage::Meter::start(pool.0, &user_id);
+
+        let driver = match self.setup_driver(&driver, url.as_str(), warm.0.unwrap_or(false), &navigation).await {
             Ok(d) => d,
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to setup driver");
@@ -316,7 +603,7 @@ impl Selenium {
             });
         }
 
-        match self.cleanup_driver(driver, url.as_str()).await {
+        match self.release_driver(driver, url.as_str(), warm.0.unwrap_or(false)).await {
             Ok(_) => (),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to cleanup driver");
@@ -329,37 +616,80 @@ impl Selenium {
         // sort images by size
         images_vec.sort_by(|a, b| b.size.partial_cmp(&a.size).unwrap());
 
+        cache::put(cache_key, &images_vec).await;
         return Ok(ResponseObject::ok(images_vec));
     }
 
+    /// browser usage of the user in the current month
+    #[oai(path = "/usage/", method = "get", operation_id = "browser::get_usage")]
+    async fn get_usage(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<BrowserUsage>, JsonError<String>> {
+        match verify_apikey(req).await {
+            Ok(_) => (),
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        }
+
+        match usage::current(pool.0, &usage::user_of(req)).await {
+            Ok(usage) => Ok(ResponseObject::ok(usage)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
     async fn setup_driver<'a>(
         &'a self,
         driver: &'a MutexGuard<'_, WebDriver>,
         url: &str,
+        warm: bool,
+        navigation: &Navigation,
     ) -> Result<&MutexGuard<'_, WebDriver>, String> {
-        let tab = match driver.new_tab().await {
-            Ok(t) => t,
-            Err(e) => {
-                error!(url=?*url, error=?e, "Failed to create new tab");
-                thread::spawn(|| {
-                    thread::sleep(Duration::from_secs(1));
-                    info!("triggering restart to regain access to the browser");
-                    process::exit(-1);
-                });
-                return Err("Failed to create new tab".to_string());
+        let reused = warm && self.take_warm_tab(driver, url).await;
+        if !reused {
+            let tab = match driver.new_tab().await {
+                Ok(t) => t,
+                Err(e) => {
+                    error!(url=?*url, error=?e, "Failed to create new tab");
+                    thread::spawn(|| {
+                        thread::sleep(Duration::from_secs(1));
+                        info!("triggering restart to regain access to the browser");
+                        process::exit(-1);
+                    });
+                    return Err("Failed to create new tab".to_string());
+                }
+            };
+
+            match driver.switch_to_window(tab).await {
+                Ok(_) => (),
+                Err(e) => {
+                    error!(url=?*url, error=?e, "Failed to switch to new tab");
+                    self.cleanup_driver(driver, url).await?;
+                    return Err("Failed to switch to new tab".to_string());
+                }
             }
-        };
+        }
 
-        match driver.switch_to_window(tab).await {
+        match navigation.apply(driver, reused).await {
             Ok(_) => (),
             Err(e) => {
-                error!(url=?*url, error=?e, "Failed to switch to new tab");
+                error!(url=?*url, error=?e, "Failed to set request headers");
                 self.cleanup_driver(driver, url).await?;
-                return Err("Failed to switch to new tab".to_string());
+                return Err("Failed to set request headers".to_string());
             }
         }
 
-        match driver.goto(url.to_string()).await {
+        let target = match navigation.url(url) {
+            Ok(target) => target,
+            Err(e) => {
+                self.cleanup_driver(driver, url).await?;
+                return Err(e);
+            }
+        };
+
+        match driver.goto(target).await {
             Ok(_) => (),
             Err(e) => {
                 error!(url=?*url, error=?e, "Failed to navigate to URL");
@@ -377,6 +707,65 @@ impl Selenium {
         return Ok(driver);
     }
 
+    /// Switch to the warm tab of the site of the url. Tabs past their TTL or that
+    /// can't be switched to anymore are dropped, the caller opens a fresh tab then.
+    async fn take_warm_tab(&self, driver: &MutexGuard<'_, WebDriver>, url: &str) -> bool {
+        let site = match site_of(url) {
+            Some(site) => site,
+            None => return false,
+        };
+        let (handle, opened_at) = match self.warm_tabs.lock().unwrap().get(&site) {
+            Some(tab) => (tab.handle.clone(), tab.opened_at),
+            None => return false,
+        };
+
+        if driver.switch_to_window(handle).await.is_err() {
+            info!(url=?*url, "Warm tab is gone, opening a new one");
+            self.warm_tabs.lock().unwrap().remove(&site);
+            return false;
+        }
+
+        if opened_at.elapsed() < Duration::from_secs(*WARM_TAB_TTL_SECS) {
+            return true;
+        }
+
+        info!(url=?*url, "Warm tab is stale, opening a new one");
+        self.warm_tabs.lock().unwrap().remove(&site);
+        if let Err(e) = self.cleanup_driver(driver, url).await {
+            error!(url=?*url, error=?e, "Failed to close stale warm tab");
+        }
+        false
+    }
+
+    /// Finish a request, warm requests leave the tab open for the next request to the same site
+    async fn release_driver<'a>(
+        &'a self,
+        driver: &'a MutexGuard<'_, WebDriver>,
+        url: &str,
+        warm: bool,
+    ) -> Result<(), String> {
+        if warm {
+            if let (Some(site), Ok(handle)) = (site_of(url), driver.window().await) {
+                let mut warm_tabs = self.warm_tabs.lock().unwrap();
+                if warm_tabs.get(&site).is_some_and(|tab| tab.handle == handle) {
+                    return Ok(());
+                }
+                if warm_tabs.len() < MAX_WARM_TABS {
+                    warm_tabs.insert(
+                        site,
+                        WarmTab {
+                            handle,
+                            opened_at: Instant::now(),
+                        },
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        self.cleanup_driver(driver, url).await
+    }
+
     async fn cleanup_driver<'a>(
         &'a self,
         driver: &'a MutexGuard<'_, WebDriver>,
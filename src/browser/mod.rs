@@ -1,11 +1,25 @@
+use crate::quota::QuotaStatus;
 use crate::utils;
+use sqlx::SqlitePool;
 use thirtyfour::{DesiredCapabilities, WebDriver};
 use tokio::sync::Mutex;
 
+mod cache;
 mod handler;
 mod model;
+mod navigation;
+mod ocr;
+mod politeness;
+mod supervisor;
+mod usage;
 
 pub async fn selenium() -> (handler::Selenium, WebDriver) {
+    if let Some(chromedriver) = utils::CHROMEDRIVER_PATH.clone() {
+        if let Err(e) = supervisor::supervise(chromedriver).await {
+            panic!("Failed to launch chromedriver: {}", e)
+        }
+    }
+
     let mut caps = DesiredCapabilities::chrome();
     caps.set_headless().unwrap();
     caps.set_ignore_certificate_errors().unwrap();
@@ -25,3 +39,10 @@ pub async fn selenium() -> (handler::Selenium, WebDriver) {
 
     return (selenium_api, web_driver);
 }
+
+/// Usage of the user's monthly browser quotas, usage is attributed by the User-Id header
+pub async fn quotas(pool: &SqlitePool, user_id: &str) -> Result<Vec<QuotaStatus>, sqlx::Error> {
+    usage::current(pool, user_id)
+        .await
+        .map(|usage| usage::quotas(&usage))
+}
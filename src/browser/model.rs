@@ -1,8 +1,8 @@
 use poem_openapi::Object;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Image on the page
-#[derive(Debug, Object, Clone, Serialize)]
+#[derive(Debug, Object, Clone, Serialize, Deserialize)]
 pub struct Image {
     /// URL of the image
     pub url: String,
@@ -15,3 +15,46 @@ pub struct Image {
     /// Size of the image
     pub size: f64,
 }
+
+/// Browser usage of a user in the current calendar month (UTC)
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct BrowserUsage {
+    /// User the usage is attributed to (User-Id header)
+    pub user_id: String,
+    /// Month of the usage, e.g. 2024-05
+    pub month: String,
+    /// Number of browser tasks run
+    pub tasks: i64,
+    /// Seconds the browser was in use
+    pub browser_seconds: i64,
+    /// Tasks allowed per month
+    pub task_limit: Option<i64>,
+    /// Browser seconds allowed per month
+    pub seconds_limit: Option<i64>,
+}
+
+/// Word recognized on the page, positions are in screenshot pixels
+#[derive(Debug, Object, Clone, Serialize, Deserialize)]
+pub struct OcrWord {
+    /// Recognized text
+    pub text: String,
+    /// Confidence of the recognition from 0 to 100
+    pub confidence: f64,
+    /// Distance from the left edge of the screenshot
+    pub left: i64,
+    /// Distance from the top edge of the screenshot
+    pub top: i64,
+    /// Width of the word
+    pub width: i64,
+    /// Height of the word
+    pub height: i64,
+}
+
+/// Text recognized on a screenshot of the page
+#[derive(Debug, Object, Clone, Serialize, Deserialize)]
+pub struct OcrResult {
+    /// Recognized text, one line per line of text on the page
+    pub text: String,
+    /// Recognized words with their bounding boxes
+    pub words: Vec<OcrWord>,
+}
@@ -0,0 +1,84 @@
+use serde_json::json;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+};
+use thirtyfour::{extensions::cdp::ChromeDevTools, prelude::*};
+use url::Url;
+
+/// Extra request headers and basic auth credentials used while navigating to the
+/// page, taken from the Browser-Headers and Browser-Auth headers of the request
+#[derive(Default)]
+pub struct Navigation {
+    headers: BTreeMap<String, String>,
+    credentials: Option<(String, String)>,
+}
+
+impl Navigation {
+    pub fn from_request(req: &poem::Request) -> Result<Self, String> {
+        let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+
+        let headers = match header("Browser-Headers") {
+            Some(headers) => serde_json::from_str(headers)
+                .map_err(|_| "Browser-Headers must be a JSON object of strings".to_string())?,
+            None => BTreeMap::new(),
+        };
+
+        let credentials = match header("Browser-Auth") {
+            Some(auth) => match auth.split_once(':') {
+                Some((username, password)) => Some((username.to_string(), password.to_string())),
+                None => return Err("Browser-Auth must be in username:password format".to_string()),
+            },
+            None => None,
+        };
+
+        Ok(Navigation {
+            headers,
+            credentials,
+        })
+    }
+
+    /// Fingerprint of the options, so cached results are only shared between
+    /// requests sent with the same headers and credentials
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.headers.hash(&mut hasher);
+        self.credentials.hash(&mut hasher);
+        format!("{:x}", hasher.finish())
+    }
+
+    /// Url to navigate to. Credentials are embedded in the url rather than sent as a
+    /// header so they only reach the origin of the page, not the sites it loads from.
+    pub fn url(&self, url: &str) -> Result<String, String> {
+        let (username, password) = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Ok(url.to_string()),
+        };
+
+        let mut url = Url::parse(url).map_err(|e| e.to_string())?;
+        url.set_username(username)
+            .and_then(|_| url.set_password(Some(password)))
+            .map_err(|_| "url can't carry credentials".to_string())?;
+
+        Ok(url.to_string())
+    }
+
+    /// Send the extra headers with every request of the current tab. Reused tabs are
+    /// always reset as they may still carry the headers of a previous request.
+    pub async fn apply(&self, driver: &WebDriver, reused: bool) -> WebDriverResult<()> {
+        if self.headers.is_empty() && !reused {
+            return Ok(());
+        }
+
+        let dev_tools = ChromeDevTools::new(driver.handle.clone());
+        dev_tools.execute_cdp("Network.enable").await?;
+        dev_tools
+            .execute_cdp_with_params(
+                "Network.setExtraHTTPHeaders",
+                json!({ "headers": self.headers }),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
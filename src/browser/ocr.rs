@@ -0,0 +1,85 @@
+use super::model::{OcrResult, OcrWord};
+use lazy_static::lazy_static;
+use std::{env, process::Stdio};
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::error;
+
+lazy_static! {
+    // tesseract binary used to recognize text on screenshots
+    static ref TESSERACT_BINARY: String =
+        env::var("TESSERACT_BINARY").unwrap_or("tesseract".to_string());
+    // languages the text is recognized in, e.g. eng+deu (requires the matching traineddata)
+    static ref OCR_LANGUAGES: String = env::var("OCR_LANGUAGES").unwrap_or("eng".to_string());
+}
+
+/// Words of tesseract's TSV output (level 5 rows), grouped into lines of text
+fn parse_tsv(tsv: &str) -> OcrResult {
+    let mut words = Vec::new();
+    let mut lines: Vec<((i64, i64, i64), Vec<String>)> = Vec::new();
+
+    // level page block paragraph line word left top width height confidence text
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.split('\t').collect();
+        if columns.len() < 12 || columns[0] != "5" {
+            continue;
+        }
+
+        let text = columns[11].trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let number = |idx: usize| columns[idx].parse::<i64>().unwrap_or_default();
+        let line = (number(2), number(3), number(4));
+        match lines.last_mut() {
+            Some((last, line_words)) if *last == line => line_words.push(text.to_string()),
+            _ => lines.push((line, vec![text.to_string()])),
+        }
+
+        words.push(OcrWord {
+            text: text.to_string(),
+            confidence: columns[10].parse().unwrap_or_default(),
+            left: number(6),
+            top: number(7),
+            width: number(8),
+            height: number(9),
+        });
+    }
+
+    OcrResult {
+        text: lines
+            .into_iter()
+            .map(|(_, line_words)| line_words.join(" "))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        words,
+    }
+}
+
+/// Recognize the text of a PNG image
+pub async fn recognize(png: &[u8]) -> Result<OcrResult, String> {
+    let mut child = Command::new(TESSERACT_BINARY.as_str())
+        .args(["stdin", "stdout", "-l", OCR_LANGUAGES.as_str(), "tsv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            error!(error = ?e, "Failed to start tesseract");
+            "OCR is not available".to_string()
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(png).await.map_err(|e| e.to_string())?;
+    }
+
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!(status = %output.status, stderr = %stderr, "tesseract failed");
+        return Err("Failed to recognize the text of the screenshot".to_string());
+    }
+
+    Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+}
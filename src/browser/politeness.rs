@@ -0,0 +1,202 @@
+use crate::http;
+use crate::kv;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+use tracing::{info, warn};
+use url::Url;
+
+lazy_static! {
+    // hosts the browser never visits, `.example.com` also blocks subdomains
+    static ref BLOCKLIST: Vec<String> = env::var("BROWSER_DOMAIN_BLOCKLIST")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect();
+    // skip pages disallowed for every user agent by the robots.txt of the site
+    static ref RESPECT_ROBOTS: bool = env::var("BROWSER_RESPECT_ROBOTS")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    // least time between two page loads from the same host (0 disables pacing)
+    static ref MIN_INTERVAL_MS: u64 = env::var("BROWSER_DOMAIN_MIN_INTERVAL_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    static ref ROBOTS: Mutex<HashMap<String, (Instant, Vec<Rule>)>> = Mutex::new(HashMap::new());
+    static ref NEXT_VISIT: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+// how long a fetched robots.txt is trusted
+const ROBOTS_TTL: Duration = Duration::from_secs(3600);
+
+struct Rule {
+    allow: bool,
+    // path pattern, `*` matches anything and a trailing `$` anchors the end
+    pattern: String,
+    matcher: Regex,
+}
+
+fn host_matches(host: &str, blocked: &str) -> bool {
+    match blocked.strip_prefix('.') {
+        Some(domain) => host == domain || host.ends_with(blocked),
+        None => host == blocked,
+    }
+}
+
+/// Rules of the `*` user agent groups of a robots.txt
+fn parse_robots(body: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    let mut in_group = false;
+    let mut applies = false;
+
+    for line in body.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+
+        match field.as_str() {
+            "user-agent" => {
+                // consecutive user agent lines share the rules that follow them
+                if in_group {
+                    applies = false;
+                    in_group = false;
+                }
+                applies |= value == "*";
+            }
+            "allow" | "disallow" if applies => {
+                in_group = true;
+                if value.is_empty() {
+                    continue;
+                }
+
+                let anchored = value.ends_with('$');
+                let escaped = regex::escape(value.trim_end_matches('$')).replace(r"\*", ".*");
+                let pattern = format!("^{}{}", escaped, if anchored { "$" } else { "" });
+                if let Ok(matcher) = Regex::new(&pattern) {
+                    rules.push(Rule {
+                        allow: field == "allow",
+                        pattern: value.to_string(),
+                        matcher,
+                    });
+                }
+            }
+            "allow" | "disallow" => in_group = true,
+            _ => {}
+        }
+    }
+
+    rules
+}
+
+/// The most specific matching rule wins, allow wins ties
+fn is_allowed(rules: &[Rule], path: &str) -> bool {
+    rules
+        .iter()
+        .filter(|rule| rule.matcher.is_match(path))
+        .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+        .is_none_or(|rule| rule.allow)
+}
+
+async fn fetch_robots(origin: &str) -> Vec<Rule> {
+    let response = http::CLIENT
+        .get(format!("{}/robots.txt", origin))
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+
+    // a missing or unreachable robots.txt doesn't restrict anything
+    match response {
+        Ok(response) if response.status().is_success() => {
+            parse_robots(&response.text().await.unwrap_or_default())
+        }
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            warn!(origin = %origin, error = ?e, "Failed to fetch robots.txt");
+            Vec::new()
+        }
+    }
+}
+
+/// Check the url against the domain blocklist and, when enabled, the robots.txt of the site
+pub async fn check(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid url: {}", e))?;
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+
+    if BLOCKLIST.iter().any(|blocked| host_matches(&host, blocked)) {
+        return Err(format!("{} is blocked", host));
+    }
+
+    if !*RESPECT_ROBOTS {
+        return Ok(());
+    }
+
+    let origin = url.origin().ascii_serialization();
+    let cached = {
+        let robots = ROBOTS.lock().unwrap();
+        robots
+            .get(&origin)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < ROBOTS_TTL)
+            .map(|(_, rules)| is_allowed(rules, url.path()))
+    };
+
+    let allowed = match cached {
+        Some(allowed) => allowed,
+        None => {
+            let rules = fetch_robots(&origin).await;
+            let allowed = is_allowed(&rules, url.path());
+            ROBOTS
+                .lock()
+                .unwrap()
+                .insert(origin, (Instant::now(), rules));
+            allowed
+        }
+    };
+
+    if !allowed {
+        return Err(format!("{} is disallowed by robots.txt", url.path()));
+    }
+
+    Ok(())
+}
+
+/// Wait until the host of the url may be visited again
+pub async fn pace(url: &str) {
+    if *MIN_INTERVAL_MS == 0 {
+        return;
+    }
+
+    let host = match Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+    {
+        Some(host) => host,
+        None => return,
+    };
+
+    // reserve the next slot before waiting so concurrent requests queue up, the
+    // slots are shared with the other instances when redis is configured
+    let interval = Duration::from_millis(*MIN_INTERVAL_MS);
+    let wait = match kv::reserve(&format!("browser-pace:{}", host), interval).await {
+        Some(wait) => wait,
+        None => {
+            let mut next_visit = NEXT_VISIT.lock().unwrap();
+            let now = Instant::now();
+            let visit_at = next_visit.get(&host).copied().unwrap_or(now).max(now);
+            next_visit.insert(host.clone(), visit_at + interval);
+            visit_at.saturating_duration_since(now)
+        }
+    };
+    if !wait.is_zero() {
+        info!(host = %host, wait_ms = wait.as_millis() as u64, "Pacing visit");
+        sleep(wait).await;
+    }
+}
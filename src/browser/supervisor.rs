@@ -0,0 +1,132 @@
+use crate::{http, utils::CHROME_DRIVER_ENDPOINT};
+use lazy_static::lazy_static;
+use std::{env, process::Stdio, time::Duration};
+use tokio::{
+    process::{Child, Command},
+    time::sleep,
+};
+use tracing::{error, info, warn};
+use url::Url;
+
+lazy_static! {
+    // chrome binary the chromedriver version is checked against
+    static ref CHROME_BINARY: String =
+        env::var("CHROME_BINARY").unwrap_or("google-chrome".to_string());
+}
+
+// attempts to reach a freshly launched chromedriver before giving up
+const READY_ATTEMPTS: u32 = 20;
+// delay before relaunching a crashed chromedriver, doubled up to a minute
+const RESTART_BACKOFF_SECS: u64 = 1;
+
+/// Major version from `--version` output, e.g. `ChromeDriver 120.0.6099.109 (...)`
+fn major_version(output: &str) -> Option<u32> {
+    output
+        .split_whitespace()
+        .find(|part| part.contains('.'))
+        .and_then(|version| version.split('.').next())
+        .and_then(|major| major.parse().ok())
+}
+
+async fn version_of(binary: &str) -> Option<u32> {
+    let output = Command::new(binary).arg("--version").output().await;
+
+    match output {
+        Ok(output) => major_version(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            warn!(binary = %binary, error = ?e, "Failed to read version");
+            None
+        }
+    }
+}
+
+/// Chrome refuses sessions from a chromedriver built for another major version
+async fn check_versions(chromedriver: &str) {
+    let (driver_version, chrome_version) = (
+        version_of(chromedriver).await,
+        version_of(&CHROME_BINARY).await,
+    );
+
+    match (driver_version, chrome_version) {
+        (Some(driver), Some(chrome)) if driver != chrome => error!(
+            chromedriver = driver,
+            chrome, "chromedriver doesn't match the installed Chrome, sessions will fail"
+        ),
+        (Some(driver), Some(_)) => info!(version = driver, "chromedriver matches Chrome"),
+        _ => warn!("Couldn't compare the chromedriver and Chrome versions"),
+    }
+}
+
+fn spawn(chromedriver: &str, port: u16) -> std::io::Result<Child> {
+    Command::new(chromedriver)
+        .arg(format!("--port={}", port))
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+}
+
+async fn wait_until_ready() -> Result<(), String> {
+    let status_url = format!("{}/status", CHROME_DRIVER_ENDPOINT.trim_end_matches('/'));
+
+    for _ in 0..READY_ATTEMPTS {
+        let response = http::CLIENT
+            .get(&status_url)
+            .timeout(Duration::from_secs(1))
+            .send()
+            .await;
+        if response.is_ok_and(|r| r.status().is_success()) {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    Err(format!("chromedriver didn't respond on {}", status_url))
+}
+
+/// Launch chromedriver on the port of CHROME_DRIVER_ENDPOINT and relaunch it whenever
+/// it exits. Returns once the driver accepts connections.
+pub async fn supervise(chromedriver: String) -> Result<(), String> {
+    let port = Url::parse(&CHROME_DRIVER_ENDPOINT)
+        .ok()
+        .and_then(|url| url.port_or_known_default())
+        .ok_or("CHROME_DRIVER_ENDPOINT has no port".to_string())?;
+
+    check_versions(&chromedriver).await;
+
+    let mut child = spawn(&chromedriver, port).map_err(|e| e.to_string())?;
+    info!(path = %chromedriver, port, "Launched chromedriver");
+    wait_until_ready().await?;
+
+    tokio::spawn(async move {
+        let mut backoff = RESTART_BACKOFF_SECS;
+        loop {
+            match child.wait().await {
+                Ok(status) => error!(status = %status, "chromedriver exited"),
+                Err(e) => error!(error = ?e, "Failed to wait for chromedriver"),
+            }
+
+            loop {
+                sleep(Duration::from_secs(backoff)).await;
+                backoff = (backoff * 2).min(60);
+
+                match spawn(&chromedriver, port) {
+                    Ok(restarted) => {
+                        child = restarted;
+                        break;
+                    }
+                    Err(e) => error!(error = ?e, "Failed to relaunch chromedriver"),
+                }
+            }
+
+            match wait_until_ready().await {
+                Ok(_) => {
+                    info!("Relaunched chromedriver");
+                    backoff = RESTART_BACKOFF_SECS;
+                }
+                Err(e) => warn!(error = %e, "Relaunched chromedriver isn't ready"),
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use super::model::BrowserUsage;
+use crate::quota::{self, QuotaStatus};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use poem::Request;
+use sqlx::SqlitePool;
+use std::{env, time::Instant};
+use tracing::error;
+
+lazy_static! {
+    // browser tasks a user may run per calendar month (0 = unlimited)
+    static ref MONTHLY_TASKS: i64 = env::var("BROWSER_MONTHLY_TASKS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    // seconds of browser time a user may use per calendar month (0 = unlimited)
+    static ref MONTHLY_SECONDS: i64 = env::var("BROWSER_MONTHLY_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+}
+
+// usage of requests without a User-Id header
+const DEFAULT_USER: &str = "default";
+
+/// User the request is attributed to
+pub fn user_of(req: &Request) -> String {
+    req.headers()
+        .get("User-Id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .unwrap_or(DEFAULT_USER)
+        .to_string()
+}
+
+fn current_month() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
+
+fn limit(value: i64) -> Option<i64> {
+    (value > 0).then_some(value)
+}
+
+/// Usage of the user in the current month
+pub async fn current(pool: &SqlitePool, user_id: &str) -> Result<BrowserUsage, sqlx::Error> {
+    let month = current_month();
+
+    let usage = sqlx::query!(
+        "SELECT tasks, browser_ms FROM browser_usage WHERE user_id = ? AND month = ?",
+        user_id,
+        month
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let (tasks, browser_ms) = usage
+        .map(|usage| (usage.tasks, usage.browser_ms))
+        .unwrap_or_default();
+
+    Ok(BrowserUsage {
+        user_id: user_id.to_string(),
+        month,
+        tasks,
+        browser_seconds: browser_ms / 1000,
+        task_limit: limit(*MONTHLY_TASKS),
+        seconds_limit: limit(*MONTHLY_SECONDS),
+    })
+}
+
+/// Check whether the user has quota left for another task
+pub fn check(usage: &BrowserUsage) -> Result<(), String> {
+    if usage.task_limit.is_some_and(|limit| usage.tasks >= limit) {
+        return Err(format!(
+            "monthly browser task quota of {} reached",
+            *MONTHLY_TASKS
+        ));
+    }
+
+    if usage
+        .seconds_limit
+        .is_some_and(|limit| usage.browser_seconds >= limit)
+    {
+        return Err(format!(
+            "monthly browser time quota of {} seconds reached",
+            *MONTHLY_SECONDS
+        ));
+    }
+
+    Ok(())
+}
+
+/// Usage of the monthly browser quotas
+pub fn quotas(usage: &BrowserUsage) -> Vec<QuotaStatus> {
+    vec![
+        QuotaStatus::new(
+            "browser_tasks",
+            usage.tasks,
+            usage.task_limit,
+            Some(usage.month.clone()),
+        ),
+        QuotaStatus::new(
+            "browser_seconds",
+            usage.browser_seconds,
+            usage.seconds_limit,
+            Some(usage.month.clone()),
+        ),
+    ]
+}
+
+/// Records a task and the time it held the browser when dropped, so every exit
+/// path of a handler is metered
+pub struct Meter {
+    pool: SqlitePool,
+    user_id: String,
+    started: Instant,
+}
+
+impl Meter {
+    pub fn start(pool: &SqlitePool, user_id: &str) -> Self {
+        Meter {
+            pool: pool.clone(),
+            user_id: user_id.to_string(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Meter {
+    fn drop(&mut self) {
+        let pool = self.pool.clone();
+        let user_id = std::mem::take(&mut self.user_id);
+        let browser_ms = self.started.elapsed().as_millis() as i64;
+
+        tokio::spawn(async move {
+            let month = current_month();
+            let current_time = Utc::now().naive_utc();
+
+            let result = sqlx::query!(
+                "INSERT INTO browser_usage (user_id, month, tasks, browser_ms, updated_at)
+                VALUES (?, ?, 1, ?, ?)
+                ON CONFLICT (user_id, month) DO UPDATE SET
+                    tasks = tasks + 1,
+                    browser_ms = browser_ms + excluded.browser_ms,
+                    updated_at = excluded.updated_at",
+                user_id,
+                month,
+                browser_ms,
+                current_time
+            )
+            .execute(&pool)
+            .await;
+
+            if let Err(e) = result {
+                error!(user_id = %user_id, error = ?e, "Failed to record browser usage");
+                return;
+            }
+
+            match current(&pool, &user_id).await {
+                Ok(usage) => {
+                    for quota in quotas(&usage) {
+                        quota::warn_once(&pool, &user_id, &quota).await;
+                    }
+                }
+                Err(e) => error!(user_id = %user_id, error = ?e, "Failed to check browser quotas"),
+            }
+        });
+    }
+}
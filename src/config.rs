@@ -0,0 +1,76 @@
+use lazy_static::lazy_static;
+use std::{
+    env, fs,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use tracing::{error, info};
+
+type Reload =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<String, String>> + Send>> + Send + Sync>;
+
+lazy_static! {
+    static ref RELOADERS: Mutex<Vec<(String, Reload)>> = Mutex::new(Vec::new());
+}
+
+/// Register a piece of configuration that can be reloaded without a restart. The
+/// closure returns a short summary of the loaded configuration.
+pub fn on_reload<F, Fut>(name: &str, reload: F)
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, String>> + Send + 'static,
+{
+    let reload: Reload = Arc::new(move || Box::pin(reload()));
+    RELOADERS.lock().unwrap().push((name.to_string(), reload));
+}
+
+/// Apply the .env file again, its values override the current environment, then
+/// reload every registered piece of configuration. Settings read only at startup
+/// (database, listeners, worker concurrency) still need a restart.
+pub async fn reload() -> Vec<(String, Result<String, String>)> {
+    // dotenv never overrides variables, so the ones from the file are cleared first
+    match fs::read_to_string(".env") {
+        Ok(contents) => {
+            contents
+                .lines()
+                .filter_map(|line| line.trim().trim_start_matches("export ").split_once('='))
+                .map(|(key, _)| key.trim())
+                .filter(|key| !key.is_empty() && !key.starts_with('#'))
+                .for_each(|key| env::remove_var(key));
+            if let Err(e) = dotenv::dotenv() {
+                error!(error = ?e, "Error reading .env");
+            }
+        }
+        Err(e) => info!(error = ?e, "No .env file to reload"),
+    }
+
+    let reloaders = RELOADERS.lock().unwrap().clone();
+    let mut results = Vec::new();
+    for (name, reload) in reloaders {
+        let result = reload().await;
+        match &result {
+            Ok(summary) => info!(name = %name, summary = %summary, "Reloaded configuration"),
+            Err(e) => error!(name = %name, error = %e, "Error reloading configuration"),
+        }
+        results.push((name, result));
+    }
+
+    results
+}
+
+/// Reload the configuration every time the process receives SIGHUP
+pub async fn reload_on_sighup() {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!(error = ?e, "Error listening for SIGHUP");
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading configuration");
+        reload().await;
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::Value;
+use std::{env, sync::OnceLock};
+use tokio::sync::mpsc::{self, error::TrySendError, Sender};
+use tracing::warn;
+
+lazy_static! {
+    // NATS server domain events are published to, events are dropped when unset
+    static ref NATS_URL: Option<String> = env::var("EVENTS_NATS_URL").ok();
+    // events are published on `<prefix>.<type>`, e.g. toolkit.events.schedule.created
+    static ref SUBJECT_PREFIX: String =
+        env::var("EVENTS_SUBJECT_PREFIX").unwrap_or("toolkit.events".to_string());
+}
+
+// version of the event envelope, bumped on breaking changes to it or to any event data
+const SCHEMA_VERSION: u32 = 1;
+// events waiting to be published, new events are dropped while the sink is unreachable
+const BUFFER_SIZE: usize = 1000;
+
+static SENDER: OnceLock<Sender<Event>> = OnceLock::new();
+
+/// Envelope of every published event
+#[derive(Debug, Serialize)]
+pub struct Event {
+    /// what happened, e.g. `schedule.created`, also the last part of the subject
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub version: u32,
+    pub occurred_at: NaiveDateTime,
+    pub data: Value,
+}
+
+/// Start publishing events when a sink is configured
+pub fn start() {
+    let url = match NATS_URL.as_ref() {
+        Some(url) => url.clone(),
+        None => return,
+    };
+
+    let (sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    if SENDER.set(sender).is_ok() {
+        tokio::spawn(sink::run(url, SUBJECT_PREFIX.clone(), receiver));
+    }
+}
+
+/// Publish a domain event without waiting for the sink
+pub fn publish(kind: &'static str, data: impl Serialize) {
+    let sender = match SENDER.get() {
+        Some(sender) => sender,
+        None => return,
+    };
+
+    let data = match serde_json::to_value(data) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!(kind, error = ?e, "Failed to serialize event");
+            return;
+        }
+    };
+
+    let event = Event {
+        kind,
+        version: SCHEMA_VERSION,
+        occurred_at: Utc::now().naive_utc(),
+        data,
+    };
+
+    match sender.try_send(event) {
+        Ok(_) => {}
+        Err(TrySendError::Full(event)) => {
+            warn!(kind = event.kind, "Event buffer is full, dropping event")
+        }
+        Err(TrySendError::Closed(_)) => {}
+    }
+}
+
+#[cfg(feature = "nats")]
+mod sink {
+    use super::Event;
+    use tokio::sync::mpsc::Receiver;
+    use tracing::{error, info, warn};
+
+    pub async fn run(url: String, prefix: String, mut events: Receiver<Event>) {
+        // the client reconnects on its own once the first connection is made
+        let client = loop {
+            match async_nats::connect(url.as_str()).await {
+                Ok(client) => break client,
+                Err(e) => {
+                    error!(error = ?e, "Failed to connect to NATS, retrying");
+                    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                }
+            }
+        };
+        info!(prefix = %prefix, "Publishing events to NATS");
+
+        while let Some(event) = events.recv().await {
+            let payload = match serde_json::to_vec(&event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!(kind = event.kind, error = ?e, "Failed to serialize event");
+                    continue;
+                }
+            };
+
+            let subject = format!("{}.{}", prefix, event.kind);
+            if let Err(e) = client.publish(subject, payload.into()).await {
+                warn!(kind = event.kind, error = ?e, "Failed to publish event");
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "nats"))]
+mod sink {
+    use super::Event;
+    use tokio::sync::mpsc::Receiver;
+    use tracing::warn;
+
+    pub async fn run(_url: String, _prefix: String, _events: Receiver<Event>) {
+        warn!("EVENTS_NATS_URL is set but the server is built without the nats feature");
+    }
+}
@@ -0,0 +1,44 @@
+use super::verifier;
+use super::worker::read_in_serivce_accounts;
+use gcp_auth::{AuthenticationManager, Error};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Service accounts of the firebase projects the server sends for. Clones share the
+/// same accounts, so a reload is seen by the api handlers and the delivery lanes.
+#[derive(Clone, Default)]
+pub struct ServiceAccounts(Arc<RwLock<HashMap<String, Arc<AuthenticationManager>>>>);
+
+impl ServiceAccounts {
+    pub async fn load() -> Result<Self, Error> {
+        let accounts = Self::default();
+        accounts.reload().await?;
+        Ok(accounts)
+    }
+
+    /// Read the service accounts directory again. The current accounts are kept
+    /// when any of the files can't be loaded.
+    pub async fn reload(&self) -> Result<Vec<String>, Error> {
+        let accounts = read_in_serivce_accounts().await?;
+        let mut projects: Vec<String> = accounts.keys().cloned().collect();
+        projects.sort();
+        verifier::set_projects(&projects);
+
+        *self.0.write().unwrap() = accounts
+            .into_iter()
+            .map(|(project_id, manager)| (project_id, Arc::new(manager)))
+            .collect();
+
+        Ok(projects)
+    }
+
+    pub fn contains(&self, project_id: &str) -> bool {
+        self.0.read().unwrap().contains_key(project_id)
+    }
+
+    pub fn get(&self, project_id: &str) -> Option<Arc<AuthenticationManager>> {
+        self.0.read().unwrap().get(project_id).cloned()
+    }
+}
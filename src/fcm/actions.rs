@@ -0,0 +1,326 @@
+use super::followups;
+use super::model::FCMSchedule;
+use crate::utils::{ApiTags, JsonError, ResponseObject};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use lazy_static::lazy_static;
+use poem::web::Data;
+use poem_openapi::{
+    param::Path,
+    payload::{Html, PlainText},
+    ApiResponse, OpenApi,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::env;
+use tracing::info;
+
+lazy_static! {
+    // key action links are signed with, links can't be rendered without it
+    static ref LINK_SECRET: Option<String> = env::var("FCM_ACTION_LINK_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty());
+    // public URL of the API the links point to, e.g. https://toolkit.example.com/api/v1
+    static ref LINK_BASE_URL: Option<String> = env::var("FCM_ACTION_LINK_BASE_URL")
+        .ok()
+        .map(|url| url.trim_end_matches('/').to_string());
+    // how long a link keeps working after the notification is sent
+    static ref LINK_TTL_SECS: i64 = env::var("FCM_ACTION_LINK_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(24 * 60 * 60);
+}
+
+// minutes a snooze link waits when the template doesn't say
+pub const DEFAULT_SNOOZE_MINUTES: i64 = 10;
+
+// Action links let a notification be acknowledged or snoozed with a single tap,
+// the link itself carries a signed, short-lived token so the app doesn't need
+// to make an authenticated call. Opening a link only asks to confirm the action,
+// link previews and prefetching must not snooze a notification.
+
+/// What tapping a link does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkAction {
+    /// mark the delivery of the notification acknowledged
+    Ack,
+    /// acknowledge the delivery and send the notification again later
+    Snooze,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LinkClaims {
+    /// schedule the notification was sent by
+    sid: i64,
+    /// execution of the schedule the notification was sent by
+    eid: String,
+    act: LinkAction,
+    /// minutes a snooze waits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<i64>,
+    iat: i64,
+    exp: i64,
+}
+
+/// Signed link performing the action on the execution of the schedule when opened
+pub fn link(
+    schedule_id: i64,
+    execution_key: &str,
+    action: LinkAction,
+    minutes: Option<i64>,
+) -> Result<String, String> {
+    let (secret, base_url) = match (LINK_SECRET.as_ref(), LINK_BASE_URL.as_ref()) {
+        (Some(secret), Some(base_url)) => (secret, base_url),
+        _ => {
+            return Err(
+                "Action links need FCM_ACTION_LINK_SECRET and FCM_ACTION_LINK_BASE_URL".to_string(),
+            )
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let claims = LinkClaims {
+        sid: schedule_id,
+        eid: execution_key.to_string(),
+        act: action,
+        min: minutes,
+        iat: now,
+        exp: now + *LINK_TTL_SECS,
+    };
+
+    sign(secret, &claims).map(|token| format!("{}/a/{}", base_url, token))
+}
+
+fn sign(secret: &str, claims: &LinkClaims) -> Result<String, String> {
+    encode(
+        &Header::new(Algorithm::HS256),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| format!("Error signing action link: {}", e))
+}
+
+fn verify(token: &str) -> Option<LinkClaims> {
+    verify_with(LINK_SECRET.as_ref()?, token)
+}
+
+fn verify_with(secret: &str, token: &str) -> Option<LinkClaims> {
+    decode::<LinkClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    )
+    .ok()
+    .map(|data| data.claims)
+    // links rendered without a key can't be matched to their execution
+    .filter(|claims| !claims.eid.is_empty())
+}
+
+#[derive(ApiResponse)]
+enum ConfirmResponse {
+    /// Page confirming the action
+    #[oai(status = 200)]
+    Ok(Html<String>),
+    /// Link is invalid or expired
+    #[oai(status = 400)]
+    Invalid(PlainText<String>),
+}
+
+#[derive(ApiResponse)]
+enum ActionResponse {
+    /// Action performed
+    #[oai(status = 200)]
+    Ok(PlainText<String>),
+    /// Link is invalid or expired
+    #[oai(status = 400)]
+    Invalid(PlainText<String>),
+    /// Schedule of the link no longer exists
+    #[oai(status = 404)]
+    NotFound(PlainText<String>),
+}
+
+#[derive(Default)]
+pub struct FirebaseActions;
+
+#[OpenApi(prefix_path = "/a/", tag = "ApiTags::FirebaseMessaging")]
+impl FirebaseActions {
+    // Page asking to confirm the action of a link embedded in a notification
+    #[oai(
+        path = "/:token",
+        method = "get",
+        operation_id = "fcm::confirm_action_link"
+    )]
+    async fn confirm_action_link(&self, token: Path<String>) -> ConfirmResponse {
+        let claims = match verify(&token.0) {
+            Some(claims) => claims,
+            None => {
+                return ConfirmResponse::Invalid(PlainText(
+                    "This link is invalid or has expired".to_string(),
+                ));
+            }
+        };
+
+        let action = match claims.act {
+            LinkAction::Ack => "Acknowledge".to_string(),
+            LinkAction::Snooze => format!(
+                "Snooze for {} minutes",
+                claims.min.unwrap_or(DEFAULT_SNOOZE_MINUTES)
+            ),
+        };
+
+        // the form posts back to the link itself
+        ConfirmResponse::Ok(Html(format!(
+            "<!DOCTYPE html><html><head><meta name=\"viewport\" content=\"width=device-width\"><title>{0}</title></head>\
+            <body><form method=\"post\"><button type=\"submit\">{0}</button></form></body></html>",
+            action
+        )))
+    }
+
+    // Perform the action of a link embedded in a notification, the signed token is the only credential
+    #[oai(path = "/:token", method = "post", operation_id = "fcm::action_link")]
+    async fn action_link(
+        &self,
+        pool: Data<&SqlitePool>,
+        token: Path<String>,
+    ) -> Result<ActionResponse, JsonError<String>> {
+        let claims = match verify(&token.0) {
+            Some(claims) => claims,
+            None => {
+                return Ok(ActionResponse::Invalid(PlainText(
+                    "This link is invalid or has expired".to_string(),
+                )));
+            }
+        };
+
+        let schedule = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = ?",
+            claims.sid
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let schedule = match schedule {
+            Ok(Some(schedule)) => schedule,
+            Ok(None) => {
+                return Ok(ActionResponse::NotFound(PlainText(
+                    "This notification no longer exists".to_string(),
+                )));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // an execution is handled once, tapping a link again (or another link of the
+        // same notification, or the notification on another device) doesn't snooze it twice
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "UPDATE fcm_execution_log SET acknowledged_at = ?1
+            WHERE schedule_id = ?2 AND execution_key = ?3 AND status = 'success'
+            AND NOT EXISTS (
+                SELECT 1 FROM fcm_execution_log
+                WHERE schedule_id = ?2 AND execution_key = ?3 AND acknowledged_at IS NOT NULL
+            )",
+            current_time,
+            schedule.id,
+            claims.eid
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Ok(ActionResponse::Ok(PlainText(
+                    "Already acknowledged".to_string(),
+                )));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        match claims.act {
+            LinkAction::Ack => {
+                info!(schedule_id = schedule.id, "Notification acknowledged");
+                Ok(ActionResponse::Ok(PlainText("Acknowledged".to_string())))
+            }
+            LinkAction::Snooze => {
+                let minutes = claims.min.unwrap_or(DEFAULT_SNOOZE_MINUTES);
+                let next_execution = match followups::delay_until(minutes) {
+                    Some(next_execution) => next_execution,
+                    None => {
+                        return Ok(ActionResponse::Invalid(PlainText(
+                            "This link is invalid or has expired".to_string(),
+                        )));
+                    }
+                };
+                match followups::send_again(pool.0, &schedule, next_execution).await {
+                    Ok(id) => {
+                        info!(
+                            schedule_id = schedule.id,
+                            reminder_id = id,
+                            minutes,
+                            "Notification snoozed"
+                        );
+                        Ok(ActionResponse::Ok(PlainText(format!(
+                            "Snoozed for {} minutes",
+                            minutes
+                        ))))
+                    }
+                    Err(e) => Err(ResponseObject::internal_server_error(e)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn claims(eid: &str, exp: i64) -> LinkClaims {
+        let now = Utc::now().timestamp();
+        LinkClaims {
+            sid: 1,
+            eid: eid.to_string(),
+            act: LinkAction::Snooze,
+            min: Some(30),
+            iat: now,
+            exp: now + exp,
+        }
+    }
+
+    #[test]
+    fn verifies_signed_links() {
+        let token = sign(SECRET, &claims("key", 60)).unwrap();
+        let verified = verify_with(SECRET, &token).unwrap();
+        assert_eq!(verified.sid, 1);
+        assert_eq!(verified.eid, "key");
+        assert_eq!(verified.act, LinkAction::Snooze);
+        assert_eq!(verified.min, Some(30));
+    }
+
+    #[test]
+    fn rejects_links_signed_with_another_secret() {
+        let token = sign("another-secret", &claims("key", 60)).unwrap();
+        assert!(verify_with(SECRET, &token).is_none());
+    }
+
+    #[test]
+    fn rejects_expired_links() {
+        // past the leeway jsonwebtoken allows
+        let token = sign(SECRET, &claims("key", -120)).unwrap();
+        assert!(verify_with(SECRET, &token).is_none());
+    }
+
+    #[test]
+    fn rejects_links_without_execution() {
+        let token = sign(SECRET, &claims("", 60)).unwrap();
+        assert!(verify_with(SECRET, &token).is_none());
+    }
+}
@@ -0,0 +1,531 @@
+use super::impersonation;
+use super::model::{
+    BlockSchedule, DispatcherStats, FCMSchedule, Impersonation, ImpersonationRequest,
+    ImpersonationToken, ScheduleEvent, ScheduleStatus, StartImpersonation,
+};
+use super::utils::resume_execution;
+use crate::events;
+use crate::outbox;
+use crate::utils::{verify_apikey, ApiTags, JsonError, JsonSuccess, PageMeta, ResponseObject};
+use chrono::{Duration, Utc};
+use poem::{web::Data, Request};
+use poem_openapi::{
+    param::{Path, Query},
+    payload::Json,
+    OpenApi,
+};
+use serde_json::json;
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+#[derive(Default)]
+pub struct FirebaseAdmin;
+
+#[OpenApi(
+    prefix_path = "/admin/fcm/",
+    request_header(name = "API-Key", ty = "String", description = "Private API Key"),
+    tag = "ApiTags::Admin"
+)]
+impl FirebaseAdmin {
+    /// list the schedules of every user, optionally of a single project or user
+    #[oai(
+        path = "/schedules",
+        method = "get",
+        operation_id = "admin::fcm::list_schedules"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    async fn list_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// only return schedules of the firebase project
+        fb_project_id: Query<Option<String>>,
+        /// only return schedules of the firebase user
+        fb_user_id: Query<Option<String>>,
+        /// only return schedules in the state
+        status: Query<Option<ScheduleStatus>>,
+        /// number of schedules per page
+        #[oai(
+            default = "default_admin_page_limit",
+            validator(minimum(value = "1"), maximum(value = "200"))
+        )]
+        limit: Query<i64>,
+        /// number of schedules to skip
+        #[oai(default, validator(minimum(value = "0")))]
+        offset: Query<i64>,
+    ) -> Result<JsonSuccess<Vec<FCMSchedule>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let status = status.0.map(|status| status.as_str());
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule
+            WHERE (?1 IS NULL OR fb_project_id = ?1)
+            AND (?2 IS NULL OR fb_user_id = ?2)
+            AND (?3 IS NULL OR (?3 = 'active' AND disabled_reason IS NULL)
+                OR (?3 = 'disabled' AND disabled_reason IS NOT NULL)
+                OR (?3 = 'failed' AND disabled_reason IN ('failed', 'invalid_token', 'invalid_cron'))
+                OR (?3 = 'blocked' AND disabled_reason = 'blocked'))"#,
+            fb_project_id.0,
+            fb_user_id.0,
+            status
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let total = match total {
+            Ok(total) => total,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let schedules = sqlx::query_as!(
+            FCMSchedule,
+            r#"SELECT * FROM fcm_schedule
+            WHERE (?1 IS NULL OR fb_project_id = ?1)
+            AND (?2 IS NULL OR fb_user_id = ?2)
+            AND (?3 IS NULL OR (?3 = 'active' AND disabled_reason IS NULL)
+                OR (?3 = 'disabled' AND disabled_reason IS NOT NULL)
+                OR (?3 = 'failed' AND disabled_reason IN ('failed', 'invalid_token', 'invalid_cron'))
+                OR (?3 = 'blocked' AND disabled_reason = 'blocked'))
+            ORDER BY id
+            LIMIT ?4 OFFSET ?5"#,
+            fb_project_id.0,
+            fb_user_id.0,
+            status,
+            limit.0,
+            offset.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match schedules {
+            Ok(schedules) => Ok(ResponseObject::ok_with_meta(
+                schedules,
+                PageMeta {
+                    total,
+                    limit: Some(limit.0),
+                    offset: offset.0,
+                },
+            )),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// stop an abusive schedule, its owner can't enable it again by updating it
+    #[oai(
+        path = "/schedules/:id/block",
+        method = "post",
+        operation_id = "admin::fcm::block_schedule"
+    )]
+    async fn block_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        body: Json<BlockSchedule>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET disabled_reason = 'blocked', version = version + 1, updated_at = ? WHERE id = ?",
+            current_time,
+            id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(ResponseObject::not_found("Schedule not found"));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        warn!(schedule_id = id.0, reason = ?body.reason, "Schedule blocked by an operator");
+
+        updated_schedule(pool.0, id.0).await
+    }
+
+    /// let a blocked schedule send again, occurrences missed while it was blocked are skipped
+    #[oai(
+        path = "/schedules/:id/unblock",
+        method = "post",
+        operation_id = "admin::fcm::unblock_schedule"
+    )]
+    async fn unblock_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let schedule = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = ? AND disabled_reason = 'blocked'",
+            id.0
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let schedule = match schedule {
+            Ok(Some(schedule)) => schedule,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Blocked schedule not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let next_execution = match resume_execution(&schedule) {
+            Ok(next) => next,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+        let current_time = Utc::now().naive_utc();
+
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET disabled_reason = NULL, next_execution = ?, version = version + 1, updated_at = ? WHERE id = ? AND disabled_reason = 'blocked'",
+            next_execution,
+            current_time,
+            id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(ResponseObject::not_found("Blocked schedule not found"));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        info!(schedule_id = id.0, "Schedule unblocked by an operator");
+
+        updated_schedule(pool.0, id.0).await
+    }
+
+    /// current load of the scheduler and the delivery lanes
+    #[oai(
+        path = "/stats",
+        method = "get",
+        operation_id = "admin::fcm::get_stats"
+    )]
+    async fn get_stats(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<DispatcherStats>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let schedules = sqlx::query!(
+            r#"SELECT
+                COUNT(*) as "total!: i64",
+                COALESCE(SUM(disabled_reason IS NULL), 0) as "active!: i64",
+                COALESCE(SUM(disabled_reason = 'blocked'), 0) as "blocked!: i64",
+                COALESCE(SUM(disabled_reason IS NULL AND next_execution < datetime('now')), 0) as "due!: i64"
+            FROM fcm_schedule"#
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let schedules = match schedules {
+            Ok(schedules) => schedules,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let queue = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(status = 'pending'), 0) as "pending!: i64",
+                COALESCE(SUM(status = 'sending'), 0) as "sending!: i64"
+            FROM outbox WHERE status IN ('pending', 'sending')"#
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let queue = match queue {
+            Ok(queue) => queue,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // final outcomes only, retries in progress, skipped sends and dry runs aren't deliveries
+        let executions = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(status = 'success'), 0) as "successes!: i64",
+                COALESCE(SUM(status IN ('failure', 'timeout')), 0) as "failures!: i64",
+                CAST(AVG(latency_ms) AS INTEGER) as "average_latency_ms: i64"
+            FROM fcm_execution_log
+            WHERE executed_at >= datetime('now', '-1 day') AND status NOT IN ('retrying', 'skipped') AND dry_run = 0"#
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let executions = match executions {
+            Ok(executions) => executions,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        Ok(ResponseObject::ok(DispatcherStats {
+            paused: outbox::is_paused(),
+            schedules: schedules.total,
+            active_schedules: schedules.active,
+            blocked_schedules: schedules.blocked,
+            due_schedules: schedules.due,
+            pending_messages: queue.pending,
+            sending_messages: queue.sending,
+            successes_24h: executions.successes,
+            failures_24h: executions.failures,
+            average_latency_ms_24h: executions.average_latency_ms,
+        }))
+    }
+
+    /// issue a short-lived token to call the API as the user, every request made with it is recorded
+    #[oai(
+        path = "/impersonations",
+        method = "post",
+        operation_id = "admin::fcm::start_impersonation"
+    )]
+    async fn start_impersonation(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        body: Json<StartImpersonation>,
+    ) -> Result<JsonSuccess<ImpersonationToken>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let fb_project_id = sqlx::query_scalar!(
+            "SELECT fb_project_id FROM fcm_user WHERE fb_user_id = ?",
+            body.fb_user_id
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let fb_project_id = match fb_project_id {
+            Ok(Some(fb_project_id)) => fb_project_id,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("User not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let current_time = Utc::now().naive_utc();
+        let expires_at = current_time
+            + Duration::minutes(
+                body.ttl_minutes
+                    .unwrap_or(impersonation::DEFAULT_TTL_MINUTES),
+            );
+        let id = sqlx::query_scalar!(
+            r#"INSERT INTO fcm_impersonation (fb_user_id, fb_project_id, operator, reason, expires_at, created_at)
+            VALUES (?, ?, ?, ?, ?, ?) RETURNING id as "id!""#,
+            body.fb_user_id,
+            fb_project_id,
+            body.operator,
+            body.reason,
+            expires_at,
+            current_time
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let id = match id {
+            Ok(id) => id,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let impersonation = Impersonation {
+            id,
+            fb_user_id: body.fb_user_id.clone(),
+            fb_project_id,
+            operator: body.operator.clone(),
+            reason: body.reason.clone(),
+            expires_at,
+            revoked_at: None,
+            created_at: current_time,
+            requests: 0,
+        };
+
+        let token = match impersonation::issue(&impersonation) {
+            Ok(token) => token,
+            Err(e) => {
+                // an impersonation without a token can never be used
+                let _ = sqlx::query!("DELETE FROM fcm_impersonation WHERE id = ?", id)
+                    .execute(pool.0)
+                    .await;
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        warn!(
+            impersonation_id = id,
+            fb_user_id = %impersonation.fb_user_id,
+            operator = %impersonation.operator,
+            reason = %impersonation.reason,
+            expires_at = %impersonation.expires_at,
+            "User impersonation started"
+        );
+        events::publish("user.impersonated", &impersonation);
+
+        Ok(ResponseObject::created(ImpersonationToken {
+            token,
+            impersonation,
+        }))
+    }
+
+    /// audit log of impersonations, newest first
+    #[oai(
+        path = "/impersonations",
+        method = "get",
+        operation_id = "admin::fcm::list_impersonations"
+    )]
+    async fn list_impersonations(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// only return impersonations of the firebase user
+        fb_user_id: Query<Option<String>>,
+    ) -> Result<JsonSuccess<Vec<Impersonation>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        match impersonation::list(pool.0, fb_user_id.0.as_deref()).await {
+            Ok(impersonations) => Ok(ResponseObject::ok(impersonations)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// requests made with an impersonation token, oldest first
+    #[oai(
+        path = "/impersonations/:id/requests",
+        method = "get",
+        operation_id = "admin::fcm::list_impersonation_requests"
+    )]
+    async fn list_impersonation_requests(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Vec<ImpersonationRequest>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        match impersonation::find(pool.0, id.0).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Impersonation not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let requests = sqlx::query_as!(
+            ImpersonationRequest,
+            "SELECT method, path, created_at FROM fcm_impersonation_request WHERE impersonation_id = ? ORDER BY id",
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match requests {
+            Ok(requests) => Ok(ResponseObject::ok(requests)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// stop an impersonation token from working before it expires
+    #[oai(
+        path = "/impersonations/:id/revoke",
+        method = "post",
+        operation_id = "admin::fcm::revoke_impersonation"
+    )]
+    async fn revoke_impersonation(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Impersonation>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "UPDATE fcm_impersonation SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL",
+            current_time,
+            id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        match impersonation::find(pool.0, id.0).await {
+            Ok(Some(impersonation)) => {
+                info!(impersonation_id = id.0, "User impersonation revoked");
+                events::publish(
+                    "user.impersonation_revoked",
+                    json!({ "id": impersonation.id, "fb_user_id": impersonation.fb_user_id }),
+                );
+                Ok(ResponseObject::ok(impersonation))
+            }
+            Ok(None) => Err(ResponseObject::not_found("Impersonation not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
+
+async fn updated_schedule(
+    pool: &SqlitePool,
+    id: i64,
+) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+    let schedule = sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id)
+        .fetch_one(pool)
+        .await;
+
+    match schedule {
+        Ok(schedule) => {
+            events::publish("schedule.updated", ScheduleEvent::from(&schedule));
+            Ok(ResponseObject::ok(schedule))
+        }
+        Err(e) => Err(ResponseObject::internal_server_error(e)),
+    }
+}
+
+fn default_admin_page_limit() -> i64 {
+    50
+}
@@ -0,0 +1,185 @@
+use crate::events;
+use crate::http;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::{collections::HashSet, env, time::Duration};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+lazy_static! {
+    // how often the send volume of every project is checked
+    static ref BURST_CHECK_SECS: u64 = env::var("FCM_BURST_CHECK_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5 * 60);
+    // a project bursts when its last hour sends this many times its usual hourly volume
+    static ref BURST_FACTOR: f64 = env::var("FCM_BURST_FACTOR")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v: &f64| *v > 1.0)
+        .unwrap_or(5.0);
+    // hours fewer sends than this are never a burst, so small projects don't alert on every spike
+    static ref BURST_MIN_SENDS: i64 = env::var("FCM_BURST_MIN_SENDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(100);
+}
+
+// the usual volume is the hourly average over the week before the last hour
+const BASELINE_HOURS: i64 = 7 * 24;
+
+// Burst detection catches runaway clients, e.g. an app creating schedules in a
+// loop, by comparing the messages a project enqueued in the last hour with its
+// usual hourly volume. Which projects are bursting is kept in memory, so each
+// burst alerts once when it starts and once when it ends.
+
+/// Data of the `project.burst` event
+#[derive(Debug, Serialize)]
+struct BurstEvent<'a> {
+    fb_project_id: &'a str,
+    /// messages enqueued in the last hour
+    sends_1h: i64,
+    /// average messages enqueued per hour over the last week
+    baseline_1h: f64,
+    /// whether sending the project's schedules was paused
+    paused: bool,
+}
+
+/// Check the send volume of every project periodically, alerting the project admins
+/// and pausing the project when its settings ask for it
+pub async fn watch_send_volume(pool: SqlitePool) {
+    let mut bursting: HashSet<String> = HashSet::new();
+
+    loop {
+        sleep(Duration::from_secs(*BURST_CHECK_SECS)).await;
+
+        let since = format!("-{} hours", BASELINE_HOURS + 1);
+        let volumes = sqlx::query!(
+            r#"SELECT
+                project_id,
+                COALESCE(SUM(created_at >= datetime('now', '-1 hour')), 0) as "recent!: i64",
+                COALESCE(SUM(created_at < datetime('now', '-1 hour')), 0) as "history!: i64"
+            FROM outbox
+            WHERE created_at >= datetime('now', ?) AND dry_run = 0
+            GROUP BY project_id"#,
+            since
+        )
+        .fetch_all(&pool)
+        .await;
+
+        let volumes = match volumes {
+            Ok(volumes) => volumes,
+            Err(e) => {
+                error!(error = ?e, "Error reading send volumes");
+                continue;
+            }
+        };
+
+        let mut current = HashSet::new();
+        for volume in volumes {
+            let baseline = volume.history as f64 / BASELINE_HOURS as f64;
+            let burst = volume.recent >= *BURST_MIN_SENDS
+                && volume.recent as f64 > baseline * *BURST_FACTOR;
+            if !burst {
+                continue;
+            }
+
+            current.insert(volume.project_id.clone());
+            if !bursting.contains(&volume.project_id) {
+                burst_started(&pool, &volume.project_id, volume.recent, baseline).await;
+            }
+        }
+
+        for project_id in bursting.difference(&current) {
+            info!(fb_project_id = %project_id, "Project send volume is back to normal");
+            events::publish(
+                "project.burst_ended",
+                json!({ "fb_project_id": project_id }),
+            );
+            let text = format!("Send volume of {} is back to normal", project_id);
+            notify(&pool, project_id, &text, "recovered").await;
+        }
+
+        bursting = current;
+    }
+}
+
+async fn burst_started(pool: &SqlitePool, project_id: &str, sends: i64, baseline: f64) {
+    let current_time = Utc::now().naive_utc();
+    let paused = sqlx::query!(
+        "UPDATE fcm_project SET paused_at = ?, updated_at = ? WHERE fb_project_id = ? AND pause_on_burst = 1 AND paused_at IS NULL",
+        current_time,
+        current_time,
+        project_id
+    )
+    .execute(pool)
+    .await;
+
+    let paused = match paused {
+        Ok(result) => result.rows_affected() > 0,
+        Err(e) => {
+            error!(fb_project_id = %project_id, error = ?e, "Error pausing project");
+            false
+        }
+    };
+
+    warn!(fb_project_id = %project_id, sends_1h = sends, baseline_1h = baseline, paused, "Project send volume burst");
+
+    let event = BurstEvent {
+        fb_project_id: project_id,
+        sends_1h: sends,
+        baseline_1h: baseline,
+        paused,
+    };
+    events::publish("project.burst", &event);
+
+    let mut text = format!(
+        "{} sent {} messages in the last hour, it usually sends {:.1} per hour",
+        project_id, sends, baseline
+    );
+    if paused {
+        text.push_str(", its schedules are paused until a project admin resumes them");
+    }
+    notify(pool, project_id, &text, "burst").await;
+}
+
+/// Post the alert to the project's alert webhook, if it has one
+async fn notify(pool: &SqlitePool, project_id: &str, text: &str, state: &str) {
+    let url = sqlx::query_scalar!(
+        "SELECT alert_webhook_url FROM fcm_project WHERE fb_project_id = ?",
+        project_id
+    )
+    .fetch_optional(pool)
+    .await;
+
+    let url = match url {
+        Ok(Some(Some(url))) => url,
+        Ok(_) => return,
+        Err(e) => {
+            error!(fb_project_id = %project_id, error = ?e, "Error loading project alert webhook");
+            return;
+        }
+    };
+
+    let body = json!({
+        "text": text,
+        "fb_project_id": project_id,
+        "state": state,
+    });
+
+    // the url's host may have been pointed to an internal address since it was saved
+    if let Err(e) = http::check_public_url(&url).await {
+        error!(fb_project_id = %project_id, error = %e, "Refusing to send burst alert");
+        return;
+    }
+
+    let result = http::send(http::PUBLIC_CLIENT.post(&url).json(&body))
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        error!(fb_project_id = %project_id, error = ?e, "Failed to send burst alert");
+    }
+}
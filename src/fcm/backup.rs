@@ -0,0 +1,247 @@
+use super::accounts::ServiceAccounts;
+use super::links;
+use super::media;
+use super::model::{
+    ConflictStrategy, ExportedSchedule, FCMSchedule, ImportOutcome, ProjectSettings, ScheduleEvent,
+    ScheduleExport, ScheduleImportItem, ScheduleImportResult, TargetType, EXPORT_VERSION,
+};
+use super::policy::{self, Feature};
+use super::store;
+use super::tokens;
+use super::utils::{validate_schedule, Claims};
+use crate::events;
+use crate::outbox::Priority;
+use chrono::Utc;
+use poem_openapi::types::{ParseFromJSON, ToJSON};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+// Exports hold the schedules a user owns outside organizations. Ids differ
+// between instances, so an imported schedule conflicts with an existing one
+// when the user already has a schedule with the same name.
+
+/// Every schedule the user owns outside organizations, along with its additional devices
+pub async fn export_schedules(
+    pool: &SqlitePool,
+    fb_user_id: &str,
+) -> Result<ScheduleExport, sqlx::Error> {
+    let schedules = sqlx::query_as!(
+        FCMSchedule,
+        "SELECT * FROM fcm_schedule WHERE fb_user_id = ? ORDER BY id",
+        fb_user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let tokens = sqlx::query!(
+        "SELECT fcm_schedule_tokens.schedule_id, fcm_schedule_tokens.push_token FROM fcm_schedule_tokens
+        JOIN fcm_schedule ON fcm_schedule.id = fcm_schedule_tokens.schedule_id
+        WHERE fcm_schedule.fb_user_id = ? AND fcm_schedule.organization_id IS NULL
+        ORDER BY fcm_schedule_tokens.id",
+        fb_user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut push_tokens: HashMap<i64, Vec<String>> = HashMap::new();
+    for token in tokens {
+        push_tokens
+            .entry(token.schedule_id)
+            .or_default()
+            .push(token.push_token);
+    }
+
+    Ok(ScheduleExport {
+        version: EXPORT_VERSION,
+        exported_at: Some(Utc::now().naive_utc()),
+        schedules: schedules
+            .iter()
+            .filter(|schedule| schedule.organization_id.is_none())
+            .map(|schedule| ExportedSchedule {
+                schedule: schedule.into(),
+                push_tokens: push_tokens.remove(&schedule.id).unwrap_or_default(),
+            })
+            .collect(),
+    })
+}
+
+/// Render an export as YAML
+pub fn to_yaml(export: &ScheduleExport) -> Result<String, String> {
+    serde_yaml::to_string(&export.to_json()).map_err(|e| e.to_string())
+}
+
+/// Read an export from YAML, validated the same way as a JSON export
+pub fn from_yaml(yaml: &str) -> Result<ScheduleExport, String> {
+    let value: Value = serde_yaml::from_str(yaml).map_err(|e| format!("Invalid YAML: {}", e))?;
+    ScheduleExport::parse_from_json(Some(value)).map_err(|e| e.into_message())
+}
+
+/// Import every schedule of the export on its own, `schedule_count` is the number of
+/// schedules the user owned before the import
+pub async fn import_schedules(
+    pool: &SqlitePool,
+    service_accounts: &ServiceAccounts,
+    claims: &Claims,
+    project: Option<&ProjectSettings>,
+    export: &ScheduleExport,
+    strategy: ConflictStrategy,
+    schedule_count: i64,
+) -> ScheduleImportResult {
+    let mut result = ScheduleImportResult {
+        created: 0,
+        updated: 0,
+        skipped: 0,
+        failed: 0,
+        items: Vec::with_capacity(export.schedules.len()),
+    };
+
+    for (index, exported) in export.schedules.iter().enumerate() {
+        let imported = import_schedule(
+            pool,
+            service_accounts,
+            claims,
+            project,
+            exported,
+            strategy,
+            schedule_count + result.created as i64,
+        )
+        .await;
+
+        let (id, outcome, error) = match imported {
+            Ok((id, outcome)) => (Some(id), outcome, None),
+            Err(e) => (None, ImportOutcome::Failed, Some(e)),
+        };
+
+        match outcome {
+            ImportOutcome::Created => result.created += 1,
+            ImportOutcome::Updated => result.updated += 1,
+            ImportOutcome::Skipped => result.skipped += 1,
+            ImportOutcome::Failed => result.failed += 1,
+        }
+
+        result.items.push(ScheduleImportItem {
+            index: index as u64,
+            name: exported.schedule.name.clone(),
+            id,
+            outcome,
+            error,
+        });
+    }
+
+    result
+}
+
+async fn import_schedule(
+    pool: &SqlitePool,
+    service_accounts: &ServiceAccounts,
+    claims: &Claims,
+    project: Option<&ProjectSettings>,
+    exported: &ExportedSchedule,
+    strategy: ConflictStrategy,
+    schedule_count: i64,
+) -> Result<(i64, ImportOutcome), String> {
+    let schedule = &exported.schedule;
+
+    let existing = match strategy {
+        ConflictStrategy::Duplicate => None,
+        ConflictStrategy::Skip | ConflictStrategy::Overwrite => sqlx::query_scalar!(
+            r#"SELECT id as "id!" FROM fcm_schedule WHERE fb_user_id = ? AND organization_id IS NULL AND name = ? ORDER BY id LIMIT 1"#,
+            claims.user_id,
+            schedule.name
+        )
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?,
+    };
+
+    if let (Some(id), ConflictStrategy::Skip) = (existing, strategy) {
+        return Ok((id, ImportOutcome::Skipped));
+    }
+
+    if existing.is_none() {
+        policy::authorize_anonymous(claims, schedule_count)?;
+    }
+    if schedule.priority == Priority::High {
+        policy::authorize(claims, Feature::HighPriority)?;
+    }
+    links::validate_links(&schedule.payload, project)?;
+    let next_execution = validate_schedule(schedule)?;
+    media::validate_media(&schedule.payload).await?;
+    if schedule.target_type == TargetType::Token {
+        tokens::verify(service_accounts, &claims.aud, &schedule.push_token).await?;
+    }
+
+    // the schedule's own push_token is always sent to
+    let push_tokens: Vec<String> = exported
+        .push_tokens
+        .iter()
+        .filter(|push_token| **push_token != schedule.push_token)
+        .cloned()
+        .collect();
+    if !push_tokens.is_empty() {
+        policy::authorize(claims, Feature::FanOut)?;
+    }
+    let disabled_reason = policy::approval_hold(claims, project);
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let (id, outcome) = match existing {
+        Some(id) => {
+            store::update_schedule(
+                &mut *tx,
+                id,
+                schedule,
+                next_execution,
+                disabled_reason,
+                None,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+            (id, ImportOutcome::Updated)
+        }
+        None => {
+            let id = store::insert_schedule(
+                &mut *tx,
+                &claims.user_id,
+                &claims.aud,
+                None,
+                schedule,
+                next_execution,
+                disabled_reason,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+            (id, ImportOutcome::Created)
+        }
+    };
+
+    store::replace_schedule_tokens(&mut tx, id, &push_tokens)
+        .await
+        .map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    match outcome {
+        ImportOutcome::Updated => {
+            let updated =
+                sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id)
+                    .fetch_one(pool)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            events::publish("schedule.updated", ScheduleEvent::from(&updated));
+        }
+        _ => events::publish(
+            "schedule.created",
+            ScheduleEvent::new(
+                id,
+                &claims.user_id,
+                &claims.aud,
+                schedule,
+                next_execution,
+                disabled_reason,
+            ),
+        ),
+    }
+
+    Ok((id, outcome))
+}
@@ -0,0 +1,158 @@
+use super::callbacks;
+use super::model::{BatchCompleted, BatchMessageResult};
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::{collections::BTreeMap, env};
+
+lazy_static! {
+    // public URL of the API the detail links point to, e.g. https://toolkit.example.com/api/v1
+    static ref DETAIL_BASE_URL: Option<String> = env::var("FCM_WEBHOOK_DETAIL_BASE_URL")
+        .ok()
+        .map(|url| url.trim_end_matches('/').to_string());
+}
+
+// An execution sent to several devices is tracked as a batch. Instead of an
+// `execution.failed` event per device, the callback gets a single
+// `execution.batch_completed` event once every device has a final outcome, with
+// the counts and a breakdown of the errors. The outcome for every device is
+// served at the detail URL of the event.
+
+/// Track the messages queued for one execution, `messages` are outbox ids and their tokens
+pub async fn create(
+    conn: &mut SqliteConnection,
+    schedule_id: i64,
+    messages: &[(i64, String)],
+) -> Result<i64, sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+    let total = messages.len() as i64;
+
+    let batch_id = sqlx::query!(
+        "INSERT INTO fcm_execution_batch (schedule_id, total, created_at) VALUES (?, ?, ?)",
+        schedule_id,
+        total,
+        current_time
+    )
+    .execute(&mut *conn)
+    .await?
+    .last_insert_rowid();
+
+    for (outbox_id, push_token) in messages {
+        sqlx::query!(
+            "INSERT INTO fcm_execution_batch_message (outbox_id, batch_id, push_token) VALUES (?, ?, ?)",
+            outbox_id,
+            batch_id,
+            push_token
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(batch_id)
+}
+
+/// Record the final outcome of a message, false when the message isn't part of a batch
+pub async fn record(
+    pool: &SqlitePool,
+    outbox_id: i64,
+    status: &str,
+    error_code: Option<&str>,
+    error: Option<&str>,
+) -> Result<bool, String> {
+    let current_time = Utc::now().naive_utc();
+    let batch_id = sqlx::query_scalar!(
+        "UPDATE fcm_execution_batch_message SET status = ?, error_code = ?, error = ?, executed_at = ?
+        WHERE outbox_id = ? RETURNING batch_id",
+        status,
+        error_code,
+        error,
+        current_time,
+        outbox_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    match batch_id {
+        Some(batch_id) => complete(pool, batch_id).await.map(|_| true),
+        None => Ok(false),
+    }
+}
+
+// the last outcome of a batch completes it, only one of the deliveries racing for it wins
+async fn complete(pool: &SqlitePool, batch_id: i64) -> Result<(), String> {
+    let current_time = Utc::now().naive_utc();
+    let batch = sqlx::query!(
+        "UPDATE fcm_execution_batch SET completed_at = ?1
+        WHERE id = ?2 AND completed_at IS NULL
+            AND NOT EXISTS (SELECT 1 FROM fcm_execution_batch_message WHERE batch_id = ?2 AND status = 'pending')
+        RETURNING schedule_id, total",
+        current_time,
+        batch_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let batch = match batch {
+        Some(batch) => batch,
+        None => return Ok(()),
+    };
+
+    let results = results(pool, batch_id).await.map_err(|e| e.to_string())?;
+    let (succeeded, failed, _, errors) = summarize(&results);
+    let event = BatchCompleted {
+        batch_id,
+        schedule_id: batch.schedule_id,
+        total: batch.total,
+        succeeded,
+        failed,
+        errors,
+        detail_url: detail_url(batch.schedule_id, batch_id),
+    };
+
+    callbacks::enqueue(pool, batch.schedule_id, "execution.batch_completed", &event).await
+}
+
+/// Outcome of every message of the batch
+pub async fn results(
+    pool: &SqlitePool,
+    batch_id: i64,
+) -> Result<Vec<BatchMessageResult>, sqlx::Error> {
+    sqlx::query_as!(
+        BatchMessageResult,
+        r#"SELECT push_token, status, error_code, error, executed_at as "executed_at: NaiveDateTime"
+        FROM fcm_execution_batch_message WHERE batch_id = ? ORDER BY outbox_id"#,
+        batch_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Succeeded, failed and pending deliveries, and the failures by error code
+pub fn summarize(results: &[BatchMessageResult]) -> (i64, i64, i64, BTreeMap<String, i64>) {
+    let (mut succeeded, mut failed, mut pending) = (0, 0, 0);
+    let mut errors = BTreeMap::new();
+    for result in results {
+        match result.status.as_str() {
+            "success" => succeeded += 1,
+            "pending" => pending += 1,
+            _ => {
+                failed += 1;
+                let code = result.error_code.as_deref().unwrap_or("unknown");
+                *errors.entry(code.to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    (succeeded, failed, pending, errors)
+}
+
+/// Link to the outcome of every device, relative to the API without FCM_WEBHOOK_DETAIL_BASE_URL
+pub fn detail_url(schedule_id: i64, batch_id: i64) -> String {
+    format!(
+        "{}/fcm/{}/batches/{}",
+        DETAIL_BASE_URL.as_deref().unwrap_or(""),
+        schedule_id,
+        batch_id
+    )
+}
@@ -0,0 +1,253 @@
+use super::accounts::ServiceAccounts;
+use super::model::{Broadcast, NewBroadcast};
+use super::worker::run_broadcast;
+use crate::utils::{select_fields, verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
+use chrono::Utc;
+use poem::{web::Data, Request};
+use poem_openapi::{
+    param::{Path, Query},
+    payload::Json,
+    OpenApi,
+};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use tracing::info;
+
+pub struct FirebaseBroadcasts {
+    pub projects: ServiceAccounts,
+}
+
+#[OpenApi(
+    prefix_path = "/admin/fcm/broadcasts/",
+    request_header(name = "API-Key", ty = "String", description = "Private API Key"),
+    tag = "ApiTags::Admin"
+)]
+impl FirebaseBroadcasts {
+    // create new instance
+    pub fn new(projects: ServiceAccounts) -> Self {
+        Self { projects }
+    }
+
+    // Send a notification to every registered token of a project
+    #[oai(path = "/", method = "post", operation_id = "fcm::create_broadcast")]
+    async fn create_broadcast(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        broadcast: Json<NewBroadcast>,
+    ) -> Result<JsonSuccess<Broadcast>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        if !self.projects.contains(&broadcast.fb_project_id) {
+            return Err(ResponseObject::bad_request("Invalid project id"));
+        }
+
+        let mut payload = match &broadcast.data {
+            Some(Value::Object(map)) => map.clone(),
+            Some(_) => {
+                return Err(ResponseObject::bad_request("Invalid data"));
+            }
+            None => serde_json::Map::new(),
+        };
+        payload.insert("title".to_string(), Value::from(broadcast.title.clone()));
+        payload.insert("body".to_string(), Value::from(broadcast.body.clone()));
+        let payload = Value::Object(payload);
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(DISTINCT push_token) as "count: i64" FROM fcm_schedule
+            WHERE fb_project_id = ? AND disabled_reason IS NULL"#,
+            broadcast.fb_project_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let total = match total {
+            Ok(total) => total,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // tokens are random, so the first ones in token order make a fair sample
+        let canary_size = broadcast
+            .canary_percent
+            .map(|percent| ((total * percent + 99) / 100).max(1));
+        let current_time = Utc::now().naive_utc();
+
+        let result = sqlx::query!(
+            "INSERT INTO fcm_broadcast (fb_project_id, payload, priority, total, canary_size, canary_delay_seconds, canary_max_error_rate, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            broadcast.fb_project_id,
+            payload,
+            broadcast.priority,
+            total,
+            canary_size,
+            broadcast.canary_delay_seconds,
+            broadcast.canary_max_error_rate,
+            current_time,
+            current_time
+        )
+        .execute(pool.0)
+        .await;
+
+        let id = match result {
+            Ok(result) => result.last_insert_rowid(),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        info!(broadcast_id = id, project_id = %broadcast.fb_project_id, total, "Starting broadcast");
+        tokio::spawn(run_broadcast(pool.0.clone(), id));
+
+        match fetch_broadcast(pool.0, id).await {
+            Ok(Some(broadcast)) => Ok(ResponseObject::created(broadcast)),
+            Ok(None) => Err(ResponseObject::not_found("Broadcast not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // List broadcasts, most recent first, items are Broadcast objects limited to the requested fields
+    #[oai(path = "/", method = "get", operation_id = "fcm::list_broadcasts")]
+    async fn list_broadcasts(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// comma separated fields to return, e.g. `id,status,delivered` (defaults to every field)
+        fields: Query<Option<String>>,
+    ) -> Result<JsonSuccess<Vec<Value>>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let broadcasts = sqlx::query_as!(
+            Broadcast,
+            r#"SELECT b.id as "id!", b.fb_project_id, b.payload as "payload: Value", b.priority, b.status, b.total, b.enqueued,
+                (SELECT COUNT(*) FROM outbox o WHERE o.broadcast_id = b.id AND o.status = 'delivered') as "delivered!: i64",
+                (SELECT COUNT(*) FROM outbox o WHERE o.broadcast_id = b.id AND o.status = 'failed') as "failed!: i64",
+                b.canary_size, b.canary_status, b.created_at, b.updated_at
+            FROM fcm_broadcast b ORDER BY b.id DESC"#
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let broadcasts = match broadcasts {
+            Ok(broadcasts) => broadcasts,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        match select_fields(broadcasts, fields.0.as_deref()) {
+            Ok(broadcasts) => Ok(ResponseObject::ok(broadcasts)),
+            Err(e) => Err(ResponseObject::bad_request(e)),
+        }
+    }
+
+    // Progress of a broadcast
+    #[oai(path = "/:id", method = "get", operation_id = "fcm::get_broadcast")]
+    async fn get_broadcast(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Broadcast>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        match fetch_broadcast(pool.0, id.0).await {
+            Ok(Some(broadcast)) => Ok(ResponseObject::ok(broadcast)),
+            Ok(None) => Err(ResponseObject::not_found("Broadcast not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Stop queueing the broadcast and drop its undelivered messages
+    #[oai(
+        path = "/:id/abort",
+        method = "post",
+        operation_id = "fcm::abort_broadcast"
+    )]
+    async fn abort_broadcast(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Broadcast>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let current_time = Utc::now().naive_utc();
+
+        let result = sqlx::query!(
+            "UPDATE fcm_broadcast SET status = 'aborted', updated_at = ? WHERE id = ?",
+            current_time,
+            id.0
+        )
+        .execute(&mut *tx)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(ResponseObject::not_found("Broadcast not found"));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        // messages already being sent can't be recalled
+        let result = sqlx::query!(
+            "UPDATE outbox SET status = 'aborted', updated_at = ? WHERE broadcast_id = ? AND status = 'pending'",
+            current_time,
+            id.0
+        )
+        .execute(&mut *tx)
+        .await;
+
+        let dropped = match result {
+            Ok(result) => result.rows_affected(),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        info!(broadcast_id = id.0, dropped, "Aborted broadcast");
+
+        match fetch_broadcast(pool.0, id.0).await {
+            Ok(Some(broadcast)) => Ok(ResponseObject::ok(broadcast)),
+            Ok(None) => Err(ResponseObject::not_found("Broadcast not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
+
+async fn fetch_broadcast(pool: &SqlitePool, id: i64) -> Result<Option<Broadcast>, sqlx::Error> {
+    sqlx::query_as!(
+        Broadcast,
+        r#"SELECT b.id as "id!", b.fb_project_id, b.payload as "payload: Value", b.priority, b.status, b.total, b.enqueued,
+            (SELECT COUNT(*) FROM outbox o WHERE o.broadcast_id = b.id AND o.status = 'delivered') as "delivered!: i64",
+            (SELECT COUNT(*) FROM outbox o WHERE o.broadcast_id = b.id AND o.status = 'failed') as "failed!: i64",
+            b.canary_size, b.canary_status, b.created_at, b.updated_at
+        FROM fcm_broadcast b WHERE b.id = ?"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
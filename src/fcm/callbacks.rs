@@ -0,0 +1,283 @@
+use crate::http;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use openssl::{hash::MessageDigest, pkey::PKey, rand::rand_bytes, sign::Signer};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::{env, time::Duration};
+use tokio::{task::JoinSet, time::sleep};
+use tracing::{error, info, warn};
+
+lazy_static! {
+    // attempts made to deliver an event before giving up on it
+    static ref MAX_ATTEMPTS: i64 = env::var("FCM_WEBHOOK_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v: &i64| *v > 0)
+        .unwrap_or(8);
+    // wait before the first retry, doubled after every failed attempt
+    static ref RETRY_BACKOFF_SECS: i64 = env::var("FCM_WEBHOOK_BACKOFF_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    // longest a callback may take to respond
+    static ref TIMEOUT_SECS: u64 = env::var("FCM_WEBHOOK_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+}
+
+// events delivered at once
+const BATCH_SIZE: i64 = 20;
+
+// Callbacks tell the owner of a schedule about deliveries that failed for good.
+// Events are queued in fcm_webhook_delivery and posted by a single loop on the
+// primary instance, which retries them with exponential backoff. Every request
+// carries `X-Webhook-Signature: t=<unix time>,v1=<hex HMAC-SHA256>` computed over
+// `<unix time>.<body>` with the schedule's secret, so receivers can check where
+// it came from and reject replays. The event id stays the same across retries.
+
+/// Check the callback URL is an absolute http(s) URL of a public host
+pub fn validate_url(url: &str) -> Result<(), String> {
+    match http::validate_public_url(url) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Invalid webhook url: {}", e)),
+    }
+}
+
+/// Random secret to sign the events of a schedule with
+pub fn generate_secret() -> Result<String, String> {
+    let mut secret = [0u8; 32];
+    rand_bytes(&mut secret).map_err(|e| e.to_string())?;
+    Ok(to_hex(&secret))
+}
+
+/// Value of the signature header of a body sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> Result<String, String> {
+    let key = PKey::hmac(secret.as_bytes()).map_err(|e| e.to_string())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer
+        .update(format!("{}.{}", timestamp, body).as_bytes())
+        .map_err(|e| e.to_string())?;
+    let signature = signer.sign_to_vec().map_err(|e| e.to_string())?;
+    Ok(format!("t={},v1={}", timestamp, to_hex(&signature)))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Queue the event for the callback of the schedule, schedules without one are skipped
+pub async fn enqueue<T: Serialize>(
+    pool: &SqlitePool,
+    schedule_id: i64,
+    event: &str,
+    data: &T,
+) -> Result<(), String> {
+    let payload = serde_json::to_string(data).map_err(|e| e.to_string())?;
+    let current_time = Utc::now().naive_utc();
+
+    sqlx::query!(
+        "INSERT INTO fcm_webhook_delivery (schedule_id, event, payload, next_attempt_at, created_at)
+        SELECT schedule_id, ?, ?, ?, ? FROM fcm_schedule_webhook WHERE schedule_id = ?",
+        event,
+        payload,
+        current_time,
+        current_time,
+        schedule_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+struct PendingDelivery {
+    id: i64,
+    schedule_id: i64,
+    event: String,
+    payload: String,
+    attempts: i64,
+    created_at: NaiveDateTime,
+    url: Option<String>,
+    secret: Option<String>,
+}
+
+/// Post queued events once they're due, forever
+pub async fn deliver_pending(pool: SqlitePool) {
+    loop {
+        let current_time = Utc::now().naive_utc();
+        let deliveries = sqlx::query_as!(
+            PendingDelivery,
+            r#"SELECT
+                fcm_webhook_delivery.id as "id!",
+                fcm_webhook_delivery.schedule_id,
+                fcm_webhook_delivery.event,
+                fcm_webhook_delivery.payload,
+                fcm_webhook_delivery.attempts,
+                fcm_webhook_delivery.created_at,
+                fcm_schedule_webhook.url as "url?",
+                fcm_schedule_webhook.secret as "secret?"
+            FROM fcm_webhook_delivery
+            LEFT JOIN fcm_schedule_webhook ON fcm_schedule_webhook.schedule_id = fcm_webhook_delivery.schedule_id
+            WHERE fcm_webhook_delivery.delivered_at IS NULL AND fcm_webhook_delivery.failed_at IS NULL
+                AND fcm_webhook_delivery.next_attempt_at <= ?
+            ORDER BY fcm_webhook_delivery.next_attempt_at
+            LIMIT ?"#,
+            current_time,
+            BATCH_SIZE
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap_or_else(|e| {
+            error!(error = ?e, "Error loading webhook deliveries");
+            vec![]
+        });
+
+        let claimed = deliveries.len() as i64;
+        let mut posts = JoinSet::new();
+        for delivery in deliveries {
+            let pool = pool.clone();
+            posts.spawn(async move { attempt(&pool, delivery).await });
+        }
+        while posts.join_next().await.is_some() {}
+
+        // keep draining a busy queue, otherwise wait for new events
+        if claimed < BATCH_SIZE {
+            sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
+
+async fn attempt(pool: &SqlitePool, delivery: PendingDelivery) {
+    let current_time = Utc::now().naive_utc();
+    let attempts = delivery.attempts + 1;
+
+    let (url, secret) = match (&delivery.url, &delivery.secret) {
+        (Some(url), Some(secret)) => (url, secret),
+        // the callback was removed while the event was queued
+        _ => {
+            let result = sqlx::query!(
+                "UPDATE fcm_webhook_delivery SET failed_at = ?, error = ? WHERE id = ?",
+                current_time,
+                "webhook was removed",
+                delivery.id
+            )
+            .execute(pool)
+            .await;
+            if let Err(e) = result {
+                error!(delivery_id = delivery.id, error = ?e, "Error updating webhook delivery");
+            }
+            return;
+        }
+    };
+
+    let result = post(url, secret, &delivery).await;
+
+    let result = match &result {
+        Ok(()) => {
+            info!(delivery_id = delivery.id, schedule_id = delivery.schedule_id, url = %url, "Delivered webhook event");
+            sqlx::query!(
+                "UPDATE fcm_webhook_delivery SET attempts = ?, delivered_at = ?, error = NULL WHERE id = ?",
+                attempts,
+                current_time,
+                delivery.id
+            )
+            .execute(pool)
+            .await
+        }
+        Err(e) if attempts >= *MAX_ATTEMPTS => {
+            warn!(delivery_id = delivery.id, schedule_id = delivery.schedule_id, url = %url, error = %e, attempts, "Giving up on webhook event");
+            sqlx::query!(
+                "UPDATE fcm_webhook_delivery SET attempts = ?, failed_at = ?, error = ? WHERE id = ?",
+                attempts,
+                current_time,
+                e,
+                delivery.id
+            )
+            .execute(pool)
+            .await
+        }
+        Err(e) => {
+            let backoff = *RETRY_BACKOFF_SECS * 2i64.pow((attempts - 1).min(16) as u32);
+            let next_attempt_at = current_time + chrono::Duration::seconds(backoff);
+            warn!(delivery_id = delivery.id, schedule_id = delivery.schedule_id, url = %url, error = %e, attempts, "Retrying webhook event later");
+            sqlx::query!(
+                "UPDATE fcm_webhook_delivery SET attempts = ?, next_attempt_at = ?, error = ? WHERE id = ?",
+                attempts,
+                next_attempt_at,
+                e,
+                delivery.id
+            )
+            .execute(pool)
+            .await
+        }
+    };
+
+    if let Err(e) = result {
+        error!(delivery_id = delivery.id, error = ?e, "Error updating webhook delivery");
+    }
+}
+
+async fn post(url: &str, secret: &str, delivery: &PendingDelivery) -> Result<(), String> {
+    // the url's host may have been pointed to an internal address since it was saved
+    http::check_public_url(url).await?;
+
+    let data: Value = serde_json::from_str(&delivery.payload).map_err(|e| e.to_string())?;
+    let body = json!({
+        "id": delivery.id,
+        "type": delivery.event,
+        "created_at": delivery.created_at,
+        "data": data,
+    })
+    .to_string();
+
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(secret, timestamp, &body)?;
+
+    // sent once, failed attempts are retried by the queue with a longer backoff
+    let response = http::PUBLIC_CLIENT
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Id", delivery.id)
+        .header("X-Webhook-Signature", signature)
+        .body(body)
+        .timeout(Duration::from_secs(*TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match response.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_the_timestamp_and_body() {
+        let signature = sign("secret", 1700000000, r#"{"id":1}"#).unwrap();
+        let (timestamp, digest) = signature.split_once(',').unwrap();
+        assert_eq!(timestamp, "t=1700000000");
+
+        // HMAC-SHA256 of `1700000000.{"id":1}` with the secret, hex encoded
+        assert_eq!(
+            digest,
+            "v1=3dd1b9aef568d75f6790a84bd2e5dfa1f44409eef3cbdbd3f10b837376100c11"
+        );
+    }
+
+    #[test]
+    fn signatures_depend_on_secret_timestamp_and_body() {
+        let signature = sign("secret", 1700000000, "body").unwrap();
+        assert_eq!(signature, sign("secret", 1700000000, "body").unwrap());
+        assert_ne!(signature, sign("other", 1700000000, "body").unwrap());
+        assert_ne!(signature, sign("secret", 1700000001, "body").unwrap());
+        assert_ne!(signature, sign("secret", 1700000000, "other").unwrap());
+    }
+}
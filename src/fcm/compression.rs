@@ -0,0 +1,122 @@
+use super::model::Compression;
+use base64::{engine::general_purpose, Engine as _};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, env};
+
+lazy_static! {
+    // bytes of data over which `auto` schedules pack it
+    static ref THRESHOLD: usize = env::var("FCM_COMPRESSION_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(2048);
+}
+
+// Packed data replaces every key of the data block with two keys the client SDK
+// checks for: `compression` set to `gzip` and `payload` holding the original data
+// as a JSON object, gzipped and base64 encoded. The notification block is never
+// packed as the system displays it without the app.
+
+/// Data key flagging a packed message, its value is the encoding of `payload`
+pub const COMPRESSION_KEY: &str = "compression";
+/// Data key holding the packed data
+pub const PAYLOAD_KEY: &str = "payload";
+
+const GZIP: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Bytes the data takes up in the message
+pub fn size(data: &HashMap<String, String>) -> usize {
+    serde_json::to_string(data).map(|d| d.len()).unwrap_or(0)
+}
+
+/// Data as it's sent with the compression of the schedule, `auto` only packs data
+/// over FCM_COMPRESSION_THRESHOLD bytes that gets smaller by packing it
+pub fn apply(data: HashMap<String, String>, compression: Compression) -> HashMap<String, String> {
+    match compression {
+        Compression::None => data,
+        Compression::Gzip => pack(&data),
+        Compression::Auto => {
+            if size(&data) <= *THRESHOLD {
+                return data;
+            }
+            let packed = pack(&data);
+            if size(&packed) < size(&data) {
+                packed
+            } else {
+                data
+            }
+        }
+    }
+}
+
+fn pack(data: &HashMap<String, String>) -> HashMap<String, String> {
+    let json = serde_json::to_string(data).unwrap_or("{}".to_string());
+    HashMap::from([
+        (COMPRESSION_KEY.to_string(), "gzip".to_string()),
+        (
+            PAYLOAD_KEY.to_string(),
+            general_purpose::STANDARD.encode(gzip(json.as_bytes())),
+        ),
+    ])
+}
+
+/// Gzip member (RFC 1952) around the deflated bytes
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let deflated = miniz_oxide::deflate::compress_to_vec(bytes, 9);
+
+    // magic, deflate, no flags, no mtime, max compression, unknown OS
+    let mut member = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 2, 0xff];
+    member.reserve(deflated.len() + 8);
+    member.extend_from_slice(&deflated);
+    member.extend_from_slice(&GZIP.checksum(bytes).to_le_bytes());
+    member.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    member
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(value: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("title".to_string(), "Hello".to_string()),
+            ("body".to_string(), value.to_string()),
+        ])
+    }
+
+    fn unpack(packed: &HashMap<String, String>) -> HashMap<String, String> {
+        assert_eq!(packed.len(), 2);
+        assert_eq!(packed[COMPRESSION_KEY], "gzip");
+        let member = general_purpose::STANDARD
+            .decode(&packed[PAYLOAD_KEY])
+            .unwrap();
+        assert_eq!(member[..3], [0x1f, 0x8b, 8]);
+
+        let (deflated, trailer) = member[10..].split_at(member.len() - 18);
+        let json = miniz_oxide::inflate::decompress_to_vec(deflated).unwrap();
+        assert_eq!(trailer[..4], GZIP.checksum(&json).to_le_bytes());
+        assert_eq!(trailer[4..], (json.len() as u32).to_le_bytes());
+        serde_json::from_slice(&json).unwrap()
+    }
+
+    #[test]
+    fn none_leaves_data_alone() {
+        assert_eq!(apply(data("world"), Compression::None), data("world"));
+    }
+
+    #[test]
+    fn gzip_packs_the_data() {
+        let packed = apply(data("world"), Compression::Gzip);
+        assert_eq!(unpack(&packed), data("world"));
+    }
+
+    #[test]
+    fn auto_packs_large_data_only() {
+        assert_eq!(apply(data("world"), Compression::Auto), data("world"));
+
+        let large = data(&"repeated text ".repeat(500));
+        let packed = apply(large.clone(), Compression::Auto);
+        assert!(size(&packed) < size(&large));
+        assert_eq!(unpack(&packed), large);
+    }
+}
@@ -0,0 +1,391 @@
+use super::model::{ConditionKind, Conditions, SendCondition, UptimeState};
+use crate::http;
+use crate::kv;
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::{cmp::Ordering, env, time::Duration};
+
+lazy_static! {
+    // longest a condition may wait for its URL, the scheduler evaluates conditions one at a time
+    static ref CONDITION_TIMEOUT_SECS: u64 = env::var("FCM_CONDITION_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+}
+
+// most conditions a schedule can have
+const MAX_CONDITIONS: usize = 5;
+
+/// Check the conditions are complete and their expressions parse
+pub fn validate(conditions: &Conditions) -> Result<(), String> {
+    if conditions.0.len() > MAX_CONDITIONS {
+        return Err(format!(
+            "a schedule can have at most {} conditions",
+            MAX_CONDITIONS
+        ));
+    }
+
+    for condition in &conditions.0 {
+        match condition.kind {
+            ConditionKind::Uptime => {
+                validate_url(condition.url.as_deref())?;
+                if condition.state.is_none() {
+                    return Err("uptime conditions need a state".to_string());
+                }
+            }
+            ConditionKind::Kv => {
+                if condition.key.as_deref().unwrap_or_default().is_empty() {
+                    return Err("kv conditions need a key".to_string());
+                }
+                if condition.value.is_none() {
+                    return Err("kv conditions need a value".to_string());
+                }
+            }
+            ConditionKind::Json => {
+                validate_url(condition.url.as_deref())?;
+                match condition.expression.as_deref() {
+                    Some(expression) => {
+                        parse(expression)?;
+                    }
+                    None => return Err("json conditions need an expression".to_string()),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_url(url: Option<&str>) -> Result<(), String> {
+    let url = match url {
+        Some(url) => url,
+        None => return Err("the condition needs a url".to_string()),
+    };
+    match http::validate_public_url(url) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Invalid condition url: {}", e)),
+    }
+}
+
+/// Evaluate the conditions in order, returning why the first unmet one failed
+pub async fn evaluate(conditions: &Conditions) -> Result<(), String> {
+    for condition in &conditions.0 {
+        evaluate_one(condition).await?;
+    }
+    Ok(())
+}
+
+async fn evaluate_one(condition: &SendCondition) -> Result<(), String> {
+    let url = condition.url.as_deref().unwrap_or_default();
+    let timeout = Duration::from_secs(*CONDITION_TIMEOUT_SECS);
+
+    // the url's host may have been pointed to an internal address since it was saved
+    if condition.kind != ConditionKind::Kv {
+        http::check_public_url(url).await?;
+    }
+
+    match condition.kind {
+        ConditionKind::Uptime => {
+            let up = match http::send(http::PUBLIC_CLIENT.get(url).timeout(timeout)).await {
+                Ok(response) => response.status().is_success(),
+                Err(_) => false,
+            };
+            let expected = condition.state == Some(UptimeState::Up);
+            match up == expected {
+                true => Ok(()),
+                false => Err(format!("{} is {}", url, if up { "up" } else { "down" })),
+            }
+        }
+        ConditionKind::Kv => {
+            let key = condition.key.as_deref().unwrap_or_default();
+            let value = kv::get(key).await;
+            match value.as_deref() == condition.value.as_deref() {
+                true => Ok(()),
+                false => Err(format!(
+                    "{} is {}",
+                    key,
+                    value.as_deref().unwrap_or("unset")
+                )),
+            }
+        }
+        ConditionKind::Json => {
+            let response = http::send(http::PUBLIC_CLIENT.get(url).timeout(timeout))
+                .await
+                .and_then(|response| response.error_for_status());
+            let document = match response {
+                Ok(response) => response.json::<Value>().await,
+                Err(e) => return Err(format!("Error fetching {}: {}", url, e)),
+            };
+            let document = match document {
+                Ok(document) => document,
+                Err(e) => return Err(format!("{} didn't answer with JSON: {}", url, e)),
+            };
+
+            let expression = condition.expression.as_deref().unwrap_or_default();
+            let result = parse(expression)?.evaluate(&document);
+            match truthy(&result) {
+                true => Ok(()),
+                false => Err(format!("{} is {}", expression, result)),
+            }
+        }
+    }
+}
+
+// A small JMESPath subset: field and index access (`a.b[0]`, `@` for the whole
+// document), raw strings ('text'), JSON literals (`3`), comparisons and
+// `&&`, `||`, `!` with parentheses, following JMESPath's truthiness rules.
+
+#[derive(Debug)]
+enum Expr {
+    Current,
+    Literal(Value),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, i64),
+    Compare(Box<Expr>, Comparator, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Expr {
+    fn evaluate(&self, current: &Value) -> Value {
+        match self {
+            Expr::Current => current.clone(),
+            Expr::Literal(value) => value.clone(),
+            Expr::Field(target, name) => match target.evaluate(current) {
+                Value::Object(mut map) => map.remove(name).unwrap_or(Value::Null),
+                _ => Value::Null,
+            },
+            Expr::Index(target, index) => match target.evaluate(current) {
+                Value::Array(mut items) => {
+                    let len = items.len() as i64;
+                    let index = if *index < 0 { len + index } else { *index };
+                    match (0..len).contains(&index) {
+                        true => items.swap_remove(index as usize),
+                        false => Value::Null,
+                    }
+                }
+                _ => Value::Null,
+            },
+            Expr::Compare(left, comparator, right) => compare(
+                &left.evaluate(current),
+                *comparator,
+                &right.evaluate(current),
+            ),
+            Expr::And(left, right) => {
+                let left = left.evaluate(current);
+                match truthy(&left) {
+                    true => right.evaluate(current),
+                    false => left,
+                }
+            }
+            Expr::Or(left, right) => {
+                let left = left.evaluate(current);
+                match truthy(&left) {
+                    true => left,
+                    false => right.evaluate(current),
+                }
+            }
+            Expr::Not(target) => Value::Bool(!truthy(&target.evaluate(current))),
+        }
+    }
+}
+
+/// JMESPath truthiness, empty strings, arrays and objects are false
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::String(value) => !value.is_empty(),
+        Value::Array(value) => !value.is_empty(),
+        Value::Object(value) => !value.is_empty(),
+        Value::Number(_) => true,
+    }
+}
+
+/// Ordering comparisons are only defined between numbers and are null otherwise
+fn compare(left: &Value, comparator: Comparator, right: &Value) -> Value {
+    let ordering = match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => left.partial_cmp(&right),
+        _ => None,
+    };
+
+    match (comparator, ordering) {
+        (Comparator::Eq, _) => Value::Bool(left == right),
+        (Comparator::Ne, _) => Value::Bool(left != right),
+        (Comparator::Lt, Some(ordering)) => Value::Bool(ordering == Ordering::Less),
+        (Comparator::Le, Some(ordering)) => Value::Bool(ordering != Ordering::Greater),
+        (Comparator::Gt, Some(ordering)) => Value::Bool(ordering == Ordering::Greater),
+        (Comparator::Ge, Some(ordering)) => Value::Bool(ordering != Ordering::Less),
+        (_, None) => Value::Null,
+    }
+}
+
+fn parse(expression: &str) -> Result<Expr, String> {
+    let mut parser = Parser { rest: expression };
+    let expr = parser.or()?;
+    match parser.rest.trim_start() {
+        "" => Ok(expr),
+        rest => Err(format!("Invalid expression: unexpected `{}`", rest)),
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    /// Consume the token when the remaining input starts with it
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest.trim_start().strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        // `!=` is a comparator, `!` is only a negation in front of an operand
+        if !self.rest.trim_start().starts_with("!=") && self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.operand()?;
+        // two character comparators first so `<=` isn't read as `<`
+        for (token, comparator) in [
+            ("==", Comparator::Eq),
+            ("!=", Comparator::Ne),
+            ("<=", Comparator::Le),
+            (">=", Comparator::Ge),
+            ("<", Comparator::Lt),
+            (">", Comparator::Gt),
+        ] {
+            if self.eat(token) {
+                let right = self.operand()?;
+                return Ok(Expr::Compare(Box::new(left), comparator, Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err("Invalid expression: missing `)`".to_string());
+            }
+            return Ok(expr);
+        }
+
+        let rest = self.rest.trim_start();
+        if let Some(rest) = rest.strip_prefix('\'') {
+            let (text, rest) = match rest.split_once('\'') {
+                Some(parts) => parts,
+                None => return Err("Invalid expression: unterminated string".to_string()),
+            };
+            self.rest = rest;
+            return Ok(Expr::Literal(Value::String(text.to_string())));
+        }
+        if let Some(rest) = rest.strip_prefix('`') {
+            let (literal, rest) = match rest.split_once('`') {
+                Some(parts) => parts,
+                None => return Err("Invalid expression: unterminated literal".to_string()),
+            };
+            let value = match serde_json::from_str(literal) {
+                Ok(value) => value,
+                Err(e) => return Err(format!("Invalid expression: bad literal: {}", e)),
+            };
+            self.rest = rest;
+            return Ok(Expr::Literal(value));
+        }
+
+        self.path()
+    }
+
+    fn path(&mut self) -> Result<Expr, String> {
+        let mut expr = match self.eat("@") {
+            true => Expr::Current,
+            false => match self.rest.trim_start().starts_with('[') {
+                true => Expr::Current,
+                false => Expr::Field(Box::new(Expr::Current), self.identifier()?),
+            },
+        };
+
+        loop {
+            if self.rest.starts_with('.') {
+                self.rest = &self.rest[1..];
+                expr = Expr::Field(Box::new(expr), self.identifier()?);
+            } else if self.eat("[") {
+                let end = self.rest.find(']').unwrap_or(self.rest.len());
+                let index = match self.rest[..end].trim().parse() {
+                    Ok(index) => index,
+                    Err(_) => return Err("Invalid expression: bad index".to_string()),
+                };
+                self.rest = &self.rest[end..];
+                if !self.eat("]") {
+                    return Err("Invalid expression: missing `]`".to_string());
+                }
+                expr = Expr::Index(Box::new(expr), index);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Plain identifier, or any text in double quotes
+    fn identifier(&mut self) -> Result<String, String> {
+        let rest = self.rest.trim_start();
+        if let Some(rest) = rest.strip_prefix('"') {
+            return match rest.split_once('"') {
+                Some((name, rest)) => {
+                    self.rest = rest;
+                    Ok(name.to_string())
+                }
+                None => Err("Invalid expression: unterminated identifier".to_string()),
+            };
+        }
+
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if end == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(match rest.is_empty() {
+                true => "Invalid expression: unexpected end".to_string(),
+                false => format!("Invalid expression: unexpected `{}`", rest),
+            });
+        }
+        self.rest = &rest[end..];
+        Ok(rest[..end].to_string())
+    }
+}
@@ -0,0 +1,140 @@
+use super::model::{FCMSchedule, ScheduleType};
+use super::utils::{cron_after, decode_run_at};
+use chrono::NaiveDateTime;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+// Local time schedules are due at a different instant on every device. The
+// schedule's next execution is the earliest time any of its devices is due, and
+// every device whose time came since then is sent to when the schedule runs.
+
+/// Devices of a local time schedule due now and when the next device is due
+pub struct LocalPlan {
+    /// push tokens to send to now
+    pub due: Vec<String>,
+    /// time (UTC) the next device is due, absent once a one-shot schedule reached every device
+    pub next: Option<NaiveDateTime>,
+}
+
+/// Split the push tokens of a local time schedule into the ones due by `now` and the
+/// time the next one is due, each token in the timezone its device registered
+pub async fn plan(
+    pool: &SqlitePool,
+    schedule: &FCMSchedule,
+    push_tokens: Vec<String>,
+    now: NaiveDateTime,
+) -> Result<LocalPlan, String> {
+    let timezones = timezones(pool, &schedule.fb_user_id).await?;
+
+    // every device is at or after the schedule's next execution, which was the earliest of them
+    let since = schedule.next_execution - chrono::Duration::seconds(1);
+
+    let mut due = Vec::new();
+    let mut next: Option<NaiveDateTime> = None;
+    for push_token in push_tokens {
+        let timezone = timezones
+            .get(&push_token)
+            .map(String::as_str)
+            .unwrap_or(&schedule.timezone);
+
+        if local_after(schedule, timezone, since)?.is_some_and(|at| at <= now) {
+            due.push(push_token);
+        }
+        if let Some(at) = local_after(schedule, timezone, now)? {
+            next = Some(next.map_or(at, |next| next.min(at)));
+        }
+    }
+
+    Ok(LocalPlan { due, next })
+}
+
+/// First time after `after` the schedule is due in the timezone
+fn local_after(
+    schedule: &FCMSchedule,
+    timezone: &str,
+    after: NaiveDateTime,
+) -> Result<Option<NaiveDateTime>, String> {
+    match schedule.schedule_type {
+        ScheduleType::Recurring => cron_after(&schedule.cron_pattern, timezone, after).map(Some),
+        // a device whose local run_at falls in a daylight saving gap is never sent to
+        ScheduleType::Once => Ok(decode_run_at(schedule.run_at, timezone)
+            .ok()
+            .filter(|at| *at > after)),
+    }
+}
+
+/// Timezones of the user's devices by push token
+async fn timezones(pool: &SqlitePool, fb_user_id: &str) -> Result<HashMap<String, String>, String> {
+    let devices = sqlx::query!(
+        "SELECT push_token, timezone FROM fcm_device WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(devices
+        .into_iter()
+        .map(|device| (device.push_token, device.timezone))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poem_openapi::types::Example;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn schedule(schedule_type: ScheduleType) -> FCMSchedule {
+        FCMSchedule {
+            cron_pattern: "0 9 * * *".to_string(),
+            schedule_type,
+            run_at: Some(at("2026-01-01 09:00:00")),
+            local_time: true,
+            ..FCMSchedule::example()
+        }
+    }
+
+    #[test]
+    fn recurring_follows_the_device_timezone() {
+        let schedule = schedule(ScheduleType::Recurring);
+        let after = at("2025-12-31 12:00:00");
+        assert_eq!(
+            local_after(&schedule, "Asia/Tokyo", after),
+            Ok(Some(at("2026-01-01 00:00:00")))
+        );
+        assert_eq!(
+            local_after(&schedule, "America/New_York", after),
+            Ok(Some(at("2025-12-31 14:00:00")))
+        );
+        assert!(local_after(&schedule, "Mars/Olympus", after).is_err());
+    }
+
+    #[test]
+    fn once_is_due_once_per_device() {
+        let schedule = schedule(ScheduleType::Once);
+        assert_eq!(
+            local_after(&schedule, "Europe/London", at("2026-01-01 08:00:00")),
+            Ok(Some(at("2026-01-01 09:00:00")))
+        );
+        assert_eq!(
+            local_after(&schedule, "Europe/London", at("2026-01-01 09:00:00")),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn once_in_a_daylight_saving_gap_is_skipped() {
+        let schedule = FCMSchedule {
+            run_at: Some(at("2026-03-29 01:30:00")),
+            ..schedule(ScheduleType::Once)
+        };
+        assert_eq!(
+            local_after(&schedule, "Europe/London", at("2026-03-28 00:00:00")),
+            Ok(None)
+        );
+    }
+}
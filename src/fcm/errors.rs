@@ -0,0 +1,119 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+// https://firebase.google.com/docs/reference/fcm/rest/v1/ErrorCode
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    details: Vec<ErrorDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    #[serde(rename = "errorCode")]
+    error_code: Option<String>,
+}
+
+/// Typed reason a send failed, recorded with each failed execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// the push token is no longer valid for the project, the schedule gets disabled
+    InvalidToken,
+    /// the project or device exceeded its sending quota, retried with backoff
+    QuotaExceeded,
+    /// FCM or the network failed, retried with backoff
+    ServerError,
+    /// the message is larger than FCM accepts
+    PayloadTooBig,
+    /// FCM refused the message for another reason
+    InvalidArgument,
+    /// the service account of the project was rejected, needs the owner's attention
+    AuthError,
+    /// the send didn't complete within the allowed time
+    Timeout,
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidToken => "invalid_token",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::ServerError => "server_error",
+            ErrorCode::PayloadTooBig => "payload_too_big",
+            ErrorCode::InvalidArgument => "invalid_argument",
+            ErrorCode::AuthError => "auth_error",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+
+    /// What the sender can change for the message to go through
+    pub fn hint(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidToken => "the token was unregistered or belongs to another Firebase project, get a fresh token from the app with getToken() and check it was issued for this project",
+            ErrorCode::QuotaExceeded => "the project or the device is sending too fast, wait before sending again and spread sends out over time",
+            ErrorCode::ServerError => "FCM is temporarily unavailable, send again later",
+            ErrorCode::PayloadTooBig => "the message is over the 4096 byte limit of FCM, shorten the payload or enable compression",
+            ErrorCode::InvalidArgument => "FCM refused a field of the message, check the response for the field at fault",
+            ErrorCode::AuthError => "the service account was rejected, check it has the Firebase Cloud Messaging API enabled and the cloudmessaging.messages.create permission",
+            ErrorCode::Timeout => "FCM didn't answer in time, check the network of the server and send again",
+            ErrorCode::Unknown => "FCM answered with an unexpected error, check the response for details",
+        }
+    }
+
+    /// Whether sending the same message again later can succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorCode::QuotaExceeded | ErrorCode::ServerError)
+    }
+}
+
+/// Classify an unsuccessful FCM response from its status and error body
+pub fn classify(status: StatusCode, body: &str) -> ErrorCode {
+    let error = serde_json::from_str::<ErrorResponse>(body)
+        .ok()
+        .map(|r| r.error);
+
+    let fcm_code = error
+        .as_ref()
+        .and_then(|e| e.details.iter().find_map(|d| d.error_code.clone()));
+    let message = error
+        .as_ref()
+        .map(|e| e.message.to_lowercase())
+        .unwrap_or_default();
+
+    match fcm_code
+        .as_deref()
+        .or(error.as_ref().map(|e| e.status.as_str()))
+    {
+        Some("UNREGISTERED") | Some("SENDER_ID_MISMATCH") => ErrorCode::InvalidToken,
+        Some("QUOTA_EXCEEDED") | Some("RESOURCE_EXHAUSTED") => ErrorCode::QuotaExceeded,
+        Some("UNAVAILABLE") | Some("INTERNAL") => ErrorCode::ServerError,
+        Some("THIRD_PARTY_AUTH_ERROR") | Some("UNAUTHENTICATED") | Some("PERMISSION_DENIED") => {
+            ErrorCode::AuthError
+        }
+        Some("INVALID_ARGUMENT") if message.contains("too big") => ErrorCode::PayloadTooBig,
+        Some("INVALID_ARGUMENT") if message.contains("registration token") => {
+            ErrorCode::InvalidToken
+        }
+        Some("INVALID_ARGUMENT") => ErrorCode::InvalidArgument,
+        _ => match status {
+            StatusCode::NOT_FOUND => ErrorCode::InvalidToken,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::QuotaExceeded,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooBig,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorCode::AuthError,
+            StatusCode::BAD_REQUEST => ErrorCode::InvalidArgument,
+            s if s.is_server_error() => ErrorCode::ServerError,
+            _ => ErrorCode::Unknown,
+        },
+    }
+}
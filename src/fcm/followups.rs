@@ -0,0 +1,283 @@
+use super::model::{
+    ExecutionEvent, FCMSchedule, FollowUp, FollowUpAction, FollowUpOutcome, NewFollowUp,
+    ScheduleType, UpdateSchedule,
+};
+use super::store;
+use crate::http;
+use chrono::{Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use sqlx::SqlitePool;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
+use tracing::{error, info, warn};
+
+lazy_static! {
+    // longest a webhook follow-up may take, it holds up the delivery lane it runs on
+    static ref WEBHOOK_TIMEOUT_SECS: u64 = env::var("FCM_FOLLOWUP_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+}
+
+/// Longest a schedule can be sent again after, a day
+pub const MAX_DELAY_MINUTES: i64 = 1440;
+
+// Follow-ups chain schedules together: a schedule can trigger other schedules,
+// call a webhook or send itself again later once a delivery succeeds or fails.
+// Triggers form a DAG, a schedule never ends up triggering itself again.
+
+/// Check every follow-up has what its action needs
+pub fn validate(schedule_id: i64, followups: &[NewFollowUp]) -> Result<(), String> {
+    for followup in followups {
+        match followup.action {
+            FollowUpAction::Trigger => match followup.target_schedule_id {
+                Some(target) if target == schedule_id => {
+                    return Err("a schedule can't trigger itself".to_string());
+                }
+                Some(_) => {}
+                None => return Err("trigger follow-ups need a target_schedule_id".to_string()),
+            },
+            FollowUpAction::Webhook => {
+                let url = match followup.url.as_deref() {
+                    Some(url) => url,
+                    None => return Err("webhook follow-ups need a url".to_string()),
+                };
+                if let Err(e) = http::validate_public_url(url) {
+                    return Err(format!("Invalid follow-up url: {}", e));
+                }
+            }
+            FollowUpAction::Remind => match followup.delay_minutes {
+                Some(minutes) if (1..=MAX_DELAY_MINUTES).contains(&minutes) => {}
+                Some(_) => {
+                    return Err(format!(
+                        "delay_minutes must be between 1 and {}",
+                        MAX_DELAY_MINUTES
+                    ));
+                }
+                None => return Err("remind follow-ups need delay_minutes".to_string()),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether triggering `targets` from the schedule would lead back to it
+pub async fn creates_cycle(
+    pool: &SqlitePool,
+    schedule_id: i64,
+    targets: &[i64],
+) -> Result<bool, sqlx::Error> {
+    // the schedule's current triggers are about to be replaced
+    let edges = sqlx::query!(
+        r#"SELECT schedule_id, target_schedule_id as "target_schedule_id!"
+        FROM fcm_schedule_followup
+        WHERE action = 'trigger' AND target_schedule_id IS NOT NULL AND schedule_id != ?"#,
+        schedule_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut graph: HashMap<i64, Vec<i64>> = HashMap::new();
+    for edge in edges {
+        graph
+            .entry(edge.schedule_id)
+            .or_default()
+            .push(edge.target_schedule_id);
+    }
+
+    let mut pending = targets.to_vec();
+    let mut seen = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if id == schedule_id {
+            return Ok(true);
+        }
+        if seen.insert(id) {
+            if let Some(next) = graph.get(&id) {
+                pending.extend(next);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Run the follow-ups of a schedule for the final outcome of its delivery to `target`,
+/// deliveries to the additional devices of the schedule don't run them again
+pub async fn run(pool: &SqlitePool, target: &str, event: &ExecutionEvent<'_>) {
+    let schedule = sqlx::query_as!(
+        FCMSchedule,
+        "SELECT * FROM fcm_schedule WHERE id = ?",
+        event.schedule_id
+    )
+    .fetch_optional(pool)
+    .await;
+
+    let schedule = match schedule {
+        Ok(Some(schedule)) if schedule.push_token == target => schedule,
+        Ok(_) => return,
+        Err(e) => {
+            error!(schedule_id = event.schedule_id, error = ?e, "Error loading schedule for follow-ups");
+            return;
+        }
+    };
+
+    let outcome = match event.status {
+        "success" => FollowUpOutcome::Success,
+        _ => FollowUpOutcome::Failure,
+    };
+
+    let followups = sqlx::query_as!(
+        FollowUp,
+        r#"SELECT id as "id!", schedule_id, outcome, action, target_schedule_id, url, delay_minutes, created_at
+        FROM fcm_schedule_followup WHERE schedule_id = ? AND outcome = ? ORDER BY id"#,
+        schedule.id,
+        outcome
+    )
+    .fetch_all(pool)
+    .await;
+
+    let followups = match followups {
+        Ok(followups) => followups,
+        Err(e) => {
+            error!(schedule_id = schedule.id, error = ?e, "Error loading follow-ups");
+            return;
+        }
+    };
+
+    for followup in followups {
+        let result = match followup.action {
+            FollowUpAction::Trigger => trigger(pool, &followup).await,
+            FollowUpAction::Webhook => {
+                call_webhook(&followup, event).await;
+                Ok(())
+            }
+            FollowUpAction::Remind => remind(pool, &schedule, &followup).await,
+        };
+
+        if let Err(e) = result {
+            error!(schedule_id = schedule.id, followup_id = followup.id, error = ?e, "Error running follow-up");
+        }
+    }
+}
+
+/// Make the target schedule due so the next scheduler run sends it, disabled
+/// schedules (including completed one-shot schedules) are left alone
+async fn trigger(pool: &SqlitePool, followup: &FollowUp) -> Result<(), sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+    let result = sqlx::query!(
+        "UPDATE fcm_schedule SET next_execution = ?, version = version + 1, updated_at = ? WHERE id = ? AND disabled_reason IS NULL",
+        current_time,
+        current_time,
+        followup.target_schedule_id
+    )
+    .execute(pool)
+    .await?;
+
+    match result.rows_affected() {
+        0 => {
+            info!(followup_id = followup.id, target_schedule_id = ?followup.target_schedule_id, "Follow-up target is disabled, not triggering it")
+        }
+        _ => {
+            info!(followup_id = followup.id, target_schedule_id = ?followup.target_schedule_id, "Triggered follow-up schedule")
+        }
+    }
+    Ok(())
+}
+
+async fn call_webhook(followup: &FollowUp, event: &ExecutionEvent<'_>) {
+    let url = followup.url.as_deref().unwrap_or_default();
+    if event.dry_run {
+        info!(followup_id = followup.id, url = %url, "Dry run, skipping follow-up webhook");
+        return;
+    }
+
+    // the url's host may have been pointed to an internal address since it was saved
+    if let Err(e) = http::check_public_url(url).await {
+        warn!(followup_id = followup.id, url = %url, error = %e, "Refusing to call follow-up webhook");
+        return;
+    }
+
+    let request = http::PUBLIC_CLIENT
+        .post(url)
+        .json(event)
+        .timeout(std::time::Duration::from_secs(*WEBHOOK_TIMEOUT_SECS));
+
+    match http::send(request)
+        .await
+        .and_then(|response| response.error_for_status())
+    {
+        Ok(_) => info!(followup_id = followup.id, url = %url, "Called follow-up webhook"),
+        Err(e) => {
+            warn!(followup_id = followup.id, url = %url, error = ?e, "Error calling follow-up webhook")
+        }
+    }
+}
+
+/// When a schedule sent again `minutes` from now is due, `None` when the delay is out of range
+pub fn delay_until(minutes: i64) -> Option<NaiveDateTime> {
+    if !(1..=MAX_DELAY_MINUTES).contains(&minutes) {
+        return None;
+    }
+    Utc::now()
+        .naive_utc()
+        .checked_add_signed(Duration::try_minutes(minutes)?)
+}
+
+/// Send a one-shot copy of the schedule at `next_execution` and return its id, the copy
+/// has no follow-ups of its own so reminders don't repeat. Deleting the copy before
+/// it's due cancels it
+pub async fn send_again(
+    pool: &SqlitePool,
+    schedule: &FCMSchedule,
+    next_execution: NaiveDateTime,
+) -> Result<i64, sqlx::Error> {
+    let timezone = schedule.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+
+    let mut reminder = UpdateSchedule::from(schedule);
+    reminder.schedule_type = ScheduleType::Once;
+    reminder.run_at = Some(timezone.from_utc_datetime(&next_execution).naive_local());
+    reminder.expires_at = None;
+    reminder.max_executions = None;
+
+    store::insert_schedule(
+        pool,
+        &schedule.fb_user_id,
+        &schedule.fb_project_id,
+        schedule.organization_id,
+        &reminder,
+        next_execution,
+        None,
+    )
+    .await
+}
+
+async fn remind(
+    pool: &SqlitePool,
+    schedule: &FCMSchedule,
+    followup: &FollowUp,
+) -> Result<(), sqlx::Error> {
+    let minutes = followup.delay_minutes.unwrap_or(1);
+    let next_execution = match delay_until(minutes) {
+        Some(next_execution) => next_execution,
+        None => {
+            warn!(
+                followup_id = followup.id,
+                minutes, "Follow-up delay is out of range, not reminding"
+            );
+            return Ok(());
+        }
+    };
+    let id = send_again(pool, schedule, next_execution).await?;
+
+    info!(
+        schedule_id = schedule.id,
+        reminder_id = id,
+        minutes,
+        "Scheduled follow-up reminder"
+    );
+    Ok(())
+}
@@ -1,15 +1,85 @@
-use super::model::{FCMSchedule, UpdateSchedule};
-use super::utils::{decode_cron, extract_claims};
-use crate::utils::{ApiTags, JsonError, JsonSuccess, ResponseObject};
-use chrono::Utc;
+use super::accounts::ServiceAccounts;
+use super::backup;
+use super::batches;
+use super::callbacks;
+use super::conditions;
+use super::errors::classify;
+use super::followups;
+use super::holidays;
+use super::links;
+use super::media;
+use super::model::{
+    Account, BulkCreateItem, BulkCreateResult, BulkDelete, BulkDeleteItem, BulkDeleteResult,
+    Compression, ConflictStrategy, CronOccurrence, CronPreview, CronPreviewRequest, Device,
+    Execution, ExecutionBatch, ExecutionPage, FCMSchedule, FollowUp, FollowUpAction, FollowUps,
+    Heatmap, HeatmapDay, Holiday, ImportResult, ImportRowResult, MergeAccount, MergeResult,
+    NewScheduleWebhook, OrganizationRole, ProjectSettings, RegisterDevice, RunResult,
+    ScheduleEvent, ScheduleExport, ScheduleImportResult, ScheduleOrder, ScheduleStatus,
+    ScheduleToken, ScheduleTokens, ScheduleWebhook, SnoozeSchedule, TagBulkResult, Tags,
+    TargetType, TemplatePreview, TemplatePreviewRequest, TestSend, TestSendResult, TokenHealth,
+    TokenHeartbeat, TokenHeartbeatResult, TokenReplacement, TokenReplacementResult, TokenValidity,
+    TriggerResult, UpdateProjectSettings, UpdateSchedule, WebhookDelivery, EXPORT_VERSION,
+};
+use super::organizations;
+use super::payload;
+use super::policy::{self, Feature};
+use super::sender::{build_content, build_message, send_message, send_raw, SendError};
+use super::sharing::{self, Access};
+use super::store;
+use super::template::{self, Context};
+use super::tokens;
+use super::utils::{
+    authenticate, cron_occurrences, decode_cron, etag, extract_claims, next_execution,
+    parse_if_match, resume_execution, validate_interval, validate_schedule, validate_tags,
+    validate_target,
+};
+use super::worker;
+use crate::browser;
+use crate::events;
+use crate::http;
+use crate::outbox::{self, Priority};
+use crate::quota;
+use crate::utils::{
+    select_fields, ApiTags, JsonError, JsonSuccess, JsonTagged, PageMeta, ResponseObject,
+    SortDirection, DRY_RUN, SEND_TIMEOUT_SECS,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use poem::{web::Data, Request};
-use poem_openapi::param::Path;
-use poem_openapi::{payload::Json, OpenApi};
+use poem_openapi::param::{Path, Query};
+use poem_openapi::{
+    payload::{Json, PlainText},
+    ApiRequest, ApiResponse, OpenApi,
+};
 use serde_json::Value;
 use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
 
 pub struct FirebaseMessaging {
-    pub projects: Vec<String>,
+    pub projects: ServiceAccounts,
+}
+
+#[derive(ApiResponse)]
+enum ExportResponse {
+    /// Schedules of the user, import the document as is to restore them
+    #[oai(status = 200)]
+    Ok(Json<ScheduleExport>),
+}
+
+#[derive(ApiResponse)]
+enum YamlExportResponse {
+    /// Schedules of the user rendered as YAML
+    #[oai(status = 200, content_type = "application/yaml")]
+    Ok(PlainText<String>),
+}
+
+#[derive(ApiRequest)]
+enum ImportPayload {
+    /// Export made by `GET /fcm/export`
+    Json(Json<ScheduleExport>),
+    /// Export made by `GET /fcm/export.yaml`
+    #[oai(content_type = "application/yaml")]
+    Yaml(PlainText<String>),
 }
 
 #[OpenApi(
@@ -23,33 +93,95 @@ pub struct FirebaseMessaging {
 )]
 impl FirebaseMessaging {
     // create new instance
-    pub fn new(projects: Vec<String>) -> Self {
+    pub fn new(projects: ServiceAccounts) -> Self {
         Self { projects }
     }
 
-    // create schedule
+    // create schedule, with `dry_run` the schedule is validated and returned as it would be
+    // created, with its `next_execution`, without saving it
     #[oai(path = "/", method = "post", operation_id = "fcm::create_schedule")]
     async fn create_schedule(
         &self,
         req: &Request,
         pool: Data<&SqlitePool>,
         payload: Json<FCMSchedule>,
+        /// only validate the schedule
+        #[oai(default)]
+        dry_run: Query<bool>,
     ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
         // extract user id from token
-        let data = match extract_claims(req.header("firebase-auth")) {
+        let data = match authenticate(req, pool.0).await {
             Ok(data) => data,
             Err(e) => {
                 return Err(ResponseObject::unauthorized(e));
             }
         };
 
-        let fb_user_id = data.user_id;
-        let fb_project_id = data.aud;
+        if let Err(e) = policy::authorize(&data, Feature::CreateSchedule) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        if payload.priority == Priority::High {
+            if let Err(e) = policy::authorize(&data, Feature::HighPriority) {
+                return Err(ResponseObject::forbidden(e));
+            }
+        }
+
+        let fb_user_id = data.user_id.clone();
+        let fb_project_id = data.aud.clone();
 
         if !self.projects.contains(&fb_project_id) {
             return Err(ResponseObject::unauthorized("Invalid project id"));
         }
 
+        // schedules of an organization are created by its members, in the organization's project
+        if let Some(organization_id) = payload.organization_id {
+            organizations::authorize(
+                pool.0,
+                organization_id,
+                &fb_user_id,
+                OrganizationRole::Member,
+            )
+            .await?;
+
+            let organization_project = sqlx::query_scalar!(
+                "SELECT fb_project_id FROM fcm_organization WHERE id = ?",
+                organization_id
+            )
+            .fetch_one(pool.0)
+            .await;
+
+            match organization_project {
+                Ok(project_id) if project_id == fb_project_id => {}
+                Ok(_) => {
+                    return Err(ResponseObject::bad_request(
+                        "Organization belongs to a different project",
+                    ));
+                }
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        let schedule_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule WHERE fb_user_id = ?"#,
+            fb_user_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let schedule_count = match schedule_count {
+            Ok(count) => count,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize_anonymous(&data, schedule_count) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
         // validate payload
         match payload.payload {
             Value::Object(_) => {}
@@ -58,34 +190,89 @@ impl FirebaseMessaging {
             }
         }
 
-        let next_execution = match decode_cron(&payload.cron_pattern.as_ref()) {
+        if let Err(e) = validate_tags(&payload.tags) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        if let Err(e) = validate_target(payload.target_type, &payload.push_token) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        if payload.target_type == TargetType::Token {
+            if let Err(e) =
+                tokens::verify(&self.projects, &fb_project_id, &payload.push_token).await
+            {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        if let Err(e) = conditions::validate(&payload.conditions) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        if let Err(e) = template::validate(&payload.payload) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = payload::validate_message(&payload.message) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = template::validate_message(&payload.message) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = holidays::validate(payload.holiday_calendar.as_deref()) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let project = match find_project(pool.0, &data.aud).await {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if let Err(e) = links::validate_links(&payload.payload, project.as_ref()) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = media::validate_media(&payload.payload).await {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let schedule = UpdateSchedule::from(&payload.0);
+        if let Err(e) = payload::validate_size(&schedule) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let next_execution = match next_execution(&schedule) {
             Ok(next) => next,
             Err(e) => {
                 return Err(ResponseObject::bad_request(e));
             }
         };
 
-        let current_time = Utc::now().naive_local();
+        let disabled_reason = policy::approval_hold(&data, project.as_ref());
 
-        let result = sqlx::query!(
-            "INSERT INTO fcm_schedule (
-                name, fb_user_id, push_token, fb_project_id, cron_pattern, payload, last_execution, next_execution, created_at, updated_at
-            ) 
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            payload.name,
-            fb_user_id,
-            payload.push_token,
-            fb_project_id,
-            payload.cron_pattern,
-            payload.payload,
-            current_time,
+        // a dry run saves the schedule in a transaction it rolls back, so it's returned
+        // exactly as it would be created
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let result = store::insert_schedule(
+            &mut *tx,
+            &fb_user_id,
+            &fb_project_id,
+            payload.organization_id,
+            &schedule,
             next_execution,
-            current_time,
-            current_time
-        ).execute(pool.0).await;
+            disabled_reason,
+        )
+        .await;
 
         let result = match result {
-            Ok(result) => result.last_insert_rowid(),
+            Ok(id) => id,
             Err(e) => {
                 return Err(ResponseObject::internal_server_error(e));
             }
@@ -96,7 +283,7 @@ impl FirebaseMessaging {
             "SELECT * FROM fcm_schedule WHERE id = ?",
             result
         )
-        .fetch_one(pool.0)
+        .fetch_one(&mut *tx)
         .await;
 
         let schedule = match schedule {
@@ -106,18 +293,63 @@ impl FirebaseMessaging {
             }
         };
 
-        Ok(ResponseObject::created(schedule))
+        let mut warnings =
+            payload::analyze_schedule(&schedule.payload, &schedule.message, schedule.compression);
+        let quota = policy::schedule_quota(&data, schedule_count + 1);
+        warnings.extend(quota.message());
+
+        if dry_run.0 {
+            if let Err(e) = tx.rollback().await {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+            return Ok(ResponseObject::ok_with_warnings(schedule, warnings));
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        events::publish("schedule.created", ScheduleEvent::from(&schedule));
+        quota::warn_once(pool.0, &fb_user_id, &quota).await;
+
+        Ok(ResponseObject::created_with_warnings(schedule, warnings))
     }
 
-    // find all schedules for the user
+    // find all schedules for the user, items are FCMSchedule objects limited to the requested fields.
+    // The total number of matching schedules is returned in `meta`, pass `limit` and `offset` to page through them
     #[oai(path = "/", method = "get", operation_id = "fcm::find_all_schedules")]
+    #[allow(clippy::too_many_arguments)]
     async fn find_all_schedules(
         &self,
         req: &Request,
         pool: Data<&SqlitePool>,
-    ) -> Result<JsonSuccess<Vec<FCMSchedule>>, JsonError<String>> {
+        /// comma separated fields to return, e.g. `id,name,next_execution` (defaults to every field)
+        fields: Query<Option<String>>,
+        /// number of schedules per page (defaults to every schedule)
+        #[oai(validator(minimum(value = "1"), maximum(value = "200")))]
+        limit: Query<Option<i64>>,
+        /// number of schedules to skip
+        #[oai(default, validator(minimum(value = "0")))]
+        offset: Query<i64>,
+        /// field to sort the schedules by
+        #[oai(default)]
+        order_by: Query<ScheduleOrder>,
+        /// sort direction
+        #[oai(default)]
+        order: Query<SortDirection>,
+        /// only return schedules whose name contains the text, ignoring case
+        name_contains: Query<Option<String>>,
+        /// only return enabled (true) or disabled (false) schedules
+        enabled: Query<Option<bool>>,
+        /// only return schedules in the state, `failed` lists schedules disabled because their deliveries kept failing
+        status: Query<Option<ScheduleStatus>>,
+        /// `me` (default) for the schedules of the user outside organizations, `org:<id>` for the schedules of an organization of the user
+        owner: Query<Option<String>>,
+        /// only return schedules carrying the tag
+        tag: Query<Option<String>>,
+    ) -> Result<JsonSuccess<Vec<Value>>, JsonError<String>> {
         // extract user id from token
-        let data = match extract_claims(req.header("firebase-auth")) {
+        let data = match authenticate(req, pool.0).await {
             Ok(data) => data,
             Err(e) => {
                 return Err(ResponseObject::unauthorized(e));
@@ -126,10 +358,93 @@ impl FirebaseMessaging {
 
         let fb_user_id = data.user_id;
 
+        let organization_id = parse_owner(owner.0.as_deref())?;
+        if let Some(organization_id) = organization_id {
+            organizations::authorize(
+                pool.0,
+                organization_id,
+                &fb_user_id,
+                OrganizationRole::Viewer,
+            )
+            .await?;
+        }
+        let name_contains = name_contains.0.filter(|name| !name.is_empty());
+        let status = status.0.map(|status| status.as_str());
+        let tag = match &tag.0 {
+            Some(tag) => Some(parse_tag(tag)?),
+            None => None,
+        };
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule
+            WHERE CASE WHEN ?5 IS NULL THEN fb_user_id = ?1 AND organization_id IS NULL ELSE organization_id = ?5 END
+            AND (?2 IS NULL OR instr(lower(name), lower(?2)) > 0)
+            AND (?3 IS NULL OR (disabled_reason IS NULL) = ?3)
+            AND (?4 IS NULL OR (?4 = 'active' AND disabled_reason IS NULL)
+                OR (?4 = 'disabled' AND disabled_reason IS NOT NULL)
+                OR (?4 = 'failed' AND disabled_reason IN ('failed', 'invalid_token', 'invalid_cron'))
+                OR (?4 = 'blocked' AND disabled_reason = 'blocked')
+                OR (?4 = 'paused' AND disabled_reason = 'paused'))
+            AND (?6 IS NULL OR id IN (SELECT schedule_id FROM fcm_schedule_tag WHERE tag = ?6))"#,
+            fb_user_id,
+            name_contains,
+            enabled.0,
+            status,
+            organization_id,
+            tag
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let total = match total {
+            Ok(total) => total,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // sqlite can't bind column names, so the sort key is picked with a CASE and
+        // a negative limit returns every row
+        let order_by = order_by.0.as_str();
+        let descending = order.0 == SortDirection::Desc;
+        let page_limit = limit.0.unwrap_or(-1);
         let schedules = sqlx::query_as!(
             FCMSchedule,
-            "SELECT * FROM fcm_schedule WHERE fb_user_id = ?",
-            fb_user_id
+            r#"SELECT * FROM fcm_schedule
+            WHERE CASE WHEN ?9 IS NULL THEN fb_user_id = ?1 AND organization_id IS NULL ELSE organization_id = ?9 END
+            AND (?2 IS NULL OR instr(lower(name), lower(?2)) > 0)
+            AND (?3 IS NULL OR (disabled_reason IS NULL) = ?3)
+            AND (?8 IS NULL OR (?8 = 'active' AND disabled_reason IS NULL)
+                OR (?8 = 'disabled' AND disabled_reason IS NOT NULL)
+                OR (?8 = 'failed' AND disabled_reason IN ('failed', 'invalid_token', 'invalid_cron'))
+                OR (?8 = 'blocked' AND disabled_reason = 'blocked')
+                OR (?8 = 'paused' AND disabled_reason = 'paused'))
+            AND (?10 IS NULL OR id IN (SELECT schedule_id FROM fcm_schedule_tag WHERE tag = ?10))
+            ORDER BY
+                CASE WHEN ?4 THEN NULL ELSE CASE ?5
+                    WHEN 'name' THEN lower(name)
+                    WHEN 'next_execution' THEN next_execution
+                    WHEN 'created_at' THEN created_at
+                    WHEN 'updated_at' THEN updated_at
+                    ELSE id END END ASC,
+                CASE WHEN ?4 THEN CASE ?5
+                    WHEN 'name' THEN lower(name)
+                    WHEN 'next_execution' THEN next_execution
+                    WHEN 'created_at' THEN created_at
+                    WHEN 'updated_at' THEN updated_at
+                    ELSE id END END DESC,
+                id ASC
+            LIMIT ?6 OFFSET ?7"#,
+            fb_user_id,
+            name_contains,
+            enabled.0,
+            descending,
+            order_by,
+            page_limit,
+            offset.0,
+            status,
+            organization_id,
+            tag
         )
         .fetch_all(pool.0)
         .await;
@@ -141,7 +456,271 @@ impl FirebaseMessaging {
             }
         };
 
-        Ok(ResponseObject::ok(schedules))
+        let meta = PageMeta {
+            total,
+            limit: limit.0,
+            offset: offset.0,
+        };
+
+        match select_fields(schedules, fields.0.as_deref()) {
+            Ok(schedules) => Ok(ResponseObject::ok_with_meta(schedules, meta)),
+            Err(e) => Err(ResponseObject::bad_request(e)),
+        }
+    }
+
+    // Full text search over the names and payloads of the schedules, best matches first.
+    // Every word of the query has to match, words match as prefixes
+    #[oai(
+        path = "/search",
+        method = "get",
+        operation_id = "fcm::search_schedules"
+    )]
+    async fn search_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// words to look for
+        #[oai(validator(min_length = 1, max_length = 200))]
+        q: Query<String>,
+        /// number of schedules to return
+        #[oai(
+            default = "default_search_limit",
+            validator(minimum(value = "1"), maximum(value = "100"))
+        )]
+        limit: Query<i64>,
+        /// `me` (default) for the schedules of the user outside organizations, `org:<id>` for the schedules of an organization of the user
+        owner: Query<Option<String>>,
+    ) -> Result<JsonSuccess<Vec<FCMSchedule>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let organization_id = parse_owner(owner.0.as_deref())?;
+        if let Some(organization_id) = organization_id {
+            organizations::authorize(
+                pool.0,
+                organization_id,
+                &data.user_id,
+                OrganizationRole::Viewer,
+            )
+            .await?;
+        }
+
+        let query = match search_query(&q.0) {
+            Some(query) => query,
+            None => return Err(ResponseObject::bad_request("q must contain a word")),
+        };
+
+        let schedules = sqlx::query_as!(
+            FCMSchedule,
+            r#"SELECT fcm_schedule.* FROM fcm_schedule_search
+            JOIN fcm_schedule ON fcm_schedule.id = fcm_schedule_search.rowid
+            WHERE fcm_schedule_search MATCH ?1
+            AND CASE WHEN ?3 IS NULL THEN fb_user_id = ?2 AND organization_id IS NULL ELSE organization_id = ?3 END
+            ORDER BY fcm_schedule_search.rank
+            LIMIT ?4"#,
+            query,
+            data.user_id,
+            organization_id,
+            limit.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match schedules {
+            Ok(schedules) => Ok(ResponseObject::ok(schedules)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Preview the next times a cron pattern matches, using the same evaluation as schedules
+    #[oai(
+        path = "/validate-cron",
+        method = "post",
+        operation_id = "fcm::validate_cron"
+    )]
+    async fn validate_cron(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        preview: Json<CronPreviewRequest>,
+    ) -> Result<JsonSuccess<CronPreview>, JsonError<String>> {
+        // extract user id from token
+        if let Err(e) = authenticate(req, pool.0).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let preview = preview.0;
+        if let Err(e) = validate_interval(&preview.cron_pattern, &preview.timezone) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let occurrences = match cron_occurrences(
+            &preview.cron_pattern,
+            &preview.timezone,
+            preview.count as usize,
+        ) {
+            Ok(occurrences) => occurrences,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        Ok(ResponseObject::ok(CronPreview {
+            cron_pattern: preview.cron_pattern,
+            timezone: preview.timezone,
+            occurrences: occurrences
+                .into_iter()
+                .map(|(utc, local)| CronOccurrence { utc, local })
+                .collect(),
+        }))
+    }
+
+    // Render a payload's placeholders the way the scheduler would when sending it
+    #[oai(
+        path = "/template-preview",
+        method = "post",
+        operation_id = "fcm::template_preview"
+    )]
+    async fn template_preview(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        preview: Json<TemplatePreviewRequest>,
+    ) -> Result<JsonSuccess<TemplatePreview>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let preview = preview.0;
+        let timezone = match preview.timezone.parse::<Tz>() {
+            Ok(timezone) => timezone,
+            Err(_) => {
+                return Err(ResponseObject::bad_request("Invalid timezone"));
+            }
+        };
+
+        let next_run = match &preview.cron_pattern {
+            Some(cron_pattern) => match decode_cron(cron_pattern, &preview.timezone) {
+                Ok(next) => Some(timezone.from_utc_datetime(&next)),
+                Err(e) => {
+                    return Err(ResponseObject::bad_request(e));
+                }
+            },
+            None => None,
+        };
+
+        let context = Context {
+            now: Utc::now().with_timezone(&timezone),
+            next_run,
+            user_id: data.user_id,
+            name: preview.name.unwrap_or_default(),
+            schedule_id: 0,
+            execution_key: String::new(),
+        };
+
+        match template::render_payload(&preview.payload, &context) {
+            Ok(payload) => Ok(ResponseObject::ok(TemplatePreview { payload })),
+            Err(e) => Err(ResponseObject::bad_request(e)),
+        }
+    }
+
+    // List the dates a holiday calendar skips in a year
+    #[oai(
+        path = "/holidays",
+        method = "get",
+        operation_id = "fcm::list_holidays"
+    )]
+    async fn list_holidays(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// built-in calendar (de, gb or us) or URL of an ICS file one of the user's schedules uses
+        calendar: Query<String>,
+        /// year to list, defaults to the current year
+        #[oai(validator(minimum(value = "1970"), maximum(value = "2100")))]
+        year: Query<Option<i32>>,
+    ) -> Result<JsonSuccess<Vec<Holiday>>, JsonError<String>> {
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        // only calendars that were checked when a schedule was saved are fetched
+        if !holidays::is_builtin(&calendar.0) {
+            let saved = sqlx::query_scalar!(
+                r#"SELECT EXISTS (SELECT 1 FROM fcm_schedule WHERE fb_user_id = ? AND holiday_calendar = ?) as "saved!: bool""#,
+                data.user_id,
+                calendar.0
+            )
+            .fetch_one(pool.0)
+            .await;
+
+            match saved {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(ResponseObject::not_found(
+                        "None of your schedules uses this holiday calendar",
+                    ));
+                }
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        let year = year.0.unwrap_or_else(|| Utc::now().year());
+        match holidays::list(&calendar.0, year).await {
+            Ok(days) => Ok(ResponseObject::ok(
+                days.into_iter()
+                    .map(|(date, name)| Holiday { date, name })
+                    .collect(),
+            )),
+            Err(e) => Err(ResponseObject::bad_request(e)),
+        }
+    }
+
+    // Get a schedule by id, its version is returned as the ETag
+    #[oai(path = "/:id", method = "get", operation_id = "fcm::get_schedule")]
+    async fn get_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonTagged<FCMSchedule>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::View).await?;
+
+        let schedule =
+            sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id.0)
+                .fetch_optional(pool.0)
+                .await;
+
+        match schedule {
+            Ok(Some(schedule)) => {
+                let etag = etag(schedule.version);
+                Ok(ResponseObject::ok_with_etag(schedule, etag, vec![]))
+            }
+            Ok(None) => Err(ResponseObject::not_found("Schedule not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
     }
 
     // Delete schedule by id (only if it belongs to the user)
@@ -157,7 +736,7 @@ impl FirebaseMessaging {
         id: Path<i64>,
     ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
         // extract user id from token
-        let data = match extract_claims(req.header("firebase-auth")) {
+        let data = match authenticate(req, pool.0).await {
             Ok(data) => data,
             Err(e) => {
                 return Err(ResponseObject::unauthorized(e));
@@ -166,6 +745,9 @@ impl FirebaseMessaging {
 
         let fb_user_id = data.user_id;
 
+        // users the schedule is shared with can't delete it
+        sharing::authorize(pool.0, id.0, &fb_user_id, Access::Own).await?;
+
         let schedule = sqlx::query_as!(
             FCMSchedule,
             "SELECT * FROM fcm_schedule WHERE id = ? AND fb_user_id = ?",
@@ -201,10 +783,13 @@ impl FirebaseMessaging {
             return Err(ResponseObject::not_found("Schedule not found"));
         }
 
+        events::publish("schedule.deleted", ScheduleEvent::from(&schedule));
+
         Ok(ResponseObject::ok(schedule))
     }
 
-    // Update schedule by id (only if it belongs to the user)
+    // Update schedule by id (only if it belongs to the user), If-Match has to carry the ETag
+    // of the version being changed
     #[oai(path = "/:id", method = "put", operation_id = "fcm::update_schedule")]
     async fn update_schedule(
         &self,
@@ -212,33 +797,39 @@ impl FirebaseMessaging {
         pool: Data<&SqlitePool>,
         id: Path<i64>,
         payload: Json<UpdateSchedule>,
-    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        /// only validate the changes
+        #[oai(default)]
+        dry_run: Query<bool>,
+    ) -> Result<JsonTagged<FCMSchedule>, JsonError<String>> {
         // extract user id from token
-        let data = match extract_claims(req.header("firebase-auth")) {
+        let data = match authenticate(req, pool.0).await {
             Ok(data) => data,
             Err(e) => {
                 return Err(ResponseObject::unauthorized(e));
             }
         };
 
-        let fb_user_id = data.user_id;
-
-        let schedule = sqlx::query_as!(
-            FCMSchedule,
-            "SELECT * FROM fcm_schedule WHERE id = ? AND fb_user_id = ?",
-            id.0,
-            fb_user_id
-        )
-        .fetch_one(pool.0)
-        .await;
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
 
-        let _ = match schedule {
-            Ok(schedule) => schedule,
-            Err(_) => {
-                return Err(ResponseObject::not_found("Schedule not found"));
+        // the schedule may have been edited by someone else since it was fetched
+        let version = match req.header("If-Match").map(parse_if_match) {
+            Some(Ok(version)) => version,
+            Some(Err(e)) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+            None => {
+                return Err(ResponseObject::precondition_required(
+                    "If-Match with the ETag of the schedule is required",
+                ));
             }
         };
 
+        if payload.priority == Priority::High {
+            if let Err(e) = policy::authorize(&data, Feature::HighPriority) {
+                return Err(ResponseObject::forbidden(e));
+            }
+        }
+
         match payload.payload {
             Value::Object(_) => {}
             _ => {
@@ -246,56 +837,3172 @@ impl FirebaseMessaging {
             }
         }
 
-        let next_execution = match decode_cron(&payload.cron_pattern) {
-            Ok(next) => next,
-            Err(e) => {
-                return Err(ResponseObject::bad_request(e));
-            }
-        };
+        if let Err(e) = validate_tags(&payload.tags) {
+            return Err(ResponseObject::bad_request(e));
+        }
 
-        let current_time = Utc::now().naive_local();
+        if let Err(e) = validate_target(payload.target_type, &payload.push_token) {
+            return Err(ResponseObject::bad_request(e));
+        }
 
-        let result = sqlx::query!(
-            "UPDATE fcm_schedule SET name = ?, push_token = ?, cron_pattern = ?, payload = ?, next_execution = ?, updated_at = ? WHERE id = ? AND fb_user_id = ?",
-            payload.name,
-            payload.push_token,
-            payload.cron_pattern,
-            payload.payload,
-            next_execution,
-            current_time,
-            id.0,
-            fb_user_id
+        // tokens are only checked with FCM when they change
+        let current = sqlx::query!(
+            "SELECT fb_project_id, push_token, version FROM fcm_schedule WHERE id = ?",
+            id.0
         )
-        .execute(pool.0)
+        .fetch_one(pool.0)
         .await;
 
-        let result = match result {
-            Ok(result) => result,
+        let current = match current {
+            Ok(current) => current,
             Err(e) => {
                 return Err(ResponseObject::internal_server_error(e));
             }
         };
 
-        if result.rows_affected() == 0 {
-            return Err(ResponseObject::not_found("Schedule not found"));
+        if payload.target_type == TargetType::Token && payload.push_token != current.push_token {
+            if let Err(e) =
+                tokens::verify(&self.projects, &current.fb_project_id, &payload.push_token).await
+            {
+                return Err(ResponseObject::bad_request(e));
+            }
         }
 
-        let schedule = sqlx::query_as!(
-            FCMSchedule,
-            "SELECT * FROM fcm_schedule WHERE id = ? AND fb_user_id = ?",
-            id.0,
-            fb_user_id
-        )
-        .fetch_one(pool.0)
-        .await;
+        if let Err(e) = conditions::validate(&payload.conditions) {
+            return Err(ResponseObject::bad_request(e));
+        }
 
-        let schedule = match schedule {
-            Ok(schedule) => schedule,
+        if let Err(e) = template::validate(&payload.payload) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = payload::validate_message(&payload.message) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = template::validate_message(&payload.message) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = holidays::validate(payload.holiday_calendar.as_deref()) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let project = match find_project(pool.0, &data.aud).await {
+            Ok(project) => project,
             Err(e) => {
                 return Err(ResponseObject::internal_server_error(e));
             }
         };
 
-        Ok(ResponseObject::ok(schedule))
-    }
+        if let Err(e) = links::validate_links(&payload.payload, project.as_ref()) {
+            return Err(ResponseObject::bad_request(e));
+        }
+        if let Err(e) = media::validate_media(&payload.payload).await {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        if let Err(e) = payload::validate_size(&payload) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let next_execution = match next_execution(&payload) {
+            Ok(next) => next,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(e));
+            }
+        };
+
+        // edits need to be approved again
+        let disabled_reason = policy::approval_hold(&data, project.as_ref());
+
+        // a dry run applies the changes in a transaction it rolls back
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let updated = store::update_schedule(
+            &mut *tx,
+            id.0,
+            &payload,
+            next_execution,
+            disabled_reason,
+            version,
+        )
+        .await;
+
+        let updated = match updated {
+            Ok(updated) => updated,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if !updated {
+            return Err(ResponseObject::precondition_failed(
+                "Schedule was changed since it was fetched, fetch it again and reapply the changes",
+            ));
+        }
+
+        let schedule =
+            sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id.0)
+                .fetch_one(&mut *tx)
+                .await;
+
+        let schedule = match schedule {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let warnings =
+            payload::analyze_schedule(&schedule.payload, &schedule.message, schedule.compression);
+
+        // the ETag stays the one of the saved schedule, as it's left unchanged
+        if dry_run.0 {
+            if let Err(e) = tx.rollback().await {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+            return Ok(ResponseObject::ok_with_etag(
+                schedule,
+                etag(current.version),
+                warnings,
+            ));
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        events::publish("schedule.updated", ScheduleEvent::from(&schedule));
+        let etag = etag(schedule.version);
+
+        Ok(ResponseObject::ok_with_etag(schedule, etag, warnings))
+    }
+
+    // Account of the signed in user with the usage of its quotas, quotas at 80% or more are flagged
+    #[oai(path = "/me", method = "get", operation_id = "fcm::get_account")]
+    async fn get_account(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Account>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let schedule_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule WHERE fb_user_id = ?"#,
+            data.user_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let schedule_count = match schedule_count {
+            Ok(count) => count,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut quotas = vec![policy::schedule_quota(&data, schedule_count)];
+        match browser::quotas(pool.0, &data.user_id).await {
+            Ok(browser_quotas) => quotas.extend(browser_quotas),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        Ok(ResponseObject::ok(Account {
+            anonymous: data.is_anonymous(),
+            fb_user_id: data.user_id,
+            fb_project_id: data.aud,
+            quotas,
+        }))
+    }
+
+    // Merge the data of a previous (usually anonymous) account into the current one
+    #[oai(
+        path = "/me/merge",
+        method = "post",
+        operation_id = "fcm::merge_account"
+    )]
+    async fn merge_account(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        payload: Json<MergeAccount>,
+    ) -> Result<JsonSuccess<MergeResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let previous = match extract_claims(Some(&payload.previous_token)).await {
+            Ok(previous) => previous,
+            Err(e) => {
+                return Err(ResponseObject::bad_request(format!(
+                    "previous_token: {}",
+                    e
+                )));
+            }
+        };
+
+        if previous.aud != data.aud {
+            return Err(ResponseObject::bad_request(
+                "Accounts belong to different projects",
+            ));
+        }
+
+        if previous.user_id == data.user_id {
+            return Err(ResponseObject::bad_request(
+                "Cannot merge an account into itself",
+            ));
+        }
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let current_time = Utc::now().naive_utc();
+
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET fb_user_id = ?, version = version + 1, updated_at = ? WHERE fb_user_id = ?",
+            data.user_id,
+            current_time,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        let merged_schedules = match result {
+            Ok(result) => result.rows_affected(),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // the history and pending sends of the schedules move with them
+        let result = sqlx::query!(
+            "UPDATE fcm_execution_log SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "UPDATE outbox SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        // token hints the current account reported too are kept as they are
+        let result = sqlx::query!(
+            "UPDATE OR IGNORE fcm_token_hint SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_token_hint WHERE fb_user_id = ?",
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        // browser usage of a month both accounts used counts towards the quota once merged
+        let result = sqlx::query!(
+            "INSERT INTO browser_usage (user_id, month, tasks, browser_ms, updated_at)
+            SELECT ?, month, tasks, browser_ms, updated_at FROM browser_usage WHERE user_id = ?
+            ON CONFLICT (user_id, month) DO UPDATE SET
+                tasks = tasks + excluded.tasks,
+                browser_ms = browser_ms + excluded.browser_ms,
+                updated_at = MAX(updated_at, excluded.updated_at)",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "DELETE FROM browser_usage WHERE user_id = ?",
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        // quotas both accounts were warned about aren't warned about again
+        let result = sqlx::query!(
+            "UPDATE OR IGNORE quota_warning SET user_id = ? WHERE user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "DELETE FROM quota_warning WHERE user_id = ?",
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "UPDATE fcm_email_inbox SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        // schedules shared with the previous account stay shared with the current one
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule_share SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        // devices and token heartbeats would be deleted along with the previous account
+        let result = sqlx::query!(
+            "UPDATE fcm_device SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "UPDATE fcm_token_heartbeat SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        // memberships the current account already has keep their role
+        let result = sqlx::query!(
+            "UPDATE OR IGNORE fcm_organization_member SET fb_user_id = ? WHERE fb_user_id = ?",
+            data.user_id,
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_organization_member WHERE fb_user_id = ?",
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_user WHERE fb_user_id = ?",
+            previous.user_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        Ok(ResponseObject::ok(MergeResult {
+            previous_user_id: previous.user_id,
+            merged_schedules,
+        }))
+    }
+
+    // Get the notification defaults of the project
+    #[oai(
+        path = "/project",
+        method = "get",
+        operation_id = "fcm::get_project_settings"
+    )]
+    async fn get_project_settings(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<ProjectSettings>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let settings = sqlx::query_as!(
+            ProjectSettings,
+            "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+            data.aud
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match settings {
+            Ok(Some(settings)) => Ok(ResponseObject::ok(settings)),
+            Ok(None) => Err(ResponseObject::not_found("Project settings not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Update the notification defaults of the project (project admins only)
+    #[oai(
+        path = "/project",
+        method = "put",
+        operation_id = "fcm::update_project_settings"
+    )]
+    async fn update_project_settings(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        payload: Json<UpdateProjectSettings>,
+    ) -> Result<JsonSuccess<ProjectSettings>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if !self.projects.contains(&data.aud) {
+            return Err(ResponseObject::unauthorized("Invalid project id"));
+        }
+
+        if let Err(e) = policy::authorize(&data, Feature::ManageProject) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        if let Some(url) = &payload.alert_webhook_url {
+            if let Err(e) = http::validate_public_url(url) {
+                return Err(ResponseObject::bad_request(format!(
+                    "Invalid alert_webhook_url: {}",
+                    e
+                )));
+            }
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let allowed_link_schemes = payload.allowed_link_schemes.to_db();
+        let allowed_link_hosts = payload.allowed_link_hosts.to_db();
+
+        let result = sqlx::query!(
+            "INSERT INTO fcm_project (fb_project_id, icon, color, click_action, channel_id, allowed_link_schemes, allowed_link_hosts, require_approval, alert_webhook_url, pause_on_burst, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (fb_project_id) DO UPDATE SET
+                icon = excluded.icon,
+                color = excluded.color,
+                click_action = excluded.click_action,
+                channel_id = excluded.channel_id,
+                allowed_link_schemes = excluded.allowed_link_schemes,
+                allowed_link_hosts = excluded.allowed_link_hosts,
+                require_approval = excluded.require_approval,
+                alert_webhook_url = excluded.alert_webhook_url,
+                pause_on_burst = excluded.pause_on_burst,
+                updated_at = excluded.updated_at",
+            data.aud,
+            payload.icon,
+            payload.color,
+            payload.click_action,
+            payload.channel_id,
+            allowed_link_schemes,
+            allowed_link_hosts,
+            payload.require_approval,
+            payload.alert_webhook_url,
+            payload.pause_on_burst,
+            current_time,
+            current_time
+        )
+        .execute(pool.0)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let settings = sqlx::query_as!(
+            ProjectSettings,
+            "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+            data.aud
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match settings {
+            Ok(settings) => Ok(ResponseObject::ok(settings)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Resume sending the project's schedules after a burst paused them (project admins only),
+    // schedules that came due while paused are sent on the next scheduler run
+    #[oai(
+        path = "/project/resume",
+        method = "post",
+        operation_id = "fcm::resume_project"
+    )]
+    async fn resume_project(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<ProjectSettings>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize(&data, Feature::ManageProject) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let settings = sqlx::query_as!(
+            ProjectSettings,
+            "UPDATE fcm_project SET paused_at = NULL, updated_at = ? WHERE fb_project_id = ? RETURNING *",
+            current_time,
+            data.aud
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match settings {
+            Ok(Some(settings)) => Ok(ResponseObject::ok(settings)),
+            Ok(None) => Err(ResponseObject::not_found("Project settings not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Schedules of the project waiting for approval (project admins only)
+    #[oai(
+        path = "/approvals",
+        method = "get",
+        operation_id = "fcm::list_pending_approvals"
+    )]
+    async fn list_pending_approvals(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<FCMSchedule>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize(&data, Feature::ManageProject) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        let schedules = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE fb_project_id = ? AND disabled_reason = 'pending_approval' ORDER BY updated_at",
+            data.aud
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match schedules {
+            Ok(schedules) => Ok(ResponseObject::ok(schedules)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Approve a pending schedule of the project so the scheduler starts sending it (project admins only)
+    #[oai(
+        path = "/:id/approve",
+        method = "post",
+        operation_id = "fcm::approve_schedule"
+    )]
+    async fn approve_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        self.review_schedule(req, pool.0, id.0, true).await
+    }
+
+    // Reject a pending schedule of the project, the owner can submit it again by updating it (project admins only)
+    #[oai(
+        path = "/:id/reject",
+        method = "post",
+        operation_id = "fcm::reject_schedule"
+    )]
+    async fn reject_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        self.review_schedule(req, pool.0, id.0, false).await
+    }
+
+    // Push the next send of the schedule back once, the sends after it follow the cron pattern again
+    #[oai(
+        path = "/:id/snooze",
+        method = "post",
+        operation_id = "fcm::snooze_schedule"
+    )]
+    async fn snooze_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        body: Json<SnoozeSchedule>,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        // snoozing again pushes the already snoozed send further back
+        let current_time = Utc::now().naive_utc();
+        let minutes = format!("+{} minutes", body.minutes);
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET next_execution = datetime(next_execution, ?1),
+                snoozed_until = datetime(next_execution, ?1), version = version + 1, updated_at = ?2
+            WHERE id = ?3 AND disabled_reason IS NULL",
+            minutes,
+            current_time,
+            id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(ResponseObject::bad_request(
+                    "Disabled schedules can't be snoozed",
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let schedule =
+            sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id.0)
+                .fetch_one(pool.0)
+                .await;
+
+        let schedule = match schedule {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        events::publish("schedule.updated", ScheduleEvent::from(&schedule));
+
+        Ok(ResponseObject::ok(schedule))
+    }
+
+    // Send the schedule to its push token right away to test the payload, the next
+    // execution is left unchanged and a real send is not recorded in the execution history.
+    // A dry run, asked for or with DRY_RUN set, only renders the message and records it
+    // as a dry run execution
+    #[oai(
+        path = "/:id/trigger",
+        method = "post",
+        operation_id = "fcm::run_schedule"
+    )]
+    async fn run_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        /// render the message without sending it
+        #[oai(default)]
+        dry_run: Query<bool>,
+    ) -> Result<JsonSuccess<RunResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        let schedule =
+            sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id.0)
+                .fetch_optional(pool.0)
+                .await;
+
+        let mut schedule = match schedule {
+            Ok(Some(schedule)) => schedule,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Schedule not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // a pending or rejected schedule must not reach devices before it is approved
+        if let Some(reason) = &schedule.disabled_reason {
+            return Err(ResponseObject::bad_request(format!(
+                "Schedule is disabled: {}",
+                reason
+            )));
+        }
+
+        let dry_run = dry_run.0 || *DRY_RUN;
+        if outbox::is_paused() && !dry_run {
+            return Err(ResponseObject::forbidden("Sends are paused"));
+        }
+
+        let project = match find_project(pool.0, &schedule.fb_project_id).await {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // run-now sends aren't logged, action links have no execution to act on
+        let next_run = Some(schedule.next_execution);
+        template::render_schedule(&mut schedule, next_run, "");
+        let firebase_message = build_message(&schedule, project.as_ref());
+        let message = match serde_json::to_value(&firebase_message) {
+            Ok(message) => message,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if dry_run {
+            let current_time = Utc::now().naive_utc();
+            let result = sqlx::query!(
+                "INSERT INTO fcm_execution_log (schedule_id, fb_user_id, push_token, status, dry_run, executed_at)
+                VALUES (?, ?, ?, 'success', 1, ?)",
+                schedule.id,
+                schedule.fb_user_id,
+                schedule.push_token,
+                current_time
+            )
+            .execute(pool.0)
+            .await;
+
+            if let Err(e) = result {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+
+            return Ok(ResponseObject::ok(RunResult {
+                schedule_id: schedule.id,
+                success: true,
+                message_id: None,
+                error_code: None,
+                error: None,
+                message,
+                dry_run: true,
+                latency_ms: None,
+            }));
+        }
+
+        let timeout = schedule
+            .timeout_seconds
+            .map(|secs| secs as u64)
+            .unwrap_or(*SEND_TIMEOUT_SECS);
+        let started = Instant::now();
+        let send = send_message(&self.projects, &schedule.fb_project_id, &firebase_message);
+        let result = match tokio::time::timeout(Duration::from_secs(timeout), send).await {
+            Ok(result) => result,
+            Err(_) => Err(SendError::Timeout(timeout)),
+        };
+        let latency_ms = Some(started.elapsed().as_millis() as i64);
+
+        let result = match result {
+            Ok(name) => RunResult {
+                schedule_id: schedule.id,
+                success: true,
+                message_id: Some(name).filter(|name| !name.is_empty()),
+                error_code: None,
+                error: None,
+                message,
+                dry_run: false,
+                latency_ms,
+            },
+            Err(e) => RunResult {
+                schedule_id: schedule.id,
+                success: false,
+                message_id: None,
+                error_code: Some(e.code().as_str().to_string()),
+                error: Some(e.to_string()),
+                message,
+                dry_run: false,
+                latency_ms,
+            },
+        };
+
+        Ok(ResponseObject::ok(result))
+    }
+
+    // Send a payload to a device without a schedule and return everything FCM answered
+    // with, to debug tokens, payloads and service accounts
+    #[oai(path = "/test-send", method = "post", operation_id = "fcm::test_send")]
+    async fn test_send(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        payload: Json<TestSend>,
+    ) -> Result<JsonSuccess<TestSendResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let fb_project_id = data.aud.clone();
+
+        if !self.projects.contains(&fb_project_id) {
+            return Err(ResponseObject::unauthorized("Invalid project id"));
+        }
+
+        if let Err(e) = tokens::validate_format(&payload.push_token) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        if !payload.message.is_empty() {
+            if let Err(e) = payload::validate_message(&payload.message) {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        if outbox::is_paused() && !payload.dry_run {
+            return Err(ResponseObject::forbidden("Sends are paused"));
+        }
+
+        let project = match find_project(pool.0, &fb_project_id).await {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut firebase_message = build_content(
+            &payload.push_token,
+            &payload.payload,
+            &payload.message,
+            project.as_ref(),
+        );
+        firebase_message.set_validate_only(payload.dry_run);
+        let message = match serde_json::to_value(&firebase_message) {
+            Ok(message) => message,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut result = TestSendResult {
+            success: false,
+            dry_run: payload.dry_run,
+            status: None,
+            message_id: None,
+            response: None,
+            error_code: None,
+            retryable: false,
+            hints: payload::analyze_schedule(&payload.payload, &payload.message, Compression::None),
+            message,
+            size: firebase_message.size() as i64,
+            latency_ms: None,
+        };
+
+        // nothing leaves the server, FCM can't be asked to validate either
+        if *DRY_RUN {
+            result.success = true;
+            result.dry_run = true;
+            result.hints.push(
+                "the server runs with DRY_RUN, the message was only built and not sent to FCM"
+                    .to_string(),
+            );
+            return Ok(ResponseObject::ok(result));
+        }
+
+        let timeout = *SEND_TIMEOUT_SECS;
+        let started = Instant::now();
+        let send = send_raw(&self.projects, &fb_project_id, &firebase_message);
+        let sent = match tokio::time::timeout(Duration::from_secs(timeout), send).await {
+            Ok(sent) => sent,
+            Err(_) => Err(SendError::Timeout(timeout)),
+        };
+        result.latency_ms = Some(started.elapsed().as_millis() as i64);
+
+        let code = match sent {
+            Ok((status, body)) => {
+                let code = (!status.is_success()).then(|| classify(status, &body));
+                let response = serde_json::from_str::<Value>(&body).unwrap_or(Value::String(body));
+                result.status = Some(status.as_u16());
+                result.success = code.is_none();
+                result.message_id = response["name"]
+                    .as_str()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string);
+                result.response = Some(response);
+                code
+            }
+            Err(e) => {
+                result.response = Some(Value::String(e.to_string()));
+                Some(e.code())
+            }
+        };
+
+        if let Some(code) = code {
+            result.error_code = Some(code.as_str().to_string());
+            result.retryable = code.is_retryable();
+            result.hints.insert(0, code.hint().to_string());
+        }
+
+        Ok(ResponseObject::ok(result))
+    }
+
+    // Import schedules from a CSV file with name, cron, token and payload columns
+    #[oai(
+        path = "/import.csv",
+        method = "post",
+        operation_id = "fcm::import_csv"
+    )]
+    async fn import_csv(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// CSV file with a header row of `name,cron,token,payload` and an optional `timezone` column
+        body: PlainText<String>,
+    ) -> Result<JsonSuccess<ImportResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize(&data, Feature::CreateSchedule) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        if !self.projects.contains(&data.aud) {
+            return Err(ResponseObject::unauthorized("Invalid project id"));
+        }
+
+        let schedule_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule WHERE fb_user_id = ?"#,
+            data.user_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let mut schedule_count = match schedule_count {
+            Ok(count) => count,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(body.0.as_bytes());
+
+        let headers = match reader.headers() {
+            Ok(headers) => headers.clone(),
+            Err(e) => {
+                return Err(ResponseObject::bad_request(format!("Invalid CSV: {}", e)));
+            }
+        };
+
+        let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let (name_idx, cron_idx, token_idx, payload_idx) = match (
+            column("name"),
+            column("cron"),
+            column("token"),
+            column("payload"),
+        ) {
+            (Some(name), Some(cron), Some(token), Some(payload)) => (name, cron, token, payload),
+            _ => {
+                return Err(ResponseObject::bad_request(
+                    "CSV header must contain name, cron, token and payload columns",
+                ));
+            }
+        };
+
+        let timezone_idx = column("timezone");
+
+        let project = match find_project(pool.0, &data.aud).await {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let disabled_reason = policy::approval_hold(&data, project.as_ref());
+        let mut rows = Vec::new();
+
+        for (index, record) in reader.records().enumerate() {
+            let row = index as u64 + 1;
+
+            let result = match record {
+                Ok(record) => {
+                    let field = |idx: usize| record.get(idx).unwrap_or_default().to_string();
+                    match serde_json::from_str::<Value>(&field(payload_idx)) {
+                        Ok(payload) => Ok(UpdateSchedule {
+                            name: field(name_idx),
+                            push_token: field(token_idx),
+                            cron_pattern: field(cron_idx),
+                            timezone: timezone_idx
+                                .map(field)
+                                .filter(|timezone| !timezone.is_empty())
+                                .unwrap_or_else(|| "UTC".to_string()),
+                            payload,
+                            ..Default::default()
+                        }),
+                        Err(e) => Err(format!("Invalid payload: {}", e)),
+                    }
+                }
+                Err(e) => Err(format!("Invalid row: {}", e)),
+            };
+
+            let result = result.and_then(|schedule| {
+                policy::authorize_anonymous(&data, schedule_count)?;
+                links::validate_links(&schedule.payload, project.as_ref())?;
+                let next_execution = validate_schedule(&schedule)?;
+                Ok((schedule, next_execution))
+            });
+
+            let result = match result {
+                Ok((schedule, next_execution)) => media::validate_media(&schedule.payload)
+                    .await
+                    .map(|_| (schedule, next_execution)),
+                Err(e) => Err(e),
+            };
+
+            let result = match result {
+                Ok((schedule, next_execution)) if schedule.target_type == TargetType::Token => {
+                    tokens::verify(&self.projects, &data.aud, &schedule.push_token)
+                        .await
+                        .map(|_| (schedule, next_execution))
+                }
+                result => result,
+            };
+
+            let (schedule, next_execution) = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    rows.push(ImportRowResult {
+                        row,
+                        id: None,
+                        error: Some(e),
+                    });
+                    continue;
+                }
+            };
+
+            let id = store::insert_schedule(
+                pool.0,
+                &data.user_id,
+                &data.aud,
+                None,
+                &schedule,
+                next_execution,
+                disabled_reason,
+            )
+            .await;
+
+            match id {
+                Ok(id) => {
+                    events::publish(
+                        "schedule.created",
+                        ScheduleEvent::new(
+                            id,
+                            &data.user_id,
+                            &data.aud,
+                            &schedule,
+                            next_execution,
+                            disabled_reason,
+                        ),
+                    );
+                    schedule_count += 1;
+                    rows.push(ImportRowResult {
+                        row,
+                        id: Some(id),
+                        error: None,
+                    });
+                }
+                Err(e) => rows.push(ImportRowResult {
+                    row,
+                    id: None,
+                    error: Some(e.to_string()),
+                }),
+            }
+        }
+
+        let imported = rows.iter().filter(|r| r.id.is_some()).count() as u64;
+
+        let quota = policy::schedule_quota(&data, schedule_count);
+        quota::warn_once(pool.0, &data.user_id, &quota).await;
+
+        Ok(ResponseObject::ok_with_warnings(
+            ImportResult {
+                imported,
+                failed: rows.len() as u64 - imported,
+                rows,
+            },
+            quota.message().into_iter().collect(),
+        ))
+    }
+
+    // Back up every schedule of the user outside organizations as JSON
+    #[oai(
+        path = "/export",
+        method = "get",
+        operation_id = "fcm::export_schedules"
+    )]
+    async fn export_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<ExportResponse, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        match backup::export_schedules(pool.0, &data.user_id).await {
+            Ok(export) => Ok(ExportResponse::Ok(Json(export))),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Same as /export rendered as YAML
+    #[oai(
+        path = "/export.yaml",
+        method = "get",
+        operation_id = "fcm::export_schedules_yaml"
+    )]
+    async fn export_schedules_yaml(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<YamlExportResponse, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let export = match backup::export_schedules(pool.0, &data.user_id).await {
+            Ok(export) => export,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        match backup::to_yaml(&export) {
+            Ok(yaml) => Ok(YamlExportResponse::Ok(PlainText(yaml))),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Restore schedules from a JSON or YAML export, each schedule is imported on its own
+    #[oai(
+        path = "/import",
+        method = "post",
+        operation_id = "fcm::import_schedules"
+    )]
+    async fn import_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// what to do with a schedule when the user already has a schedule with its name
+        #[oai(default)]
+        strategy: Query<ConflictStrategy>,
+        body: ImportPayload,
+    ) -> Result<JsonSuccess<ScheduleImportResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize(&data, Feature::CreateSchedule) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        if !self.projects.contains(&data.aud) {
+            return Err(ResponseObject::unauthorized("Invalid project id"));
+        }
+
+        let export = match body {
+            ImportPayload::Json(export) => export.0,
+            ImportPayload::Yaml(yaml) => match backup::from_yaml(&yaml.0) {
+                Ok(export) => export,
+                Err(e) => {
+                    return Err(ResponseObject::bad_request(e));
+                }
+            },
+        };
+
+        if export.version != EXPORT_VERSION {
+            return Err(ResponseObject::bad_request(format!(
+                "Unsupported export version {}, expected {}",
+                export.version, EXPORT_VERSION
+            )));
+        }
+
+        let schedule_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule WHERE fb_user_id = ?"#,
+            data.user_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let schedule_count = match schedule_count {
+            Ok(count) => count,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let project = match find_project(pool.0, &data.aud).await {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let result = backup::import_schedules(
+            pool.0,
+            &self.projects,
+            &data,
+            project.as_ref(),
+            &export,
+            strategy.0,
+            schedule_count,
+        )
+        .await;
+
+        let quota = policy::schedule_quota(&data, schedule_count + result.created as i64);
+        quota::warn_once(pool.0, &data.user_id, &quota).await;
+
+        Ok(ResponseObject::ok_with_warnings(
+            result,
+            quota.message().into_iter().collect(),
+        ))
+    }
+
+    // Create several schedules at once, either every schedule is created or none is
+    #[oai(path = "/bulk", method = "post", operation_id = "fcm::bulk_create")]
+    async fn bulk_create(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        body: Json<Vec<UpdateSchedule>>,
+    ) -> Result<JsonSuccess<BulkCreateResult>, JsonError<BulkCreateResult>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize(&data, Feature::CreateSchedule) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        if !self.projects.contains(&data.aud) {
+            return Err(ResponseObject::unauthorized("Invalid project id"));
+        }
+
+        let schedules = body.0;
+        if schedules.is_empty() || schedules.len() > MAX_BULK_SCHEDULES {
+            return Err(ResponseObject::bad_request(format!(
+                "between 1 and {} schedules can be created at once",
+                MAX_BULK_SCHEDULES
+            )));
+        }
+
+        let schedule_count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule WHERE fb_user_id = ?"#,
+            data.user_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let schedule_count = match schedule_count {
+            Ok(count) => count,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let project = match find_project(pool.0, &data.aud).await {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // every schedule is checked before anything is written
+        let mut validated = Vec::with_capacity(schedules.len());
+        for (index, schedule) in schedules.iter().enumerate() {
+            let result = (|| {
+                policy::authorize_anonymous(&data, schedule_count + index as i64)?;
+                if schedule.priority == Priority::High {
+                    policy::authorize(&data, Feature::HighPriority)?;
+                }
+                links::validate_links(&schedule.payload, project.as_ref())?;
+                validate_schedule(schedule)
+            })();
+
+            let result = match result {
+                Ok(next_execution) => media::validate_media(&schedule.payload)
+                    .await
+                    .map(|_| next_execution),
+                Err(e) => Err(e),
+            };
+
+            let result = match result {
+                Ok(next_execution) if schedule.target_type == TargetType::Token => {
+                    tokens::verify(&self.projects, &data.aud, &schedule.push_token)
+                        .await
+                        .map(|_| next_execution)
+                }
+                result => result,
+            };
+            validated.push(result);
+        }
+
+        if validated.iter().any(Result::is_err) {
+            let items = validated
+                .into_iter()
+                .enumerate()
+                .map(|(index, result)| BulkCreateItem {
+                    index: index as u64,
+                    id: None,
+                    error: result.err(),
+                })
+                .collect();
+            return Err(ResponseObject::bad_request_with_data(
+                BulkCreateResult { created: 0, items },
+                "Some schedules are invalid, none were created",
+            ));
+        }
+
+        let disabled_reason = policy::approval_hold(&data, project.as_ref());
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut created = Vec::with_capacity(schedules.len());
+        for (schedule, next_execution) in schedules.iter().zip(validated.into_iter().flatten()) {
+            let id = store::insert_schedule(
+                &mut *tx,
+                &data.user_id,
+                &data.aud,
+                None,
+                schedule,
+                next_execution,
+                disabled_reason,
+            )
+            .await;
+
+            match id {
+                Ok(id) => created.push((id, next_execution)),
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let mut items = Vec::with_capacity(created.len());
+        for (index, (schedule, (id, next_execution))) in schedules.iter().zip(created).enumerate() {
+            events::publish(
+                "schedule.created",
+                ScheduleEvent::new(
+                    id,
+                    &data.user_id,
+                    &data.aud,
+                    schedule,
+                    next_execution,
+                    disabled_reason,
+                ),
+            );
+            items.push(BulkCreateItem {
+                index: index as u64,
+                id: Some(id),
+                error: None,
+            });
+        }
+
+        let quota = policy::schedule_quota(&data, schedule_count + items.len() as i64);
+        quota::warn_once(pool.0, &data.user_id, &quota).await;
+
+        Ok(ResponseObject::created_with_warnings(
+            BulkCreateResult {
+                created: items.len() as u64,
+                items,
+            },
+            quota.message().into_iter().collect(),
+        ))
+    }
+
+    // Delete several schedules of the user at once, each schedule is deleted on its own
+    #[oai(path = "/bulk", method = "delete", operation_id = "fcm::bulk_delete")]
+    async fn bulk_delete(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        body: Json<BulkDelete>,
+    ) -> Result<JsonSuccess<BulkDeleteResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let mut items = Vec::with_capacity(body.ids.len());
+        for &id in &body.ids {
+            // only the owner deletes a schedule, the same as deleting it by id
+            let schedule = sqlx::query_as!(
+                FCMSchedule,
+                "SELECT * FROM fcm_schedule WHERE id = ? AND fb_user_id = ?",
+                id,
+                data.user_id
+            )
+            .fetch_optional(pool.0)
+            .await;
+
+            let schedule = match schedule {
+                Ok(Some(schedule)) => schedule,
+                Ok(None) => {
+                    items.push(BulkDeleteItem {
+                        id,
+                        deleted: false,
+                        error: Some("Schedule not found".to_string()),
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let result = sqlx::query!(
+                "DELETE FROM fcm_schedule WHERE id = ? AND fb_user_id = ?",
+                id,
+                data.user_id
+            )
+            .execute(pool.0)
+            .await;
+
+            let deleted = match result {
+                Ok(result) => result.rows_affected() > 0,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            if deleted {
+                events::publish("schedule.deleted", ScheduleEvent::from(&schedule));
+            }
+            items.push(BulkDeleteItem {
+                id,
+                deleted,
+                error: (!deleted).then(|| "Schedule not found".to_string()),
+            });
+        }
+
+        Ok(ResponseObject::ok(BulkDeleteResult {
+            deleted: items.iter().filter(|item| item.deleted).count() as u64,
+            items,
+        }))
+    }
+
+    // Daily success/failure counts of the schedule for rendering an activity heatmap
+    #[oai(
+        path = "/:id/heatmap",
+        method = "get",
+        operation_id = "fcm::get_heatmap"
+    )]
+    async fn get_heatmap(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        /// number of days to include, ending today
+        #[oai(
+            default = "default_heatmap_days",
+            validator(minimum(value = "1"), maximum(value = "366"))
+        )]
+        days: Query<i64>,
+    ) -> Result<JsonSuccess<Heatmap>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::View).await?;
+
+        let to = Utc::now().date_naive();
+        let from = to - chrono::Duration::days(days.0 - 1);
+
+        let rows = sqlx::query!(
+            r#"SELECT date(executed_at) as "date!: NaiveDate",
+                SUM(CASE WHEN status = 'success' THEN 1 ELSE 0 END) as "success!: i64",
+                SUM(CASE WHEN status = 'success' THEN 0 ELSE 1 END) as "failure!: i64"
+            FROM fcm_execution_log
+            WHERE schedule_id = ? AND date(executed_at) >= ? AND status NOT IN ('retrying', 'skipped') AND dry_run = 0
+            GROUP BY date(executed_at)
+            ORDER BY date(executed_at)"#,
+            id.0,
+            from
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        Ok(ResponseObject::ok(Heatmap {
+            from,
+            to,
+            days: rows
+                .into_iter()
+                .map(|row| HeatmapDay {
+                    date: row.date,
+                    success: row.success,
+                    failure: row.failure,
+                })
+                .collect(),
+        }))
+    }
+
+    // Execution history of the schedule, newest first. Pass the returned `next_cursor`
+    // as `cursor` to fetch the following page
+    #[oai(
+        path = "/:id/executions",
+        method = "get",
+        operation_id = "fcm::list_executions"
+    )]
+    async fn list_executions(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        /// id of the last execution of the previous page
+        cursor: Query<Option<i64>>,
+        /// number of executions per page
+        #[oai(
+            default = "default_page_limit",
+            validator(minimum(value = "1"), maximum(value = "200"))
+        )]
+        limit: Query<i64>,
+    ) -> Result<JsonSuccess<ExecutionPage>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::View).await?;
+
+        // keyset on the rowid so deep pages are served straight from the schedule_id index
+        let cursor = cursor.0.unwrap_or(i64::MAX);
+        let items = sqlx::query_as!(
+            Execution,
+            r#"SELECT id as "id!", schedule_id, push_token, status, error, error_code, dry_run as "dry_run: bool", message_id, attempts, executed_at, acknowledged_at
+            FROM fcm_execution_log
+            WHERE schedule_id = ? AND id < ?
+            ORDER BY id DESC
+            LIMIT ?"#,
+            id.0,
+            cursor,
+            limit.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let items = match items {
+            Ok(items) => items,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let next_cursor = match items.last() {
+            Some(last) if items.len() as i64 == limit.0 => Some(last.id),
+            _ => None,
+        };
+
+        Ok(ResponseObject::ok(ExecutionPage { items, next_cursor }))
+    }
+
+    // Devices the schedule is sent to, with the results of their deliveries
+    #[oai(
+        path = "/:id/tokens",
+        method = "get",
+        operation_id = "fcm::list_schedule_tokens"
+    )]
+    async fn list_schedule_tokens(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Vec<ScheduleToken>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::View).await?;
+
+        let schedule =
+            sqlx::query_scalar!("SELECT push_token FROM fcm_schedule WHERE id = ?", id.0)
+                .fetch_optional(pool.0)
+                .await;
+
+        let primary = match schedule {
+            Ok(Some(push_token)) => push_token,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Schedule not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let extra = sqlx::query_scalar!(
+            "SELECT push_token FROM fcm_schedule_tokens WHERE schedule_id = ? ORDER BY id",
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let extra = match extra {
+            Ok(extra) => extra,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // retries in progress and dry runs are left out, only final outcomes count
+        let stats = sqlx::query!(
+            r#"SELECT push_token as "push_token!",
+                SUM(status = 'success') as "successes!: i64",
+                SUM(status IN ('failure', 'timeout')) as "failures!: i64",
+                MAX(id) as "last_id!: i64"
+            FROM fcm_execution_log
+            WHERE schedule_id = ? AND push_token IS NOT NULL AND status NOT IN ('retrying', 'skipped') AND dry_run = 0
+            GROUP BY push_token"#,
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let stats = match stats {
+            Ok(stats) => stats,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut tokens = Vec::with_capacity(extra.len() + 1);
+        for push_token in std::iter::once(primary.clone()).chain(extra) {
+            let mut token = ScheduleToken {
+                primary: push_token == primary,
+                push_token,
+                successes: 0,
+                failures: 0,
+                last_status: None,
+                last_executed_at: None,
+            };
+
+            if let Some(stat) = stats
+                .iter()
+                .find(|stat| stat.push_token == token.push_token)
+            {
+                token.successes = stat.successes;
+                token.failures = stat.failures;
+
+                let last = sqlx::query!(
+                    "SELECT status, executed_at FROM fcm_execution_log WHERE id = ?",
+                    stat.last_id
+                )
+                .fetch_one(pool.0)
+                .await;
+
+                match last {
+                    Ok(last) => {
+                        token.last_status = Some(last.status);
+                        token.last_executed_at = Some(last.executed_at);
+                    }
+                    Err(e) => {
+                        return Err(ResponseObject::internal_server_error(e));
+                    }
+                }
+            }
+
+            tokens.push(token);
+        }
+
+        Ok(ResponseObject::ok(tokens))
+    }
+
+    // Replace the additional devices the schedule is sent to along with its `push_token`
+    #[oai(
+        path = "/:id/tokens",
+        method = "put",
+        operation_id = "fcm::replace_schedule_tokens"
+    )]
+    async fn replace_schedule_tokens(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        body: Json<ScheduleTokens>,
+    ) -> Result<JsonSuccess<Vec<String>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        // sending one schedule to several devices can be restricted, clearing them can't
+        if !body.push_tokens.is_empty() {
+            if let Err(e) = policy::authorize(&data, Feature::FanOut) {
+                return Err(ResponseObject::forbidden(e));
+            }
+        }
+
+        let schedule =
+            sqlx::query_scalar!("SELECT push_token FROM fcm_schedule WHERE id = ?", id.0)
+                .fetch_optional(pool.0)
+                .await;
+
+        let primary = match schedule {
+            Ok(Some(push_token)) => push_token,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Schedule not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        for push_token in &body.push_tokens {
+            if let Err(e) = tokens::validate_format(push_token) {
+                return Err(ResponseObject::bad_request(e));
+            }
+        }
+
+        // the schedule's own push_token is always sent to
+        let push_tokens: Vec<String> = body
+            .0
+            .push_tokens
+            .into_iter()
+            .filter(|push_token| *push_token != primary)
+            .collect();
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if let Err(e) = store::replace_schedule_tokens(&mut tx, id.0, &push_tokens).await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        Ok(ResponseObject::ok(push_tokens))
+    }
+
+    // Actions run after the deliveries of the schedule
+    #[oai(
+        path = "/:id/followups",
+        method = "get",
+        operation_id = "fcm::list_followups"
+    )]
+    async fn list_followups(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Vec<FollowUp>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::View).await?;
+
+        let followups = sqlx::query_as!(
+            FollowUp,
+            r#"SELECT id as "id!", schedule_id, outcome, action, target_schedule_id, url, delay_minutes, created_at
+            FROM fcm_schedule_followup WHERE schedule_id = ? ORDER BY id"#,
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match followups {
+            Ok(followups) => Ok(ResponseObject::ok(followups)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Replace the actions run after the deliveries of the schedule
+    #[oai(
+        path = "/:id/followups",
+        method = "put",
+        operation_id = "fcm::replace_followups"
+    )]
+    async fn replace_followups(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        body: Json<FollowUps>,
+    ) -> Result<JsonSuccess<Vec<FollowUp>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        let followups = body.0.followups;
+        if let Err(e) = followups::validate(id.0, &followups) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        // triggering a schedule sends it, so it takes the same access as running it
+        let targets: Vec<i64> = followups
+            .iter()
+            .filter(|followup| followup.action == FollowUpAction::Trigger)
+            .filter_map(|followup| followup.target_schedule_id)
+            .collect();
+        for target in &targets {
+            sharing::authorize(pool.0, *target, &data.user_id, Access::Edit).await?;
+        }
+
+        match followups::creates_cycle(pool.0, id.0, &targets).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return Err(ResponseObject::bad_request(
+                    "Follow-ups can't trigger a schedule that leads back to this one",
+                ));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let removed = sqlx::query!(
+            "DELETE FROM fcm_schedule_followup WHERE schedule_id = ?",
+            id.0
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = removed {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let now = Utc::now().naive_utc();
+        for followup in &followups {
+            // only the fields of the action are kept
+            let target_schedule_id = followup
+                .target_schedule_id
+                .filter(|_| followup.action == FollowUpAction::Trigger);
+            let url = followup
+                .url
+                .as_deref()
+                .filter(|_| followup.action == FollowUpAction::Webhook);
+            let delay_minutes = followup
+                .delay_minutes
+                .filter(|_| followup.action == FollowUpAction::Remind);
+
+            let inserted = sqlx::query!(
+                "INSERT INTO fcm_schedule_followup (schedule_id, outcome, action, target_schedule_id, url, delay_minutes, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)",
+                id.0,
+                followup.outcome,
+                followup.action,
+                target_schedule_id,
+                url,
+                delay_minutes,
+                now
+            )
+            .execute(&mut *tx)
+            .await;
+
+            if let Err(e) = inserted {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let followups = sqlx::query_as!(
+            FollowUp,
+            r#"SELECT id as "id!", schedule_id, outcome, action, target_schedule_id, url, delay_minutes, created_at
+            FROM fcm_schedule_followup WHERE schedule_id = ? ORDER BY id"#,
+            id.0
+        )
+        .fetch_all(&mut *tx)
+        .await;
+
+        let followups = match followups {
+            Ok(followups) => followups,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        Ok(ResponseObject::ok(followups))
+    }
+
+    // Callback URL the failed deliveries of the schedule are posted to
+    #[oai(
+        path = "/:id/webhook",
+        method = "get",
+        operation_id = "fcm::get_schedule_webhook"
+    )]
+    async fn get_schedule_webhook(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<ScheduleWebhook>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        // the secret lets its holder forge events, so viewers don't get it
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        let webhook = sqlx::query_as!(
+            ScheduleWebhook,
+            "SELECT * FROM fcm_schedule_webhook WHERE schedule_id = ?",
+            id.0
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match webhook {
+            Ok(Some(webhook)) => Ok(ResponseObject::ok(webhook)),
+            Ok(None) => Err(ResponseObject::not_found("Schedule has no webhook")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Set the callback URL the failed deliveries of the schedule are posted to, the
+    // signing secret is generated the first time and kept unless `rotate_secret` is set
+    #[oai(
+        path = "/:id/webhook",
+        method = "put",
+        operation_id = "fcm::set_schedule_webhook"
+    )]
+    async fn set_schedule_webhook(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        body: Json<NewScheduleWebhook>,
+    ) -> Result<JsonSuccess<ScheduleWebhook>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        if let Err(e) = callbacks::validate_url(&body.url) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        let secret = match callbacks::generate_secret() {
+            Ok(secret) => secret,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let now = Utc::now().naive_utc();
+        let webhook = sqlx::query_as!(
+            ScheduleWebhook,
+            "INSERT INTO fcm_schedule_webhook (schedule_id, url, secret, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)
+            ON CONFLICT (schedule_id) DO UPDATE SET url = excluded.url, secret = CASE WHEN ?5 THEN excluded.secret ELSE secret END, updated_at = excluded.updated_at
+            RETURNING *",
+            id.0,
+            body.url,
+            secret,
+            now,
+            body.rotate_secret
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match webhook {
+            Ok(webhook) => Ok(ResponseObject::ok(webhook)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Stop posting the failed deliveries of the schedule, queued events are dropped
+    #[oai(
+        path = "/:id/webhook",
+        method = "delete",
+        operation_id = "fcm::delete_schedule_webhook"
+    )]
+    async fn delete_schedule_webhook(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<ScheduleWebhook>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        let webhook = sqlx::query_as!(
+            ScheduleWebhook,
+            "DELETE FROM fcm_schedule_webhook WHERE schedule_id = ? RETURNING *",
+            id.0
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match webhook {
+            Ok(Some(webhook)) => Ok(ResponseObject::ok(webhook)),
+            Ok(None) => Err(ResponseObject::not_found("Schedule has no webhook")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Events posted to the callback URL of the schedule, newest first
+    #[oai(
+        path = "/:id/webhook/deliveries",
+        method = "get",
+        operation_id = "fcm::list_webhook_deliveries"
+    )]
+    async fn list_webhook_deliveries(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Vec<WebhookDelivery>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::Edit).await?;
+
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            r#"SELECT id as "id!", schedule_id, event, attempts, next_attempt_at, delivered_at, failed_at, error, created_at
+            FROM fcm_webhook_delivery WHERE schedule_id = ? ORDER BY id DESC LIMIT 100"#,
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match deliveries {
+            Ok(deliveries) => Ok(ResponseObject::ok(deliveries)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Outcome of an execution sent to several devices, for every device
+    #[oai(
+        path = "/:id/batches/:batch_id",
+        method = "get",
+        operation_id = "fcm::get_execution_batch"
+    )]
+    async fn get_execution_batch(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        batch_id: Path<i64>,
+    ) -> Result<JsonSuccess<ExecutionBatch>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, id.0, &data.user_id, Access::View).await?;
+
+        let batch = sqlx::query!(
+            r#"SELECT total, completed_at as "completed_at: NaiveDateTime", created_at
+            FROM fcm_execution_batch WHERE id = ? AND schedule_id = ?"#,
+            batch_id.0,
+            id.0
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let batch = match batch {
+            Ok(Some(batch)) => batch,
+            Ok(None) => return Err(ResponseObject::not_found("Batch not found")),
+            Err(e) => return Err(ResponseObject::internal_server_error(e)),
+        };
+
+        let results = match batches::results(pool.0, batch_id.0).await {
+            Ok(results) => results,
+            Err(e) => return Err(ResponseObject::internal_server_error(e)),
+        };
+
+        let (succeeded, failed, pending, errors) = batches::summarize(&results);
+        Ok(ResponseObject::ok(ExecutionBatch {
+            id: batch_id.0,
+            schedule_id: id.0,
+            total: batch.total,
+            succeeded,
+            failed,
+            pending,
+            errors,
+            completed_at: batch.completed_at,
+            created_at: batch.created_at,
+            results,
+        }))
+    }
+
+    // Immediately send every enabled schedule of the user carrying the tag to all of its
+    // devices, without changing their next execution. Use `dry_run` to get the number of
+    // matching schedules and pass it back as `expected` to confirm
+    #[oai(
+        path = "/trigger",
+        method = "post",
+        operation_id = "fcm::trigger_schedules"
+    )]
+    async fn trigger_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// tag of the schedules to send
+        tag: Query<String>,
+        /// only count the matching schedules
+        #[oai(default)]
+        dry_run: Query<bool>,
+        /// number of schedules the caller expects to send, the request is rejected when it differs
+        expected: Query<Option<u64>>,
+    ) -> Result<JsonSuccess<TriggerResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let mut schedules =
+            find_tagged_schedules(pool.0, &data.user_id, &tag.0, Some(None)).await?;
+
+        let matched = schedules.len() as u64;
+        let schedule_ids: Vec<i64> = schedules.iter().map(|s| s.id).collect();
+        confirm_matched(matched, expected.0)?;
+
+        if dry_run.0 || schedules.is_empty() {
+            return Ok(ResponseObject::ok(TriggerResult {
+                matched,
+                triggered: 0,
+                schedule_ids,
+                dry_run: dry_run.0,
+            }));
+        }
+
+        let project = sqlx::query_as!(
+            ProjectSettings,
+            "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+            data.aud
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        let project = match project {
+            Ok(project) => project,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // every device of a schedule is sent to like on a scheduled run
+        let mut executions = Vec::with_capacity(schedules.len());
+        for schedule in &mut schedules {
+            let push_tokens = match worker::schedule_tokens(pool.0, schedule).await {
+                Ok(push_tokens) => push_tokens,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let execution_key = match worker::execution_key() {
+                Ok(execution_key) => execution_key,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let next_run = Some(schedule.next_execution);
+            template::render_schedule(schedule, next_run, &execution_key);
+            let payloads = match worker::build_payloads(schedule, project.as_ref(), push_tokens) {
+                Ok(payloads) => payloads,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            executions.push((execution_key, payloads));
+        }
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        for (schedule, (execution_key, payloads)) in schedules.iter().zip(&executions) {
+            let result =
+                worker::enqueue_execution(&mut tx, schedule, payloads, execution_key, None).await;
+            if let Err(e) = result {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        Ok(ResponseObject::ok(TriggerResult {
+            matched,
+            triggered: matched,
+            schedule_ids,
+            dry_run: false,
+        }))
+    }
+
+    // Pause every enabled schedule of the user carrying the tag until it's resumed. Use
+    // `dry_run` to get the number of matching schedules and pass it back as `expected` to confirm
+    #[oai(
+        path = "/pause",
+        method = "post",
+        operation_id = "fcm::pause_schedules"
+    )]
+    async fn pause_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// tag of the schedules to pause
+        tag: Query<String>,
+        /// only count the matching schedules
+        #[oai(default)]
+        dry_run: Query<bool>,
+        /// number of schedules the caller expects to pause, the request is rejected when it differs
+        expected: Query<Option<u64>>,
+    ) -> Result<JsonSuccess<TagBulkResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let schedules = find_tagged_schedules(pool.0, &data.user_id, &tag.0, Some(None)).await?;
+
+        let matched = schedules.len() as u64;
+        let schedule_ids: Vec<i64> = schedules.iter().map(|s| s.id).collect();
+        confirm_matched(matched, expected.0)?;
+
+        if dry_run.0 {
+            return Ok(ResponseObject::ok(TagBulkResult {
+                matched,
+                updated: 0,
+                schedule_ids,
+                dry_run: true,
+            }));
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let mut updated = 0;
+        for mut schedule in schedules {
+            // a schedule disabled since it was listed keeps its reason
+            let result = sqlx::query!(
+                "UPDATE fcm_schedule SET disabled_reason = 'paused', version = version + 1, updated_at = ? WHERE id = ? AND disabled_reason IS NULL",
+                current_time,
+                schedule.id
+            )
+            .execute(pool.0)
+            .await;
+
+            match result {
+                Ok(result) if result.rows_affected() > 0 => {
+                    updated += 1;
+                    schedule.disabled_reason = Some("paused".to_string());
+                    schedule.updated_at = current_time;
+                    events::publish("schedule.updated", ScheduleEvent::from(&schedule));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        Ok(ResponseObject::ok(TagBulkResult {
+            matched,
+            updated,
+            schedule_ids,
+            dry_run: false,
+        }))
+    }
+
+    // Resume every paused schedule of the user carrying the tag, occurrences missed while
+    // paused are skipped. Use `dry_run` and `expected` the same as when pausing them
+    #[oai(
+        path = "/resume",
+        method = "post",
+        operation_id = "fcm::resume_schedules"
+    )]
+    async fn resume_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// tag of the schedules to resume
+        tag: Query<String>,
+        /// only count the matching schedules
+        #[oai(default)]
+        dry_run: Query<bool>,
+        /// number of schedules the caller expects to resume, the request is rejected when it differs
+        expected: Query<Option<u64>>,
+    ) -> Result<JsonSuccess<TagBulkResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let schedules =
+            find_tagged_schedules(pool.0, &data.user_id, &tag.0, Some(Some("paused"))).await?;
+
+        let matched = schedules.len() as u64;
+        let schedule_ids: Vec<i64> = schedules.iter().map(|s| s.id).collect();
+        confirm_matched(matched, expected.0)?;
+
+        if dry_run.0 {
+            return Ok(ResponseObject::ok(TagBulkResult {
+                matched,
+                updated: 0,
+                schedule_ids,
+                dry_run: true,
+            }));
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let mut updated = 0;
+        for mut schedule in schedules {
+            let next_execution = match resume_execution(&schedule) {
+                Ok(next) => next,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let result = sqlx::query!(
+                "UPDATE fcm_schedule SET disabled_reason = NULL, next_execution = ?, version = version + 1, updated_at = ? WHERE id = ? AND disabled_reason = 'paused'",
+                next_execution,
+                current_time,
+                schedule.id
+            )
+            .execute(pool.0)
+            .await;
+
+            match result {
+                Ok(result) if result.rows_affected() > 0 => {
+                    updated += 1;
+                    schedule.disabled_reason = None;
+                    schedule.next_execution = next_execution;
+                    schedule.updated_at = current_time;
+                    events::publish("schedule.updated", ScheduleEvent::from(&schedule));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        Ok(ResponseObject::ok(TagBulkResult {
+            matched,
+            updated,
+            schedule_ids,
+            dry_run: false,
+        }))
+    }
+
+    // Delete every schedule of the user carrying the tag. Use `dry_run` to get the number
+    // of matching schedules and pass it back as `expected` to confirm
+    #[oai(
+        path = "/",
+        method = "delete",
+        operation_id = "fcm::delete_tagged_schedules"
+    )]
+    async fn delete_tagged_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        /// tag of the schedules to delete
+        tag: Query<String>,
+        /// only count the matching schedules
+        #[oai(default)]
+        dry_run: Query<bool>,
+        /// number of schedules the caller expects to delete, the request is rejected when it differs
+        expected: Query<Option<u64>>,
+    ) -> Result<JsonSuccess<TagBulkResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        // only the owner deletes a schedule, the same as deleting it by id
+        let schedules = find_tagged_schedules(pool.0, &data.user_id, &tag.0, None).await?;
+
+        let matched = schedules.len() as u64;
+        let schedule_ids: Vec<i64> = schedules.iter().map(|s| s.id).collect();
+        confirm_matched(matched, expected.0)?;
+
+        if dry_run.0 {
+            return Ok(ResponseObject::ok(TagBulkResult {
+                matched,
+                updated: 0,
+                schedule_ids,
+                dry_run: true,
+            }));
+        }
+
+        let mut updated = 0;
+        for schedule in schedules {
+            let result = sqlx::query!(
+                "DELETE FROM fcm_schedule WHERE id = ? AND fb_user_id = ?",
+                schedule.id,
+                data.user_id
+            )
+            .execute(pool.0)
+            .await;
+
+            match result {
+                Ok(result) if result.rows_affected() > 0 => {
+                    updated += 1;
+                    events::publish("schedule.deleted", ScheduleEvent::from(&schedule));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        Ok(ResponseObject::ok(TagBulkResult {
+            matched,
+            updated,
+            schedule_ids,
+            dry_run: false,
+        }))
+    }
+
+    // Register a device of the user or update its timezone, local time schedules reach it at its local time
+    #[oai(
+        path = "/me/devices",
+        method = "put",
+        operation_id = "fcm::register_device"
+    )]
+    async fn register_device(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        body: Json<RegisterDevice>,
+    ) -> Result<JsonSuccess<Device>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if body.timezone.parse::<Tz>().is_err() {
+            return Err(ResponseObject::bad_request("Invalid timezone"));
+        }
+
+        // a device registered by another account stays with it, accounts are merged to move it
+        let current_time = Utc::now().naive_utc();
+        let device = sqlx::query_as!(
+            Device,
+            "INSERT INTO fcm_device (push_token, fb_user_id, timezone, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (push_token) DO UPDATE SET timezone = excluded.timezone, updated_at = excluded.updated_at
+            WHERE fcm_device.fb_user_id = excluded.fb_user_id
+            RETURNING push_token, timezone, created_at, updated_at",
+            body.push_token,
+            data.user_id,
+            body.timezone,
+            current_time,
+            current_time
+        )
+        .fetch_optional(pool.0)
+        .await;
+
+        match device {
+            Ok(Some(device)) => Ok(ResponseObject::ok(device)),
+            Ok(None) => Err(ResponseObject::conflict(
+                "push_token is registered by another account",
+            )),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Devices the user registered with their timezones
+    #[oai(
+        path = "/me/devices",
+        method = "get",
+        operation_id = "fcm::list_devices"
+    )]
+    async fn list_devices(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<Device>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let devices = sqlx::query_as!(
+            Device,
+            "SELECT push_token, timezone, created_at, updated_at FROM fcm_device WHERE fb_user_id = ? ORDER BY created_at",
+            data.user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match devices {
+            Ok(devices) => Ok(ResponseObject::ok(devices)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Delivery health of every push token used by the user's schedules
+    #[oai(
+        path = "/me/tokens/health",
+        method = "get",
+        operation_id = "fcm::get_token_health"
+    )]
+    async fn get_token_health(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<TokenHealth>>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let tokens = sqlx::query!(
+            r#"SELECT push_token as "push_token!", COUNT(*) as "schedule_count!: i64",
+                MAX(CASE WHEN disabled_reason = 'invalid_token' THEN 1 ELSE 0 END) as "disabled!: bool"
+            FROM fcm_schedule WHERE fb_user_id = ? AND target_type = 'token'
+            GROUP BY push_token ORDER BY push_token"#,
+            data.user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let tokens = match tokens {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let stale_after = Utc::now().naive_utc() - chrono::Duration::days(tokens::TOKEN_STALE_DAYS);
+        let mut health = Vec::with_capacity(tokens.len());
+
+        for token in tokens {
+            let last_success_at = sqlx::query_scalar!(
+                r#"SELECT MAX(l.executed_at) as "executed_at: NaiveDateTime"
+                FROM fcm_execution_log l JOIN fcm_schedule s ON s.id = l.schedule_id
+                WHERE s.fb_user_id = ? AND s.push_token = ? AND l.status = 'success' AND l.dry_run = 0"#,
+                data.user_id,
+                token.push_token
+            )
+            .fetch_one(pool.0)
+            .await;
+
+            let last_success_at = match last_success_at {
+                Ok(last_success_at) => last_success_at,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let last_error = sqlx::query!(
+                r#"SELECT l.executed_at, l.error, l.error_code
+                FROM fcm_execution_log l JOIN fcm_schedule s ON s.id = l.schedule_id
+                WHERE s.fb_user_id = ? AND s.push_token = ? AND l.status NOT IN ('success', 'skipped')
+                ORDER BY l.id DESC LIMIT 1"#,
+                data.user_id,
+                token.push_token
+            )
+            .fetch_optional(pool.0)
+            .await;
+
+            let last_error = match last_error {
+                Ok(last_error) => last_error,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let last_error_at = last_error.as_ref().map(|e| e.executed_at);
+            let failed_last = match (last_success_at, last_error_at) {
+                (Some(success), Some(error)) => error > success,
+                (None, Some(_)) => true,
+                _ => false,
+            };
+            let rejected = token.disabled
+                || (failed_last
+                    && last_error.as_ref().and_then(|e| e.error_code.as_deref())
+                        == Some("invalid_token"));
+
+            let validity = match last_success_at {
+                _ if rejected => TokenValidity::Invalid,
+                Some(success) if success >= stale_after => TokenValidity::Valid,
+                Some(_) => TokenValidity::Stale,
+                None => TokenValidity::Unknown,
+            };
+
+            let hint = sqlx::query!(
+                "SELECT reason, replacement_token FROM fcm_token_hint WHERE fb_user_id = ? AND push_token = ?",
+                data.user_id,
+                token.push_token
+            )
+            .fetch_optional(pool.0)
+            .await;
+
+            let hint = match hint {
+                Ok(hint) => hint,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            let last_heartbeat_at = sqlx::query_scalar!(
+                "SELECT last_seen_at FROM fcm_token_heartbeat WHERE push_token = ? AND fb_user_id = ?",
+                token.push_token,
+                data.user_id
+            )
+            .fetch_optional(pool.0)
+            .await;
+
+            let last_heartbeat_at = match last_heartbeat_at {
+                Ok(last_heartbeat_at) => last_heartbeat_at,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            };
+
+            health.push(TokenHealth {
+                push_token: token.push_token,
+                schedule_count: token.schedule_count,
+                last_success_at,
+                last_error_at,
+                last_error: last_error.as_ref().and_then(|e| e.error.clone()),
+                last_error_code: last_error.and_then(|e| e.error_code),
+                validity,
+                hint: hint.as_ref().map(|h| h.reason.clone()),
+                replacement_token: hint.and_then(|h| h.replacement_token),
+                last_heartbeat_at,
+            });
+        }
+
+        Ok(ResponseObject::ok(health))
+    }
+
+    // Report the token the app holds on startup, the response tells the app when to refresh it
+    #[oai(
+        path = "/me/tokens/heartbeat",
+        method = "post",
+        operation_id = "fcm::token_heartbeat"
+    )]
+    async fn token_heartbeat(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        body: Json<TokenHeartbeat>,
+    ) -> Result<JsonSuccess<TokenHeartbeatResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        match tokens::heartbeat(pool.0, &data.user_id, &body.push_token).await {
+            Ok(result) => Ok(ResponseObject::ok(result)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Report that the firebase SDK replaced a token of the user's schedules (onTokenRefresh / onNewToken)
+    #[oai(
+        path = "/me/tokens/replace",
+        method = "post",
+        operation_id = "fcm::replace_token"
+    )]
+    async fn replace_token(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        replacement: Json<TokenReplacement>,
+    ) -> Result<JsonSuccess<TokenReplacementResult>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool.0).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if replacement.previous_token == replacement.push_token {
+            return Err(ResponseObject::bad_request(
+                "push_token must differ from previous_token",
+            ));
+        }
+
+        // only the owner of a token may report what replaced it
+        match tokens::owns_token(pool.0, &data.user_id, &replacement.previous_token).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(ResponseObject::not_found(
+                    "None of your schedules or devices use previous_token",
+                ));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let result = tokens::record_hint(
+            &mut *tx,
+            &data.user_id,
+            &replacement.previous_token,
+            Some(&replacement.push_token),
+            "rotated",
+        )
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let migrated_schedules = if replacement.migrate.unwrap_or(*tokens::AUTO_MIGRATE_TOKENS) {
+            let result = tokens::migrate_schedules(
+                &mut *tx,
+                &data.user_id,
+                &replacement.previous_token,
+                &replacement.push_token,
+            )
+            .await;
+
+            match result {
+                Ok(migrated) => migrated,
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        } else {
+            0
+        };
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        Ok(ResponseObject::ok(TokenReplacementResult {
+            migrated_schedules,
+        }))
+    }
+}
+
+impl FirebaseMessaging {
+    async fn review_schedule(
+        &self,
+        req: &Request,
+        pool: &SqlitePool,
+        id: i64,
+        approved: bool,
+    ) -> Result<JsonSuccess<FCMSchedule>, JsonError<String>> {
+        // extract user id from token
+        let data = match authenticate(req, pool).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        if let Err(e) = policy::authorize(&data, Feature::ManageProject) {
+            return Err(ResponseObject::forbidden(e));
+        }
+
+        let schedule = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = ? AND fb_project_id = ? AND disabled_reason = 'pending_approval'",
+            id,
+            data.aud
+        )
+        .fetch_optional(pool)
+        .await;
+
+        let schedule = match schedule {
+            Ok(Some(schedule)) => schedule,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Pending schedule not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        // occurrences missed while waiting for approval are skipped, a one-shot
+        // schedule whose time has passed is sent right away
+        let next_execution = match resume_execution(&schedule) {
+            Ok(next) => next,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+        let disabled_reason = if approved { None } else { Some("rejected") };
+        let current_time = Utc::now().naive_local();
+
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET disabled_reason = ?, next_execution = ?, version = version + 1, updated_at = ? WHERE id = ? AND disabled_reason = 'pending_approval'",
+            disabled_reason,
+            next_execution,
+            current_time,
+            id
+        )
+        .execute(pool)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(ResponseObject::not_found("Pending schedule not found"));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let schedule = sqlx::query_as!(FCMSchedule, "SELECT * FROM fcm_schedule WHERE id = ?", id)
+            .fetch_one(pool)
+            .await;
+
+        match schedule {
+            Ok(schedule) => {
+                events::publish("schedule.updated", ScheduleEvent::from(&schedule));
+                Ok(ResponseObject::ok(schedule))
+            }
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
+
+async fn find_project(
+    pool: &SqlitePool,
+    fb_project_id: &str,
+) -> Result<Option<ProjectSettings>, sqlx::Error> {
+    sqlx::query_as!(
+        ProjectSettings,
+        "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+        fb_project_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// most schedules a single bulk create accepts
+const MAX_BULK_SCHEDULES: usize = 100;
+
+/// Schedules of the user with the tag, oldest first. With a state, only the schedules
+/// disabled for that reason, `Some(None)` for the enabled ones
+async fn find_tagged_schedules(
+    pool: &SqlitePool,
+    fb_user_id: &str,
+    tag: &str,
+    disabled_reason: Option<Option<&str>>,
+) -> Result<Vec<FCMSchedule>, JsonError<String>> {
+    let tag = parse_tag(tag)?;
+    let any_state = disabled_reason.is_none();
+    let disabled_reason = disabled_reason.flatten();
+
+    let schedules = sqlx::query_as!(
+        FCMSchedule,
+        "SELECT fcm_schedule.* FROM fcm_schedule
+        JOIN fcm_schedule_tag ON fcm_schedule_tag.schedule_id = fcm_schedule.id
+        WHERE fcm_schedule_tag.tag = ?1 AND fcm_schedule.fb_user_id = ?2
+        AND (?3 OR fcm_schedule.disabled_reason IS ?4)
+        ORDER BY fcm_schedule.id",
+        tag,
+        fb_user_id,
+        any_state,
+        disabled_reason
+    )
+    .fetch_all(pool)
+    .await;
+
+    match schedules {
+        Ok(schedules) => Ok(schedules),
+        Err(e) => Err(ResponseObject::internal_server_error(e)),
+    }
+}
+
+/// The tag of a filter, rejected when no schedule could have it
+fn parse_tag(tag: &str) -> Result<&str, JsonError<String>> {
+    if let Err(e) = validate_tags(&Tags(vec![tag.to_string()])) {
+        return Err(ResponseObject::bad_request(e));
+    }
+    Ok(tag)
+}
+
+/// Organization of the `owner` filter, None for the user's own schedules
+fn parse_owner(owner: Option<&str>) -> Result<Option<i64>, JsonError<String>> {
+    match owner {
+        None | Some("me") => Ok(None),
+        Some(owner) => match owner.strip_prefix("org:").map(str::parse::<i64>) {
+            Some(Ok(id)) => Ok(Some(id)),
+            _ => Err(ResponseObject::bad_request(
+                "owner must be `me` or `org:<id>`",
+            )),
+        },
+    }
+}
+
+/// FTS5 query matching every word of the text as a prefix, None without any word.
+/// Words are quoted so operators and punctuation in the text are searched for literally
+fn search_query(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Reject a bulk request when the number of matching schedules isn't the one the caller expects
+fn confirm_matched(matched: u64, expected: Option<u64>) -> Result<(), JsonError<String>> {
+    match expected {
+        Some(expected) if expected != matched => Err(ResponseObject::bad_request(format!(
+            "{} schedules match the tag but {} were expected",
+            matched, expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn default_heatmap_days() -> i64 {
+    90
+}
+
+fn default_page_limit() -> i64 {
+    50
+}
+
+fn default_search_limit() -> i64 {
+    20
 }
@@ -0,0 +1,443 @@
+use crate::http;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+lazy_static! {
+    // longest fetching an ICS calendar may take, the scheduler waits for it
+    static ref ICS_TIMEOUT_SECS: u64 = env::var("FCM_HOLIDAY_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+    // how long a fetched ICS calendar is used before it's fetched again
+    static ref ICS_CACHE_SECS: u64 = env::var("FCM_HOLIDAY_CACHE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(6 * 60 * 60);
+    // fetched ICS calendars by URL
+    static ref ICS_CACHE: Mutex<HashMap<String, CachedCalendar>> = Mutex::new(HashMap::new());
+}
+
+type CachedCalendar = (Instant, Arc<Vec<Event>>);
+
+// largest ICS calendar that is fetched
+const MAX_ICS_BYTES: usize = 1024 * 1024;
+// longest event that is expanded into holidays
+const MAX_EVENT_DAYS: i64 = 366;
+
+// Holiday calendars skip the executions of a schedule that fall on a listed date in
+// the schedule's timezone. A calendar is either one of the built-in country calendars
+// (nationwide public holidays, including the weekdays they're observed on) or the
+// URL of an ICS file, e.g. one exported from a shared calendar.
+
+/// Built-in calendars by code
+pub const BUILTIN_CALENDARS: [&str; 3] = ["de", "gb", "us"];
+
+/// Check the calendar is a built-in calendar or the http(s) URL of an ICS file on a public host
+pub fn validate(calendar: Option<&str>) -> Result<(), String> {
+    let calendar = match calendar {
+        Some(calendar) => calendar,
+        None => return Ok(()),
+    };
+
+    if is_builtin(calendar) {
+        return Ok(());
+    }
+
+    match http::validate_public_url(calendar) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "holiday_calendar must be one of {} or the URL of an ICS file: {}",
+            BUILTIN_CALENDARS.join(", "),
+            e
+        )),
+    }
+}
+
+/// Name of the holiday on the date, if the calendar lists one
+pub async fn lookup(calendar: &str, date: NaiveDate) -> Result<Option<String>, String> {
+    let holidays = list(calendar, date.year()).await?;
+    Ok(holidays
+        .into_iter()
+        .find(|(day, _)| *day == date)
+        .map(|(_, name)| name))
+}
+
+/// Whether the calendar is one of the built-in calendars
+pub fn is_builtin(calendar: &str) -> bool {
+    BUILTIN_CALENDARS.contains(&calendar)
+}
+
+/// Holidays of the calendar in the year, ordered by date
+pub async fn list(calendar: &str, year: i32) -> Result<Vec<(NaiveDate, String)>, String> {
+    let mut holidays = match builtin(calendar) {
+        // substitute days can fall in the year before or after the holiday
+        Some(rules) => (year - 1..=year + 1)
+            .flat_map(|year| observed(rules(year)))
+            .filter(|(day, _)| day.year() == year)
+            .collect(),
+        None => expand(&fetch(calendar).await?, year),
+    };
+
+    holidays.sort_by_key(|(day, _)| *day);
+    Ok(holidays)
+}
+
+/// How a holiday falling on a weekend is made up for
+#[derive(Clone, Copy)]
+enum Observance {
+    /// only on the day itself
+    Exact,
+    /// on the Friday before a Saturday or the Monday after a Sunday
+    Nearest,
+    /// on the next weekday that isn't a holiday already
+    Following,
+}
+
+struct Holiday {
+    date: NaiveDate,
+    name: &'static str,
+    observance: Observance,
+}
+
+type Rules = fn(i32) -> Vec<Holiday>;
+
+fn builtin(calendar: &str) -> Option<Rules> {
+    match calendar {
+        "de" => Some(germany),
+        "gb" => Some(great_britain),
+        "us" => Some(united_states),
+        _ => None,
+    }
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap_or_default()
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap_or_default()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let first_of_next = match month {
+        12 => date(year + 1, 1, 1),
+        _ => date(year, month + 1, 1),
+    };
+    let mut day = first_of_next - Duration::days(1);
+    while day.weekday() != weekday {
+        day -= Duration::days(1);
+    }
+    day
+}
+
+/// Easter Sunday in the Gregorian calendar (anonymous Gregorian algorithm)
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    date(year, month as u32, day as u32)
+}
+
+fn holiday(date: NaiveDate, name: &'static str, observance: Observance) -> Holiday {
+    Holiday {
+        date,
+        name,
+        observance,
+    }
+}
+
+fn germany(year: i32) -> Vec<Holiday> {
+    let easter = easter(year);
+    vec![
+        holiday(date(year, 1, 1), "Neujahr", Observance::Exact),
+        holiday(easter - Duration::days(2), "Karfreitag", Observance::Exact),
+        holiday(easter + Duration::days(1), "Ostermontag", Observance::Exact),
+        holiday(date(year, 5, 1), "Tag der Arbeit", Observance::Exact),
+        holiday(
+            easter + Duration::days(39),
+            "Christi Himmelfahrt",
+            Observance::Exact,
+        ),
+        holiday(
+            easter + Duration::days(50),
+            "Pfingstmontag",
+            Observance::Exact,
+        ),
+        holiday(
+            date(year, 10, 3),
+            "Tag der Deutschen Einheit",
+            Observance::Exact,
+        ),
+        holiday(date(year, 12, 25), "1. Weihnachtstag", Observance::Exact),
+        holiday(date(year, 12, 26), "2. Weihnachtstag", Observance::Exact),
+    ]
+}
+
+/// Bank holidays of England and Wales
+fn great_britain(year: i32) -> Vec<Holiday> {
+    let easter = easter(year);
+    vec![
+        holiday(date(year, 1, 1), "New Year's Day", Observance::Following),
+        holiday(easter - Duration::days(2), "Good Friday", Observance::Exact),
+        holiday(
+            easter + Duration::days(1),
+            "Easter Monday",
+            Observance::Exact,
+        ),
+        holiday(
+            nth_weekday(year, 5, Weekday::Mon, 1),
+            "Early May bank holiday",
+            Observance::Exact,
+        ),
+        holiday(
+            last_weekday(year, 5, Weekday::Mon),
+            "Spring bank holiday",
+            Observance::Exact,
+        ),
+        holiday(
+            last_weekday(year, 8, Weekday::Mon),
+            "Summer bank holiday",
+            Observance::Exact,
+        ),
+        holiday(date(year, 12, 25), "Christmas Day", Observance::Following),
+        holiday(date(year, 12, 26), "Boxing Day", Observance::Following),
+    ]
+}
+
+/// Federal holidays of the United States
+fn united_states(year: i32) -> Vec<Holiday> {
+    vec![
+        holiday(date(year, 1, 1), "New Year's Day", Observance::Nearest),
+        holiday(
+            nth_weekday(year, 1, Weekday::Mon, 3),
+            "Martin Luther King Jr. Day",
+            Observance::Exact,
+        ),
+        holiday(
+            nth_weekday(year, 2, Weekday::Mon, 3),
+            "Washington's Birthday",
+            Observance::Exact,
+        ),
+        holiday(
+            last_weekday(year, 5, Weekday::Mon),
+            "Memorial Day",
+            Observance::Exact,
+        ),
+        holiday(
+            date(year, 6, 19),
+            "Juneteenth National Independence Day",
+            Observance::Nearest,
+        ),
+        holiday(date(year, 7, 4), "Independence Day", Observance::Nearest),
+        holiday(
+            nth_weekday(year, 9, Weekday::Mon, 1),
+            "Labor Day",
+            Observance::Exact,
+        ),
+        holiday(
+            nth_weekday(year, 10, Weekday::Mon, 2),
+            "Columbus Day",
+            Observance::Exact,
+        ),
+        holiday(date(year, 11, 11), "Veterans Day", Observance::Nearest),
+        holiday(
+            nth_weekday(year, 11, Weekday::Thu, 4),
+            "Thanksgiving Day",
+            Observance::Exact,
+        ),
+        holiday(date(year, 12, 25), "Christmas Day", Observance::Nearest),
+    ]
+}
+
+/// The holidays and the weekdays holidays falling on a weekend are observed on
+fn observed(holidays: Vec<Holiday>) -> Vec<(NaiveDate, String)> {
+    let mut days: Vec<(NaiveDate, String)> = holidays
+        .iter()
+        .map(|holiday| (holiday.date, holiday.name.to_string()))
+        .collect();
+
+    for holiday in &holidays {
+        let substitute = match (holiday.observance, holiday.date.weekday()) {
+            (Observance::Nearest, Weekday::Sat) => holiday.date - Duration::days(1),
+            (Observance::Nearest, Weekday::Sun) => holiday.date + Duration::days(1),
+            (Observance::Following, Weekday::Sat | Weekday::Sun) => {
+                let mut day = holiday.date + Duration::days(1);
+                while matches!(day.weekday(), Weekday::Sat | Weekday::Sun)
+                    || days.iter().any(|(taken, _)| *taken == day)
+                {
+                    day += Duration::days(1);
+                }
+                day
+            }
+            _ => continue,
+        };
+        days.push((substitute, format!("{} (observed)", holiday.name)));
+    }
+
+    days
+}
+
+/// All-day span of an ICS event, `end` is exclusive
+#[derive(Debug)]
+struct Event {
+    start: NaiveDate,
+    end: NaiveDate,
+    name: String,
+    yearly: bool,
+}
+
+/// VEVENT whose properties are being read
+#[derive(Default)]
+struct PartialEvent {
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    name: String,
+    yearly: bool,
+}
+
+impl PartialEvent {
+    /// events without a start are ignored, ones without an end last a day
+    fn finish(self) -> Option<Event> {
+        let start = self.start?;
+        let end = self.end.filter(|end| *end > start);
+        Some(Event {
+            start,
+            end: end.unwrap_or(start + Duration::days(1)),
+            name: self.name,
+            yearly: self.yearly,
+        })
+    }
+}
+
+async fn fetch(url: &str) -> Result<Arc<Vec<Event>>, String> {
+    if let Some((fetched_at, events)) = ICS_CACHE.lock().unwrap().get(url) {
+        if fetched_at.elapsed().as_secs() < *ICS_CACHE_SECS {
+            return Ok(events.clone());
+        }
+    }
+
+    // the url's host may have been pointed to an internal address since it was saved
+    http::check_public_url(url).await?;
+
+    let request = http::PUBLIC_CLIENT
+        .get(url)
+        .timeout(std::time::Duration::from_secs(*ICS_TIMEOUT_SECS));
+    let response = http::send(request)
+        .await
+        .and_then(|response| response.error_for_status());
+    let body = match response {
+        Ok(response) => response.bytes().await,
+        Err(e) => return Err(format!("Error fetching holiday calendar {}: {}", url, e)),
+    };
+    let body = match body {
+        Ok(body) if body.len() > MAX_ICS_BYTES => {
+            return Err(format!("Holiday calendar {} is too large", url));
+        }
+        Ok(body) => body,
+        Err(e) => return Err(format!("Error reading holiday calendar {}: {}", url, e)),
+    };
+
+    let events = Arc::new(parse_ics(&String::from_utf8_lossy(&body)));
+    ICS_CACHE
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), (Instant::now(), events.clone()));
+    Ok(events)
+}
+
+/// Events of an ICS calendar, only the date of timed events is used and of the
+/// recurrence rules only yearly ones are followed
+fn parse_ics(text: &str) -> Vec<Event> {
+    // long lines are folded onto lines starting with a space or a tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<PartialEvent> = None;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name, value),
+            None => continue,
+        };
+        // properties can carry parameters, e.g. DTSTART;VALUE=DATE:20261225
+        let property = name.split(';').next().unwrap_or_default();
+
+        match (property, current.as_mut()) {
+            ("BEGIN", _) if value == "VEVENT" => current = Some(PartialEvent::default()),
+            ("END", Some(_)) if value == "VEVENT" => {
+                if let Some(event) = current.take() {
+                    events.extend(event.finish());
+                }
+            }
+            ("DTSTART", Some(event)) => event.start = parse_ics_date(value),
+            ("DTEND", Some(event)) => event.end = parse_ics_date(value),
+            ("SUMMARY", Some(event)) => {
+                event.name = value
+                    .replace("\\n", " ")
+                    .replace("\\N", " ")
+                    .replace("\\,", ",")
+                    .replace("\\;", ";")
+                    .replace("\\\\", "\\")
+            }
+            ("RRULE", Some(event)) => {
+                event.yearly = value.split(';').any(|part| part == "FREQ=YEARLY")
+            }
+            _ => {}
+        }
+    }
+
+    events
+}
+
+fn parse_ics_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+/// Days of the year the events cover
+fn expand(events: &[Event], year: i32) -> Vec<(NaiveDate, String)> {
+    let mut days = Vec::new();
+    for event in events {
+        let length = (event.end - event.start).num_days().min(MAX_EVENT_DAYS);
+        let starts = match event.yearly {
+            // an occurrence starting the year before can run into the year
+            true => (year - 1..=year)
+                .filter(|occurrence| *occurrence >= event.start.year())
+                .filter_map(|occurrence| event.start.with_year(occurrence))
+                .collect(),
+            false => vec![event.start],
+        };
+
+        for start in starts {
+            for offset in 0..length {
+                let day = start + Duration::days(offset);
+                if day.year() == year {
+                    days.push((day, event.name.clone()));
+                }
+            }
+        }
+    }
+
+    days
+}
@@ -0,0 +1,329 @@
+use super::model::Impersonation;
+use super::utils::Claims;
+use crate::utils::READ_ONLY;
+use chrono::Utc;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use lazy_static::lazy_static;
+use poem::Request;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::{collections::HashMap, env};
+use tracing::error;
+
+lazy_static! {
+    // key impersonation tokens are signed with, impersonation is disabled without it
+    static ref IMPERSONATION_SECRET: Option<String> = env::var("FCM_IMPERSONATION_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty());
+}
+
+// minutes an impersonation token is valid for when the operator doesn't say
+pub const DEFAULT_TTL_MINUTES: i64 = 15;
+
+// Impersonation lets support reproduce a user's problem through the regular API.
+// Tokens are signed by the server rather than firebase, so they're told apart from
+// ID tokens by their algorithm, and every request made with one is recorded
+// against the impersonation it was issued for.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImpersonationClaims {
+    /// impersonation the token was issued for
+    jti: i64,
+    /// user acted as
+    sub: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// Sign a token acting as the user of the impersonation until it expires
+pub fn issue(impersonation: &Impersonation) -> Result<String, String> {
+    let secret = match IMPERSONATION_SECRET.as_ref() {
+        Some(secret) => secret,
+        None => {
+            return Err("Impersonation needs FCM_IMPERSONATION_SECRET".to_string());
+        }
+    };
+
+    sign(secret, impersonation)
+}
+
+fn sign(secret: &str, impersonation: &Impersonation) -> Result<String, String> {
+    let claims = ImpersonationClaims {
+        jti: impersonation.id,
+        sub: impersonation.fb_user_id.clone(),
+        aud: impersonation.fb_project_id.clone(),
+        iat: impersonation.created_at.and_utc().timestamp(),
+        exp: impersonation.expires_at.and_utc().timestamp(),
+    };
+
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )
+    .map_err(|e| format!("Error signing impersonation token: {}", e))
+}
+
+/// Whether the bearer token was issued by the server for an impersonation
+pub fn is_impersonation_token(token: &str) -> bool {
+    IMPERSONATION_SECRET.is_some()
+        && decode_header(token).is_ok_and(|header| header.alg == Algorithm::HS256)
+}
+
+/// Claims of the impersonated user, recording the request in the audit log
+pub async fn authenticate(req: &Request, pool: &SqlitePool, token: &str) -> Result<Claims, String> {
+    let secret = match IMPERSONATION_SECRET.as_ref() {
+        Some(secret) => secret,
+        None => {
+            return Err("invalid token".to_string());
+        }
+    };
+
+    authenticate_with(req, pool, secret, token).await
+}
+
+async fn authenticate_with(
+    req: &Request,
+    pool: &SqlitePool,
+    secret: &str,
+    token: &str,
+) -> Result<Claims, String> {
+    let claims = match decode::<ImpersonationClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    ) {
+        Ok(data) => data.claims,
+        Err(_) => {
+            return Err("invalid token".to_string());
+        }
+    };
+
+    // requests can't be recorded by replicas, so they don't accept impersonation
+    if *READ_ONLY {
+        return Err("impersonation tokens are only accepted by the primary instance".to_string());
+    }
+
+    let current_time = Utc::now().naive_utc();
+    let anonymous = sqlx::query_scalar!(
+        r#"SELECT fcm_user.anonymous as "anonymous: bool" FROM fcm_impersonation
+        JOIN fcm_user ON fcm_user.fb_user_id = fcm_impersonation.fb_user_id
+        WHERE fcm_impersonation.id = ? AND fcm_impersonation.revoked_at IS NULL AND fcm_impersonation.expires_at > ?"#,
+        claims.jti,
+        current_time
+    )
+    .fetch_optional(pool)
+    .await;
+
+    let anonymous = match anonymous {
+        Ok(Some(anonymous)) => anonymous,
+        Ok(None) => {
+            return Err("impersonation has expired or was revoked".to_string());
+        }
+        Err(e) => {
+            error!(impersonation_id = claims.jti, error = ?e, "Error loading impersonation");
+            return Err("unable to verify impersonation".to_string());
+        }
+    };
+
+    let method = req.method().as_str();
+    let path = req.uri().path();
+    let result = sqlx::query!(
+        "INSERT INTO fcm_impersonation_request (impersonation_id, method, path, created_at) VALUES (?, ?, ?, ?)",
+        claims.jti,
+        method,
+        path,
+        current_time
+    )
+    .execute(pool)
+    .await;
+
+    // a request that can't be audited isn't served
+    if let Err(e) = result {
+        error!(impersonation_id = claims.jti, error = ?e, "Failed to record impersonated request");
+        return Err("unable to record impersonated request".to_string());
+    }
+
+    let sign_in_provider = if anonymous {
+        "anonymous"
+    } else {
+        "impersonation"
+    };
+    let custom = HashMap::from([
+        ("impersonation_id".to_string(), json!(claims.jti)),
+        (
+            "firebase".to_string(),
+            json!({ "sign_in_provider": sign_in_provider }),
+        ),
+    ]);
+
+    Ok(Claims {
+        aud: claims.aud,
+        user_id: claims.sub,
+        custom,
+    })
+}
+
+/// Impersonations with the number of requests made with each, newest first
+pub async fn list(
+    pool: &SqlitePool,
+    fb_user_id: Option<&str>,
+) -> Result<Vec<Impersonation>, sqlx::Error> {
+    sqlx::query_as!(
+        Impersonation,
+        r#"SELECT
+            fcm_impersonation.id as "id!",
+            fcm_impersonation.fb_user_id,
+            fcm_impersonation.fb_project_id,
+            fcm_impersonation.operator,
+            fcm_impersonation.reason,
+            fcm_impersonation.expires_at,
+            fcm_impersonation.revoked_at,
+            fcm_impersonation.created_at,
+            COUNT(fcm_impersonation_request.id) as "requests!: i64"
+        FROM fcm_impersonation
+        LEFT JOIN fcm_impersonation_request ON fcm_impersonation_request.impersonation_id = fcm_impersonation.id
+        WHERE ?1 IS NULL OR fcm_impersonation.fb_user_id = ?1
+        GROUP BY fcm_impersonation.id
+        ORDER BY fcm_impersonation.id DESC"#,
+        fb_user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Impersonation with the number of requests made with it
+pub async fn find(pool: &SqlitePool, id: i64) -> Result<Option<Impersonation>, sqlx::Error> {
+    sqlx::query_as!(
+        Impersonation,
+        r#"SELECT
+            fcm_impersonation.id as "id!",
+            fcm_impersonation.fb_user_id,
+            fcm_impersonation.fb_project_id,
+            fcm_impersonation.operator,
+            fcm_impersonation.reason,
+            fcm_impersonation.expires_at,
+            fcm_impersonation.revoked_at,
+            fcm_impersonation.created_at,
+            COUNT(fcm_impersonation_request.id) as "requests!: i64"
+        FROM fcm_impersonation
+        LEFT JOIN fcm_impersonation_request ON fcm_impersonation_request.impersonation_id = fcm_impersonation.id
+        WHERE fcm_impersonation.id = ?
+        GROUP BY fcm_impersonation.id"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDateTime};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const SECRET: &str = "test-secret";
+
+    async fn pool() -> SqlitePool {
+        // a single connection keeps the in-memory database for the whole test
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        pool
+    }
+
+    async fn impersonation(
+        pool: &SqlitePool,
+        expires_at: NaiveDateTime,
+        revoked_at: Option<NaiveDateTime>,
+    ) -> Impersonation {
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            "INSERT OR IGNORE INTO fcm_user (fb_user_id, fb_project_id, anonymous, last_seen_at, created_at)
+            VALUES ('user', 'project', 0, ?1, ?1)",
+            now
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let id = sqlx::query!(
+            "INSERT INTO fcm_impersonation (fb_user_id, fb_project_id, operator, reason, expires_at, revoked_at, created_at)
+            VALUES ('user', 'project', 'operator', 'reason', ?, ?, ?)",
+            expires_at,
+            revoked_at,
+            now
+        )
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+        find(pool, id).await.unwrap().unwrap()
+    }
+
+    fn request() -> Request {
+        Request::builder().uri_str("/api/v1/fcm/me").finish()
+    }
+
+    #[tokio::test]
+    async fn acts_as_the_user_and_records_requests() {
+        let pool = pool().await;
+        let expires_at = Utc::now().naive_utc() + Duration::minutes(15);
+        let impersonation = impersonation(&pool, expires_at, None).await;
+        let token = sign(SECRET, &impersonation).unwrap();
+
+        let claims = authenticate_with(&request(), &pool, SECRET, &token)
+            .await
+            .unwrap();
+        assert_eq!(claims.user_id, "user");
+        assert_eq!(claims.aud, "project");
+        assert_eq!(claims.custom["impersonation_id"], json!(impersonation.id));
+
+        let impersonation = find(&pool, impersonation.id).await.unwrap().unwrap();
+        assert_eq!(impersonation.requests, 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_revoked_impersonations() {
+        let pool = pool().await;
+        let now = Utc::now().naive_utc();
+        let impersonation = impersonation(&pool, now + Duration::minutes(15), Some(now)).await;
+        let token = sign(SECRET, &impersonation).unwrap();
+
+        let result = authenticate_with(&request(), &pool, SECRET, &token).await;
+        assert!(result.is_err());
+
+        let impersonation = find(&pool, impersonation.id).await.unwrap().unwrap();
+        assert_eq!(impersonation.requests, 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_tokens() {
+        let pool = pool().await;
+        let expires_at = Utc::now().naive_utc() - Duration::minutes(5);
+        let impersonation = impersonation(&pool, expires_at, None).await;
+        let token = sign(SECRET, &impersonation).unwrap();
+
+        let result = authenticate_with(&request(), &pool, SECRET, &token).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_signed_with_another_secret() {
+        let pool = pool().await;
+        let expires_at = Utc::now().naive_utc() + Duration::minutes(15);
+        let impersonation = impersonation(&pool, expires_at, None).await;
+        let token = sign("another-secret", &impersonation).unwrap();
+
+        let result = authenticate_with(&request(), &pool, SECRET, &token).await;
+        assert!(result.is_err());
+    }
+}
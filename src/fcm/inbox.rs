@@ -0,0 +1,336 @@
+use super::model::{EmailInbox, InboundEmail, InboundEmailResult, NewEmailInbox, ProjectSettings};
+use super::sender::build_payload_message;
+use super::tokens;
+use super::utils::authenticate;
+use crate::outbox::{self, Channel, Priority};
+use crate::utils::{ApiTags, JsonError, JsonSuccess, ResponseObject, DRY_RUN};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use openssl::rand::rand_bytes;
+use poem::{web::Data, Request};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+use regex::RegexBuilder;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::env;
+use tracing::{debug, info};
+
+lazy_static! {
+    // domain the inbound email provider receives mail for, inboxes are disabled when unset
+    static ref EMAIL_INBOUND_DOMAIN: Option<String> = env::var("EMAIL_INBOUND_DOMAIN").ok();
+}
+
+// longest email text sent as the notification body
+const MAX_BODY_CHARS: usize = 512;
+
+#[derive(Default)]
+pub struct FirebaseInboxes;
+
+#[OpenApi(
+    prefix_path = "/fcm/inboxes/",
+    request_header(
+        name = "firebase-auth",
+        ty = "String",
+        description = "Bearer token generated from firebase project (example: <code>Bearer {token}</code>)"
+    ),
+    tag = "ApiTags::FirebaseMessaging"
+)]
+impl FirebaseInboxes {
+    // Create an email address whose received emails are sent to the device
+    #[oai(path = "/", method = "post", operation_id = "fcm::create_inbox")]
+    async fn create_inbox(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        inbox: Json<NewEmailInbox>,
+    ) -> Result<JsonSuccess<EmailInbox>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let domain = match EMAIL_INBOUND_DOMAIN.as_ref() {
+            Some(domain) => domain,
+            None => {
+                return Err(ResponseObject::bad_request(
+                    "Email inboxes are not configured",
+                ));
+            }
+        };
+
+        if let Err(e) = tokens::validate_format(&inbox.push_token) {
+            return Err(ResponseObject::bad_request(e));
+        }
+
+        for filter in [&inbox.sender_filter, &inbox.subject_filter]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(e) = RegexBuilder::new(filter).case_insensitive(true).build() {
+                return Err(ResponseObject::bad_request(format!(
+                    "Invalid filter: {}",
+                    e
+                )));
+            }
+        }
+
+        let local_part = match random_local_part() {
+            Ok(local_part) => local_part,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+        let address = format!("{}@{}", local_part, domain.to_lowercase());
+        let current_time = Utc::now().naive_utc();
+
+        let result = sqlx::query!(
+            "INSERT INTO fcm_email_inbox (fb_user_id, fb_project_id, push_token, address, sender_filter, subject_filter, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+            claims.user_id,
+            claims.aud,
+            inbox.push_token,
+            address,
+            inbox.sender_filter,
+            inbox.subject_filter,
+            current_time
+        )
+        .execute(pool.0)
+        .await;
+
+        let id = match result {
+            Ok(result) => result.last_insert_rowid(),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        match fetch_inbox(pool.0, id, &claims.user_id).await {
+            Ok(Some(inbox)) => Ok(ResponseObject::created(inbox)),
+            Ok(None) => Err(ResponseObject::not_found("Inbox not found")),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // List the email inboxes of the user
+    #[oai(path = "/", method = "get", operation_id = "fcm::list_inboxes")]
+    async fn list_inboxes(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<EmailInbox>>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let inboxes = sqlx::query_as!(
+            EmailInbox,
+            r#"SELECT id as "id!", push_token, address, sender_filter, subject_filter, received, created_at
+            FROM fcm_email_inbox WHERE fb_user_id = ? ORDER BY id"#,
+            claims.user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match inboxes {
+            Ok(inboxes) => Ok(ResponseObject::ok(inboxes)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Delete an email inbox, emails sent to its address are dropped afterwards
+    #[oai(path = "/:id", method = "delete", operation_id = "fcm::delete_inbox")]
+    async fn delete_inbox(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<EmailInbox>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let inbox = match fetch_inbox(pool.0, id.0, &claims.user_id).await {
+            Ok(Some(inbox)) => inbox,
+            Ok(None) => {
+                return Err(ResponseObject::not_found("Inbox not found"));
+            }
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_email_inbox WHERE id = ? AND fb_user_id = ?",
+            id.0,
+            claims.user_id
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(_) => Ok(ResponseObject::ok(inbox)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
+
+/// Queue a push notification for an email received by one of the inboxes
+pub async fn forward(
+    pool: &SqlitePool,
+    email: &InboundEmail,
+) -> Result<InboundEmailResult, String> {
+    let address = recipient_address(&email.recipient);
+
+    let inbox = sqlx::query!(
+        r#"SELECT id as "id!", fb_user_id, fb_project_id, push_token, sender_filter, subject_filter
+        FROM fcm_email_inbox WHERE address = ?"#,
+        address
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let inbox = match inbox {
+        Some(inbox) => inbox,
+        None => {
+            debug!(recipient = %email.recipient, "No inbox for the recipient");
+            return Ok(InboundEmailResult {
+                inbox_id: None,
+                forwarded: false,
+            });
+        }
+    };
+
+    let matches = |filter: &Option<String>, value: &str| {
+        filter.as_ref().is_none_or(|filter| {
+            RegexBuilder::new(filter)
+                .case_insensitive(true)
+                .build()
+                .is_ok_and(|regex| regex.is_match(value))
+        })
+    };
+
+    if !matches(&inbox.sender_filter, &email.sender)
+        || !matches(&inbox.subject_filter, &email.subject)
+    {
+        debug!(inbox_id = inbox.id, "Email did not match the inbox filters");
+        return Ok(InboundEmailResult {
+            inbox_id: Some(inbox.id),
+            forwarded: false,
+        });
+    }
+
+    let title = if email.subject.trim().is_empty() {
+        email.sender.clone()
+    } else {
+        email.subject.trim().to_string()
+    };
+    let body = match email.text.as_deref().map(str::trim) {
+        Some(text) if !text.is_empty() => text.chars().take(MAX_BODY_CHARS).collect(),
+        _ => email.sender.clone(),
+    };
+    let payload = json!({
+        "title": title,
+        "body": body,
+        "sender": email.sender,
+        "subject": email.subject,
+        "inbox_id": inbox.id.to_string(),
+    });
+
+    let project = sqlx::query_as!(
+        ProjectSettings,
+        "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+        inbox.fb_project_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let firebase_message = build_payload_message(&inbox.push_token, &payload, project.as_ref());
+    let payload = serde_json::to_value(&firebase_message).map_err(|e| e.to_string())?;
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+
+    outbox::enqueue(
+        &mut *tx,
+        outbox::NewMessage {
+            channel: Channel::Fcm,
+            target: &inbox.push_token,
+            project_id: &inbox.fb_project_id,
+            schedule_id: None,
+            fb_user_id: Some(&inbox.fb_user_id),
+            payload,
+            dry_run: *DRY_RUN,
+            timeout_seconds: None,
+            priority: Priority::High,
+            broadcast_id: None,
+            scheduled_at: None,
+            execution_key: None,
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    sqlx::query!(
+        "UPDATE fcm_email_inbox SET received = received + 1 WHERE id = ?",
+        inbox.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    info!(
+        inbox_id = inbox.id,
+        "Forwarded email as a push notification"
+    );
+
+    Ok(InboundEmailResult {
+        inbox_id: Some(inbox.id),
+        forwarded: true,
+    })
+}
+
+async fn fetch_inbox(
+    pool: &SqlitePool,
+    id: i64,
+    fb_user_id: &str,
+) -> Result<Option<EmailInbox>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailInbox,
+        r#"SELECT id as "id!", push_token, address, sender_filter, subject_filter, received, created_at
+        FROM fcm_email_inbox WHERE id = ? AND fb_user_id = ?"#,
+        id,
+        fb_user_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Bare lowercase address of a recipient such as `Alerts <abc@example.com>`
+fn recipient_address(recipient: &str) -> String {
+    let recipient = recipient.trim();
+    let address = match (recipient.rfind('<'), recipient.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &recipient[start + 1..end],
+        _ => recipient,
+    };
+    address.trim().to_lowercase()
+}
+
+/// Unguessable local part of an inbox address, the address is the only secret of an inbox
+fn random_local_part() -> Result<String, String> {
+    let mut local_part = [0u8; 16];
+    rand_bytes(&mut local_part).map_err(|e| e.to_string())?;
+    Ok(local_part
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
@@ -0,0 +1,61 @@
+use super::model::ProjectSettings;
+use serde_json::Value;
+use url::Url;
+
+// payload keys apps commonly use to open a screen or page when the notification is clicked
+const LINK_KEYS: &[&str; 4] = &["click_action", "link", "deep_link", "url"];
+
+/// Check the links of a payload against the schemes and hosts allowed by the project.
+/// Values that aren't URIs (e.g. android intent actions) are left alone.
+pub fn validate_links(payload: &Value, project: Option<&ProjectSettings>) -> Result<(), String> {
+    let project = match project {
+        Some(project) => project,
+        None => return Ok(()),
+    };
+    let schemes = &project.allowed_link_schemes.0;
+    let hosts = &project.allowed_link_hosts.0;
+    if schemes.is_empty() && hosts.is_empty() {
+        return Ok(());
+    }
+
+    for key in LINK_KEYS {
+        let link = match payload.get(key).and_then(Value::as_str) {
+            Some(link) if link.contains(':') => link,
+            _ => continue,
+        };
+
+        let url = match Url::parse(link) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        if !schemes.is_empty() && !schemes.iter().any(|s| s.eq_ignore_ascii_case(url.scheme())) {
+            return Err(format!(
+                "{} uses the `{}` scheme which is not allowed by the project",
+                key,
+                url.scheme()
+            ));
+        }
+
+        if let Some(host) = url.host_str() {
+            if !hosts.is_empty() && !hosts.iter().any(|allowed| host_matches(host, allowed)) {
+                return Err(format!(
+                    "{} points to `{}` which is not allowed by the project",
+                    key, host
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn host_matches(host: &str, allowed: &str) -> bool {
+    let host = host.to_lowercase();
+    let allowed = allowed.to_lowercase();
+
+    match allowed.strip_prefix('.') {
+        Some(domain) => host == domain || host.ends_with(&allowed),
+        None => host == allowed,
+    }
+}
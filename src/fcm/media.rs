@@ -0,0 +1,63 @@
+use crate::http;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use serde_json::Value;
+use std::time::Duration;
+use url::Url;
+
+// FCM drops notification images larger than 1MB
+// https://firebase.google.com/docs/cloud-messaging/android/send-image
+const MAX_IMAGE_SIZE: u64 = 1024 * 1024;
+
+/// Check that the `image` of a payload can be rendered by the devices: an https URL
+/// serving an image no larger than FCM accepts
+pub async fn validate_media(payload: &Value) -> Result<(), String> {
+    let image = match payload.get("image") {
+        Some(Value::String(image)) => image,
+        Some(_) => return Err("image must be a URL".to_string()),
+        None => return Ok(()),
+    };
+
+    let url = Url::parse(image).map_err(|e| format!("Invalid image URL: {}", e))?;
+    if url.scheme() != "https" {
+        return Err("image must be served over https".to_string());
+    }
+
+    let request = http::CLIENT
+        .head(url.as_str())
+        .timeout(Duration::from_secs(10));
+    let response = http::send(request)
+        .await
+        .map_err(|e| format!("image is not reachable: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("image is not reachable: {}", response.status()));
+    }
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with("image/") {
+        return Err(format!(
+            "image must have an image content type, got `{}`",
+            content_type
+        ));
+    }
+
+    let size = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if let Some(size) = size {
+        if size > MAX_IMAGE_SIZE {
+            return Err(format!(
+                "image is {} bytes, FCM only displays images up to {} bytes",
+                size, MAX_IMAGE_SIZE
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -1,18 +1,127 @@
+use crate::config;
+use crate::metrics;
+use crate::outbox::Priority;
+use crate::utils::{
+    OUTBOX_CONCURRENCY_HIGH, OUTBOX_CONCURRENCY_LOW, OUTBOX_CONCURRENCY_NORMAL, READ_ONLY,
+};
 use sqlx::SqlitePool;
 
+mod accounts;
+mod actions;
+mod admin;
+mod anomaly;
+mod backup;
+mod batches;
+mod broadcast;
+mod callbacks;
+mod compression;
+mod conditions;
+mod devices;
+mod errors;
+mod followups;
 mod handler;
+mod holidays;
+mod impersonation;
+mod inbox;
+mod links;
+mod media;
 mod model;
+mod organizations;
+mod payload;
+mod policy;
+mod sender;
+mod sharing;
+mod store;
+mod template;
+mod tokens;
 mod utils;
+mod verifier;
+mod webhook;
 mod worker;
 
-pub async fn fcm_api(pool: SqlitePool) -> handler::FirebaseMessaging {
-    let service_accounts = worker::read_in_serivce_accounts().await.unwrap();
+pub use worker::notify_user;
 
-    let fcm_api = handler::FirebaseMessaging::new(service_accounts.keys().cloned().collect());
+pub async fn fcm_api(
+    pool: SqlitePool,
+) -> (
+    handler::FirebaseMessaging,
+    webhook::FirebaseWebhooks,
+    broadcast::FirebaseBroadcasts,
+    inbox::FirebaseInboxes,
+    sharing::FirebaseShares,
+    organizations::FirebaseOrganizations,
+    actions::FirebaseActions,
+    admin::FirebaseAdmin,
+) {
+    let service_accounts = accounts::ServiceAccounts::load().await.unwrap();
+
+    let fcm_api = handler::FirebaseMessaging::new(service_accounts.clone());
+
+    let broadcast_api = broadcast::FirebaseBroadcasts::new(service_accounts.clone());
+
+    let reloaded_accounts = service_accounts.clone();
+    config::on_reload("service_accounts", move || {
+        let service_accounts = reloaded_accounts.clone();
+        async move {
+            match service_accounts.reload().await {
+                Ok(projects) => Ok(format!("projects: {}", projects.join(", "))),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+    });
+    config::on_reload("claim_policy", || async { Ok(policy::reload()) });
+
+    // replicas only serve reads, the primary instance runs the scheduler
+    if *READ_ONLY {
+        return (
+            fcm_api,
+            webhook::FirebaseWebhooks,
+            broadcast_api,
+            inbox::FirebaseInboxes,
+            sharing::FirebaseShares,
+            organizations::FirebaseOrganizations,
+            actions::FirebaseActions,
+            admin::FirebaseAdmin,
+        );
+    }
+
+    worker::recover_outbox(&pool).await;
+    worker::recover_broadcasts(&pool).await;
+
+    for (priority, concurrency) in [
+        (Priority::High, *OUTBOX_CONCURRENCY_HIGH),
+        (Priority::Normal, *OUTBOX_CONCURRENCY_NORMAL),
+        (Priority::Low, *OUTBOX_CONCURRENCY_LOW),
+    ] {
+        tokio::spawn(worker::deliver_outbox(
+            service_accounts.clone(),
+            pool.clone(),
+            priority,
+            concurrency,
+        ));
+    }
+
+    tokio::spawn(metrics::watch_burn_rate());
+    tokio::spawn(anomaly::watch_send_volume(pool.clone()));
+    tokio::spawn(callbacks::deliver_pending(pool.clone()));
+
+    let cleanup_pool = pool.clone();
+    tokio::spawn(async move {
+        worker::run_every_minute(&pool).await;
+    });
 
     tokio::spawn(async move {
-        worker::run_every_minute(service_accounts, &pool).await;
+        worker::purge_inactive_anonymous_users(&cleanup_pool).await;
     });
 
-    return fcm_api;
+    return (
+        fcm_api,
+        webhook::FirebaseWebhooks,
+        broadcast_api,
+        inbox::FirebaseInboxes,
+        sharing::FirebaseShares,
+        organizations::FirebaseOrganizations,
+        actions::FirebaseActions,
+        admin::FirebaseAdmin,
+    );
 }
@@ -1,23 +1,454 @@
-use chrono::NaiveDateTime;
-use poem_openapi::Object;
-use serde::Serialize;
+use crate::outbox::Priority;
+use crate::quota::QuotaStatus;
+use chrono::{NaiveDate, NaiveDateTime};
+use poem_openapi::{types::Example, Enum, NewType, Object};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
 
 fn payload_example() -> Value {
     serde_json::from_str("{\"title\": \"Reminder\", \"body\": \"Drink water\", \"foo\": \"bar\"}")
         .unwrap()
 }
 
+// version of the schedule export format, bumped when imports can't read older exports
+pub const EXPORT_VERSION: i64 = 1;
+
+fn export_version() -> i64 {
+    EXPORT_VERSION
+}
+
 fn cron_example() -> String {
     "*/1 * * * *".to_string()
 }
 
+fn timezone_default() -> String {
+    "UTC".to_string()
+}
+
 fn name_example() -> String {
     "Remind me to drink water every 45 minutes".to_string()
 }
 
+fn push_token_example() -> String {
+    "dGVzdC1yZWdpc3RyYXRpb24tdG9rZW46QVBBOTFiSGV4YW1wbGVfdG9rZW4".to_string()
+}
+
+fn canary_delay_example() -> i64 {
+    300
+}
+
+fn canary_max_error_rate_example() -> i64 {
+    10
+}
+
+fn time_example() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 1, 1)
+        .and_then(|date| date.and_hms_opt(9, 0, 0))
+        .unwrap_or_default()
+}
+
+/// How a schedule decides when to send
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ScheduleType {
+    #[default]
+    /// sends every time the cron pattern matches
+    Recurring,
+    /// sends once at `run_at` and is then marked completed
+    Once,
+}
+
+impl From<String> for ScheduleType {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "once" => ScheduleType::Once,
+            _ => ScheduleType::Recurring,
+        }
+    }
+}
+
+/// Whether the data of a schedule is packed before it's sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    /// data is sent as it is
+    None,
+    /// data is always packed
+    Gzip,
+    /// data is packed when it's large and packing makes it smaller
+    Auto,
+}
+
+impl From<String> for Compression {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "gzip" => Compression::Gzip,
+            "auto" => Compression::Auto,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// What happens to executions missed while the server was down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum CatchUp {
+    /// missed executions are dropped, the schedule waits for its next time
+    Skip,
+    #[default]
+    /// a single message is sent right away, however many executions were missed
+    Once,
+    /// a message is sent for each missed execution of a recurring schedule
+    All,
+}
+
+impl From<String> for CatchUp {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "skip" => CatchUp::Skip,
+            "all" => CatchUp::All,
+            _ => CatchUp::Once,
+        }
+    }
+}
+
+/// What a user a schedule is shared with can do with it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ShareRole {
+    #[default]
+    /// sees the schedule and its executions
+    Viewer,
+    /// also edits, runs and manages the devices of the schedule
+    Editor,
+}
+
+impl From<String> for ShareRole {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "editor" => ShareRole::Editor,
+            _ => ShareRole::Viewer,
+        }
+    }
+}
+
+/// What the `push_token` of a schedule addresses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum TargetType {
+    #[default]
+    /// a device registration token
+    Token,
+    /// every device subscribed to a topic, e.g. `/topics/news`
+    Topic,
+    /// devices matching a condition over their topics, e.g. `'news' in topics && 'sports' in topics`
+    Condition,
+}
+
+impl From<String> for TargetType {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "topic" => TargetType::Topic,
+            "condition" => TargetType::Condition,
+            _ => TargetType::Token,
+        }
+    }
+}
+
+/// Outcome of a delivery a follow-up runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum FollowUpOutcome {
+    /// FCM accepted the message
+    Success,
+    /// the message failed or timed out after every retry
+    Failure,
+}
+
+impl From<String> for FollowUpOutcome {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "success" => FollowUpOutcome::Success,
+            _ => FollowUpOutcome::Failure,
+        }
+    }
+}
+
+/// What a follow-up does once it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum FollowUpAction {
+    /// send `target_schedule_id` on the next scheduler run
+    Trigger,
+    /// post the outcome of the delivery to `url`
+    Webhook,
+    /// send the schedule again once, `delay_minutes` later
+    Remind,
+}
+
+impl From<String> for FollowUpAction {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "webhook" => FollowUpAction::Webhook,
+            "remind" => FollowUpAction::Remind,
+            _ => FollowUpAction::Trigger,
+        }
+    }
+}
+
+/// Role of a member in an organization
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum, Serialize, sqlx::Type)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum OrganizationRole {
+    /// manages the members and deletes or shares the schedules of the organization
+    Admin,
+    #[default]
+    /// creates, edits and runs the schedules of the organization
+    Member,
+    /// sees the schedules of the organization and their executions
+    Viewer,
+}
+
+impl From<String> for OrganizationRole {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "admin" => OrganizationRole::Admin,
+            "member" => OrganizationRole::Member,
+            _ => OrganizationRole::Viewer,
+        }
+    }
+}
+
+impl OrganizationRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrganizationRole::Admin => "admin",
+            OrganizationRole::Member => "member",
+            OrganizationRole::Viewer => "viewer",
+        }
+    }
+}
+
+/// Field schedules are listed by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[oai(rename_all = "snake_case")]
+pub enum ScheduleOrder {
+    #[default]
+    Id,
+    Name,
+    NextExecution,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl ScheduleOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleOrder::Id => "id",
+            ScheduleOrder::Name => "name",
+            ScheduleOrder::NextExecution => "next_execution",
+            ScheduleOrder::CreatedAt => "created_at",
+            ScheduleOrder::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+/// State of a schedule's deliveries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+#[oai(rename_all = "lowercase")]
+pub enum ScheduleStatus {
+    /// deliveries are scheduled
+    Active,
+    /// deliveries were stopped for any reason
+    Disabled,
+    /// deliveries were stopped because they kept failing, the push token is no longer valid
+    /// or the cron pattern can't be parsed
+    Failed,
+    /// deliveries were stopped by an operator
+    Blocked,
+    /// deliveries were paused by the user until they're resumed
+    Paused,
+}
+
+impl ScheduleStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleStatus::Active => "active",
+            ScheduleStatus::Disabled => "disabled",
+            ScheduleStatus::Failed => "failed",
+            ScheduleStatus::Blocked => "blocked",
+            ScheduleStatus::Paused => "paused",
+        }
+    }
+}
+
+/// Labels used to select schedules in bulk, stored as a JSON array
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, NewType)]
+#[oai(from_parameter = false, from_multipart = false, to_header = false)]
+pub struct Tags(pub Vec<String>);
+
+impl From<String> for Tags {
+    fn from(value: String) -> Self {
+        Tags(serde_json::from_str(&value).unwrap_or_default())
+    }
+}
+
+impl Tags {
+    /// JSON array stored in the tags column
+    pub fn to_db(&self) -> String {
+        serde_json::to_string(&self.0).unwrap_or("[]".to_string())
+    }
+}
+
+/// What a send condition checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, Deserialize)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionKind {
+    /// whether `url` answers with a successful status (`up`) or not (`down`)
+    Uptime,
+    /// whether the shared KV store holds `value` under `key`
+    Kv,
+    /// whether `expression` holds over the JSON `url` answers with
+    Json,
+}
+
+/// State of the URL an uptime condition expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize, Deserialize)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum UptimeState {
+    Up,
+    Down,
+}
+
+/// Check evaluated right before a schedule is sent
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SendCondition {
+    pub kind: ConditionKind,
+    /// URL probed by `uptime` and fetched by `json` conditions
+    pub url: Option<String>,
+    /// state the URL has to be in for `uptime` conditions
+    pub state: Option<UptimeState>,
+    /// key looked up by `kv` conditions
+    pub key: Option<String>,
+    /// value the key has to hold for `kv` conditions
+    pub value: Option<String>,
+    /// JMESPath style predicate for `json` conditions, e.g. `status.indicator == 'none'`
+    pub expression: Option<String>,
+}
+
+/// Send conditions of a schedule, stored as a JSON array
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, NewType)]
+#[oai(from_parameter = false, from_multipart = false, to_header = false)]
+pub struct Conditions(pub Vec<SendCondition>);
+
+impl From<String> for Conditions {
+    fn from(value: String) -> Self {
+        Conditions(serde_json::from_str(&value).unwrap_or_default())
+    }
+}
+
+impl Conditions {
+    /// JSON array stored in the conditions column
+    pub fn to_db(&self) -> String {
+        serde_json::to_string(&self.0).unwrap_or("[]".to_string())
+    }
+}
+
+/// List of allowed values, stored as a JSON array
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, NewType)]
+#[oai(from_parameter = false, from_multipart = false, to_header = false)]
+pub struct Allowlist(pub Vec<String>);
+
+impl From<String> for Allowlist {
+    fn from(value: String) -> Self {
+        Allowlist(serde_json::from_str(&value).unwrap_or_default())
+    }
+}
+
+impl Allowlist {
+    /// JSON array stored in the column
+    pub fn to_db(&self) -> String {
+        serde_json::to_string(&self.0).unwrap_or("[]".to_string())
+    }
+}
+
+/// Notification shown by the device (https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#notification)
+#[derive(Debug, Object, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MessageNotification {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[oai(validator(max_length = 2048))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// http(s) URL of an image shown in the notification
+    pub image: Option<String>,
+}
+
+/// Blocks of an FCM HTTP v1 message, stored as a JSON object
+/// (https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages)
+///
+/// A message with only `notification` is shown by the system, one with only `data` is handed
+/// to the app, and one with both is shown by the system with the data passed to the app when opened
+#[derive(Debug, Object, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FcmMessage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification: Option<MessageNotification>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// key/value pairs handed to the app, e.g. {"screen": "water"}
+    pub data: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// AndroidConfig, e.g. {"priority": "high", "notification": {"channel_id": "reminders"}}
+    pub android: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// ApnsConfig, e.g. {"headers": {"apns-priority": "5"}, "payload": {"aps": {"sound": "default"}}}
+    pub apns: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// WebpushConfig, e.g. {"fcm_options": {"link": "https://example.com/water"}}
+    pub webpush: Option<Value>,
+}
+
+impl From<String> for FcmMessage {
+    fn from(value: String) -> Self {
+        serde_json::from_str(&value).unwrap_or_default()
+    }
+}
+
+impl FcmMessage {
+    /// JSON object stored in the message column
+    pub fn to_db(&self) -> String {
+        serde_json::to_string(self).unwrap_or("{}".to_string())
+    }
+
+    /// Whether the message has no blocks, the schedule's payload is sent instead
+    pub fn is_empty(&self) -> bool {
+        self == &FcmMessage::default()
+    }
+}
+
 /// Create FCM Schedule schema
 #[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+#[oai(example)]
 pub struct FCMSchedule {
     #[oai(read_only)]
     /// ID of the schedule
@@ -31,23 +462,118 @@ pub struct FCMSchedule {
     /// firebase user id (decoded from token)
     pub fb_user_id: String,
 
-    #[oai(validator(min_length = 32, max_length = 512))]
-    /// device registration token to send the FCM (https://firebase.google.com/docs/cloud-messaging/manage-tokens)
+    #[oai(validator(min_length = 1, max_length = 512))]
+    /// device registration token to send the FCM (https://firebase.google.com/docs/cloud-messaging/manage-tokens),
+    /// or the topic or condition to send it to when `target_type` says so
     pub push_token: String,
 
+    #[oai(default)]
+    /// whether `push_token` is a device token, a topic or a condition
+    pub target_type: TargetType,
+
     #[oai(read_only)]
     /// firebase project id (decoded from token)
     pub fb_project_id: String,
 
+    /// organization owning the schedule together with its creator, the user has to be a member allowed to edit
+    pub organization_id: Option<i64>,
+
     #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
     /// cron pattern to schedule the FCM (support multiple cron patterns separated by comma)
+    ///
+    /// Five fields evaluated in the schedule's timezone: `minute hour day-of-month month day-of-week`,
+    /// e.g. `0 9 * * 1-5` sends at 09:00 on weekdays. A leading sixth field sets the seconds,
+    /// e.g. `30 0 9 * * *`, and `@every 15m` sends at a fixed interval (units s, m, h and d)
+    /// after the last execution. Executions can't be closer together than FCM_MIN_INTERVAL_SECS
     pub cron_pattern: String,
 
+    #[oai(validator(max_length = 64), default = "timezone_default")]
+    /// IANA timezone the cron pattern is evaluated in (e.g. Europe/London), 09:00 stays 09:00 across DST changes
+    pub timezone: String,
+
+    #[oai(default)]
+    /// evaluate the cron pattern or `run_at` in the timezone each device registered through `/fcm/me/devices`,
+    /// so `0 9 * * *` reaches every device at 09:00 its local time, devices without one use `timezone`
+    pub local_time: bool,
+
+    #[oai(default)]
+    /// recurring schedules follow the cron pattern, one-shot schedules send once at `run_at`
+    pub schedule_type: ScheduleType,
+
+    /// local time in the schedule's timezone to send a one-shot schedule, e.g. 2026-10-20T09:00:00
+    pub run_at: Option<NaiveDateTime>,
+
     /// payload to send to the FCM (JSON) e.g. {"some": "data", "another": "data"}
     /// If title and body are present, they will be used as notification
     #[oai(default = "payload_example")]
     pub payload: Value,
 
+    #[oai(default)]
+    /// structured FCM message sent instead of `payload` when it has any block
+    pub message: FcmMessage,
+
+    #[oai(default)]
+    /// pack the data into `{"compression": "gzip", "payload": "<base64>"}` for the app to unpack,
+    /// so data near FCM's 4KB limit fits, the notification is sent as it is
+    pub compression: Compression,
+
+    #[oai(validator(minimum(value = "1"), maximum(value = "300")))]
+    /// seconds to wait for a send to complete before cancelling it (defaults to SEND_TIMEOUT_SECS)
+    pub timeout_seconds: Option<i64>,
+
+    #[oai(validator(minimum(value = "1"), maximum(value = "3600")))]
+    /// send up to this many seconds after the scheduled time, picked at random on every
+    /// execution, so schedules sharing a time don't all send at once
+    pub jitter_seconds: Option<i64>,
+
+    #[oai(default)]
+    /// executions missed while the server was down are skipped, sent once or all sent
+    pub catch_up: CatchUp,
+
+    #[oai(default)]
+    /// delivery priority, high priority schedules are never queued behind bulk sends
+    pub priority: Priority,
+
+    #[oai(default)]
+    /// labels to select the schedule in bulk operations, e.g. ["morning"]
+    pub tags: Tags,
+
+    #[oai(default)]
+    /// checks that all have to pass when the schedule is due, the send is skipped otherwise
+    pub conditions: Conditions,
+
+    /// time (UTC) the schedule stops sending, it's disabled as `expired` once reached
+    pub expires_at: Option<NaiveDateTime>,
+
+    #[oai(validator(minimum(value = "1")))]
+    /// number of sends after which the schedule is disabled as `max_executions`
+    pub max_executions: Option<i64>,
+
+    #[oai(validator(max_length = 512))]
+    /// built-in holiday calendar (de, gb or us) or URL of an ICS file, sends due on a listed date in the schedule's timezone are skipped
+    pub holiday_calendar: Option<String>,
+
+    #[oai(read_only)]
+    /// number of times the scheduler sent the schedule, skipped sends aren't counted
+    pub execution_count: i64,
+
+    #[oai(read_only)]
+    /// sends left before `max_executions` is reached, absent without a limit
+    pub remaining_executions: Option<i64>,
+
+    #[oai(read_only)]
+    /// why deliveries of the schedule were stopped (e.g. invalid_token, invalid_cron, failed, pending_approval, completed, expired, max_executions, paused),
+    /// cleared when the schedule is updated unless it was blocked or paused
+    pub disabled_reason: Option<String>,
+
+    #[oai(read_only)]
+    /// deliveries that failed in a row, the schedule is disabled as `failed` once too many fail
+    pub failed_count: i64,
+
+    #[oai(read_only)]
+    /// when a delivery last failed after every retry, cleared by the next successful delivery
+    pub errored_at: Option<NaiveDateTime>,
+
     #[oai(read_only)]
     /// last time the FCM was sent
     pub last_execution: NaiveDateTime,
@@ -56,6 +582,14 @@ pub struct FCMSchedule {
     /// next time the FCM will be sent
     pub next_execution: NaiveDateTime,
 
+    #[oai(read_only)]
+    /// time (UTC) the next send was snoozed to, cleared once it's sent or the schedule is updated
+    pub snoozed_until: Option<NaiveDateTime>,
+
+    #[oai(read_only)]
+    /// incremented by every edit of the schedule, updates have to send it back in `If-Match`
+    pub version: i64,
+
     #[oai(read_only)]
     /// created time of the schedule
     pub created_at: NaiveDateTime,
@@ -66,22 +600,1443 @@ pub struct FCMSchedule {
 }
 
 /// Update FCM Schedule schema
-#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[derive(Debug, Object, Clone, Default, Eq, PartialEq)]
+#[oai(example)]
 pub struct UpdateSchedule {
     #[oai(validator(min_length = 3, max_length = 64), default = "name_example")]
     /// Friendly name of the schedule
     pub name: String,
 
-    #[oai(validator(min_length = 32, max_length = 512))]
-    /// device registration token to send the FCM (https://firebase.google.com/docs/cloud-messaging/manage-tokens)
+    #[oai(validator(min_length = 1, max_length = 512))]
+    /// device registration token to send the FCM (https://firebase.google.com/docs/cloud-messaging/manage-tokens),
+    /// or the topic or condition to send it to when `target_type` says so
     pub push_token: String,
 
+    #[oai(default)]
+    /// whether `push_token` is a device token, a topic or a condition
+    pub target_type: TargetType,
+
     #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
     /// cron pattern to schedule the FCM (support multiple cron patterns separated by comma)
     pub cron_pattern: String,
 
+    #[oai(validator(max_length = 64), default = "timezone_default")]
+    /// IANA timezone the cron pattern is evaluated in (e.g. Europe/London), 09:00 stays 09:00 across DST changes
+    pub timezone: String,
+
+    #[oai(default)]
+    /// evaluate the cron pattern or `run_at` in the timezone each device registered through `/fcm/me/devices`,
+    /// so `0 9 * * *` reaches every device at 09:00 its local time, devices without one use `timezone`
+    pub local_time: bool,
+
+    #[oai(default)]
+    /// recurring schedules follow the cron pattern, one-shot schedules send once at `run_at`
+    pub schedule_type: ScheduleType,
+
+    /// local time in the schedule's timezone to send a one-shot schedule, e.g. 2026-10-20T09:00:00
+    pub run_at: Option<NaiveDateTime>,
+
     /// payload to send to the FCM (JSON) e.g. {"some": "data", "another": "data"}
     /// If title and body are present, they will be used as notification
     #[oai(default = "payload_example")]
     pub payload: Value,
+
+    #[oai(default)]
+    /// structured FCM message sent instead of `payload` when it has any block
+    pub message: FcmMessage,
+
+    #[oai(default)]
+    /// pack the data into `{"compression": "gzip", "payload": "<base64>"}` for the app to unpack,
+    /// so data near FCM's 4KB limit fits, the notification is sent as it is
+    pub compression: Compression,
+
+    #[oai(validator(minimum(value = "1"), maximum(value = "300")))]
+    /// seconds to wait for a send to complete before cancelling it (defaults to SEND_TIMEOUT_SECS)
+    pub timeout_seconds: Option<i64>,
+
+    #[oai(validator(minimum(value = "1"), maximum(value = "3600")))]
+    /// send up to this many seconds after the scheduled time, picked at random on every
+    /// execution, so schedules sharing a time don't all send at once
+    pub jitter_seconds: Option<i64>,
+
+    #[oai(default)]
+    /// executions missed while the server was down are skipped, sent once or all sent
+    pub catch_up: CatchUp,
+
+    #[oai(default)]
+    /// delivery priority, high priority schedules are never queued behind bulk sends
+    pub priority: Priority,
+
+    #[oai(default)]
+    /// labels to select the schedule in bulk operations, e.g. ["morning"]
+    pub tags: Tags,
+
+    #[oai(default)]
+    /// checks that all have to pass when the schedule is due, the send is skipped otherwise
+    pub conditions: Conditions,
+
+    /// time (UTC) the schedule stops sending, it's disabled as `expired` once reached
+    pub expires_at: Option<NaiveDateTime>,
+
+    #[oai(validator(minimum(value = "1")))]
+    /// number of sends after which the schedule is disabled as `max_executions`
+    pub max_executions: Option<i64>,
+
+    #[oai(validator(max_length = 512))]
+    /// built-in holiday calendar (de, gb or us) or URL of an ICS file, sends due on a listed date in the schedule's timezone are skipped
+    pub holiday_calendar: Option<String>,
+}
+
+impl From<&FCMSchedule> for UpdateSchedule {
+    fn from(schedule: &FCMSchedule) -> Self {
+        UpdateSchedule {
+            name: schedule.name.clone(),
+            push_token: schedule.push_token.clone(),
+            target_type: schedule.target_type,
+            cron_pattern: schedule.cron_pattern.clone(),
+            timezone: schedule.timezone.clone(),
+            local_time: schedule.local_time,
+            schedule_type: schedule.schedule_type,
+            run_at: schedule.run_at,
+            payload: schedule.payload.clone(),
+            message: schedule.message.clone(),
+            compression: schedule.compression,
+            timeout_seconds: schedule.timeout_seconds,
+            jitter_seconds: schedule.jitter_seconds,
+            catch_up: schedule.catch_up,
+            priority: schedule.priority,
+            tags: schedule.tags.clone(),
+            conditions: schedule.conditions.clone(),
+            expires_at: schedule.expires_at,
+            max_executions: schedule.max_executions,
+            holiday_calendar: schedule.holiday_calendar.clone(),
+        }
+    }
+}
+
+/// Account of the signed in user
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct Account {
+    pub fb_user_id: String,
+    pub fb_project_id: String,
+    /// whether the account is an anonymous firebase account
+    pub anonymous: bool,
+    /// usage of the account's quotas, browser usage is the one attributed to the user id
+    pub quotas: Vec<QuotaStatus>,
+}
+
+/// Merge account schema
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct MergeAccount {
+    /// firebase token of the account being merged into the current one (example: <code>Bearer {token}</code>)
+    pub previous_token: String,
+}
+
+/// Result of merging two accounts
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct MergeResult {
+    /// firebase user id the data was moved from
+    pub previous_user_id: String,
+    /// number of schedules moved to the current account
+    pub merged_schedules: u64,
+}
+
+/// Firebase Auth user deletion event (e.g. forwarded from a `auth.user().onDelete` cloud function)
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct UserDeletedEvent {
+    /// firebase user id of the deleted user
+    pub uid: String,
+}
+
+/// Result of purging the data of a deleted user
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct PurgeResult {
+    /// firebase user id of the purged user
+    pub uid: String,
+    /// number of schedules removed
+    pub deleted_schedules: u64,
+}
+
+/// Project wide notification defaults merged into every message of the project
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ProjectSettings {
+    #[oai(read_only)]
+    /// firebase project id (decoded from token)
+    pub fb_project_id: String,
+
+    /// default notification icon (android drawable resource name)
+    pub icon: Option<String>,
+
+    #[oai(validator(pattern = r"^#[0-9a-fA-F]{6}$"))]
+    /// default notification icon color in #rrggbb format
+    pub color: Option<String>,
+
+    /// default action to be performed when the notification is clicked
+    pub click_action: Option<String>,
+
+    /// default android notification channel id
+    pub channel_id: Option<String>,
+
+    /// URI schemes links in payloads may use, e.g. ["https", "myapp"] (empty allows every scheme)
+    pub allowed_link_schemes: Allowlist,
+
+    /// hosts links in payloads may point to, `.example.com` also allows subdomains (empty allows every host)
+    pub allowed_link_hosts: Allowlist,
+
+    /// schedules created or edited by users without manage_project wait for a project admin to approve them
+    pub require_approval: bool,
+
+    /// webhook send volume alerts of the project are posted to (e.g. a Slack incoming webhook)
+    pub alert_webhook_url: Option<String>,
+
+    /// stop sending the project's schedules when its send volume bursts, until a project admin resumes them
+    pub pause_on_burst: bool,
+
+    #[oai(read_only)]
+    /// when sending the project's schedules was paused after a burst, cleared when they're resumed
+    pub paused_at: Option<NaiveDateTime>,
+
+    #[oai(read_only)]
+    /// created time of the settings
+    pub created_at: NaiveDateTime,
+
+    #[oai(read_only)]
+    /// last time the settings were updated
+    pub updated_at: NaiveDateTime,
+}
+
+/// Update project settings schema
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct UpdateProjectSettings {
+    /// default notification icon (android drawable resource name)
+    pub icon: Option<String>,
+
+    #[oai(validator(pattern = r"^#[0-9a-fA-F]{6}$"))]
+    /// default notification icon color in #rrggbb format
+    pub color: Option<String>,
+
+    /// default action to be performed when the notification is clicked
+    pub click_action: Option<String>,
+
+    /// default android notification channel id
+    pub channel_id: Option<String>,
+
+    #[oai(default)]
+    /// URI schemes links in payloads may use, e.g. ["https", "myapp"] (empty allows every scheme)
+    pub allowed_link_schemes: Allowlist,
+
+    #[oai(default)]
+    /// hosts links in payloads may point to, `.example.com` also allows subdomains (empty allows every host)
+    pub allowed_link_hosts: Allowlist,
+
+    #[oai(default)]
+    /// schedules created or edited by users without manage_project wait for a project admin to approve them
+    pub require_approval: bool,
+
+    #[oai(validator(max_length = 512))]
+    /// webhook send volume alerts of the project are posted to (e.g. a Slack incoming webhook)
+    pub alert_webhook_url: Option<String>,
+
+    #[oai(default)]
+    /// stop sending the project's schedules when its send volume bursts, until a project admin resumes them
+    pub pause_on_burst: bool,
+}
+
+/// What importing a schedule does when the user already has a schedule with its name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[oai(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    /// keep the existing schedule
+    #[default]
+    Skip,
+    /// replace the existing schedule with the imported one
+    Overwrite,
+    /// create the imported schedule next to the existing one
+    Duplicate,
+}
+
+/// Schedule of an export along with its additional devices
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct ExportedSchedule {
+    #[oai(flatten)]
+    pub schedule: UpdateSchedule,
+
+    #[oai(
+        default,
+        validator(max_items = 20, unique_items, min_length = 32, max_length = 512)
+    )]
+    /// device registration tokens sent to along with the schedule's `push_token`
+    pub push_tokens: Vec<String>,
+}
+
+/// Backup of the schedules of a user, restored by importing it
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct ScheduleExport {
+    #[oai(default = "export_version")]
+    /// version of the export format
+    pub version: i64,
+    /// when the schedules were exported
+    pub exported_at: Option<NaiveDateTime>,
+    pub schedules: Vec<ExportedSchedule>,
+}
+
+/// What importing a schedule did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ImportOutcome {
+    Created,
+    /// an existing schedule was overwritten
+    Updated,
+    /// an existing schedule was kept
+    Skipped,
+    Failed,
+}
+
+/// Result of importing a single schedule of an export
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ScheduleImportItem {
+    /// position of the schedule in the export, starting at 0
+    pub index: u64,
+    pub name: String,
+    /// id of the created, overwritten or kept schedule
+    pub id: Option<i64>,
+    pub outcome: ImportOutcome,
+    /// reason the schedule was rejected
+    pub error: Option<String>,
+}
+
+/// Result of importing an export, each schedule is imported on its own
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ScheduleImportResult {
+    pub created: u64,
+    pub updated: u64,
+    pub skipped: u64,
+    pub failed: u64,
+    pub items: Vec<ScheduleImportItem>,
+}
+
+/// Validation result of a single imported row
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ImportRowResult {
+    /// row number in the uploaded file (header excluded, starting at 1)
+    pub row: u64,
+    /// id of the created schedule
+    pub id: Option<i64>,
+    /// reason the row was rejected
+    pub error: Option<String>,
+}
+
+/// Result of importing schedules
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ImportResult {
+    /// number of schedules created
+    pub imported: u64,
+    /// number of rows rejected
+    pub failed: u64,
+    /// per row results
+    pub rows: Vec<ImportRowResult>,
+}
+
+/// Outcome of a single schedule of a bulk create
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct BulkCreateItem {
+    /// position of the schedule in the request, starting at 0
+    pub index: u64,
+    /// id of the created schedule
+    pub id: Option<i64>,
+    /// reason the schedule was rejected
+    pub error: Option<String>,
+}
+
+/// Result of creating schedules in bulk, nothing is created when any schedule is rejected
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct BulkCreateResult {
+    /// number of schedules created
+    pub created: u64,
+    /// per schedule results, in the order of the request
+    pub items: Vec<BulkCreateItem>,
+}
+
+/// Schedules to delete in bulk
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct BulkDelete {
+    #[oai(validator(min_items = 1, max_items = 100, unique_items))]
+    pub ids: Vec<i64>,
+}
+
+/// Outcome of a single schedule of a bulk delete
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct BulkDeleteItem {
+    pub id: i64,
+    pub deleted: bool,
+    /// reason the schedule wasn't deleted
+    pub error: Option<String>,
+}
+
+/// Result of deleting schedules in bulk, every schedule is deleted on its own
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct BulkDeleteResult {
+    /// number of schedules deleted
+    pub deleted: u64,
+    /// per schedule results, in the order of the request
+    pub items: Vec<BulkDeleteItem>,
+}
+
+/// Execution counts of a single day
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct HeatmapDay {
+    /// day of the executions (UTC)
+    pub date: NaiveDate,
+    /// number of successful executions
+    pub success: i64,
+    /// number of failed executions
+    pub failure: i64,
+}
+
+/// Daily execution counts of a schedule, days without executions are omitted
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct Heatmap {
+    /// first day covered by the heatmap
+    pub from: NaiveDate,
+    /// last day covered by the heatmap
+    pub to: NaiveDate,
+    /// days with at least one execution
+    pub days: Vec<HeatmapDay>,
+}
+
+/// Broadcast request schema
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct NewBroadcast {
+    /// firebase project whose registered tokens receive the broadcast
+    pub fb_project_id: String,
+
+    #[oai(validator(min_length = 1, max_length = 256))]
+    /// notification title
+    pub title: String,
+
+    #[oai(validator(min_length = 1, max_length = 1024))]
+    /// notification body
+    pub body: String,
+
+    /// additional data sent with the notification (JSON object)
+    pub data: Option<Value>,
+
+    #[oai(default)]
+    /// delivery priority of the broadcast messages
+    pub priority: Priority,
+
+    #[oai(validator(minimum(value = "1"), maximum(value = "50")))]
+    /// percentage of the tokens sent first as a canary, the rest is only sent when the canary's error rate is acceptable
+    pub canary_percent: Option<i64>,
+
+    #[oai(
+        validator(minimum(value = "10"), maximum(value = "3600")),
+        default = "canary_delay_example"
+    )]
+    /// seconds to wait after queueing the canary before checking its error rate
+    pub canary_delay_seconds: i64,
+
+    #[oai(
+        validator(minimum(value = "0"), maximum(value = "100")),
+        default = "canary_max_error_rate_example"
+    )]
+    /// highest percentage of failed canary sends that lets the broadcast continue, otherwise it's aborted
+    pub canary_max_error_rate: i64,
+}
+
+/// Broadcast progress schema
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct Broadcast {
+    /// ID of the broadcast
+    pub id: i64,
+    /// firebase project the broadcast is sent to
+    pub fb_project_id: String,
+    /// payload sent to every token
+    pub payload: Value,
+    /// delivery priority of the broadcast messages
+    pub priority: Priority,
+    /// running, completed (every token queued) or aborted
+    pub status: String,
+    /// number of tokens registered when the broadcast started
+    pub total: i64,
+    /// number of messages queued for delivery
+    pub enqueued: i64,
+    /// number of messages delivered
+    pub delivered: i64,
+    /// number of messages that failed
+    pub failed: i64,
+    /// number of tokens in the canary
+    pub canary_size: Option<i64>,
+    /// passed or failed once the canary is checked
+    pub canary_status: Option<String>,
+    /// created time of the broadcast
+    pub created_at: NaiveDateTime,
+    /// last time the broadcast progressed
+    pub updated_at: NaiveDateTime,
+}
+
+impl Example for FCMSchedule {
+    fn example() -> Self {
+        FCMSchedule {
+            id: 1,
+            name: name_example(),
+            fb_user_id: "kZ3hG1yWq8VbT0nN2cXr5sLmP4a1".to_string(),
+            push_token: push_token_example(),
+            target_type: TargetType::Token,
+            fb_project_id: "my-firebase-project".to_string(),
+            organization_id: None,
+            cron_pattern: "*/45 8-22 * * *".to_string(),
+            timezone: "Europe/London".to_string(),
+            local_time: false,
+            schedule_type: ScheduleType::Recurring,
+            run_at: None,
+            payload: payload_example(),
+            message: FcmMessage::default(),
+            compression: Compression::None,
+            timeout_seconds: Some(30),
+            jitter_seconds: None,
+            catch_up: CatchUp::Once,
+            priority: Priority::Normal,
+            tags: Tags(vec!["morning".to_string()]),
+            conditions: Conditions::default(),
+            expires_at: None,
+            max_executions: None,
+            holiday_calendar: None,
+            execution_count: 0,
+            remaining_executions: None,
+            disabled_reason: None,
+            failed_count: 0,
+            errored_at: None,
+            last_execution: time_example(),
+            next_execution: time_example(),
+            snoozed_until: None,
+            version: 1,
+            created_at: time_example(),
+            updated_at: time_example(),
+        }
+    }
+}
+
+impl Example for UpdateSchedule {
+    fn example() -> Self {
+        UpdateSchedule::from(&FCMSchedule::example())
+    }
+}
+
+impl Example for ScheduleTokens {
+    fn example() -> Self {
+        ScheduleTokens {
+            push_tokens: vec![push_token_example()],
+        }
+    }
+}
+
+impl Example for MergeAccount {
+    fn example() -> Self {
+        MergeAccount {
+            previous_token: "Bearer eyJhbGciOiJSUzI1NiIsImtpZCI6IjEifQ...".to_string(),
+        }
+    }
+}
+
+impl Example for UserDeletedEvent {
+    fn example() -> Self {
+        UserDeletedEvent {
+            uid: "kZ3hG1yWq8VbT0nN2cXr5sLmP4a1".to_string(),
+        }
+    }
+}
+
+impl Example for UpdateProjectSettings {
+    fn example() -> Self {
+        UpdateProjectSettings {
+            icon: Some("ic_notification".to_string()),
+            color: Some("#1a73e8".to_string()),
+            click_action: Some("OPEN_REMINDERS".to_string()),
+            channel_id: Some("reminders".to_string()),
+            allowed_link_schemes: Allowlist(vec!["https".to_string(), "myapp".to_string()]),
+            allowed_link_hosts: Allowlist(vec![".example.com".to_string()]),
+            require_approval: false,
+            alert_webhook_url: Some("https://hooks.example.com/alerts".to_string()),
+            pause_on_burst: false,
+        }
+    }
+}
+
+impl Example for NewBroadcast {
+    fn example() -> Self {
+        NewBroadcast {
+            fb_project_id: "my-firebase-project".to_string(),
+            title: "Planned maintenance".to_string(),
+            body: "Reminders will be paused on Sunday between 02:00 and 03:00 UTC".to_string(),
+            data: Some(serde_json::json!({"link": "https://status.example.com"})),
+            priority: Priority::High,
+            canary_percent: Some(5),
+            canary_delay_seconds: canary_delay_example(),
+            canary_max_error_rate: canary_max_error_rate_example(),
+        }
+    }
+}
+
+/// Outcome of a single delivery of a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct Execution {
+    /// ID of the execution
+    pub id: i64,
+    /// ID of the schedule
+    pub schedule_id: i64,
+    /// device the message was sent to
+    pub push_token: Option<String>,
+    /// success, failure or timeout, retrying for a failed attempt that was retried, or skipped when a send condition wasn't met
+    pub status: String,
+    /// error returned while sending
+    pub error: Option<String>,
+    /// typed reason of the failure (e.g. invalid_token, quota_exceeded)
+    pub error_code: Option<String>,
+    /// whether the message was only logged instead of sent
+    pub dry_run: bool,
+    /// name FCM assigned to the delivered message
+    pub message_id: Option<String>,
+    /// number of delivery attempts it took to reach the final status
+    pub attempts: i64,
+    /// time of the execution
+    pub executed_at: NaiveDateTime,
+    /// time the notification was acknowledged through its `{{ ack_link }}`
+    pub acknowledged_at: Option<NaiveDateTime>,
+}
+
+/// Page of executions, newest first
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ExecutionPage {
+    /// executions of the page
+    pub items: Vec<Execution>,
+    /// cursor of the next page, absent on the last page
+    pub next_cursor: Option<i64>,
+}
+
+/// Result of pausing, resuming or deleting schedules in bulk
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TagBulkResult {
+    /// number of schedules matching the tag
+    pub matched: u64,
+    /// number of schedules paused, resumed or deleted
+    pub updated: u64,
+    /// ids of the matching schedules
+    pub schedule_ids: Vec<i64>,
+    /// whether the request only counted the matching schedules
+    pub dry_run: bool,
+}
+
+/// Result of triggering schedules in bulk
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TriggerResult {
+    /// number of schedules matching the tag
+    pub matched: u64,
+    /// number of schedules queued for delivery
+    pub triggered: u64,
+    /// ids of the matching schedules
+    pub schedule_ids: Vec<i64>,
+    /// whether the request only counted the matching schedules
+    pub dry_run: bool,
+}
+
+/// Outcome of sending a schedule right away
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct RunResult {
+    /// ID of the schedule
+    pub schedule_id: i64,
+    /// whether FCM accepted the message
+    pub success: bool,
+    /// name FCM assigned to the message, e.g. `projects/my-project/messages/0:1700000000000000%abc`
+    pub message_id: Option<String>,
+    /// classified error code when FCM refused the message
+    pub error_code: Option<String>,
+    /// response of FCM when it refused the message
+    pub error: Option<String>,
+    /// message sent to FCM
+    pub message: Value,
+    /// whether the message was only built, asked for or because the server runs with DRY_RUN
+    pub dry_run: bool,
+    /// time taken by FCM to answer in milliseconds
+    pub latency_ms: Option<i64>,
+}
+
+/// Message to send to a single device without a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct TestSend {
+    #[oai(validator(min_length = 1, max_length = 512))]
+    /// device registration token to send the FCM to
+    pub push_token: String,
+
+    /// payload of the FCM, `title`, `body` and `image` fill in the notification
+    pub payload: Value,
+
+    #[oai(default)]
+    /// structured FCM message sent instead of `payload` when it has any block
+    pub message: FcmMessage,
+
+    #[oai(default)]
+    /// only have FCM validate the message and the token without notifying the device
+    pub dry_run: bool,
+}
+
+/// Outcome of a test send with everything FCM answered
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TestSendResult {
+    /// whether FCM accepted the message
+    pub success: bool,
+    /// whether FCM only validated the message
+    pub dry_run: bool,
+    /// HTTP status FCM answered with, empty when the request never reached FCM
+    pub status: Option<u16>,
+    /// name FCM assigned to the message, e.g. `projects/my-project/messages/0:1700000000000000%abc`
+    pub message_id: Option<String>,
+    /// full response of FCM, kept as a string when it isn't JSON
+    pub response: Option<Value>,
+    /// classified error code when FCM refused the message
+    pub error_code: Option<String>,
+    /// whether sending the same message again later can succeed
+    pub retryable: bool,
+    /// what to change for the message to go through
+    pub hints: Vec<String>,
+    /// message sent to FCM
+    pub message: Value,
+    /// size in bytes of the notification and data, FCM rejects messages over 4096
+    pub size: i64,
+    /// time taken by FCM to answer in milliseconds
+    pub latency_ms: Option<i64>,
+}
+
+/// How long to hold off the next send of a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct SnoozeSchedule {
+    #[oai(validator(minimum(value = "1"), maximum(value = "10080")))]
+    /// minutes the next send is pushed back by
+    pub minutes: i64,
+}
+
+/// Why an operator is blocking a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct BlockSchedule {
+    #[oai(validator(max_length = 256))]
+    /// note kept in the server logs
+    pub reason: Option<String>,
+}
+
+/// Load of the scheduler across every project
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct DispatcherStats {
+    /// whether deliveries are paused
+    pub paused: bool,
+    /// number of schedules of every user
+    pub schedules: i64,
+    /// number of schedules that are delivering
+    pub active_schedules: i64,
+    /// number of schedules blocked by an operator
+    pub blocked_schedules: i64,
+    /// number of active schedules past their next execution
+    pub due_schedules: i64,
+    /// number of messages waiting to be sent
+    pub pending_messages: i64,
+    /// number of messages being sent
+    pub sending_messages: i64,
+    /// number of deliveries that succeeded in the last 24 hours
+    pub successes_24h: i64,
+    /// number of deliveries that failed in the last 24 hours
+    pub failures_24h: i64,
+    /// average latency of the deliveries of the last 24 hours
+    pub average_latency_ms_24h: Option<i64>,
+}
+
+/// Cron pattern to preview
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct CronPreviewRequest {
+    #[oai(validator(min_length = 3, max_length = 64), default = "cron_example")]
+    /// cron pattern in the same format as a schedule's `cron_pattern`
+    pub cron_pattern: String,
+
+    #[oai(validator(max_length = 64), default = "timezone_default")]
+    /// IANA timezone the cron pattern is evaluated in (e.g. Europe/London)
+    pub timezone: String,
+
+    #[oai(
+        validator(minimum(value = "5"), maximum(value = "10")),
+        default = "cron_preview_count_default"
+    )]
+    /// number of occurrences to return
+    pub count: u64,
+}
+
+fn cron_preview_count_default() -> u64 {
+    5
+}
+
+/// Time a cron pattern matches
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct CronOccurrence {
+    /// time in UTC, as stored in `next_execution`
+    pub utc: NaiveDateTime,
+    /// wall clock time in the timezone of the pattern
+    pub local: NaiveDateTime,
+}
+
+/// Upcoming occurrences of a cron pattern
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct CronPreview {
+    pub cron_pattern: String,
+    pub timezone: String,
+    /// next times the pattern matches, soonest first
+    pub occurrences: Vec<CronOccurrence>,
+}
+
+/// Date a holiday calendar lists
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct Holiday {
+    pub date: NaiveDate,
+    /// name of the holiday, observed substitute days are marked `(observed)`
+    pub name: String,
+}
+
+/// Payload to render as it would be sent
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct TemplatePreviewRequest {
+    /// payload with `{{ variable }}` placeholders, e.g. `{"title": "Report for {{ date:%d %B }}"}`
+    pub payload: Value,
+
+    #[oai(validator(max_length = 64), default = "timezone_default")]
+    /// IANA timezone times are rendered in
+    pub timezone: String,
+
+    #[oai(validator(min_length = 3, max_length = 64))]
+    /// cron pattern `{{ next_run }}` is worked out from, left empty without one
+    pub cron_pattern: Option<String>,
+
+    #[oai(validator(max_length = 100))]
+    /// schedule name `{{ name }}` renders to
+    pub name: Option<String>,
+}
+
+/// Payload with its placeholders rendered
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TemplatePreview {
+    pub payload: Value,
+}
+
+/// Additional devices of a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct ScheduleTokens {
+    #[oai(validator(max_items = 20, unique_items, min_length = 32, max_length = 512))]
+    /// device registration tokens sent to along with the schedule's `push_token`
+    pub push_tokens: Vec<String>,
+}
+
+/// Device of the user and the timezone it's in, updated by the app when the timezone changes
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct RegisterDevice {
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// device registration token
+    pub push_token: String,
+    #[oai(validator(max_length = 64))]
+    /// IANA timezone of the device, e.g. Asia/Colombo
+    pub timezone: String,
+}
+
+impl Example for RegisterDevice {
+    fn example() -> Self {
+        RegisterDevice {
+            push_token: push_token_example(),
+            timezone: "Asia/Colombo".to_string(),
+        }
+    }
+}
+
+/// Device registered by the user
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct Device {
+    pub push_token: String,
+    /// IANA timezone `local_time` schedules are sent in
+    pub timezone: String,
+    pub created_at: NaiveDateTime,
+    /// last time the app registered the device
+    pub updated_at: NaiveDateTime,
+}
+
+/// Device a schedule is sent to, with the results of its deliveries
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ScheduleToken {
+    pub push_token: String,
+    /// whether it's the schedule's own `push_token` rather than an additional device
+    pub primary: bool,
+    /// deliveries to the device that succeeded
+    pub successes: i64,
+    /// deliveries to the device that failed or timed out
+    pub failures: i64,
+    /// outcome of the latest delivery to the device
+    pub last_status: Option<String>,
+    /// time of the latest delivery to the device
+    pub last_executed_at: Option<NaiveDateTime>,
+}
+
+/// Predicted validity of a push token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize)]
+#[oai(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenValidity {
+    /// the last delivery succeeded recently
+    Valid,
+    /// no successful delivery for a long time, the app may have been uninstalled
+    Stale,
+    /// FCM rejected the token, its schedules are disabled until the token is updated
+    Invalid,
+    /// nothing was delivered to the token yet
+    Unknown,
+}
+
+/// Delivery health of a push token used by the user's schedules
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TokenHealth {
+    /// device registration token
+    pub push_token: String,
+    /// number of schedules sending to the token
+    pub schedule_count: i64,
+    /// last time a message was delivered to the token
+    pub last_success_at: Option<NaiveDateTime>,
+    /// last time a delivery to the token failed
+    pub last_error_at: Option<NaiveDateTime>,
+    /// error of the last failed delivery
+    pub last_error: Option<String>,
+    /// typed reason of the last failed delivery (e.g. invalid_token)
+    pub last_error_code: Option<String>,
+    /// predicted validity of the token
+    pub validity: TokenValidity,
+    /// why the token was reported stale or replaced (unregistered or rotated)
+    pub hint: Option<String>,
+    /// token reported to replace this one
+    pub replacement_token: Option<String>,
+    /// last time the app reported the token through `/fcm/me/tokens/heartbeat`
+    pub last_heartbeat_at: Option<NaiveDateTime>,
+}
+
+/// Token rotation reported by the app
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct TokenReplacement {
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// token that was replaced
+    pub previous_token: String,
+
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// new device registration token
+    pub push_token: String,
+
+    /// move the schedules of the previous token to the new one (defaults to FCM_AUTO_MIGRATE_TOKENS)
+    pub migrate: Option<bool>,
+}
+
+/// Result of reporting a token rotation
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TokenReplacementResult {
+    /// number of schedules moved to the new token
+    pub migrated_schedules: u64,
+}
+
+/// Token the app holds, reported on startup
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct TokenHeartbeat {
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// device registration token returned by the firebase SDK
+    pub push_token: String,
+}
+
+/// Why the app should ask the firebase SDK for a new token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, Serialize)]
+#[oai(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshReason {
+    /// FCM reported the token is no longer registered
+    Unregistered,
+    /// the app already reported a replacement of the token
+    Rotated,
+    /// schedules of the token were disabled because FCM rejected it
+    Invalid,
+    /// the token wasn't refreshed for a month
+    Stale,
+}
+
+/// Hints for the app after reporting its token
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct TokenHeartbeatResult {
+    /// whether the app should refresh the token and report the new one through `/fcm/me/tokens/replace`
+    pub refresh: bool,
+    /// why the token should be refreshed, only given along with `refresh`
+    pub reason: Option<RefreshReason>,
+    /// first time the token was reported
+    pub first_seen_at: NaiveDateTime,
+}
+
+/// Email inbox request schema
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct NewEmailInbox {
+    #[oai(validator(min_length = 32, max_length = 512))]
+    /// device registration token notified of the received emails
+    pub push_token: String,
+
+    #[oai(validator(max_length = 256))]
+    /// only forward emails whose sender matches this regular expression (case insensitive)
+    pub sender_filter: Option<String>,
+
+    #[oai(validator(max_length = 256))]
+    /// only forward emails whose subject matches this regular expression (case insensitive)
+    pub subject_filter: Option<String>,
+}
+
+/// Inbound email address forwarding received emails as push notifications
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct EmailInbox {
+    /// ID of the inbox
+    pub id: i64,
+    /// device registration token notified of the received emails
+    pub push_token: String,
+    /// email address of the inbox
+    pub address: String,
+    /// regular expression the sender has to match
+    pub sender_filter: Option<String>,
+    /// regular expression the subject has to match
+    pub subject_filter: Option<String>,
+    /// number of emails forwarded
+    pub received: i64,
+    /// created time of the inbox
+    pub created_at: NaiveDateTime,
+}
+
+/// Email received by the inbound email provider
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct InboundEmail {
+    /// address the email was sent to, e.g. `Alerts <k3j9x2@inbox.example.com>`
+    pub recipient: String,
+    /// address the email was sent from
+    pub sender: String,
+    /// subject of the email
+    #[oai(default)]
+    pub subject: String,
+    /// plain text body of the email
+    pub text: Option<String>,
+}
+
+/// Outcome of an inbound email
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct InboundEmailResult {
+    /// inbox the email was addressed to
+    pub inbox_id: Option<i64>,
+    /// whether a notification was queued, false when there is no inbox or a filter did not match
+    pub forwarded: bool,
+}
+
+impl Example for NewEmailInbox {
+    fn example() -> Self {
+        NewEmailInbox {
+            push_token: push_token_example(),
+            sender_filter: Some("@github\\.com$".to_string()),
+            subject_filter: Some("failed|deployment".to_string()),
+        }
+    }
+}
+
+impl Example for InboundEmail {
+    fn example() -> Self {
+        InboundEmail {
+            recipient: "k3j9x2q7m1@inbox.example.com".to_string(),
+            sender: "notifications@github.com".to_string(),
+            subject: "Run failed: deploy - main".to_string(),
+            text: Some("The deploy workflow failed on main".to_string()),
+        }
+    }
+}
+
+/// Data of the `schedule.created`, `schedule.updated` and `schedule.deleted` events,
+/// the push token and payload are left out as they are secrets of the user
+#[derive(Debug, Serialize)]
+pub struct ScheduleEvent<'a> {
+    pub id: i64,
+    pub fb_user_id: &'a str,
+    pub fb_project_id: &'a str,
+    pub name: &'a str,
+    pub schedule_type: ScheduleType,
+    pub cron_pattern: &'a str,
+    pub timezone: &'a str,
+    pub run_at: Option<NaiveDateTime>,
+    pub priority: Priority,
+    pub tags: &'a Tags,
+    pub disabled_reason: Option<&'a str>,
+    pub next_execution: NaiveDateTime,
+}
+
+impl<'a> ScheduleEvent<'a> {
+    pub fn new(
+        id: i64,
+        fb_user_id: &'a str,
+        fb_project_id: &'a str,
+        schedule: &'a UpdateSchedule,
+        next_execution: NaiveDateTime,
+        disabled_reason: Option<&'a str>,
+    ) -> Self {
+        ScheduleEvent {
+            id,
+            fb_user_id,
+            fb_project_id,
+            name: &schedule.name,
+            schedule_type: schedule.schedule_type,
+            cron_pattern: &schedule.cron_pattern,
+            timezone: &schedule.timezone,
+            run_at: schedule.run_at,
+            priority: schedule.priority,
+            tags: &schedule.tags,
+            disabled_reason,
+            next_execution,
+        }
+    }
+}
+
+impl<'a> From<&'a FCMSchedule> for ScheduleEvent<'a> {
+    fn from(schedule: &'a FCMSchedule) -> Self {
+        ScheduleEvent {
+            id: schedule.id,
+            fb_user_id: &schedule.fb_user_id,
+            fb_project_id: &schedule.fb_project_id,
+            name: &schedule.name,
+            schedule_type: schedule.schedule_type,
+            cron_pattern: &schedule.cron_pattern,
+            timezone: &schedule.timezone,
+            run_at: schedule.run_at,
+            priority: schedule.priority,
+            tags: &schedule.tags,
+            disabled_reason: schedule.disabled_reason.as_deref(),
+            next_execution: schedule.next_execution,
+        }
+    }
+}
+
+/// Data of the `execution.completed` event, sent once a delivery of a schedule reaches its final status
+#[derive(Debug, Serialize)]
+pub struct ExecutionEvent<'a> {
+    pub schedule_id: i64,
+    pub fb_user_id: &'a str,
+    /// success, failure or timeout
+    pub status: &'a str,
+    pub error_code: Option<&'a str>,
+    pub message_id: Option<&'a str>,
+    pub latency_ms: Option<i64>,
+    pub attempts: i64,
+    pub dry_run: bool,
+    pub executed_at: NaiveDateTime,
+}
+
+/// Action to run after a delivery of a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct NewFollowUp {
+    /// outcome of the delivery the action runs on
+    pub outcome: FollowUpOutcome,
+    pub action: FollowUpAction,
+    /// schedule sent by `trigger` actions, it can't lead back to this schedule
+    pub target_schedule_id: Option<i64>,
+    #[oai(validator(max_length = 2048))]
+    /// http(s) URL `webhook` actions post the outcome to
+    pub url: Option<String>,
+    #[oai(validator(minimum(value = "1"), maximum(value = "1440")))]
+    /// minutes `remind` actions wait before sending the schedule again
+    pub delay_minutes: Option<i64>,
+}
+
+/// Follow-ups of a schedule, replacing the current ones
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct FollowUps {
+    #[oai(validator(max_items = 10))]
+    pub followups: Vec<NewFollowUp>,
+}
+
+impl Example for FollowUps {
+    fn example() -> Self {
+        FollowUps {
+            followups: vec![NewFollowUp {
+                outcome: FollowUpOutcome::Success,
+                action: FollowUpAction::Remind,
+                target_schedule_id: None,
+                url: None,
+                delay_minutes: Some(10),
+            }],
+        }
+    }
+}
+
+/// Action run after a delivery of a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct FollowUp {
+    pub id: i64,
+    pub schedule_id: i64,
+    pub outcome: FollowUpOutcome,
+    pub action: FollowUpAction,
+    pub target_schedule_id: Option<i64>,
+    pub url: Option<String>,
+    pub delay_minutes: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Callback URL the failed deliveries of a schedule are posted to
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct NewScheduleWebhook {
+    #[oai(validator(max_length = 2048))]
+    /// http(s) URL the signed events are posted to
+    pub url: String,
+    #[oai(default)]
+    /// replace the signing secret, events are signed with the new one right away
+    pub rotate_secret: bool,
+}
+
+/// Callback URL of a schedule along with the secret its events are signed with
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct ScheduleWebhook {
+    pub schedule_id: i64,
+    pub url: String,
+    /// HMAC-SHA256 key of the `X-Webhook-Signature` header
+    pub secret: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Event posted to the callback URL of a schedule
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub schedule_id: i64,
+    pub event: String,
+    /// attempts made so far
+    pub attempts: i64,
+    pub next_attempt_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    /// set once every attempt failed or the callback was removed
+    pub failed_at: Option<NaiveDateTime>,
+    /// error of the last failed attempt
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Data of the `execution.failed` webhook event
+#[derive(Debug, Serialize)]
+pub struct ExecutionFailure<'a> {
+    #[serde(flatten)]
+    pub execution: &'a ExecutionEvent<'a>,
+    pub error: Option<&'a str>,
+}
+
+/// Data of the `execution.batch_completed` webhook event
+#[derive(Debug, Serialize)]
+pub struct BatchCompleted {
+    pub batch_id: i64,
+    pub schedule_id: i64,
+    pub total: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    /// failed deliveries by error code
+    pub errors: BTreeMap<String, i64>,
+    /// where the outcome for every device can be fetched
+    pub detail_url: String,
+}
+
+/// Execution of a schedule fanned out to several devices
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct ExecutionBatch {
+    pub id: i64,
+    pub schedule_id: i64,
+    /// devices the execution was sent to
+    pub total: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    /// deliveries without a final outcome yet
+    pub pending: i64,
+    /// failed deliveries by error code
+    pub errors: BTreeMap<String, i64>,
+    /// set once every delivery has a final outcome
+    pub completed_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub results: Vec<BatchMessageResult>,
+}
+
+/// Outcome of a batched execution for a single device
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct BatchMessageResult {
+    pub push_token: String,
+    /// pending, success, failure or timeout
+    pub status: String,
+    pub error_code: Option<String>,
+    pub error: Option<String>,
+    pub executed_at: Option<NaiveDateTime>,
+}
+
+/// Invitation to share a schedule with another user
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct NewScheduleShare {
+    #[oai(validator(min_length = 1, max_length = 320))]
+    /// firebase user id or verified email address of the user
+    pub invitee: String,
+    #[oai(default)]
+    pub role: ShareRole,
+}
+
+impl Example for NewScheduleShare {
+    fn example() -> Self {
+        NewScheduleShare {
+            invitee: "someone@example.com".to_string(),
+            role: ShareRole::Editor,
+        }
+    }
+}
+
+/// Schedule shared with another user, pending until they accept it
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ScheduleShare {
+    pub id: i64,
+    pub schedule_id: i64,
+    pub schedule_name: String,
+    /// firebase user id or email address the invitation was sent to
+    pub invitee: String,
+    pub role: ShareRole,
+    /// user who accepted the invitation
+    pub fb_user_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub accepted_at: Option<NaiveDateTime>,
+}
+
+/// Schedule of another user shared with the current one
+#[derive(Debug, Object, Clone)]
+pub struct SharedSchedule {
+    pub role: ShareRole,
+    pub schedule: FCMSchedule,
+}
+
+/// Team owning schedules collectively
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct NewOrganization {
+    #[oai(validator(min_length = 3, max_length = 64))]
+    pub name: String,
+}
+
+impl Example for NewOrganization {
+    fn example() -> Self {
+        NewOrganization {
+            name: "Household".to_string(),
+        }
+    }
+}
+
+/// Organization the user is a member of
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct Organization {
+    pub id: i64,
+    pub name: String,
+    pub fb_project_id: String,
+    /// role of the current user
+    pub role: OrganizationRole,
+    pub created_at: NaiveDateTime,
+}
+
+/// Member to add to an organization, or whose role to change
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct NewOrganizationMember {
+    #[oai(validator(min_length = 1, max_length = 128))]
+    /// firebase user id of the member
+    pub fb_user_id: String,
+    #[oai(default)]
+    pub role: OrganizationRole,
+}
+
+impl Example for NewOrganizationMember {
+    fn example() -> Self {
+        NewOrganizationMember {
+            fb_user_id: "kZ3hG1yWq8VbT0nN2cXr5sLmP4a1".to_string(),
+            role: OrganizationRole::Member,
+        }
+    }
+}
+
+/// Member of an organization
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct OrganizationMember {
+    pub fb_user_id: String,
+    pub role: OrganizationRole,
+    pub created_at: NaiveDateTime,
+}
+
+/// Support session acting as a user, requested by an operator
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+#[oai(example)]
+pub struct StartImpersonation {
+    #[oai(validator(min_length = 1, max_length = 128))]
+    /// firebase user id to act as
+    pub fb_user_id: String,
+    #[oai(validator(min_length = 1, max_length = 128))]
+    /// who is impersonating the user, kept in the audit log
+    pub operator: String,
+    #[oai(validator(min_length = 1, max_length = 512))]
+    /// why the user is impersonated, e.g. a support ticket
+    pub reason: String,
+    #[oai(validator(minimum(value = "1"), maximum(value = "60")))]
+    /// minutes the token stays valid, 15 when absent
+    pub ttl_minutes: Option<i64>,
+}
+
+impl Example for StartImpersonation {
+    fn example() -> Self {
+        StartImpersonation {
+            fb_user_id: "kZ3hG1yWq8VbT0nN2cXr5sLmP4a1".to_string(),
+            operator: "jane@example.com".to_string(),
+            reason: "SUP-1042 schedule isn't firing".to_string(),
+            ttl_minutes: Some(15),
+        }
+    }
+}
+
+/// Audit record of an impersonation
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct Impersonation {
+    pub id: i64,
+    /// user acted as
+    pub fb_user_id: String,
+    pub fb_project_id: String,
+    /// who impersonated the user
+    pub operator: String,
+    pub reason: String,
+    /// when the token stops working
+    pub expires_at: NaiveDateTime,
+    /// when an operator revoked the token before it expired
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    /// number of API requests made with the token
+    pub requests: i64,
+}
+
+/// Token to call the API as the user, sent in the `firebase-auth` header like an ID token
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct ImpersonationToken {
+    pub token: String,
+    pub impersonation: Impersonation,
+}
+
+/// API request made with an impersonation token
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct ImpersonationRequest {
+    pub method: String,
+    pub path: String,
+    pub created_at: NaiveDateTime,
 }
@@ -0,0 +1,451 @@
+use super::model::{
+    NewOrganization, NewOrganizationMember, Organization, OrganizationMember, OrganizationRole,
+};
+use super::sharing::{self, Access};
+use super::utils::authenticate;
+use crate::utils::{ApiTags, JsonError, JsonSuccess, ResponseObject};
+use chrono::Utc;
+use poem::{web::Data, Request};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+use sqlx::SqlitePool;
+
+/// Role of the user in the organization, None when they aren't a member
+pub async fn organization_role(
+    pool: &SqlitePool,
+    organization_id: i64,
+    fb_user_id: &str,
+) -> Result<Option<OrganizationRole>, sqlx::Error> {
+    let role = sqlx::query_scalar!(
+        "SELECT role FROM fcm_organization_member WHERE organization_id = ? AND fb_user_id = ?",
+        organization_id,
+        fb_user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(role.map(OrganizationRole::from))
+}
+
+/// Access to the schedules of the organization a role grants
+pub fn schedule_access(role: OrganizationRole) -> Access {
+    match role {
+        OrganizationRole::Admin => Access::Own,
+        OrganizationRole::Member => Access::Edit,
+        OrganizationRole::Viewer => Access::View,
+    }
+}
+
+/// Check the user is a member of the organization with at least the required role,
+/// organizations the user doesn't belong to are reported as not found
+pub async fn authorize(
+    pool: &SqlitePool,
+    organization_id: i64,
+    fb_user_id: &str,
+    required: OrganizationRole,
+) -> Result<OrganizationRole, JsonError<String>> {
+    match organization_role(pool, organization_id, fb_user_id).await {
+        Ok(Some(role)) if schedule_access(role) >= schedule_access(required) => Ok(role),
+        Ok(Some(_)) => Err(ResponseObject::forbidden(format!(
+            "Requires the {} role in the organization",
+            required.as_str()
+        ))),
+        Ok(None) => Err(ResponseObject::not_found("Organization not found")),
+        Err(e) => Err(ResponseObject::internal_server_error(e)),
+    }
+}
+
+/// Reject changes leaving the organization without an admin
+async fn keep_an_admin(
+    pool: &SqlitePool,
+    organization_id: i64,
+    fb_user_id: &str,
+) -> Result<(), JsonError<String>> {
+    let admins = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count: i64" FROM fcm_organization_member
+        WHERE organization_id = ? AND role = 'admin' AND fb_user_id != ?"#,
+        organization_id,
+        fb_user_id
+    )
+    .fetch_one(pool)
+    .await;
+
+    match admins {
+        Ok(0) => Err(ResponseObject::bad_request(
+            "An organization needs at least one admin",
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err(ResponseObject::internal_server_error(e)),
+    }
+}
+
+#[derive(Default)]
+pub struct FirebaseOrganizations;
+
+#[OpenApi(
+    prefix_path = "/fcm/organizations/",
+    request_header(
+        name = "firebase-auth",
+        ty = "String",
+        description = "Bearer token generated from firebase project (example: <code>Bearer {token}</code>)"
+    ),
+    tag = "ApiTags::FirebaseMessaging"
+)]
+impl FirebaseOrganizations {
+    // Create an organization, the user becomes its first admin
+    #[oai(path = "/", method = "post", operation_id = "fcm::create_organization")]
+    async fn create_organization(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        organization: Json<NewOrganization>,
+    ) -> Result<JsonSuccess<Organization>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "INSERT INTO fcm_organization (name, fb_project_id, created_at) VALUES (?, ?, ?)",
+            organization.name,
+            claims.aud,
+            current_time
+        )
+        .execute(&mut *tx)
+        .await;
+
+        let id = match result {
+            Ok(result) => result.last_insert_rowid(),
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let result = sqlx::query!(
+            "INSERT INTO fcm_organization_member (organization_id, fb_user_id, role, created_at)
+            VALUES (?, ?, 'admin', ?)",
+            id,
+            claims.user_id,
+            current_time
+        )
+        .execute(&mut *tx)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        Ok(ResponseObject::created(Organization {
+            id,
+            name: organization.0.name,
+            fb_project_id: claims.aud,
+            role: OrganizationRole::Admin,
+            created_at: current_time,
+        }))
+    }
+
+    // List the organizations the user is a member of
+    #[oai(path = "/", method = "get", operation_id = "fcm::list_organizations")]
+    async fn list_organizations(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<Organization>>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let organizations = sqlx::query_as!(
+            Organization,
+            r#"SELECT o.id as "id!", o.name, o.fb_project_id, m.role, o.created_at
+            FROM fcm_organization o
+            JOIN fcm_organization_member m ON m.organization_id = o.id
+            WHERE m.fb_user_id = ?
+            ORDER BY o.id"#,
+            claims.user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match organizations {
+            Ok(organizations) => Ok(ResponseObject::ok(organizations)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Delete the organization, its schedules stay with the users who created them
+    #[oai(
+        path = "/:id",
+        method = "delete",
+        operation_id = "fcm::delete_organization"
+    )]
+    async fn delete_organization(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, OrganizationRole::Admin).await?;
+
+        let result = sqlx::query!("DELETE FROM fcm_organization WHERE id = ?", id.0)
+            .execute(pool.0)
+            .await;
+
+        match result {
+            Ok(_) => Ok(ResponseObject::ok("Organization deleted".to_string())),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // List the members of the organization
+    #[oai(
+        path = "/:id/members",
+        method = "get",
+        operation_id = "fcm::list_organization_members"
+    )]
+    async fn list_members(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Vec<OrganizationMember>>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, OrganizationRole::Viewer).await?;
+
+        let members = sqlx::query_as!(
+            OrganizationMember,
+            "SELECT fb_user_id, role, created_at FROM fcm_organization_member
+            WHERE organization_id = ?
+            ORDER BY created_at, fb_user_id",
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match members {
+            Ok(members) => Ok(ResponseObject::ok(members)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Add a member to the organization, or change the role of an existing one
+    #[oai(
+        path = "/:id/members",
+        method = "put",
+        operation_id = "fcm::put_organization_member"
+    )]
+    async fn put_member(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        member: Json<NewOrganizationMember>,
+    ) -> Result<JsonSuccess<OrganizationMember>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, OrganizationRole::Admin).await?;
+
+        if member.role != OrganizationRole::Admin {
+            keep_an_admin(pool.0, id.0, &member.fb_user_id).await?;
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "INSERT INTO fcm_organization_member (organization_id, fb_user_id, role, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (organization_id, fb_user_id) DO UPDATE SET role = excluded.role",
+            id.0,
+            member.fb_user_id,
+            member.role,
+            current_time
+        )
+        .execute(pool.0)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let member = sqlx::query_as!(
+            OrganizationMember,
+            "SELECT fb_user_id, role, created_at FROM fcm_organization_member
+            WHERE organization_id = ? AND fb_user_id = ?",
+            id.0,
+            member.fb_user_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match member {
+            Ok(member) => Ok(ResponseObject::ok(member)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Remove a member from the organization, members can remove themselves
+    #[oai(
+        path = "/:id/members/:fb_user_id",
+        method = "delete",
+        operation_id = "fcm::remove_organization_member"
+    )]
+    async fn remove_member(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        fb_user_id: Path<String>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let required = match fb_user_id.0 == claims.user_id {
+            true => OrganizationRole::Viewer,
+            false => OrganizationRole::Admin,
+        };
+        authorize(pool.0, id.0, &claims.user_id, required).await?;
+        keep_an_admin(pool.0, id.0, &fb_user_id.0).await?;
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_organization_member WHERE organization_id = ? AND fb_user_id = ?",
+            id.0,
+            fb_user_id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(ResponseObject::not_found("Member not found"))
+            }
+            Ok(_) => Ok(ResponseObject::ok("Member removed".to_string())),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Move a schedule the user owns into the organization
+    #[oai(
+        path = "/:id/schedules/:schedule_id",
+        method = "put",
+        operation_id = "fcm::add_organization_schedule"
+    )]
+    async fn add_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        schedule_id: Path<i64>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, OrganizationRole::Member).await?;
+        sharing::authorize(pool.0, schedule_id.0, &claims.user_id, Access::Own).await?;
+
+        // the organization sends with the service account of its project
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET organization_id = ?1, version = version + 1, updated_at = ?2
+            WHERE id = ?3 AND fb_project_id = (SELECT fb_project_id FROM fcm_organization WHERE id = ?1)",
+            id.0,
+            current_time,
+            schedule_id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => Err(ResponseObject::bad_request(
+                "Schedule belongs to a different project than the organization",
+            )),
+            Ok(_) => Ok(ResponseObject::ok(
+                "Schedule moved to the organization".to_string(),
+            )),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Move a schedule out of the organization, back to the user who created it
+    #[oai(
+        path = "/:id/schedules/:schedule_id",
+        method = "delete",
+        operation_id = "fcm::remove_organization_schedule"
+    )]
+    async fn remove_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        schedule_id: Path<i64>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        sharing::authorize(pool.0, schedule_id.0, &claims.user_id, Access::Own).await?;
+
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET organization_id = NULL, version = version + 1, updated_at = ? WHERE id = ? AND organization_id = ?",
+            current_time,
+            schedule_id.0,
+            id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => Err(ResponseObject::not_found(
+                "Schedule is not part of the organization",
+            )),
+            Ok(_) => Ok(ResponseObject::ok(
+                "Schedule removed from the organization".to_string(),
+            )),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
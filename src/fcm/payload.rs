@@ -0,0 +1,385 @@
+use super::compression::COMPRESSION_KEY;
+use super::model::{Compression, FcmMessage, UpdateSchedule};
+use super::sender::message_sizes;
+use serde_json::{Map, Value};
+use url::Url;
+
+// https://firebase.google.com/docs/cloud-messaging/concept-options#data_messages
+const RESERVED_KEYS: &[&str; 4] = &["from", "notification", "message_type", "collapse_key"];
+const RESERVED_PREFIXES: &[&str; 2] = &["google.", "gcm."];
+
+// FCM rejects messages with a payload larger than 4096 bytes
+const MAX_PAYLOAD_SIZE: usize = 4096;
+
+/// Inspect a schedule payload and return non-fatal warnings about how FCM will treat it
+pub fn analyze(payload: &Value) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let map = match payload {
+        Value::Object(map) => map,
+        _ => return warnings,
+    };
+
+    let has_title = map.contains_key("title");
+    let has_body = map.contains_key("body");
+    if has_body && !has_title {
+        warnings.push("notification.title missing — push may render blank".to_string());
+    } else if has_title && !has_body {
+        warnings.push("notification.body missing — push will only show the title".to_string());
+    } else if !has_title && !has_body {
+        warnings.push(
+            "no title or body — message will be delivered as data-only and won't be displayed"
+                .to_string(),
+        );
+    }
+
+    for (key, value) in map {
+        if RESERVED_KEYS.contains(&key.as_str())
+            || RESERVED_PREFIXES.iter().any(|p| key.starts_with(p))
+        {
+            warnings.push(format!(
+                "data key `{}` is reserved by FCM and will be rejected",
+                key
+            ));
+        }
+
+        match value {
+            Value::String(_) => {}
+            Value::Object(_) | Value::Array(_) => warnings.push(format!(
+                "data value `{}` is nested and will be serialized as a JSON string",
+                key
+            )),
+            _ => warnings.push(format!("data value `{}` will be coerced to a string", key)),
+        }
+    }
+
+    warnings
+}
+
+// fields of the message blocks FCM accepts, anything else is rejected by FCM when sending
+// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#androidconfig
+const ANDROID_KEYS: &[&str] = &[
+    "collapse_key",
+    "priority",
+    "ttl",
+    "restricted_package_name",
+    "data",
+    "notification",
+    "fcm_options",
+    "direct_boot_ok",
+];
+// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#androidnotification
+const ANDROID_NOTIFICATION_KEYS: &[&str] = &[
+    "title",
+    "body",
+    "icon",
+    "color",
+    "sound",
+    "tag",
+    "click_action",
+    "body_loc_key",
+    "body_loc_args",
+    "title_loc_key",
+    "title_loc_args",
+    "channel_id",
+    "ticker",
+    "sticky",
+    "event_time",
+    "local_only",
+    "notification_priority",
+    "default_sound",
+    "default_vibrate_timings",
+    "default_light_settings",
+    "vibrate_timings",
+    "visibility",
+    "notification_count",
+    "light_settings",
+    "image",
+    "bypass_proxy_notification",
+    "proxy",
+];
+// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#apnsconfig
+const APNS_KEYS: &[&str] = &["headers", "payload", "fcm_options", "live_activity_token"];
+// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#webpushconfig
+const WEBPUSH_KEYS: &[&str] = &["headers", "data", "notification", "fcm_options"];
+const FCM_OPTIONS_KEYS: &[&str] = &["analytics_label", "image", "link"];
+
+/// Check the blocks of a structured message against the FCM HTTP v1 schema
+pub fn validate_message(message: &FcmMessage) -> Result<(), String> {
+    if let Some(notification) = &message.notification {
+        if let Some(image) = &notification.image {
+            validate_url("notification.image", image)?;
+        }
+    }
+
+    if let Some(data) = &message.data {
+        validate_data_keys("data", data.keys())?;
+    }
+
+    if let Some(android) = &message.android {
+        let android = object("android", android, ANDROID_KEYS)?;
+        for (key, value) in android {
+            let field = format!("android.{}", key);
+            match key.as_str() {
+                "priority" => match value.as_str().map(str::to_lowercase).as_deref() {
+                    Some("normal" | "high") => {}
+                    _ => return Err(format!("{} must be normal or high", field)),
+                },
+                "ttl" => {
+                    if !value.as_str().is_some_and(valid_duration) {
+                        return Err(format!(
+                            "{} must be a duration in seconds, e.g. 3.5s",
+                            field
+                        ));
+                    }
+                }
+                "direct_boot_ok" => boolean(&field, value)?,
+                "data" => {
+                    let data = string_map(&field, value)?;
+                    validate_data_keys(&field, data.keys())?;
+                }
+                "notification" => {
+                    let notification = object(&field, value, ANDROID_NOTIFICATION_KEYS)?;
+                    if let Some(color) = notification.get("color") {
+                        if !color.as_str().is_some_and(valid_color) {
+                            return Err(format!("{}.color must be in the #rrggbb format", field));
+                        }
+                    }
+                    if let Some(image) = notification.get("image") {
+                        validate_url(&format!("{}.image", field), string(&field, image)?)?;
+                    }
+                }
+                "fcm_options" => {
+                    object(&field, value, &["analytics_label"])?;
+                }
+                _ => {
+                    string(&field, value)?;
+                }
+            }
+        }
+    }
+
+    if let Some(apns) = &message.apns {
+        let apns = object("apns", apns, APNS_KEYS)?;
+        for (key, value) in apns {
+            let field = format!("apns.{}", key);
+            match key.as_str() {
+                "headers" => {
+                    string_map(&field, value)?;
+                }
+                "payload" => {
+                    let payload = object(&field, value, &[])?;
+                    if let Some(aps) = payload.get("aps") {
+                        if !aps.is_object() {
+                            return Err(format!("{}.aps must be an object", field));
+                        }
+                    }
+                }
+                "fcm_options" => validate_fcm_options(&field, value)?,
+                _ => {
+                    string(&field, value)?;
+                }
+            }
+        }
+    }
+
+    if let Some(webpush) = &message.webpush {
+        let webpush = object("webpush", webpush, WEBPUSH_KEYS)?;
+        for (key, value) in webpush {
+            let field = format!("webpush.{}", key);
+            match key.as_str() {
+                "headers" => {
+                    string_map(&field, value)?;
+                }
+                "data" => {
+                    let data = string_map(&field, value)?;
+                    validate_data_keys(&field, data.keys())?;
+                }
+                "notification" => {
+                    object(&field, value, &[])?;
+                }
+                _ => validate_fcm_options(&field, value)?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Warnings about the message a schedule sends, its structured message when it has one
+pub fn analyze_schedule(
+    payload: &Value,
+    message: &FcmMessage,
+    compression: Compression,
+) -> Vec<String> {
+    let mut warnings = if message.is_empty() {
+        analyze(payload)
+    } else {
+        analyze_message(message)
+    };
+
+    let (size, packed_size) = message_sizes(payload, message, compression);
+    if packed_size > MAX_PAYLOAD_SIZE {
+        warnings.push(size_error(size, packed_size));
+    }
+
+    warnings
+}
+
+/// Check the message of a schedule fits in an FCM message once its data is packed, oversized
+/// payloads sent as they are only get a warning as they were accepted before
+pub fn validate_size(schedule: &UpdateSchedule) -> Result<(), String> {
+    if schedule.compression == Compression::None && schedule.message.is_empty() {
+        return Ok(());
+    }
+
+    // the app couldn't tell data that wasn't packed apart from packed data
+    let flagged = match &schedule.message.data {
+        Some(data) => data.contains_key(COMPRESSION_KEY),
+        None => schedule.message.is_empty() && schedule.payload.get(COMPRESSION_KEY).is_some(),
+    };
+    if flagged && schedule.compression != Compression::None {
+        return Err(format!(
+            "data key `{}` is reserved for compressed messages",
+            COMPRESSION_KEY
+        ));
+    }
+
+    let (size, packed_size) =
+        message_sizes(&schedule.payload, &schedule.message, schedule.compression);
+    if packed_size > MAX_PAYLOAD_SIZE {
+        return Err(size_error(size, packed_size));
+    }
+
+    Ok(())
+}
+
+fn size_error(size: usize, packed_size: usize) -> String {
+    if size == packed_size {
+        format!(
+            "message is {} bytes, FCM rejects messages larger than {} bytes",
+            size, MAX_PAYLOAD_SIZE
+        )
+    } else {
+        format!(
+            "message is {} bytes and {} bytes compressed, FCM rejects messages larger than {} bytes",
+            size, packed_size, MAX_PAYLOAD_SIZE
+        )
+    }
+}
+
+/// Inspect a structured message and return non-fatal warnings about how FCM will treat it
+fn analyze_message(message: &FcmMessage) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    match &message.notification {
+        None => warnings.push(
+            "no notification — message will be delivered as data-only and won't be displayed"
+                .to_string(),
+        ),
+        Some(notification) if notification.title.is_none() => {
+            warnings.push("notification.title missing — push may render blank".to_string())
+        }
+        Some(_) => {}
+    }
+
+    warnings
+}
+
+fn validate_data_keys<'a>(
+    field: &str,
+    keys: impl Iterator<Item = &'a String>,
+) -> Result<(), String> {
+    for key in keys {
+        if key.is_empty()
+            || RESERVED_KEYS.contains(&key.as_str())
+            || RESERVED_PREFIXES.iter().any(|p| key.starts_with(p))
+        {
+            return Err(format!("{} key `{}` is reserved by FCM", field, key));
+        }
+    }
+    Ok(())
+}
+
+fn validate_fcm_options(field: &str, value: &Value) -> Result<(), String> {
+    let options = object(field, value, FCM_OPTIONS_KEYS)?;
+    for (key, value) in options {
+        let value = string(&format!("{}.{}", field, key), value)?;
+        if key != "analytics_label" {
+            validate_url(&format!("{}.{}", field, key), value)?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_url(field: &str, url: &str) -> Result<(), String> {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        _ => Err(format!("{} must be an http(s) URL", field)),
+    }
+}
+
+/// The value as an object holding only the allowed keys, any key is allowed when there are none
+fn object<'a>(
+    field: &str,
+    value: &'a Value,
+    allowed: &[&str],
+) -> Result<&'a Map<String, Value>, String> {
+    let map = match value {
+        Value::Object(map) => map,
+        _ => return Err(format!("{} must be an object", field)),
+    };
+
+    if !allowed.is_empty() {
+        if let Some(key) = map.keys().find(|key| !allowed.contains(&key.as_str())) {
+            return Err(format!("{} has an unknown field `{}`", field, key));
+        }
+    }
+    Ok(map)
+}
+
+fn string_map<'a>(field: &str, value: &'a Value) -> Result<&'a Map<String, Value>, String> {
+    let map = object(field, value, &[])?;
+    if let Some(key) = map
+        .iter()
+        .find(|(_, value)| !value.is_string())
+        .map(|(key, _)| key)
+    {
+        return Err(format!("{}.{} must be a string", field, key));
+    }
+    Ok(map)
+}
+
+fn string<'a>(field: &str, value: &'a Value) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("{} must be a string", field))
+}
+
+fn boolean(field: &str, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Bool(_) => Ok(()),
+        _ => Err(format!("{} must be a boolean", field)),
+    }
+}
+
+// protobuf durations are seconds with up to nine fractional digits, e.g. "3.5s"
+fn valid_duration(duration: &str) -> bool {
+    let seconds = match duration.strip_suffix('s') {
+        Some(seconds) => seconds,
+        None => return false,
+    };
+    let (whole, fraction) = match seconds.split_once('.') {
+        Some((_, "")) => return false,
+        Some((whole, fraction)) => (whole, fraction),
+        None => (seconds, ""),
+    };
+    !whole.is_empty()
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.len() <= 9
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+fn valid_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
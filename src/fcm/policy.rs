@@ -0,0 +1,218 @@
+use super::model::ProjectSettings;
+use super::utils::Claims;
+use crate::quota::QuotaStatus;
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::{collections::HashMap, env, sync::RwLock};
+use tracing::warn;
+
+lazy_static! {
+    static ref CONFIG: RwLock<PolicyConfig> = RwLock::new(PolicyConfig::from_env());
+    /// days of inactivity after which anonymous users are purged (0 disables the cleanup)
+    pub static ref ANONYMOUS_RETENTION_DAYS: i64 = env::var("FCM_ANONYMOUS_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    /// consecutive failed deliveries after which a schedule is disabled as failed (0 never disables)
+    pub static ref MAX_CONSECUTIVE_FAILURES: i64 = env::var("FCM_MAX_CONSECUTIVE_FAILURES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5);
+    /// shortest time between two executions of a recurring schedule
+    pub static ref MIN_INTERVAL_SECS: i64 = env::var("FCM_MIN_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v: &i64| *v > 0)
+        .unwrap_or(60);
+    /// seconds an execution may be overdue before it counts as missed, the scheduler polls once a minute
+    pub static ref CATCH_UP_GRACE_SECS: i64 = env::var("FCM_CATCH_UP_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+}
+
+struct PolicyConfig {
+    // e.g. FCM_CLAIM_POLICY="create_schedule=premium:true|role:admin"
+    claims: HashMap<String, Vec<(String, String)>>,
+    // how schedules from anonymous accounts are handled: allow, restrict or reject
+    anonymous: AnonymousPolicy,
+    anonymous_max_schedules: i64,
+}
+
+impl PolicyConfig {
+    fn from_env() -> Self {
+        PolicyConfig {
+            claims: parse_policy(&env::var("FCM_CLAIM_POLICY").unwrap_or_default()),
+            anonymous: AnonymousPolicy::from(
+                env::var("FCM_ANONYMOUS_POLICY")
+                    .unwrap_or_default()
+                    .as_str(),
+            ),
+            anonymous_max_schedules: env::var("FCM_ANONYMOUS_MAX_SCHEDULES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+        }
+    }
+}
+
+/// Read the claim and anonymous user policies from the environment again
+pub fn reload() -> String {
+    let config = PolicyConfig::from_env();
+    let summary = format!(
+        "{} feature rule(s), anonymous accounts: {:?}",
+        config.claims.len(),
+        config.anonymous
+    );
+    *CONFIG.write().unwrap() = config;
+    summary
+}
+
+/// Features that can be restricted to users with specific custom claims
+#[derive(Debug, Clone, Copy)]
+pub enum Feature {
+    CreateSchedule,
+    ManageProject,
+    HighPriority,
+    FanOut,
+}
+
+impl Feature {
+    fn key(&self) -> &'static str {
+        match self {
+            Feature::CreateSchedule => "create_schedule",
+            Feature::ManageProject => "manage_project",
+            Feature::HighPriority => "high_priority",
+            Feature::FanOut => "fan_out",
+        }
+    }
+
+    // administrative features are closed unless a policy grants access
+    fn allowed_by_default(&self) -> bool {
+        !matches!(self, Feature::ManageProject)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnonymousPolicy {
+    Allow,
+    Restrict,
+    Reject,
+}
+
+impl From<&str> for AnonymousPolicy {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "restrict" => AnonymousPolicy::Restrict,
+            "reject" => AnonymousPolicy::Reject,
+            _ => AnonymousPolicy::Allow,
+        }
+    }
+}
+
+fn parse_policy(raw: &str) -> HashMap<String, Vec<(String, String)>> {
+    let mut policy = HashMap::new();
+
+    for rule in raw.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+        let (feature, requirements) = match rule.split_once('=') {
+            Some(parts) => parts,
+            None => {
+                warn!(rule = %rule, "Ignoring malformed claim policy rule");
+                continue;
+            }
+        };
+
+        let requirements = requirements
+            .split('|')
+            .filter_map(|r| r.split_once(':'))
+            .map(|(claim, value)| (claim.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        policy.insert(feature.trim().to_string(), requirements);
+    }
+
+    policy
+}
+
+fn claim_matches(claim: Option<&Value>, expected: &str) -> bool {
+    match claim {
+        Some(Value::String(s)) => s == expected,
+        Some(Value::Array(values)) => values.iter().any(|v| claim_matches(Some(v), expected)),
+        Some(value) => serde_json::from_str::<Value>(expected).is_ok_and(|e| &e == value),
+        None => false,
+    }
+}
+
+/// Check whether the custom claims of the user grant access to the feature.
+/// Features without a configured policy fall back to their default availability.
+pub fn authorize(claims: &Claims, feature: Feature) -> Result<(), String> {
+    let config = CONFIG.read().unwrap();
+    let granted = match config.claims.get(feature.key()) {
+        Some(requirements) => requirements
+            .iter()
+            .any(|(claim, expected)| claim_matches(claims.custom.get(claim), expected)),
+        None => feature.allowed_by_default(),
+    };
+
+    if !granted {
+        return Err(format!(
+            "{} is not available for this account",
+            feature.key()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check whether the user is allowed to own another schedule under the anonymous user policy
+pub fn authorize_anonymous(claims: &Claims, schedule_count: i64) -> Result<(), String> {
+    if !claims.is_anonymous() {
+        return Ok(());
+    }
+
+    let config = CONFIG.read().unwrap();
+    match config.anonymous {
+        AnonymousPolicy::Allow => Ok(()),
+        AnonymousPolicy::Reject => {
+            Err("anonymous accounts are not allowed to create schedules".to_string())
+        }
+        AnonymousPolicy::Restrict if schedule_count >= config.anonymous_max_schedules => {
+            Err(format!(
+                "anonymous accounts are limited to {} schedule(s), sign in to create more",
+                config.anonymous_max_schedules
+            ))
+        }
+        AnonymousPolicy::Restrict => Ok(()),
+    }
+}
+
+/// Schedules the user may own under the anonymous user policy, `None` when unlimited
+pub fn schedule_limit(claims: &Claims) -> Option<i64> {
+    if !claims.is_anonymous() {
+        return None;
+    }
+
+    let config = CONFIG.read().unwrap();
+    match config.anonymous {
+        AnonymousPolicy::Allow => None,
+        AnonymousPolicy::Reject => Some(0),
+        AnonymousPolicy::Restrict => Some(config.anonymous_max_schedules),
+    }
+}
+
+/// Usage of the user's schedule quota with `schedule_count` schedules
+pub fn schedule_quota(claims: &Claims, schedule_count: i64) -> QuotaStatus {
+    QuotaStatus::new("schedules", schedule_count, schedule_limit(claims), None)
+}
+
+/// Reason schedules saved by the user are held back from delivery. Projects can require
+/// a project admin to approve the schedules of everyone else.
+pub fn approval_hold(claims: &Claims, project: Option<&ProjectSettings>) -> Option<&'static str> {
+    let require_approval = project.is_some_and(|p| p.require_approval);
+
+    if require_approval && authorize(claims, Feature::ManageProject).is_err() {
+        return Some("pending_approval");
+    }
+
+    None
+}
@@ -0,0 +1,479 @@
+use super::accounts::ServiceAccounts;
+use super::compression;
+use super::errors::{classify, ErrorCode};
+use super::model::{Compression, FCMSchedule, FcmMessage, ProjectSettings, TargetType};
+use super::utils::topic_name;
+use crate::http;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, Value};
+use std::collections::HashMap;
+use tracing::{debug, error, warn};
+
+// https://firebase.google.com/docs/cloud-messaging/concept-options#notification-messages-with-optional-data-payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FCM {
+    message: FCMBody,
+    /// FCM checks the message and its target without delivering it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    validate_only: bool,
+}
+
+impl FCM {
+    /// Bytes of the notification and data, FCM rejects messages over 4KB
+    pub fn size(&self) -> usize {
+        let notification = if self.message.notification.is_empty() {
+            0
+        } else {
+            serde_json::to_string(&self.message.notification)
+                .map(|n| n.len())
+                .unwrap_or(0)
+        };
+        notification + compression::size(&self.message.data)
+    }
+
+    /// Have FCM only validate the message instead of delivering it
+    pub fn set_validate_only(&mut self, validate_only: bool) {
+        self.validate_only = validate_only;
+    }
+
+    fn compress(&mut self, compression: Compression) {
+        self.message.data = compression::apply(std::mem::take(&mut self.message.data), compression);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Notification {
+    title: Option<String>,
+    body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+}
+
+impl Notification {
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.body.is_none() && self.image.is_none()
+    }
+}
+
+// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#androidnotification
+#[derive(Debug, Serialize, Deserialize)]
+struct AndroidNotification {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    click_action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AndroidConfig {
+    notification: AndroidNotification,
+}
+
+impl AndroidConfig {
+    fn from_project(settings: &ProjectSettings) -> Option<Self> {
+        if settings.icon.is_none()
+            && settings.color.is_none()
+            && settings.click_action.is_none()
+            && settings.channel_id.is_none()
+        {
+            return None;
+        }
+
+        Some(AndroidConfig {
+            notification: AndroidNotification {
+                icon: settings.icon.clone(),
+                color: settings.color.clone(),
+                click_action: settings.click_action.clone(),
+                channel_id: settings.channel_id.clone(),
+            },
+        })
+    }
+}
+
+// https://firebase.google.com/docs/reference/fcm/rest/v1/projects.messages#apnsconfig
+#[derive(Debug, Serialize, Deserialize)]
+struct ApnsConfig {
+    payload: Value,
+    fcm_options: ApnsFcmOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApnsFcmOptions {
+    image: String,
+}
+
+impl ApnsConfig {
+    // iOS only downloads the image when the notification service extension may modify the push
+    fn with_image(image: &str) -> Self {
+        ApnsConfig {
+            payload: serde_json::json!({ "aps": { "mutable-content": 1 } }),
+            fcm_options: ApnsFcmOptions {
+                image: image.to_owned(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FCMBody {
+    #[serde(skip_serializing_if = "Notification::is_empty")]
+    notification: Notification,
+    data: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    condition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    android: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apns: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webpush: Option<Value>,
+}
+
+const SCOPES: &[&str; 1] = &["https://www.googleapis.com/auth/firebase.messaging"];
+
+/// Reasons a message could not be delivered
+#[derive(Debug)]
+pub enum SendError {
+    /// the message never reached FCM or FCM asked to back off, it can be retried later
+    Transient(ErrorCode, String),
+    /// FCM received the message but refused to deliver it
+    Rejected(ErrorCode, String),
+    /// the send didn't complete within the allowed time
+    Timeout(u64),
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Transient(_, e) => write!(f, "{}", e),
+            SendError::Rejected(_, e) => write!(f, "{}", e),
+            SendError::Timeout(secs) => write!(f, "Send timed out after {} seconds", secs),
+        }
+    }
+}
+
+impl SendError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SendError::Transient(code, _) | SendError::Rejected(code, _) => *code,
+            SendError::Timeout(_) => ErrorCode::Timeout,
+        }
+    }
+}
+
+/// Build the FCM message of a schedule, merging in the project defaults
+pub fn build_message(schedule: &FCMSchedule, project: Option<&ProjectSettings>) -> FCM {
+    if let Value::String(s) = &schedule.payload {
+        if from_str::<HashMap<String, String>>(s).is_err() {
+            warn!(project_id = ?schedule.fb_project_id, message_id=?schedule.id, payload=&s, "Error parsing payload, defaulting to empty hashmap");
+        }
+    }
+
+    let mut firebase_message = build_device_message(&schedule.push_token, schedule, project);
+
+    // exactly one of token, topic and condition addresses the message
+    match schedule.target_type {
+        TargetType::Token => {}
+        TargetType::Topic => {
+            firebase_message.message.token = None;
+            firebase_message.message.topic = Some(topic_name(&schedule.push_token).to_string());
+        }
+        TargetType::Condition => {
+            firebase_message.message.token = None;
+            firebase_message.message.condition = Some(schedule.push_token.clone());
+        }
+    }
+
+    firebase_message
+}
+
+/// Build the FCM message of a schedule sent to a single token, from its structured
+/// message when it has one and from its payload otherwise, packed as the schedule asks
+pub fn build_device_message(
+    token: &str,
+    schedule: &FCMSchedule,
+    project: Option<&ProjectSettings>,
+) -> FCM {
+    let mut firebase_message = build_content(token, &schedule.payload, &schedule.message, project);
+    firebase_message.compress(schedule.compression);
+    firebase_message
+}
+
+/// Size of the message a schedule sends before and after packing its data
+pub fn message_sizes(
+    payload: &Value,
+    message: &FcmMessage,
+    compression: Compression,
+) -> (usize, usize) {
+    let mut firebase_message = build_content("", payload, message, None);
+    let size = firebase_message.size();
+    firebase_message.compress(compression);
+    (size, firebase_message.size())
+}
+
+/// Build the FCM message sent to a single token, from the structured message when it
+/// has any block and from the payload otherwise
+pub fn build_content(
+    token: &str,
+    payload: &Value,
+    message: &FcmMessage,
+    project: Option<&ProjectSettings>,
+) -> FCM {
+    if message.is_empty() {
+        build_payload_message(token, payload, project)
+    } else {
+        build_structured_message(token, message, project)
+    }
+}
+
+/// Build the FCM message of a structured message sent to a single token, the project
+/// defaults fill in the android fields the message leaves out
+fn build_structured_message(
+    token: &str,
+    message: &FcmMessage,
+    project: Option<&ProjectSettings>,
+) -> FCM {
+    let notification = match &message.notification {
+        Some(notification) => Notification {
+            title: notification.title.clone(),
+            body: notification.body.clone(),
+            image: notification.image.clone(),
+        },
+        None => Notification {
+            title: None,
+            body: None,
+            image: None,
+        },
+    };
+
+    let mut android = project
+        .and_then(AndroidConfig::from_project)
+        .and_then(|android| serde_json::to_value(android).ok());
+    if let Some(overrides) = &message.android {
+        match &mut android {
+            Some(android) => merge(android, overrides),
+            None => android = Some(overrides.clone()),
+        }
+    }
+
+    let apns = message.apns.clone().or_else(|| {
+        notification
+            .image
+            .as_deref()
+            .and_then(|image| serde_json::to_value(ApnsConfig::with_image(image)).ok())
+    });
+
+    FCM {
+        message: FCMBody {
+            notification,
+            data: message
+                .data
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            token: Some(token.to_owned()),
+            topic: None,
+            condition: None,
+            android,
+            apns,
+            webpush: message.webpush.clone(),
+        },
+        validate_only: false,
+    }
+}
+
+/// Merge the fields of `overrides` into `base`, objects are merged key by key
+fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// Build the FCM message of a payload sent to a single token, merging in the project defaults
+pub fn build_payload_message(
+    token: &str,
+    payload: &Value,
+    project: Option<&ProjectSettings>,
+) -> FCM {
+    let mut data: HashMap<String, String> = HashMap::new();
+    let mut image = None;
+    match payload {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("image", Value::String(url)) => image = Some(url.to_owned()),
+                    _ => {
+                        data.insert(key.to_owned(), value.to_string());
+                    }
+                }
+            }
+        }
+        Value::String(s) => {
+            data = from_str::<HashMap<String, String>>(s).unwrap_or_default();
+        }
+        _ => {}
+    }
+
+    let notification = Notification {
+        title: data.remove("title"),
+        body: data.remove("body"),
+        image: image.or_else(|| data.remove("image")),
+    };
+
+    let apns = notification
+        .image
+        .as_deref()
+        .and_then(|image| serde_json::to_value(ApnsConfig::with_image(image)).ok());
+
+    FCM {
+        message: FCMBody {
+            notification,
+            data,
+            token: Some(token.to_owned()),
+            topic: None,
+            condition: None,
+            android: project
+                .and_then(AndroidConfig::from_project)
+                .and_then(|android| serde_json::to_value(android).ok()),
+            apns,
+            webpush: None,
+        },
+        validate_only: false,
+    }
+}
+
+/// Message FCM only validates, checking the token is registered without notifying the device
+pub fn build_validation_message(token: &str) -> FCM {
+    FCM {
+        message: FCMBody {
+            notification: Notification {
+                title: None,
+                body: None,
+                image: None,
+            },
+            data: HashMap::new(),
+            token: Some(token.to_owned()),
+            topic: None,
+            condition: None,
+            android: None,
+            apns: None,
+            webpush: None,
+        },
+        validate_only: true,
+    }
+}
+
+/// Send a message through the FCM HTTP v1 API of the project, returns the
+/// name FCM assigned to the message (`projects/{project}/messages/{id}`)
+pub async fn send_message(
+    service_accounts: &ServiceAccounts,
+    project_id: &str,
+    firebase_message: &FCM,
+) -> Result<String, SendError> {
+    let (status, resp) = send_raw(service_accounts, project_id, firebase_message).await?;
+
+    if status.is_success() {
+        let resp = from_str::<Value>(&resp).unwrap_or_default();
+        let name = resp["name"].as_str().unwrap_or_default().to_string();
+        debug!(project_id = ?project_id, name = %name, "Successfully sent request");
+        Ok(name)
+    } else {
+        let code = classify(status, &resp);
+        warn!(project_id = ?project_id, status = %status, code = code.as_str(), response=?resp, "Error sending request");
+        if code.is_retryable() {
+            Err(SendError::Transient(code, resp))
+        } else {
+            Err(SendError::Rejected(code, resp))
+        }
+    }
+}
+
+/// Send a message through the FCM HTTP v1 API of the project, returns the status and
+/// the body FCM answered with, errors only when the request never reached FCM
+pub async fn send_raw(
+    service_accounts: &ServiceAccounts,
+    project_id: &str,
+    firebase_message: &FCM,
+) -> Result<(StatusCode, String), SendError> {
+    let auth_manager = match service_accounts.get(project_id) {
+        Some(auth_manager) => auth_manager,
+        None => {
+            warn!(project_id = ?project_id, "No auth manager found for project id");
+            return Err(SendError::Rejected(
+                ErrorCode::AuthError,
+                "No auth manager found for project id".to_string(),
+            ));
+        }
+    };
+
+    let token = match auth_manager.get_token(SCOPES).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!(project_id = ?project_id, error=?e, "Error getting token");
+            return Err(SendError::Transient(
+                ErrorCode::AuthError,
+                format!("Error getting token: {}", e),
+            ));
+        }
+    };
+
+    let header = match format!("Bearer {}", token.as_str()).parse() {
+        Ok(header) => header,
+        Err(e) => {
+            error!(project_id = ?project_id, error=?e, "Error parsing header");
+            return Err(SendError::Rejected(
+                ErrorCode::AuthError,
+                format!("Error parsing header: {}", e),
+            ));
+        }
+    };
+
+    // Create the authorization header with the token
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, header);
+
+    let endpoint = format!(
+        "https://fcm.googleapis.com/v1/projects/{}/messages:send",
+        project_id
+    );
+
+    // Send the HTTP POST request once, a send that timed out may have been delivered
+    // already and the outbox retries failed sends with its own backoff and execution log
+    let response = http::CLIENT
+        .post(endpoint)
+        .headers(headers)
+        .json(firebase_message)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            let resp = response.text().await.unwrap_or_default();
+            Ok((status, resp))
+        }
+        Err(e) => {
+            error!(project_id = ?project_id, error=?e, "Error sending request");
+            Err(SendError::Transient(
+                ErrorCode::ServerError,
+                format!("Error sending request: {}", e),
+            ))
+        }
+    }
+}
@@ -0,0 +1,477 @@
+use super::model::{
+    FCMSchedule, NewScheduleShare, OrganizationRole, ScheduleShare, ShareRole, SharedSchedule,
+};
+use super::organizations;
+use super::utils::authenticate;
+use crate::utils::{ApiTags, JsonError, JsonSuccess, ResponseObject};
+use chrono::Utc;
+use poem::{web::Data, Request};
+use poem_openapi::{param::Path, payload::Json, OpenApi};
+use sqlx::SqlitePool;
+
+// most users a single schedule can be shared with, pending invitations included
+const MAX_SHARES: i64 = 20;
+
+/// Access a user has to a schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    View,
+    Edit,
+    Own,
+}
+
+/// Access of the user to the schedule, None when it doesn't exist or the user neither
+/// owns it, has it shared with them nor belongs to its organization
+pub async fn schedule_access(
+    pool: &SqlitePool,
+    schedule_id: i64,
+    fb_user_id: &str,
+) -> Result<Option<Access>, sqlx::Error> {
+    let access = sqlx::query!(
+        r#"SELECT CASE WHEN s.fb_user_id = ?2 THEN 'owner' ELSE sh.role END as "share?: String",
+            m.role as "organization_role?: String"
+        FROM fcm_schedule s
+        LEFT JOIN fcm_schedule_share sh
+            ON sh.schedule_id = s.id AND sh.fb_user_id = ?2 AND sh.accepted_at IS NOT NULL
+        LEFT JOIN fcm_organization_member m
+            ON m.organization_id = s.organization_id AND m.fb_user_id = ?2
+        WHERE s.id = ?1"#,
+        schedule_id,
+        fb_user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let access = match access {
+        Some(access) => access,
+        None => return Ok(None),
+    };
+
+    let shared = match access.share.as_deref() {
+        Some("owner") => Some(Access::Own),
+        Some("editor") => Some(Access::Edit),
+        Some("viewer") => Some(Access::View),
+        _ => None,
+    };
+    let organization = access
+        .organization_role
+        .map(|role| organizations::schedule_access(OrganizationRole::from(role)));
+
+    Ok(shared.max(organization))
+}
+
+/// Check the user has at least the required access to the schedule. Schedules that
+/// aren't shared with the user are reported as not found rather than forbidden.
+pub async fn authorize(
+    pool: &SqlitePool,
+    schedule_id: i64,
+    fb_user_id: &str,
+    required: Access,
+) -> Result<Access, JsonError<String>> {
+    match schedule_access(pool, schedule_id, fb_user_id).await {
+        Ok(Some(access)) if access >= required => Ok(access),
+        Ok(Some(Access::View)) => Err(ResponseObject::forbidden(
+            "Schedule is shared with you read-only",
+        )),
+        Ok(Some(_)) => Err(ResponseObject::forbidden(
+            "Only the owner of the schedule can do this",
+        )),
+        Ok(None) => Err(ResponseObject::not_found("Schedule not found")),
+        Err(e) => Err(ResponseObject::internal_server_error(e)),
+    }
+}
+
+#[derive(Default)]
+pub struct FirebaseShares;
+
+#[OpenApi(
+    prefix_path = "/fcm/",
+    request_header(
+        name = "firebase-auth",
+        ty = "String",
+        description = "Bearer token generated from firebase project (example: <code>Bearer {token}</code>)"
+    ),
+    tag = "ApiTags::FirebaseMessaging"
+)]
+impl FirebaseShares {
+    // Invite another user, by firebase user id or verified email address, to view or
+    // co-manage the schedule. Inviting the same user again changes their role
+    #[oai(
+        path = "/:id/shares",
+        method = "post",
+        operation_id = "fcm::share_schedule"
+    )]
+    async fn share_schedule(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        share: Json<NewScheduleShare>,
+    ) -> Result<JsonSuccess<ScheduleShare>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, Access::Own).await?;
+
+        // emails are matched against the verified email of the token, which firebase lowercases
+        let invitee = share.invitee.trim();
+        let invitee = match invitee.contains('@') {
+            true => invitee.to_lowercase(),
+            false => invitee.to_string(),
+        };
+
+        if invitee.is_empty() {
+            return Err(ResponseObject::bad_request("Invitee is required"));
+        }
+        if invitee == claims.user_id || Some(&invitee) == claims.verified_email().as_ref() {
+            return Err(ResponseObject::bad_request(
+                "Cannot share a schedule with yourself",
+            ));
+        }
+
+        let shares = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count: i64" FROM fcm_schedule_share WHERE schedule_id = ? AND invitee != ?"#,
+            id.0,
+            invitee
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match shares {
+            Ok(shares) if shares >= MAX_SHARES => {
+                return Err(ResponseObject::bad_request(format!(
+                    "Schedule is already shared with {} users",
+                    MAX_SHARES
+                )));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "INSERT INTO fcm_schedule_share (schedule_id, invitee, role, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (schedule_id, invitee) DO UPDATE SET role = excluded.role",
+            id.0,
+            invitee,
+            share.role,
+            current_time
+        )
+        .execute(pool.0)
+        .await;
+
+        if let Err(e) = result {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        let share = sqlx::query_as!(
+            ScheduleShare,
+            r#"SELECT sh.id as "id!", sh.schedule_id, s.name as schedule_name, sh.invitee, sh.role, sh.fb_user_id, sh.created_at, sh.accepted_at
+            FROM fcm_schedule_share sh
+            JOIN fcm_schedule s ON s.id = sh.schedule_id
+            WHERE sh.schedule_id = ? AND sh.invitee = ?"#,
+            id.0,
+            invitee
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match share {
+            Ok(share) => Ok(ResponseObject::created(share)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // List the users the schedule is shared with, including pending invitations
+    #[oai(
+        path = "/:id/shares",
+        method = "get",
+        operation_id = "fcm::list_shares"
+    )]
+    async fn list_shares(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+    ) -> Result<JsonSuccess<Vec<ScheduleShare>>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, Access::Own).await?;
+
+        let shares = sqlx::query_as!(
+            ScheduleShare,
+            r#"SELECT sh.id as "id!", sh.schedule_id, s.name as schedule_name, sh.invitee, sh.role, sh.fb_user_id, sh.created_at, sh.accepted_at
+            FROM fcm_schedule_share sh
+            JOIN fcm_schedule s ON s.id = sh.schedule_id
+            WHERE sh.schedule_id = ?
+            ORDER BY sh.id"#,
+            id.0
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match shares {
+            Ok(shares) => Ok(ResponseObject::ok(shares)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Stop sharing the schedule with a user, or withdraw a pending invitation
+    #[oai(
+        path = "/:id/shares/:share_id",
+        method = "delete",
+        operation_id = "fcm::revoke_share"
+    )]
+    async fn revoke_share(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        id: Path<i64>,
+        share_id: Path<i64>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        authorize(pool.0, id.0, &claims.user_id, Access::Own).await?;
+
+        let result = sqlx::query!(
+            "DELETE FROM fcm_schedule_share WHERE id = ? AND schedule_id = ?",
+            share_id.0,
+            id.0
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(ResponseObject::not_found("Share not found"))
+            }
+            Ok(_) => Ok(ResponseObject::ok("Share revoked".to_string())),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // List the pending invitations sent to the user id or verified email of the user
+    #[oai(
+        path = "/invitations",
+        method = "get",
+        operation_id = "fcm::list_invitations"
+    )]
+    async fn list_invitations(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<ScheduleShare>>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let email = claims.verified_email();
+        let invitations = sqlx::query_as!(
+            ScheduleShare,
+            r#"SELECT sh.id as "id!", sh.schedule_id, s.name as schedule_name, sh.invitee, sh.role, sh.fb_user_id, sh.created_at, sh.accepted_at
+            FROM fcm_schedule_share sh
+            JOIN fcm_schedule s ON s.id = sh.schedule_id
+            WHERE sh.accepted_at IS NULL AND (sh.invitee = ? OR sh.invitee = ?)
+            ORDER BY sh.id"#,
+            claims.user_id,
+            email
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        match invitations {
+            Ok(invitations) => Ok(ResponseObject::ok(invitations)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Accept an invitation, the schedule then shows up among the shared schedules
+    #[oai(
+        path = "/invitations/:share_id/accept",
+        method = "post",
+        operation_id = "fcm::accept_invitation"
+    )]
+    async fn accept_invitation(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        share_id: Path<i64>,
+    ) -> Result<JsonSuccess<SharedSchedule>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let email = claims.verified_email();
+        let current_time = Utc::now().naive_utc();
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule_share SET fb_user_id = ?, accepted_at = ?
+            WHERE id = ? AND accepted_at IS NULL AND (invitee = ? OR invitee = ?)",
+            claims.user_id,
+            current_time,
+            share_id.0,
+            claims.user_id,
+            email
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                return Err(ResponseObject::not_found("Invitation not found"));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        }
+
+        let share = sqlx::query!(
+            "SELECT schedule_id, role FROM fcm_schedule_share WHERE id = ?",
+            share_id.0
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let share = match share {
+            Ok(share) => share,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let schedule = sqlx::query_as!(
+            FCMSchedule,
+            "SELECT * FROM fcm_schedule WHERE id = ?",
+            share.schedule_id
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match schedule {
+            Ok(schedule) => Ok(ResponseObject::ok(SharedSchedule {
+                role: ShareRole::from(share.role),
+                schedule,
+            })),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // Decline a pending invitation, or stop receiving a schedule shared with the user
+    #[oai(
+        path = "/invitations/:share_id",
+        method = "delete",
+        operation_id = "fcm::decline_invitation"
+    )]
+    async fn decline_invitation(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        share_id: Path<i64>,
+    ) -> Result<JsonSuccess<String>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let email = claims.verified_email();
+        let result = sqlx::query!(
+            "DELETE FROM fcm_schedule_share
+            WHERE id = ? AND (fb_user_id = ? OR (accepted_at IS NULL AND (invitee = ? OR invitee = ?)))",
+            share_id.0,
+            claims.user_id,
+            claims.user_id,
+            email
+        )
+        .execute(pool.0)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(ResponseObject::not_found("Invitation not found"))
+            }
+            Ok(_) => Ok(ResponseObject::ok("Invitation declined".to_string())),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    // List the schedules other users shared with the user
+    #[oai(
+        path = "/shared",
+        method = "get",
+        operation_id = "fcm::list_shared_schedules"
+    )]
+    async fn list_shared_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<JsonSuccess<Vec<SharedSchedule>>, JsonError<String>> {
+        let claims = match authenticate(req, pool.0).await {
+            Ok(claims) => claims,
+            Err(e) => {
+                return Err(ResponseObject::unauthorized(e));
+            }
+        };
+
+        let roles = sqlx::query!(
+            "SELECT schedule_id, role FROM fcm_schedule_share WHERE fb_user_id = ? AND accepted_at IS NOT NULL ORDER BY schedule_id",
+            claims.user_id
+        )
+        .fetch_all(pool.0)
+        .await;
+
+        let roles = match roles {
+            Ok(roles) => roles,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let mut shared = Vec::with_capacity(roles.len());
+        for role in roles {
+            let schedule = sqlx::query_as!(
+                FCMSchedule,
+                "SELECT * FROM fcm_schedule WHERE id = ?",
+                role.schedule_id
+            )
+            .fetch_one(pool.0)
+            .await;
+
+            match schedule {
+                Ok(schedule) => shared.push(SharedSchedule {
+                    role: ShareRole::from(role.role),
+                    schedule,
+                }),
+                Err(e) => {
+                    return Err(ResponseObject::internal_server_error(e));
+                }
+            }
+        }
+
+        Ok(ResponseObject::ok(shared))
+    }
+}
@@ -0,0 +1,144 @@
+use super::model::UpdateSchedule;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{Executor, Sqlite, SqliteConnection};
+
+/// Insert a new schedule for the user, optionally owned together with an organization,
+/// and return its id. Schedules with a disabled reason are not delivered until it's cleared
+pub async fn insert_schedule<'e, E>(
+    executor: E,
+    fb_user_id: &str,
+    fb_project_id: &str,
+    organization_id: Option<i64>,
+    schedule: &UpdateSchedule,
+    next_execution: NaiveDateTime,
+    disabled_reason: Option<&str>,
+) -> Result<i64, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let current_time = Utc::now().naive_local();
+    let tags = schedule.tags.to_db();
+    let conditions = schedule.conditions.to_db();
+    let message = schedule.message.to_db();
+
+    let result = sqlx::query!(
+        "INSERT INTO fcm_schedule (
+            name, fb_user_id, push_token, target_type, fb_project_id, organization_id, cron_pattern, timezone, local_time, schedule_type, run_at, payload, message, compression, timeout_seconds, jitter_seconds, catch_up, priority, tags, conditions, expires_at, max_executions, holiday_calendar, disabled_reason, last_execution, next_execution, created_at, updated_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        schedule.name,
+        fb_user_id,
+        schedule.push_token,
+        schedule.target_type,
+        fb_project_id,
+        organization_id,
+        schedule.cron_pattern,
+        schedule.timezone,
+        schedule.local_time,
+        schedule.schedule_type,
+        schedule.run_at,
+        schedule.payload,
+        message,
+        schedule.compression,
+        schedule.timeout_seconds,
+        schedule.jitter_seconds,
+        schedule.catch_up,
+        schedule.priority,
+        tags,
+        conditions,
+        schedule.expires_at,
+        schedule.max_executions,
+        schedule.holiday_calendar,
+        disabled_reason,
+        current_time,
+        next_execution,
+        current_time,
+        current_time
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Replace every field of the schedule the user can edit, returning whether it was updated.
+/// With a version, the schedule is only updated when it's still at that version
+pub async fn update_schedule<'e, E>(
+    executor: E,
+    id: i64,
+    schedule: &UpdateSchedule,
+    next_execution: NaiveDateTime,
+    disabled_reason: Option<&str>,
+    version: Option<i64>,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let current_time = Utc::now().naive_local();
+    let tags = schedule.tags.to_db();
+    let conditions = schedule.conditions.to_db();
+    let message = schedule.message.to_db();
+
+    // schedules blocked by an operator or paused by the user stay that way
+    let result = sqlx::query!(
+        "UPDATE fcm_schedule SET name = ?, push_token = ?, target_type = ?, cron_pattern = ?, timezone = ?, local_time = ?, schedule_type = ?, run_at = ?, payload = ?, message = ?, compression = ?, timeout_seconds = ?, jitter_seconds = ?, catch_up = ?, priority = ?, tags = ?, conditions = ?, expires_at = ?, max_executions = ?, holiday_calendar = ?, disabled_reason = CASE WHEN disabled_reason IN ('blocked', 'paused') THEN disabled_reason ELSE ? END, failed_count = 0, next_execution = ?, snoozed_until = NULL, version = version + 1, updated_at = ? WHERE id = ? AND (? IS NULL OR version = ?)",
+        schedule.name,
+        schedule.push_token,
+        schedule.target_type,
+        schedule.cron_pattern,
+        schedule.timezone,
+        schedule.local_time,
+        schedule.schedule_type,
+        schedule.run_at,
+        schedule.payload,
+        message,
+        schedule.compression,
+        schedule.timeout_seconds,
+        schedule.jitter_seconds,
+        schedule.catch_up,
+        schedule.priority,
+        tags,
+        conditions,
+        schedule.expires_at,
+        schedule.max_executions,
+        schedule.holiday_calendar,
+        disabled_reason,
+        next_execution,
+        current_time,
+        id,
+        version,
+        version
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace the additional devices of a schedule
+pub async fn replace_schedule_tokens(
+    conn: &mut SqliteConnection,
+    schedule_id: i64,
+    push_tokens: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM fcm_schedule_tokens WHERE schedule_id = ?",
+        schedule_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let now = Utc::now().naive_utc();
+    for push_token in push_tokens {
+        sqlx::query!(
+            "INSERT INTO fcm_schedule_tokens (schedule_id, push_token, created_at) VALUES (?, ?, ?)",
+            schedule_id,
+            push_token,
+            now
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
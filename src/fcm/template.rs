@@ -0,0 +1,191 @@
+use super::actions::{self, LinkAction, DEFAULT_SNOOZE_MINUTES};
+use super::followups::MAX_DELAY_MINUTES;
+use super::model::{FCMSchedule, FcmMessage};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, NaiveDateTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use serde_json::Value;
+use tracing::warn;
+
+// Payload strings can contain `{{ variable }}` placeholders, rendered when the
+// message is built. Time variables take an optional strftime format after a
+// colon, e.g. `{{ date:%d %B }}`. `{{ ack_link }}` and `{{ snooze_link:30 }}`
+// render signed one-tap links (see actions.rs). Unknown variables are rejected
+// when the schedule is saved so typos don't reach devices.
+
+/// Values placeholders are rendered with, times are in the schedule's timezone
+pub struct Context {
+    pub now: DateTime<Tz>,
+    /// when the schedule sends next, None for one-shot schedules that are done
+    pub next_run: Option<DateTime<Tz>>,
+    pub user_id: String,
+    pub name: String,
+    /// schedule action links act on
+    pub schedule_id: i64,
+    /// execution of the schedule action links act on, shared by all of its devices
+    pub execution_key: String,
+}
+
+impl Context {
+    /// Context of a schedule sent now, `next_run` is the execution after this one in UTC
+    pub fn for_schedule(
+        schedule: &FCMSchedule,
+        next_run: Option<NaiveDateTime>,
+        execution_key: &str,
+    ) -> Self {
+        let timezone = schedule.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+        Context {
+            now: Utc::now().with_timezone(&timezone),
+            next_run: next_run.map(|next| timezone.from_utc_datetime(&next)),
+            user_id: schedule.fb_user_id.clone(),
+            name: schedule.name.clone(),
+            schedule_id: schedule.id,
+            execution_key: execution_key.to_string(),
+        }
+    }
+
+    /// Placeholder values used to check templates before they are saved
+    fn sample() -> Self {
+        Context {
+            now: Utc::now().with_timezone(&Tz::UTC),
+            next_run: None,
+            user_id: String::new(),
+            name: String::new(),
+            schedule_id: 0,
+            execution_key: String::new(),
+        }
+    }
+
+    fn value(&self, variable: &str, format: Option<&str>) -> Result<String, String> {
+        let time = match variable {
+            "user_id" | "name" | "ack_link" if format.is_some() => {
+                return Err(format!("{{{{{}}}}} doesn't take a format", variable));
+            }
+            "user_id" => return Ok(self.user_id.clone()),
+            "name" => return Ok(self.name.clone()),
+            "ack_link" => {
+                return actions::link(self.schedule_id, &self.execution_key, LinkAction::Ack, None)
+            }
+            "snooze_link" => {
+                let minutes = match format.map(str::trim).filter(|format| !format.is_empty()) {
+                    Some(minutes) => match minutes.parse::<i64>() {
+                        Ok(minutes) if (1..=MAX_DELAY_MINUTES).contains(&minutes) => minutes,
+                        _ => return Err(format!("Invalid snooze minutes `{}`", minutes)),
+                    },
+                    None => DEFAULT_SNOOZE_MINUTES,
+                };
+                return actions::link(
+                    self.schedule_id,
+                    &self.execution_key,
+                    LinkAction::Snooze,
+                    Some(minutes),
+                );
+            }
+            "date" => Some((self.now, "%Y-%m-%d")),
+            "time" => Some((self.now, "%H:%M")),
+            "weekday" => Some((self.now, "%A")),
+            "next_run" => self.next_run.map(|next| (next, "%Y-%m-%d %H:%M")),
+            _ => return Err(format!("Unknown template variable {{{{{}}}}}", variable)),
+        };
+
+        let format = format.map(str::trim).filter(|format| !format.is_empty());
+        if let Some(format) = format {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(format!("Invalid time format `{}`", format));
+            }
+        }
+
+        Ok(match time {
+            Some((time, default)) => time.format(format.unwrap_or(default)).to_string(),
+            None => String::new(),
+        })
+    }
+}
+
+/// Render the payload of a schedule about to be sent, payloads that fail to
+/// render (e.g. saved before templates existed) are sent as they are. Action links
+/// act on the execution `execution_key` belongs to
+pub fn render_schedule(
+    schedule: &mut FCMSchedule,
+    next_run: Option<NaiveDateTime>,
+    execution_key: &str,
+) {
+    let context = Context::for_schedule(schedule, next_run, execution_key);
+    match render_payload(&schedule.payload, &context) {
+        Ok(payload) => schedule.payload = payload,
+        Err(e) => {
+            warn!(schedule_id = schedule.id, error = %e, "Error rendering payload template, sending it unrendered")
+        }
+    }
+    if schedule.message.is_empty() {
+        return;
+    }
+    match render_message(&schedule.message, &context) {
+        Ok(message) => schedule.message = message,
+        Err(e) => {
+            warn!(schedule_id = schedule.id, error = %e, "Error rendering message template, sending it unrendered")
+        }
+    }
+}
+
+/// Render the placeholders of a string
+pub fn render(text: &str, context: &Context) -> Result<String, String> {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..];
+        let end = match placeholder.find("}}") {
+            Some(end) => end,
+            None => return Err("Unterminated template placeholder".to_string()),
+        };
+
+        let (variable, format) = match placeholder[..end].split_once(':') {
+            Some((variable, format)) => (variable.trim(), Some(format)),
+            None => (placeholder[..end].trim(), None),
+        };
+        rendered.push_str(&context.value(variable, format)?);
+        rest = &placeholder[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Render every string in the payload, keys are left untouched
+pub fn render_payload(payload: &Value, context: &Context) -> Result<Value, String> {
+    Ok(match payload {
+        Value::String(text) => Value::String(render(text, context)?),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render_payload(item, context))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), render_payload(value, context)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+/// Check every placeholder of the payload can be rendered
+pub fn validate(payload: &Value) -> Result<(), String> {
+    render_payload(payload, &Context::sample()).map(|_| ())
+}
+
+/// Render every string in the blocks of a structured message
+pub fn render_message(message: &FcmMessage, context: &Context) -> Result<FcmMessage, String> {
+    let value = serde_json::to_value(message).map_err(|e| e.to_string())?;
+    serde_json::from_value(render_payload(&value, context)?).map_err(|e| e.to_string())
+}
+
+/// Check every placeholder of the structured message can be rendered
+pub fn validate_message(message: &FcmMessage) -> Result<(), String> {
+    render_message(message, &Context::sample()).map(|_| ())
+}
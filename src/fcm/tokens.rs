@@ -0,0 +1,324 @@
+use super::accounts::ServiceAccounts;
+use super::errors::ErrorCode;
+use super::model::{RefreshReason, TokenHeartbeatResult};
+use super::sender::{build_validation_message, send_message, SendError};
+use crate::utils::DRY_RUN;
+use chrono::{Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use sqlx::{Executor, Sqlite, SqlitePool};
+use std::env;
+use tracing::{info, warn};
+
+lazy_static! {
+    // move schedules to the replacement token as soon as one is known
+    pub static ref AUTO_MIGRATE_TOKENS: bool = env::var("FCM_AUTO_MIGRATE_TOKENS")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    // hours between two refresh hints for the same token, apps report it on every startup
+    static ref HINT_INTERVAL_HOURS: i64 = env::var("FCM_TOKEN_HINT_INTERVAL_HOURS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(24);
+    // ask FCM whether tokens are registered before schedules are saved with them
+    static ref VALIDATE_TOKENS: bool = env::var("FCM_VALIDATE_TOKENS")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+}
+
+// FCM considers tokens without activity for a month stale
+// https://firebase.google.com/docs/cloud-messaging/manage-tokens#detect-invalid-token-responses-from-the-fcm-backend
+pub const TOKEN_STALE_DAYS: i64 = 30;
+
+// FCM registration tokens are the instance id of the app and the token issued to it joined
+// by a colon, e.g. `dXJ...:APA91bH...`, in the url safe base64 alphabet
+const REGISTRATION_PREFIX: &str = "APA91b";
+
+/// Check the token looks like an FCM registration token, tokens of other push providers
+/// the firebase SDK wraps are pointed out
+pub fn validate_format(push_token: &str) -> Result<(), String> {
+    if push_token.starts_with("ExponentPushToken[") || push_token.starts_with("ExpoPushToken[") {
+        return Err("push_token is an Expo push token, send the FCM token of the device from getDevicePushTokenAsync".to_string());
+    }
+    if push_token.len() == 64 && push_token.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(
+            "push_token is an APNs device token, send the token returned by the firebase SDK"
+                .to_string(),
+        );
+    }
+    if !(32..=512).contains(&push_token.chars().count()) {
+        return Err("push_token must be between 32 and 512 characters".to_string());
+    }
+    if let Some(c) = push_token
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || "-_:".contains(*c)))
+    {
+        return Err(format!(
+            "push_token contains `{}`, registration tokens only use letters, digits, `-`, `_` and `:`",
+            c
+        ));
+    }
+    if let Some((instance_id, token)) = push_token.split_once(':') {
+        if instance_id.is_empty() || !token.starts_with(REGISTRATION_PREFIX) || token.contains(':')
+        {
+            return Err(format!(
+                "push_token must be `<instance id>:{}...` as issued by the firebase SDK",
+                REGISTRATION_PREFIX
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Send FCM a message it only validates when FCM_VALIDATE_TOKENS is on, rejecting tokens
+/// FCM doesn't know. Tokens are accepted when FCM can't be asked, so an outage doesn't
+/// block schedules from being saved
+pub async fn verify(
+    service_accounts: &ServiceAccounts,
+    project_id: &str,
+    push_token: &str,
+) -> Result<(), String> {
+    if !*VALIDATE_TOKENS || *DRY_RUN {
+        return Ok(());
+    }
+
+    let message = build_validation_message(push_token);
+    match send_message(service_accounts, project_id, &message).await {
+        Ok(_) => Ok(()),
+        Err(SendError::Rejected(ErrorCode::InvalidToken, _)) => Err(
+            "push_token isn't registered with FCM, it may have a typo or be expired".to_string(),
+        ),
+        Err(e) => {
+            warn!(project_id, error = %e, "Could not validate push token, accepting it");
+            Ok(())
+        }
+    }
+}
+
+/// Remember for the user that a token is stale or has been replaced. FCM HTTP v1
+/// only reports that a token is no longer registered, replacements are reported by
+/// the app when the firebase SDK rotates the token.
+pub async fn record_hint<'e, E>(
+    executor: E,
+    fb_user_id: &str,
+    push_token: &str,
+    replacement_token: Option<&str>,
+    reason: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let current_time = Utc::now().naive_utc();
+
+    sqlx::query!(
+        "INSERT INTO fcm_token_hint (fb_user_id, push_token, replacement_token, reason, reported_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (fb_user_id, push_token) DO UPDATE SET
+            replacement_token = COALESCE(excluded.replacement_token, replacement_token),
+            reason = excluded.reason,
+            reported_at = excluded.reported_at",
+        fb_user_id,
+        push_token,
+        replacement_token,
+        reason,
+        current_time
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Whether the user registered the token as a device or one of its schedules sends to it,
+/// as its own or an additional device
+pub async fn owns_token<'e, E>(
+    executor: E,
+    fb_user_id: &str,
+    push_token: &str,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM fcm_schedule WHERE fb_user_id = ?1 AND push_token = ?2
+            UNION ALL
+            SELECT 1 FROM fcm_schedule_tokens
+            JOIN fcm_schedule ON fcm_schedule.id = fcm_schedule_tokens.schedule_id
+            WHERE fcm_schedule.fb_user_id = ?1 AND fcm_schedule_tokens.push_token = ?2
+            UNION ALL
+            SELECT 1 FROM fcm_device WHERE fb_user_id = ?1 AND push_token = ?2
+        ) as "owned!: bool""#,
+        fb_user_id,
+        push_token
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Point the user's schedules of a token to its replacement, schedules disabled
+/// because of the old token are enabled again
+pub async fn migrate_schedules<'e, E>(
+    executor: E,
+    fb_user_id: &str,
+    push_token: &str,
+    replacement_token: &str,
+) -> Result<u64, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let current_time = Utc::now().naive_utc();
+
+    let result = sqlx::query!(
+        "UPDATE fcm_schedule SET push_token = ?,
+            disabled_reason = CASE WHEN disabled_reason IN ('invalid_token', 'failed') THEN NULL ELSE disabled_reason END,
+            failed_count = 0, version = version + 1, updated_at = ?
+        WHERE fb_user_id = ? AND push_token = ? AND target_type = 'token'",
+        replacement_token,
+        current_time,
+        fb_user_id,
+        push_token
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Record for the user that FCM no longer accepts the token and, when enabled, move
+/// its schedules to a replacement the user already reported
+pub async fn handle_unregistered(
+    pool: &SqlitePool,
+    fb_user_id: &str,
+    push_token: &str,
+) -> Result<(), sqlx::Error> {
+    record_hint(pool, fb_user_id, push_token, None, "unregistered").await?;
+
+    if !*AUTO_MIGRATE_TOKENS {
+        return Ok(());
+    }
+
+    let replacement_token = sqlx::query_scalar!(
+        "SELECT replacement_token FROM fcm_token_hint WHERE fb_user_id = ? AND push_token = ?",
+        fb_user_id,
+        push_token
+    )
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+
+    if let Some(replacement_token) = replacement_token {
+        let migrated = migrate_schedules(pool, fb_user_id, push_token, &replacement_token).await?;
+        info!(fb_user_id = %fb_user_id, migrated, "Migrated schedules to the replacement token");
+    }
+
+    Ok(())
+}
+
+/// Record that the app holds the token and tell it whether to refresh the token. A token
+/// gets at most one hint every FCM_TOKEN_HINT_INTERVAL_HOURS, and is only considered stale
+/// again a month after its last hint, so apps reporting it on every startup aren't nagged
+pub async fn heartbeat(
+    pool: &SqlitePool,
+    fb_user_id: &str,
+    push_token: &str,
+) -> Result<TokenHeartbeatResult, sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+
+    // a device signed in to another account moves to this one
+    let seen = sqlx::query!(
+        r#"INSERT INTO fcm_token_heartbeat (push_token, fb_user_id, first_seen_at, last_seen_at) VALUES (?1, ?2, ?3, ?3)
+        ON CONFLICT (push_token) DO UPDATE SET fb_user_id = excluded.fb_user_id, last_seen_at = excluded.last_seen_at
+        RETURNING first_seen_at as "first_seen_at!: NaiveDateTime", hinted_at as "hinted_at: NaiveDateTime""#,
+        push_token,
+        fb_user_id,
+        current_time
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let hint = sqlx::query_scalar!(
+        "SELECT reason FROM fcm_token_hint WHERE fb_user_id = ? AND push_token = ?",
+        fb_user_id,
+        push_token
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let invalid = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM fcm_schedule WHERE fb_user_id = ? AND push_token = ? AND target_type = 'token' AND disabled_reason = 'invalid_token'
+        ) as "invalid!: bool""#,
+        fb_user_id,
+        push_token
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let refreshed_at = seen.hinted_at.unwrap_or(seen.first_seen_at);
+    let reason = match hint.as_deref() {
+        Some("unregistered") => Some(RefreshReason::Unregistered),
+        Some("rotated") => Some(RefreshReason::Rotated),
+        _ if invalid => Some(RefreshReason::Invalid),
+        _ if refreshed_at < current_time - Duration::days(TOKEN_STALE_DAYS) => {
+            Some(RefreshReason::Stale)
+        }
+        _ => None,
+    };
+
+    let throttled = seen
+        .hinted_at
+        .is_some_and(|at| at > current_time - Duration::hours(*HINT_INTERVAL_HOURS));
+    let reason = reason.filter(|_| !throttled);
+
+    if reason.is_some() {
+        sqlx::query!(
+            "UPDATE fcm_token_heartbeat SET hinted_at = ? WHERE push_token = ?",
+            current_time,
+            push_token
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(TokenHeartbeatResult {
+        refresh: reason.is_some(),
+        reason,
+        first_seen_at: seen.first_seen_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "dXJhbmRvbWluc3Q:APA91bHsample_token-with_all-allowed_characters";
+
+    #[test]
+    fn validate_format_accepts_registration_tokens() {
+        assert_eq!(validate_format(TOKEN), Ok(()));
+        // tokens without an instance id are only checked for their characters
+        assert_eq!(validate_format(&"a".repeat(32)), Ok(()));
+    }
+
+    #[test]
+    fn validate_format_points_out_other_providers() {
+        let expo = validate_format("ExponentPushToken[xxxxxxxxxxxxxxxxxxxxxx]").unwrap_err();
+        assert!(expo.contains("Expo"), "{}", expo);
+        let apns = validate_format(&"0f".repeat(32)).unwrap_err();
+        assert!(apns.contains("APNs"), "{}", apns);
+    }
+
+    #[test]
+    fn validate_format_rejects_malformed_tokens() {
+        for push_token in [
+            "too-short".to_string(),
+            "a".repeat(513),
+            format!("{} ", TOKEN),
+            TOKEN.replace("APA91b", "APA92b"),
+            format!(":{}", TOKEN.split_once(':').unwrap().1),
+            format!("{}:extra", TOKEN),
+        ] {
+            assert!(validate_format(&push_token).is_err(), "{}", push_token);
+        }
+    }
+}
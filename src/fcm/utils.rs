@@ -1,15 +1,57 @@
-use chrono::{NaiveDateTime, Utc};
-use cron_parser::parse;
-use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use super::conditions;
+use super::holidays;
+use super::impersonation;
+use super::model::{FCMSchedule, ScheduleType, Tags, TargetType, UpdateSchedule};
+use super::payload;
+use super::policy::MIN_INTERVAL_SECS;
+use super::template;
+use super::tokens;
+use super::verifier;
+use crate::access_log;
+use crate::utils::READ_ONLY;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use cron_parser::{parse, parse_field};
+use poem::Request;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use tracing::warn;
+
+// clocks of the Line Islands (UTC+14) are ahead of every other timezone
+const EARLIEST_TIMEZONE: &str = "Pacific/Kiritimati";
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Claims {
     pub aud: String,
     pub user_id: String,
+    /// custom claims set through the firebase admin sdk (e.g. role, premium)
+    #[serde(flatten)]
+    pub custom: HashMap<String, Value>,
 }
 
-pub fn extract_claims(token: Option<&str>) -> Result<Claims, String> {
+impl Claims {
+    /// whether the token belongs to an anonymous firebase account
+    pub fn is_anonymous(&self) -> bool {
+        self.custom
+            .get("firebase")
+            .and_then(|firebase| firebase.get("sign_in_provider"))
+            .and_then(Value::as_str)
+            == Some("anonymous")
+    }
+
+    /// lowercase email address of the account, only once it has been verified
+    pub fn verified_email(&self) -> Option<String> {
+        let verified = self.custom.get("email_verified").and_then(Value::as_bool);
+        match (self.custom.get("email").and_then(Value::as_str), verified) {
+            (Some(email), Some(true)) => Some(email.to_lowercase()),
+            _ => None,
+        }
+    }
+}
+
+pub async fn extract_claims(token: Option<&str>) -> Result<Claims, String> {
     let token = match token {
         Some(token) => token,
         None => {
@@ -24,31 +66,649 @@ pub fn extract_claims(token: Option<&str>) -> Result<Claims, String> {
         }
     };
 
-    // extract user id from token
-    let key = DecodingKey::from_secret(&[]);
-    let mut validation = Validation::new(Algorithm::HS256);
-    validation.insecure_disable_signature_validation();
+    verifier::verify::<Claims>(token).await
+}
+
+/// Extract the claims from the request and record the activity of the user
+pub async fn authenticate(req: &Request, pool: &SqlitePool) -> Result<Claims, String> {
+    let header = req.header("firebase-auth");
+    let impersonation_token = header
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .filter(|token| impersonation::is_impersonation_token(token));
+
+    // impersonated requests are audited instead of counting as activity of the user
+    if let Some(token) = impersonation_token {
+        let claims = impersonation::authenticate(req, pool, token).await?;
+        access_log::set_user(&claims.user_id);
+        return Ok(claims);
+    }
+
+    let claims = extract_claims(header).await?;
+    access_log::set_user(&claims.user_id);
+
+    // activity is tracked by the primary instance
+    if *READ_ONLY {
+        return Ok(claims);
+    }
+
+    let current_time = Utc::now().naive_utc();
+    let anonymous = claims.is_anonymous();
+    let result = sqlx::query!(
+        "INSERT INTO fcm_user (fb_user_id, fb_project_id, anonymous, last_seen_at, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (fb_user_id) DO UPDATE SET anonymous = excluded.anonymous, last_seen_at = excluded.last_seen_at",
+        claims.user_id,
+        claims.aud,
+        anonymous,
+        current_time,
+        current_time
+    )
+    .execute(pool)
+    .await;
 
-    match decode::<Claims>(&token, &key, &validation) {
-        Ok(data) => Ok(data.claims), // Return the data in the Ok variant
-        Err(_) => Err("invalid token".to_string()),
+    if let Err(e) = result {
+        warn!(fb_user_id = %claims.user_id, error = ?e, "Failed to record user activity");
     }
+
+    Ok(claims)
 }
 
-pub fn decode_cron(cron_pattern: &str) -> Result<NaiveDateTime, String> {
-    let next = std::panic::catch_unwind(|| parse(cron_pattern, &Utc::now()));
+/// Delete everything stored for the user. Returns the number of schedules deleted
+pub async fn purge_user(conn: &mut SqliteConnection, fb_user_id: &str) -> Result<u64, sqlx::Error> {
+    // organizations without another admin would be left without anyone to manage them
+    sqlx::query!(
+        "DELETE FROM fcm_organization WHERE id IN (
+            SELECT organization_id FROM fcm_organization_member WHERE fb_user_id = ?1 AND role = 'admin'
+        ) AND NOT EXISTS (
+            SELECT 1 FROM fcm_organization_member m
+            WHERE m.organization_id = fcm_organization.id AND m.role = 'admin' AND m.fb_user_id != ?1
+        )",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
 
-    let next = match next {
-        Ok(next) => next,
+    sqlx::query!(
+        "DELETE FROM fcm_organization_member WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let deleted_schedules =
+        sqlx::query!("DELETE FROM fcm_schedule WHERE fb_user_id = ?", fb_user_id)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+    sqlx::query!(
+        "DELETE FROM fcm_execution_log WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!("DELETE FROM outbox WHERE fb_user_id = ?", fb_user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        "DELETE FROM fcm_token_hint WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!("DELETE FROM browser_usage WHERE user_id = ?", fb_user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        "DELETE FROM fcm_email_inbox WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM fcm_schedule_share WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!("DELETE FROM quota_warning WHERE user_id = ?", fb_user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        "DELETE FROM fcm_impersonation WHERE fb_user_id = ?",
+        fb_user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // devices and token heartbeats go with the user
+    sqlx::query!("DELETE FROM fcm_user WHERE fb_user_id = ?", fb_user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(deleted_schedules)
+}
+
+/// ETag of a schedule at the version
+pub fn etag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Version of a schedule an `If-Match` header refers to, `None` for `*` which matches every version
+pub fn parse_if_match(value: &str) -> Result<Option<i64>, String> {
+    let value = value.trim();
+    if value == "*" {
+        return Ok(None);
+    }
+
+    let tag = value.strip_prefix("W/").unwrap_or(value);
+    match tag
+        .strip_prefix('"')
+        .and_then(|tag| tag.strip_suffix('"'))
+        .and_then(|tag| tag.parse().ok())
+    {
+        Some(version) => Ok(Some(version)),
+        None => Err(format!("If-Match `{}` isn't an ETag of a schedule", value)),
+    }
+}
+
+/// Next time the cron pattern matches, evaluated in the IANA timezone and returned in UTC
+pub fn decode_cron(cron_pattern: &str, timezone: &str) -> Result<NaiveDateTime, String> {
+    let timezone = match timezone.parse::<Tz>() {
+        Ok(timezone) => timezone,
+        Err(_) => {
+            return Err("Invalid timezone".to_string());
+        }
+    };
+
+    next_occurrence(cron_pattern, &Utc::now().with_timezone(&timezone)).map(|next| next.naive_utc())
+}
+
+/// First time the cron pattern matches after the UTC time, evaluated in the IANA timezone
+/// and returned in UTC
+pub fn cron_after(
+    cron_pattern: &str,
+    timezone: &str,
+    after: NaiveDateTime,
+) -> Result<NaiveDateTime, String> {
+    let timezone = match timezone.parse::<Tz>() {
+        Ok(timezone) => timezone,
         Err(_) => {
+            return Err("Invalid timezone".to_string());
+        }
+    };
+
+    next_occurrence(cron_pattern, &timezone.from_utc_datetime(&after)).map(|next| next.naive_utc())
+}
+
+/// Upcoming times the cron pattern matches in the IANA timezone, as `(utc, local)` pairs
+pub fn cron_occurrences(
+    cron_pattern: &str,
+    timezone: &str,
+    count: usize,
+) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>, String> {
+    let timezone = match timezone.parse::<Tz>() {
+        Ok(timezone) => timezone,
+        Err(_) => {
+            return Err("Invalid timezone".to_string());
+        }
+    };
+
+    let mut occurrences = Vec::with_capacity(count);
+    let mut after = Utc::now().with_timezone(&timezone);
+    while occurrences.len() < count {
+        let next = next_occurrence(cron_pattern, &after)?;
+        if next <= after {
+            break;
+        }
+        occurrences.push((next.naive_utc(), next.naive_local()));
+        after = next;
+    }
+
+    Ok(occurrences)
+}
+
+// patterns are five field cron, six field cron with leading seconds or `@every <interval>`
+fn next_occurrence(cron_pattern: &str, after: &DateTime<Tz>) -> Result<DateTime<Tz>, String> {
+    if let Some(interval) = cron_pattern.strip_prefix("@every") {
+        return Ok(*after + parse_interval(interval)?);
+    }
+
+    let fields: Vec<&str> = cron_pattern.split_whitespace().collect();
+    if fields.len() != 6 {
+        return next_minute(cron_pattern, after);
+    }
+
+    let seconds = match std::panic::catch_unwind(|| parse_field(fields[0], 0, 59)) {
+        Ok(Ok(seconds)) if !seconds.is_empty() => seconds,
+        _ => {
             return Err("Invalid cron pattern".to_string());
         }
     };
+    let minutes = fields[1..].join(" ");
+
+    // a later second of the current minute, when the minute matches
+    let minute = after
+        .with_nanosecond(0)
+        .and_then(|after| after.with_second(0))
+        .ok_or("Invalid cron pattern")?;
+    if next_minute(&minutes, &(minute - Duration::seconds(1)))? == minute {
+        if let Some(second) = seconds.range(after.second() + 1..).next() {
+            return Ok(minute + Duration::seconds(*second as i64));
+        }
+    }
+
+    let first = *seconds.iter().next().unwrap_or(&0);
+    Ok(next_minute(&minutes, after)? + Duration::seconds(first as i64))
+}
+
+fn next_minute(cron_pattern: &str, after: &DateTime<Tz>) -> Result<DateTime<Tz>, String> {
+    let next = std::panic::catch_unwind(|| parse(cron_pattern, after));
+
     let next = match next {
         Ok(next) => next,
         Err(_) => {
             return Err("Invalid cron pattern".to_string());
         }
     };
-    Ok(next.naive_utc())
+    match next {
+        Ok(next) => Ok(next),
+        Err(_) => Err("Invalid cron pattern".to_string()),
+    }
+}
+
+/// Interval of an `@every` pattern, numbers followed by a unit, e.g. `15m` or `1h30m`
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let interval = interval.trim();
+    let invalid = || format!("Invalid interval `{}`, use e.g. `@every 15m`", interval);
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in interval.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number.parse().map_err(|_| invalid())?;
+        let unit = match c {
+            's' => Duration::seconds(1),
+            'm' => Duration::minutes(1),
+            'h' => Duration::hours(1),
+            'd' => Duration::days(1),
+            _ => return Err(invalid()),
+        };
+        total = value
+            .checked_mul(unit.num_seconds())
+            .and_then(|seconds| total.checked_add(&Duration::seconds(seconds)))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+
+    if !number.is_empty() || total <= Duration::zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+/// Reject recurring patterns that send more often than FCM_MIN_INTERVAL_SECS allows
+pub fn validate_interval(cron_pattern: &str, timezone: &str) -> Result<(), String> {
+    // sub-minute patterns show their shortest gap within their first few occurrences
+    let occurrences = cron_occurrences(cron_pattern, timezone, 20)?;
+    let shortest = occurrences
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0).num_seconds())
+        .min();
+
+    match shortest {
+        Some(shortest) if shortest < *MIN_INTERVAL_SECS => Err(format!(
+            "cron_pattern sends every {} seconds, executions have to be at least {} seconds apart",
+            shortest, *MIN_INTERVAL_SECS
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// UTC time of a one-shot schedule, `run_at` is a local time in the timezone
+pub fn decode_run_at(
+    run_at: Option<NaiveDateTime>,
+    timezone: &str,
+) -> Result<NaiveDateTime, String> {
+    let run_at = match run_at {
+        Some(run_at) => run_at,
+        None => {
+            return Err("run_at is required for one-shot schedules".to_string());
+        }
+    };
+
+    let timezone = match timezone.parse::<Tz>() {
+        Ok(timezone) => timezone,
+        Err(_) => {
+            return Err("Invalid timezone".to_string());
+        }
+    };
+
+    // the earlier of the two times is used when clocks go back
+    match timezone.from_local_datetime(&run_at).earliest() {
+        Some(run_at) => Ok(run_at.naive_utc()),
+        None => Err("run_at falls in a daylight saving gap of the timezone".to_string()),
+    }
+}
+
+/// First execution of a new or updated schedule
+pub fn next_execution(schedule: &UpdateSchedule) -> Result<NaiveDateTime, String> {
+    // devices are only looked up when the schedule is due, so a local time schedule
+    // is first due when its time comes in the timezone whose clocks are furthest ahead
+    let timezone = if schedule.local_time {
+        if schedule.target_type != TargetType::Token {
+            return Err(
+                "local_time needs a device token, topics and conditions have no timezone"
+                    .to_string(),
+            );
+        }
+        // devices without a timezone of their own still use the schedule's
+        if schedule.timezone.parse::<Tz>().is_err() {
+            return Err("Invalid timezone".to_string());
+        }
+        EARLIEST_TIMEZONE
+    } else {
+        schedule.timezone.as_str()
+    };
+
+    let next = match schedule.schedule_type {
+        ScheduleType::Recurring => {
+            validate_interval(&schedule.cron_pattern, timezone)?;
+            decode_cron(&schedule.cron_pattern, timezone)?
+        }
+        ScheduleType::Once => {
+            let next = decode_run_at(schedule.run_at, timezone)?;
+            if next <= Utc::now().naive_utc() {
+                return Err("run_at must be in the future".to_string());
+            }
+            next
+        }
+    };
+
+    // a schedule that expires before it sends would never send at all
+    if let Some(expires_at) = schedule.expires_at {
+        if expires_at <= next {
+            return Err("expires_at must be after the next execution".to_string());
+        }
+    }
+
+    Ok(next)
+}
+
+/// Next execution of a schedule that sends again after it was held back, occurrences
+/// missed meanwhile are skipped and a one-shot schedule whose time has passed is sent right away
+pub fn resume_execution(schedule: &FCMSchedule) -> Result<NaiveDateTime, String> {
+    let timezone = match schedule.local_time {
+        true => EARLIEST_TIMEZONE,
+        false => schedule.timezone.as_str(),
+    };
+
+    match schedule.schedule_type {
+        ScheduleType::Recurring => decode_cron(&schedule.cron_pattern, timezone),
+        ScheduleType::Once => decode_run_at(schedule.run_at, timezone),
+    }
+}
+
+/// Validate a schedule that didn't go through the request validators and
+/// return its next execution time
+pub fn validate_schedule(schedule: &UpdateSchedule) -> Result<NaiveDateTime, String> {
+    if !(3..=64).contains(&schedule.name.chars().count()) {
+        return Err("name must be between 3 and 64 characters".to_string());
+    }
+
+    validate_target(schedule.target_type, &schedule.push_token)?;
+
+    if !(3..=64).contains(&schedule.cron_pattern.chars().count()) {
+        return Err("cron_pattern must be between 3 and 64 characters".to_string());
+    }
+
+    if !schedule.payload.is_object() {
+        return Err("Invalid payload".to_string());
+    }
+
+    validate_tags(&schedule.tags)?;
+    conditions::validate(&schedule.conditions)?;
+    template::validate(&schedule.payload)?;
+    payload::validate_message(&schedule.message)?;
+    payload::validate_size(schedule)?;
+    template::validate_message(&schedule.message)?;
+    holidays::validate(schedule.holiday_calendar.as_deref())?;
+
+    next_execution(schedule)
+}
+
+/// Check the push token is a valid target of its type, FCM only reports
+/// malformed topics and conditions once a message is sent to them
+pub fn validate_target(target_type: TargetType, push_token: &str) -> Result<(), String> {
+    match target_type {
+        TargetType::Token => tokens::validate_format(push_token),
+        TargetType::Topic => {
+            if !valid_topic(topic_name(push_token)) {
+                return Err(
+                    "topic must match [a-zA-Z0-9-_.~%]+, optionally prefixed with /topics/"
+                        .to_string(),
+                );
+            }
+            Ok(())
+        }
+        TargetType::Condition => validate_condition(push_token),
+    }
+}
+
+/// Topic name without the `/topics/` prefix FCM accepts in legacy requests
+pub fn topic_name(topic: &str) -> &str {
+    topic.strip_prefix("/topics/").unwrap_or(topic)
+}
+
+fn valid_topic(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 900
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.~%".contains(c))
+}
+
+/// Check a condition combines `'topic' in topics` tests with `&&`, `||`, `!` and
+/// parentheses, over at most 5 topics as FCM allows
+pub fn validate_condition(condition: &str) -> Result<(), String> {
+    let mut parser = ConditionParser {
+        rest: condition,
+        topics: 0,
+    };
+    parser.expression()?;
+    if !parser.rest.trim_start().is_empty() {
+        return Err(format!(
+            "Invalid condition: unexpected `{}`",
+            parser.rest.trim()
+        ));
+    }
+    if parser.topics > 5 {
+        return Err("Invalid condition: at most 5 topics can be combined".to_string());
+    }
+    Ok(())
+}
+
+struct ConditionParser<'a> {
+    rest: &'a str,
+    topics: usize,
+}
+
+impl<'a> ConditionParser<'a> {
+    /// Consume the token when the remaining input starts with it
+    fn eat(&mut self, token: &str) -> bool {
+        let rest = self.rest.trim_start();
+        match rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<(), String> {
+        self.term()?;
+        while self.eat("&&") || self.eat("||") {
+            self.term()?;
+        }
+        Ok(())
+    }
+
+    fn term(&mut self) -> Result<(), String> {
+        if self.eat("!") {
+            return self.term();
+        }
+        if self.eat("(") {
+            self.expression()?;
+            if !self.eat(")") {
+                return Err("Invalid condition: missing `)`".to_string());
+            }
+            return Ok(());
+        }
+
+        let rest = self.rest.trim_start();
+        let quote = match rest.chars().next() {
+            Some(quote @ ('\'' | '"')) => quote,
+            _ => {
+                return Err("Invalid condition: expected `'topic' in topics`".to_string());
+            }
+        };
+        let (name, rest) = match rest[1..].split_once(quote) {
+            Some(parts) => parts,
+            None => {
+                return Err("Invalid condition: unterminated topic name".to_string());
+            }
+        };
+        if !valid_topic(name) {
+            return Err(format!("Invalid condition: invalid topic `{}`", name));
+        }
+        self.rest = rest;
+        self.topics += 1;
+
+        // `in` and `topics` are words, `'a' intopics` isn't valid
+        let rest = self.rest.trim_start();
+        let rest = rest
+            .strip_prefix("in")
+            .filter(|rest| rest.starts_with(char::is_whitespace))
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix("topics"))
+            .filter(|rest| !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_'));
+        match rest {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(())
+            }
+            None => Err(format!(
+                "Invalid condition: expected `in topics` after '{}'",
+                name
+            )),
+        }
+    }
+}
+
+pub fn validate_tags(tags: &Tags) -> Result<(), String> {
+    if tags.0.len() > 10 {
+        return Err("a schedule can have at most 10 tags".to_string());
+    }
+
+    for tag in &tags.0 {
+        let valid = (1..=32).contains(&tag.len())
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!(
+                "tag `{}` must be 1 to 32 lowercase letters, digits, dashes or underscores",
+                tag
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Tz> {
+        let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+        chrono_tz::UTC.from_utc_datetime(&time)
+    }
+
+    #[test]
+    fn parse_interval_units() {
+        assert_eq!(parse_interval(" 30s"), Ok(Duration::seconds(30)));
+        assert_eq!(parse_interval("15m"), Ok(Duration::minutes(15)));
+        assert_eq!(parse_interval("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_interval("2d"), Ok(Duration::days(2)));
+    }
+
+    #[test]
+    fn parse_interval_rejects_invalid() {
+        for interval in ["", "15", "m", "0s", "5x", "1h30", "-5m"] {
+            assert!(parse_interval(interval).is_err(), "{}", interval);
+        }
+    }
+
+    #[test]
+    fn next_occurrence_every() {
+        let after = at("2026-01-01 12:00:05");
+        assert_eq!(
+            next_occurrence("@every 90s", &after),
+            Ok(at("2026-01-01 12:01:35"))
+        );
+        assert!(next_occurrence("@every soon", &after).is_err());
+    }
+
+    #[test]
+    fn next_occurrence_five_fields() {
+        let after = at("2026-01-01 12:00:05");
+        assert_eq!(
+            next_occurrence("*/15 * * * *", &after),
+            Ok(at("2026-01-01 12:15:00"))
+        );
+    }
+
+    #[test]
+    fn next_occurrence_seconds() {
+        // a later second of a matching minute
+        assert_eq!(
+            next_occurrence("*/20 * * * * *", &at("2026-01-01 12:00:05")),
+            Ok(at("2026-01-01 12:00:20"))
+        );
+        // the first second of the next matching minute
+        assert_eq!(
+            next_occurrence("10 */5 * * * *", &at("2026-01-01 12:00:30")),
+            Ok(at("2026-01-01 12:05:10"))
+        );
+        // seconds left in a minute the pattern doesn't match
+        assert_eq!(
+            next_occurrence("30 0 13 * * *", &at("2026-01-01 12:00:05")),
+            Ok(at("2026-01-01 13:00:30"))
+        );
+        assert!(next_occurrence("61 * * * * *", &at("2026-01-01 12:00:05")).is_err());
+    }
+
+    #[test]
+    fn validate_interval_minimum() {
+        assert!(validate_interval("* * * * *", "UTC").is_ok());
+        assert!(validate_interval("@every 1m", "UTC").is_ok());
+        assert!(validate_interval("0 * * * * *", "UTC").is_ok());
+        assert!(validate_interval("@every 30s", "UTC").is_err());
+        assert!(validate_interval("*/10 * * * * *", "UTC").is_err());
+        // the shortest gap counts, not the average
+        assert!(validate_interval("0,15 * * * * *", "UTC").is_err());
+        assert!(validate_interval("* * * * *", "Mars/Olympus").is_err());
+    }
 }
@@ -0,0 +1,279 @@
+use crate::http;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashSet,
+    env,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+// public keys firebase signs ID tokens with, rotated every few hours
+const JWKS_URL: &str =
+    "https://www.googleapis.com/service_accounts/v1/jwk/securetoken@system.gserviceaccount.com";
+// used when google does not say how long the keys can be cached for
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(3600);
+// a token signed with an unknown key refetches the keys at most this often
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    // accept tokens without checking their signature, only for local development with hand made tokens
+    static ref SKIP_VERIFICATION: bool = skip_verification();
+    static ref PROJECTS: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+    static ref KEYS: RwLock<Option<CachedKeys>> = RwLock::new(None);
+    // held while the keys are fetched
+    static ref REFRESHING: Mutex<()> = Mutex::new(());
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+    expires_at: Instant,
+}
+
+/// Whether FIREBASE_SKIP_TOKEN_VERIFICATION applies, release builds always check signatures
+fn skip_verification() -> bool {
+    let skip = env::var("FIREBASE_SKIP_TOKEN_VERIFICATION")
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    if !skip {
+        return false;
+    }
+
+    if cfg!(debug_assertions) {
+        error!("FIREBASE_SKIP_TOKEN_VERIFICATION is set, firebase tokens are accepted without checking their signature");
+        true
+    } else {
+        error!("Ignoring FIREBASE_SKIP_TOKEN_VERIFICATION, release builds always check firebase token signatures");
+        false
+    }
+}
+
+/// Set the firebase projects whose tokens are accepted
+pub fn set_projects(projects: &[String]) {
+    *PROJECTS.write().unwrap() = projects.iter().cloned().collect();
+}
+
+/// Decode a firebase ID token after checking its signature, expiry, issuer and audience
+pub async fn verify<T: DeserializeOwned>(token: &str) -> Result<T, String> {
+    let header = decode_header(token).map_err(|_| "invalid token".to_string())?;
+
+    if *SKIP_VERIFICATION {
+        let mut validation = Validation::new(header.alg);
+        validation.insecure_disable_signature_validation();
+        return decode::<T>(token, &DecodingKey::from_secret(&[]), &validation)
+            .map(|data| data.claims)
+            .map_err(|_| "invalid token".to_string());
+    }
+
+    if header.alg != Algorithm::RS256 {
+        return Err("invalid token".to_string());
+    }
+
+    let kid = match header.kid {
+        Some(kid) => kid,
+        None => {
+            return Err("invalid token".to_string());
+        }
+    };
+
+    let jwk = find_key(&kid).await?;
+    let key = DecodingKey::from_jwk(&jwk).map_err(|_| "invalid token".to_string())?;
+
+    // a token of one project is never accepted by another, so the issuer has to name the audience
+    let projects: Vec<String> = PROJECTS.read().unwrap().iter().cloned().collect();
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+    validation.set_audience(&projects);
+    validation.set_issuer(
+        &projects
+            .iter()
+            .map(|project| issuer(project))
+            .collect::<Vec<_>>(),
+    );
+
+    let claims = decode::<serde_json::Value>(token, &key, &validation).map_err(|e| {
+        info!(error = ?e, "Rejected firebase token");
+        "invalid token".to_string()
+    })?;
+
+    let aud = claims.claims.get("aud").and_then(|aud| aud.as_str());
+    let iss = claims.claims.get("iss").and_then(|iss| iss.as_str());
+    if aud.is_none_or(|aud| iss != Some(issuer(aud).as_str())) {
+        return Err("invalid token".to_string());
+    }
+
+    serde_json::from_value(claims.claims).map_err(|_| "invalid token".to_string())
+}
+
+fn issuer(project_id: &str) -> String {
+    format!("https://securetoken.google.com/{}", project_id)
+}
+
+/// Public key the token was signed with, the keys are fetched again once they
+/// expire or when a token names a key that is not known yet
+async fn find_key(kid: &str) -> Result<Jwk, String> {
+    let now = Instant::now();
+    if needs_refresh(kid, now) {
+        // one request fetches the keys while the others wait for them, requests
+        // with a known key don't wait on google
+        let _refreshing = REFRESHING.lock().await;
+        if needs_refresh(kid, now) {
+            match fetch_keys().await {
+                Ok((keys, max_age)) => {
+                    info!(
+                        keys = keys.keys.len(),
+                        "Fetched firebase token signing keys"
+                    );
+                    let fetched_at = Instant::now();
+                    *KEYS.write().unwrap() = Some(CachedKeys {
+                        keys,
+                        fetched_at,
+                        expires_at: fetched_at + max_age,
+                    });
+                }
+                // the previous keys stay usable until google can be reached again
+                Err(e) => error!(error = %e, "Failed to fetch firebase token signing keys"),
+            }
+        }
+    }
+
+    let jwk = KEYS
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|cached| cached.keys.find(kid).cloned());
+    match jwk {
+        Some(jwk) => Ok(jwk),
+        None => {
+            warn!(kid = %kid, "Token signed with an unknown key");
+            Err("invalid token".to_string())
+        }
+    }
+}
+
+/// Whether the keys have to be fetched to check a token signed with `kid`, keys
+/// fetched after `now` by another request are used as they are
+fn needs_refresh(kid: &str, now: Instant) -> bool {
+    let cached = KEYS.read().unwrap();
+    let cached = match cached.as_ref() {
+        Some(cached) => cached,
+        None => return true,
+    };
+    if cached.fetched_at > now {
+        return false;
+    }
+
+    let fresh = cached.expires_at > now;
+    let known = cached.keys.find(kid).is_some();
+    let recently_fetched = now.duration_since(cached.fetched_at) < MIN_REFRESH_INTERVAL;
+    !fresh || (!known && !recently_fetched)
+}
+
+async fn fetch_keys() -> Result<(JwkSet, Duration), String> {
+    let response = http::send(http::CLIENT.get(JWKS_URL))
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?;
+
+    let max_age = response
+        .headers()
+        .get("cache-control")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split(',')
+                .find_map(|directive| directive.trim().strip_prefix("max-age="))
+        })
+        .and_then(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MAX_AGE);
+
+    let keys = response.json::<JwkSet>().await.map_err(|e| e.to_string())?;
+
+    Ok((keys, max_age))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use chrono::Utc;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use openssl::rsa::Rsa;
+    use serde_json::{json, Value};
+
+    const KID: &str = "test-key";
+
+    lazy_static! {
+        // signing key of the tokens, its public half is the only cached google key
+        static ref SIGNING_KEY: EncodingKey = {
+            let rsa = Rsa::generate(2048).unwrap();
+            let keys = json!({
+                "keys": [{
+                    "kty": "RSA",
+                    "alg": "RS256",
+                    "use": "sig",
+                    "kid": KID,
+                    "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                    "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+                }]
+            });
+            let fetched_at = Instant::now();
+            *KEYS.write().unwrap() = Some(CachedKeys {
+                keys: serde_json::from_value(keys).unwrap(),
+                fetched_at,
+                expires_at: fetched_at + Duration::from_secs(3600),
+            });
+            set_projects(&["project-a".to_string(), "project-b".to_string()]);
+            EncodingKey::from_rsa_der(&rsa.private_key_to_der().unwrap())
+        };
+    }
+
+    fn token(kid: &str, aud: &str, iss: &str) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let now = Utc::now().timestamp();
+        let claims = json!({
+            "sub": "user",
+            "aud": aud,
+            "iss": iss,
+            "iat": now,
+            "exp": now + 300,
+        });
+        encode(&header, &claims, &SIGNING_KEY).unwrap()
+    }
+
+    #[tokio::test]
+    async fn accepts_tokens_of_a_configured_project() {
+        let token = token(KID, "project-a", &issuer("project-a"));
+        let claims: Value = verify(&token).await.unwrap();
+        assert_eq!(claims["sub"], "user");
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_of_other_projects() {
+        let token = token(KID, "project-c", &issuer("project-c"));
+        assert!(verify::<Value>(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_issued_for_another_project() {
+        let token = token(KID, "project-a", &issuer("project-b"));
+        assert!(verify::<Value>(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_signed_with_an_unknown_key() {
+        let token = token("other-key", "project-a", &issuer("project-a"));
+        assert!(verify::<Value>(&token).await.is_err());
+    }
+}
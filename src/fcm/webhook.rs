@@ -0,0 +1,82 @@
+use super::inbox;
+use super::model::{InboundEmail, InboundEmailResult, PurgeResult, UserDeletedEvent};
+use super::utils;
+use crate::utils::{verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
+use poem::{web::Data, Request};
+use poem_openapi::{payload::Json, OpenApi};
+use sqlx::SqlitePool;
+use tracing::info;
+
+#[derive(Default)]
+pub struct FirebaseWebhooks;
+
+#[OpenApi(
+    prefix_path = "/fcm/webhooks/",
+    request_header(name = "API-Key", ty = "String", description = "Private API Key"),
+    tag = "ApiTags::FirebaseMessaging"
+)]
+impl FirebaseWebhooks {
+    // Purge all data of a user deleted from firebase auth
+    #[oai(
+        path = "/user-deleted",
+        method = "post",
+        operation_id = "fcm::user_deleted"
+    )]
+    async fn user_deleted(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        event: Json<UserDeletedEvent>,
+    ) -> Result<JsonSuccess<PurgeResult>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let mut tx = match pool.0.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let deleted_schedules = match utils::purge_user(&mut tx, &event.uid).await {
+            Ok(deleted_schedules) => deleted_schedules,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        if let Err(e) = tx.commit().await {
+            return Err(ResponseObject::internal_server_error(e));
+        }
+
+        info!(uid = %event.uid, deleted_schedules, "Purged data of deleted firebase user");
+
+        Ok(ResponseObject::ok(PurgeResult {
+            uid: event.0.uid,
+            deleted_schedules,
+        }))
+    }
+
+    // Email received for one of the inboxes (e.g. posted by the inbound parse webhook of the email provider)
+    #[oai(
+        path = "/inbound-email",
+        method = "post",
+        operation_id = "fcm::inbound_email"
+    )]
+    async fn inbound_email(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+        email: Json<InboundEmail>,
+    ) -> Result<JsonSuccess<InboundEmailResult>, JsonError<String>> {
+        if let Err(e) = verify_apikey(req).await {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        match inbox::forward(pool.0, &email).await {
+            Ok(result) => Ok(ResponseObject::ok(result)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+}
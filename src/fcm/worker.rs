@@ -1,42 +1,47 @@
-use super::model::FCMSchedule;
-use chrono::Utc;
-use cron_parser::parse;
+use super::accounts::ServiceAccounts;
+use super::batches;
+use super::callbacks;
+use super::conditions;
+use super::devices;
+use super::errors::ErrorCode;
+use super::followups;
+use super::holidays;
+use super::model::{
+    CatchUp, ExecutionEvent, ExecutionFailure, FCMSchedule, ProjectSettings, ScheduleType,
+};
+use super::policy::{ANONYMOUS_RETENTION_DAYS, CATCH_UP_GRACE_SECS, MAX_CONSECUTIVE_FAILURES};
+use super::sender::{
+    build_device_message, build_message, build_payload_message, send_message, SendError, FCM,
+};
+use super::template;
+use super::tokens;
+use super::utils::{self, cron_after, decode_cron};
+use crate::admin::tasks::{self, SCHEDULER_TASK};
+use crate::events;
+use crate::metrics;
+use crate::outbox::{self, Channel, OutboxMessage, Priority};
+use crate::utils::{BROADCAST_BATCH_SIZE, DRY_RUN, SEND_TIMEOUT_SECS};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use gcp_auth::{AuthenticationManager, CustomServiceAccount, Error};
-use reqwest::header::{HeaderMap, AUTHORIZATION};
-use serde::{Deserialize, Serialize};
-use serde_json::{from_str, Value};
-use sqlx::SqlitePool;
-use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
-use tokio::time::sleep;
+use openssl::{error::ErrorStack, rand::rand_bytes};
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::{
+    task::JoinSet,
+    time::{self, sleep},
+};
 use tracing::{debug, error, info, warn};
 
-// https://firebase.google.com/docs/cloud-messaging/concept-options#notification-messages-with-optional-data-payload
-#[derive(Debug, Serialize, Deserialize)]
-struct FCM {
-    message: FCMBody,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Notification {
-    title: Option<String>,
-    body: Option<String>,
-}
-
-impl Notification {
-    fn is_empty(&self) -> bool {
-        self.title.is_none() && self.body.is_none()
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct FCMBody {
-    #[serde(skip_serializing_if = "Notification::is_empty")]
-    notification: Notification,
-    data: HashMap<String, String>,
-    token: String,
-}
-
-const SCOPES: &[&str; 1] = &["https://www.googleapis.com/auth/firebase.messaging"];
+// missed executions sent at once by a schedule catching up on all of them, so an outage
+// doesn't flood devices with the sends of a schedule running every minute
+const MAX_CATCH_UP_EXECUTIONS: usize = 100;
 
 pub async fn read_in_serivce_accounts() -> Result<HashMap<String, AuthenticationManager>, Error> {
     info!("Reading in service accounts");
@@ -68,144 +73,1245 @@ pub async fn read_in_serivce_accounts() -> Result<HashMap<String, Authentication
     Ok(service_accounts)
 }
 
-pub async fn run_every_minute(
-    auth_managers: HashMap<String, AuthenticationManager>,
-    pool: &SqlitePool,
-) {
+pub async fn run_every_minute(pool: &SqlitePool) {
+    tasks::register(SCHEDULER_TASK, "every minute", None);
     loop {
-        let current_time = Utc::now().naive_local();
+        // due schedules are sent on the first run after sends are resumed
+        if outbox::is_paused() {
+            info!("Sends are paused, skipping schedules");
+            sleep(Duration::from_secs(60)).await;
+            continue;
+        }
 
+        let started = Instant::now();
+        let current_time = Utc::now().naive_utc();
+
+        // compared with the time of the wake up, `datetime('now')` drops the fraction of the
+        // second and would leave out schedules due at the second the loop woke up for
         let messages = sqlx::query_as!(
             FCMSchedule,
-            "SELECT * FROM fcm_schedule WHERE next_execution < datetime('now')"
+            "SELECT * FROM fcm_schedule WHERE disabled_reason IS NULL AND next_execution <= ?",
+            current_time
         )
         .fetch_all(pool)
         .await
         .unwrap_or_else(|_| vec![]);
 
         info!(message_count = messages.len(), "Found messages to process");
+        let due = messages.len();
+
+        let projects: HashMap<String, ProjectSettings> =
+            sqlx::query_as!(ProjectSettings, "SELECT * FROM fcm_project")
+                .fetch_all(pool)
+                .await
+                .unwrap_or_else(|_| vec![])
+                .into_iter()
+                .map(|settings| (settings.fb_project_id.to_owned(), settings))
+                .collect();
 
         for message in messages {
             debug!(message = ?message, "Processing message");
 
             let project_id = message.fb_project_id.to_owned();
+            let project = projects.get(&project_id);
 
-            let auth_manager = match auth_managers.get(&project_id) {
-                Some(auth_manager) => auth_manager,
-                None => {
-                    warn!(project_id = ?project_id, message_id=?message.id, "No auth manager found for project id");
-                    continue;
-                }
+            // schedules of a project paused after a burst stay due until it's resumed
+            if project.and_then(|project| project.paused_at).is_some() {
+                debug!(message_id=?message.id, project_id = ?project_id, "Project is paused, skipping");
+                continue;
+            }
+
+            // limits are checked before sending too, they may have been lowered since the last send
+            let limit_reached = match (message.expires_at, message.max_executions) {
+                (Some(expires_at), _) if expires_at <= current_time => Some("expired"),
+                (_, Some(max)) if message.execution_count >= max => Some("max_executions"),
+                _ => None,
             };
+            if let Some(reason) = limit_reached {
+                info!(message_id=?message.id, reason, "Schedule reached its limit, disabling it");
+                let result = sqlx::query!(
+                    "UPDATE fcm_schedule SET disabled_reason = ?, version = version + 1, updated_at = ? WHERE id = ?",
+                    reason,
+                    current_time,
+                    message.id
+                )
+                .execute(pool)
+                .await;
+                if let Err(e) = result {
+                    error!(message_id=?message.id, error=?e, "Error disabling schedule");
+                }
+                continue;
+            }
 
-            let token = match auth_manager.get_token(SCOPES).await {
-                Ok(token) => token,
+            let mut push_tokens = match schedule_tokens(pool, &message).await {
+                Ok(push_tokens) => push_tokens,
                 Err(e) => {
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error getting token");
+                    error!(message_id=?message.id, error=?e, "Error fetching additional push tokens");
                     continue;
                 }
             };
 
-            let mut payload: HashMap<String, String> = HashMap::new();
-            match message.payload {
-                Value::Object(map) => {
-                    for (key, value) in map {
-                        payload.insert(key, value.to_string());
+            // Update the next execution time, one-shot schedules are kept as completed
+            let (next, disabled_reason) = if message.local_time {
+                match devices::plan(pool, &message, push_tokens, current_time).await {
+                    Ok(plan) => {
+                        push_tokens = plan.due;
+                        match plan.next {
+                            Some(next) => (next, None),
+                            None => (message.next_execution, Some("completed")),
+                        }
+                    }
+                    Err(e) => {
+                        error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error planning local time sends");
+                        continue;
+                    }
+                }
+            } else {
+                match message.schedule_type {
+                    ScheduleType::Once => (message.next_execution, Some("completed")),
+                    ScheduleType::Recurring => {
+                        match decode_cron(&message.cron_pattern, &message.timezone) {
+                            Ok(next) => (next, None),
+                            Err(e) => {
+                                // left due, the schedule would be picked up again on every run
+                                error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error parsing cron pattern, disabling schedule");
+                                let result = sqlx::query!(
+                                    "UPDATE fcm_schedule SET disabled_reason = 'invalid_cron', version = version + 1, updated_at = ? WHERE id = ? AND disabled_reason IS NULL",
+                                    current_time,
+                                    message.id
+                                )
+                                .execute(pool)
+                                .await;
+                                if let Err(e) = result {
+                                    error!(message_id=?message.id, error=?e, "Error disabling schedule");
+                                }
+                                continue;
+                            }
+                        }
                     }
                 }
-                Value::String(s) => {
-                    payload = from_str::<HashMap<String, String>>(&s).unwrap_or({
-                        warn!(project_id = ?project_id, message_id=?message.id, payload=&s, "Error parsing payload, defaulting to empty hashmap");
-                        HashMap::new()
-                    });
+            };
+
+            // no device of a local time schedule may be due yet, e.g. right after it's created
+            if push_tokens.is_empty() {
+                debug!(message_id=?message.id, next=?next, "No device is due yet");
+                let result = sqlx::query!(
+                    "UPDATE fcm_schedule SET next_execution = ?, disabled_reason = CASE WHEN disabled_reason IN ('blocked', 'paused') THEN disabled_reason ELSE ? END, updated_at = ? WHERE id = ?",
+                    next,
+                    disabled_reason,
+                    current_time,
+                    message.id
+                )
+                .execute(pool)
+                .await;
+                if let Err(e) = result {
+                    error!(message_id=?message.id, error=?e, "Error updating next execution time");
                 }
-                _ => {}
+                continue;
             }
 
-            let notification = Notification {
-                title: payload.remove("title"),
-                body: payload.remove("body"),
+            // executions missed while the server was down are sent as the schedule's catch up policy says
+            let due = due_executions(&message, current_time);
+            let execution_keys: Result<Vec<String>, _> =
+                due.iter().map(|_| execution_key()).collect();
+            let execution_keys = match execution_keys {
+                Ok(execution_keys) => execution_keys,
+                Err(e) => {
+                    error!(message_id=?message.id, error=?e, "Error generating execution key");
+                    continue;
+                }
             };
 
-            let firebase_message = FCM {
-                message: FCMBody {
-                    notification,
-                    data: payload,
-                    token: message.push_token.to_owned(),
-                },
+            // placeholders are rendered once per execution, every device gets the same text
+            // and action links act on the execution they were sent with
+            let next_run = match message.schedule_type {
+                ScheduleType::Once => None,
+                ScheduleType::Recurring => Some(next),
+            };
+            let executions: Result<Vec<_>, _> = execution_keys
+                .into_iter()
+                .map(|execution_key| {
+                    let mut rendered = message.clone();
+                    template::render_schedule(&mut rendered, next_run, &execution_key);
+                    build_payloads(&rendered, project, push_tokens.clone())
+                        .map(|payloads| (execution_key, payloads))
+                })
+                .collect();
+            let executions = match executions {
+                Ok(executions) => executions,
+                Err(e) => {
+                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error serializing message");
+                    continue;
+                }
             };
 
-            let header = match format!("Bearer {}", token.as_str()).parse() {
-                Ok(header) => header,
+            // a holiday or an unmet condition skips this execution, the schedule still moves on to the next one
+            let skipped = if due.is_empty() {
+                Some("missed while the server was down".to_string())
+            } else {
+                match holiday(&message).await {
+                    Some(name) => Some(format!("holiday: {}", name)),
+                    None => conditions::evaluate(&message.conditions)
+                        .await
+                        .err()
+                        .map(|reason| format!("condition not met: {}", reason)),
+                }
+            };
+
+            // Enqueue the message and advance the schedule atomically so a
+            // restart can neither lose nor duplicate an execution
+            let mut tx = match pool.begin().await {
+                Ok(tx) => tx,
                 Err(e) => {
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error parsing header");
+                    error!(message_id=?message.id, error=?e, "Error starting transaction");
                     continue;
                 }
             };
 
-            // Create the authorization header with the token
-            let mut headers = HeaderMap::new();
-            headers.insert(AUTHORIZATION, header);
+            let mut result = Ok(0);
+            match &skipped {
+                Some(error) => {
+                    info!(message_id=?message.id, reason = %error, "Skipping execution");
+                    result = sqlx::query!(
+                        "INSERT INTO fcm_execution_log (schedule_id, fb_user_id, status, error, dry_run, attempts, executed_at)
+                        VALUES (?, ?, 'skipped', ?, ?, 0, ?)",
+                        message.id,
+                        message.fb_user_id,
+                        error,
+                        *DRY_RUN,
+                        current_time
+                    )
+                    .execute(&mut *tx)
+                    .await
+                    .map(|result| result.last_insert_rowid());
+                }
+                None => {
+                    for (due_at, (execution_key, payloads)) in due.iter().zip(&executions) {
+                        // every device of the execution gets the same delay
+                        let scheduled_at = *due_at + jitter(message.jitter_seconds);
+                        result = enqueue_execution(
+                            &mut tx,
+                            &message,
+                            payloads,
+                            execution_key,
+                            Some(scheduled_at),
+                        )
+                        .await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            if let Err(e) = result {
+                error!(message_id=?message.id, error=?e, "Error enqueueing message");
+                continue;
+            }
+
+            // the send that uses up a limit disables the schedule right away
+            let sent = if skipped.is_none() {
+                due.len() as i64
+            } else {
+                0
+            };
+            let disabled_reason =
+                disabled_reason.or(match (message.expires_at, message.max_executions) {
+                    (_, Some(max)) if message.execution_count + sent >= max => {
+                        Some("max_executions")
+                    }
+                    (Some(expires_at), _) if next >= expires_at => Some("expired"),
+                    _ => None,
+                });
+
+            // Update database, sends don't bump the version so an edit started before
+            // the send still applies
+            let result = sqlx::query!(
+                r#"UPDATE fcm_schedule SET next_execution = ?, last_execution = ?, execution_count = execution_count + ?, disabled_reason = CASE WHEN disabled_reason IN ('blocked', 'paused') THEN disabled_reason ELSE ? END, snoozed_until = NULL, updated_at = ? WHERE id = ?"#,
+                next,
+                current_time,
+                sent,
+                disabled_reason,
+                current_time,
+                message.id,
+            ).execute(&mut *tx).await;
+
+            if let Err(e) = result {
+                error!(message_id=?message.id, error=?e, "Error updating next execution time");
+                continue;
+            }
+
+            match tx.commit().await {
+                Ok(_) => debug!(message_id=?message.id, next=?next, "Enqueued message"),
+                Err(e) => error!(message_id=?message.id, error=?e, "Error enqueueing message"),
+            }
+        }
+
+        match outbox::lag(pool, Channel::Fcm).await {
+            Ok(lag) => metrics::record_lag(Channel::Fcm, lag),
+            Err(e) => error!(error = ?e, "Error measuring delivery lag"),
+        }
+
+        tasks::record(
+            SCHEDULER_TASK,
+            started.elapsed(),
+            Ok(format!("{} schedules due", due)),
+        );
+        // wake up for the next execution when schedules with seconds or short intervals
+        // are due within the minute, at least a second apart, schedules that became due
+        // while the loop ran are picked up after the minimum wait. Schedules that were
+        // due already and left as they were, e.g. of a paused project, don't count or
+        // the loop would wake up every second until they're sent
+        let now = Utc::now().naive_utc();
+        let upcoming = sqlx::query_scalar!(
+            r#"SELECT MIN(next_execution) as "next: NaiveDateTime" FROM fcm_schedule
+            WHERE disabled_reason IS NULL AND next_execution > ?
+            AND fb_project_id NOT IN (SELECT fb_project_id FROM fcm_project WHERE paused_at IS NOT NULL)"#,
+            current_time
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap_or_else(|e| {
+            error!(error = ?e, "Error looking up the next execution");
+            None
+        });
+        let wait = upcoming
+            .map(|next| (next - now).num_milliseconds())
+            .unwrap_or(60_000)
+            .clamp(1_000, 60_000);
+        let next_run_at = now + chrono::Duration::milliseconds(wait);
+        tasks::register(SCHEDULER_TASK, "every minute", Some(next_run_at));
 
-            let endpoint = format!(
-                "https://fcm.googleapis.com/v1/projects/{}/messages:send",
-                project_id
+        sleep(Duration::from_millis(wait as u64)).await;
+    }
+}
+
+/// Push tokens an execution of the schedule is sent to, its own first and then
+/// every additional device
+pub async fn schedule_tokens(
+    pool: &SqlitePool,
+    schedule: &FCMSchedule,
+) -> Result<Vec<String>, sqlx::Error> {
+    // every additional device of the schedule gets a message of its own
+    let tokens = sqlx::query_scalar!(
+        "SELECT push_token FROM fcm_schedule_tokens WHERE schedule_id = ? ORDER BY id",
+        schedule.id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(std::iter::once(schedule.push_token.to_owned())
+        .chain(tokens)
+        .collect())
+}
+
+/// Payload of the schedule for each of the push tokens
+pub fn build_payloads(
+    schedule: &FCMSchedule,
+    project: Option<&ProjectSettings>,
+    push_tokens: Vec<String>,
+) -> Result<Vec<(String, Value)>, serde_json::Error> {
+    push_tokens
+        .into_iter()
+        .map(|token| {
+            let firebase_message = if token == schedule.push_token {
+                build_message(schedule, project)
+            } else {
+                build_device_message(&token, schedule, project)
+            };
+            serde_json::to_value(&firebase_message).map(|payload| (token, payload))
+        })
+        .collect()
+}
+
+/// Random key of an execution of a schedule, shared by the messages to its devices
+pub fn execution_key() -> Result<String, ErrorStack> {
+    let mut key = [0u8; 16];
+    rand_bytes(&mut key)?;
+    Ok(key.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Queue an execution of the schedule for every device of the payloads, returns the id
+/// of the last queued message
+pub async fn enqueue_execution(
+    conn: &mut SqliteConnection,
+    schedule: &FCMSchedule,
+    payloads: &[(String, Value)],
+    execution_key: &str,
+    scheduled_at: Option<NaiveDateTime>,
+) -> Result<i64, sqlx::Error> {
+    let mut queued = Vec::with_capacity(payloads.len());
+    for (token, payload) in payloads {
+        let outbox_id = outbox::enqueue(
+            &mut *conn,
+            outbox::NewMessage {
+                channel: Channel::Fcm,
+                target: token,
+                project_id: &schedule.fb_project_id,
+                schedule_id: Some(schedule.id),
+                fb_user_id: Some(&schedule.fb_user_id),
+                payload: payload.clone(),
+                dry_run: *DRY_RUN,
+                timeout_seconds: schedule.timeout_seconds,
+                priority: schedule.priority,
+                broadcast_id: None,
+                scheduled_at,
+                execution_key: Some(execution_key),
+            },
+        )
+        .await?;
+        queued.push((outbox_id, token.to_owned()));
+    }
+
+    // a send to several devices reports its outcome to the callback once
+    if queued.len() > 1 && !*DRY_RUN {
+        batches::create(&mut *conn, schedule.id, &queued).await?;
+    }
+
+    Ok(queued.last().map_or(0, |(outbox_id, _)| *outbox_id))
+}
+
+/// Times of the executions to send for a due schedule, more than one only when executions of
+/// a recurring schedule were missed and it catches up on all of them, none when it skips them
+fn due_executions(schedule: &FCMSchedule, current_time: NaiveDateTime) -> Vec<NaiveDateTime> {
+    let first = schedule.next_execution;
+    let missed = current_time - first > chrono::Duration::seconds(*CATCH_UP_GRACE_SECS);
+    let catch_up_all = schedule.schedule_type == ScheduleType::Recurring && !schedule.local_time;
+
+    match schedule.catch_up {
+        _ if !missed => vec![first],
+        CatchUp::Skip => {
+            info!(schedule_id = schedule.id, missed_at = %first, "Skipping missed execution");
+            vec![]
+        }
+        CatchUp::All if catch_up_all => {
+            // sends left before the limits of the schedule are reached
+            let remaining = schedule
+                .max_executions
+                .map_or(MAX_CATCH_UP_EXECUTIONS, |max| {
+                    (max - schedule.execution_count).clamp(1, MAX_CATCH_UP_EXECUTIONS as i64)
+                        as usize
+                });
+            let mut due = vec![first];
+            while due.len() < remaining {
+                let last = due[due.len() - 1];
+                match cron_after(&schedule.cron_pattern, &schedule.timezone, last) {
+                    Ok(next)
+                        if next > last
+                            && next < current_time
+                            && schedule
+                                .expires_at
+                                .is_none_or(|expires_at| next < expires_at) =>
+                    {
+                        due.push(next)
+                    }
+                    _ => break,
+                }
+            }
+            info!(
+                schedule_id = schedule.id,
+                count = due.len(),
+                "Catching up on missed executions"
             );
+            due
+        }
+        CatchUp::Once | CatchUp::All => vec![first],
+    }
+}
+
+/// Random delay of up to `seconds`, spreading out schedules due at the same time
+fn jitter(seconds: Option<i64>) -> chrono::Duration {
+    let seconds = match seconds {
+        Some(seconds) if seconds > 0 => seconds as u64,
+        _ => return chrono::Duration::zero(),
+    };
+
+    let mut bytes = [0u8; 8];
+    if let Err(e) = rand_bytes(&mut bytes) {
+        warn!(error = ?e, "Error picking a jitter, sending without delay");
+        return chrono::Duration::zero();
+    }
+    chrono::Duration::seconds((u64::from_le_bytes(bytes) % (seconds + 1)) as i64)
+}
+
+/// Holiday the schedule's calendar lists on the day it's due in its timezone, a calendar
+/// that can't be fetched skips nothing
+async fn holiday(schedule: &FCMSchedule) -> Option<String> {
+    let calendar = schedule.holiday_calendar.as_deref()?;
+    let timezone = schedule.timezone.parse::<Tz>().unwrap_or(Tz::UTC);
+    let date = timezone
+        .from_utc_datetime(&schedule.next_execution)
+        .date_naive();
+
+    match holidays::lookup(calendar, date).await {
+        Ok(holiday) => holiday,
+        Err(e) => {
+            warn!(schedule_id = schedule.id, error = %e, "Error checking holiday calendar");
+            None
+        }
+    }
+}
+
+/// Queue a notification with the payload to every device of the user, the devices
+/// it registered, the push tokens of its enabled schedules and their additional
+/// devices. Returns the number of messages queued, none for users without devices
+pub async fn notify_user(
+    pool: &SqlitePool,
+    fb_user_id: &str,
+    payload: &Value,
+) -> Result<usize, String> {
+    let devices = sqlx::query!(
+        r#"SELECT fcm_device.push_token as "push_token!", fcm_user.fb_project_id as "fb_project_id!"
+        FROM fcm_device JOIN fcm_user ON fcm_user.fb_user_id = fcm_device.fb_user_id
+        WHERE fcm_device.fb_user_id = ?1
+        UNION
+        SELECT push_token, fb_project_id FROM fcm_schedule
+        WHERE fb_user_id = ?1 AND disabled_reason IS NULL
+        UNION
+        SELECT fcm_schedule_tokens.push_token, fcm_schedule.fb_project_id
+        FROM fcm_schedule_tokens JOIN fcm_schedule ON fcm_schedule.id = fcm_schedule_tokens.schedule_id
+        WHERE fcm_schedule.fb_user_id = ?1 AND fcm_schedule.disabled_reason IS NULL"#,
+        fb_user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut projects: HashMap<String, Option<ProjectSettings>> = HashMap::new();
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for device in &devices {
+        if !projects.contains_key(&device.fb_project_id) {
+            let project = sqlx::query_as!(
+                ProjectSettings,
+                "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+                device.fb_project_id
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+            projects.insert(device.fb_project_id.to_owned(), project);
+        }
+        let project = projects.get(&device.fb_project_id).and_then(Option::as_ref);
+
+        let firebase_message = build_payload_message(&device.push_token, payload, project);
+        let payload = serde_json::to_value(&firebase_message).map_err(|e| e.to_string())?;
+        outbox::enqueue(
+            &mut *tx,
+            outbox::NewMessage {
+                channel: Channel::Fcm,
+                target: &device.push_token,
+                project_id: &device.fb_project_id,
+                schedule_id: None,
+                fb_user_id: Some(fb_user_id),
+                payload,
+                dry_run: *DRY_RUN,
+                timeout_seconds: None,
+                priority: Priority::Normal,
+                broadcast_id: None,
+                scheduled_at: None,
+                execution_key: None,
+            },
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(devices.len())
+}
+
+/// Fail messages interrupted by a restart, must run before the delivery lanes start
+pub async fn recover_outbox(pool: &SqlitePool) {
+    match outbox::restore_paused(pool).await {
+        Ok(true) => warn!("Sends are paused, resume them with /admin/scheduler/resume"),
+        Ok(false) => {}
+        Err(e) => error!(error = ?e, "Error restoring the pause switch"),
+    }
+
+    match outbox::fail_interrupted(pool).await {
+        Ok(count) if count > 0 => warn!(count, "Failed outbox messages interrupted while sending"),
+        Ok(_) => {}
+        Err(e) => error!(error = ?e, "Error failing interrupted outbox messages"),
+    }
+}
+
+/// Resume fanning out broadcasts interrupted by a restart
+pub async fn recover_broadcasts(pool: &SqlitePool) {
+    let broadcasts = sqlx::query_scalar!("SELECT id FROM fcm_broadcast WHERE status = 'running'")
+        .fetch_all(pool)
+        .await;
 
-            // Send the HTTP POST request
-            let client = reqwest::Client::new();
-            let response = client
-                .post(endpoint)
-                .headers(headers)
-                .json(&firebase_message)
-                .send()
+    match broadcasts {
+        Ok(broadcasts) => {
+            for id in broadcasts {
+                warn!(broadcast_id = id, "Resuming interrupted broadcast");
+                tokio::spawn(run_broadcast(pool.clone(), id));
+            }
+        }
+        Err(e) => error!(error = ?e, "Error loading interrupted broadcasts"),
+    }
+}
+
+/// Queue a broadcast message for every registered token of the project in batches,
+/// stopping as soon as the broadcast gets aborted
+pub async fn run_broadcast(pool: SqlitePool, broadcast_id: i64) {
+    let broadcast = sqlx::query!(
+        r#"SELECT fb_project_id, payload as "payload: Value", priority, canary_size, canary_delay_seconds, canary_max_error_rate
+        FROM fcm_broadcast WHERE id = ?"#,
+        broadcast_id
+    )
+    .fetch_one(&pool)
+    .await;
+
+    let broadcast = match broadcast {
+        Ok(broadcast) => broadcast,
+        Err(e) => {
+            error!(broadcast_id, error = ?e, "Error loading broadcast");
+            return;
+        }
+    };
+    let priority = Priority::from(broadcast.priority);
+
+    let project = sqlx::query_as!(
+        ProjectSettings,
+        "SELECT * FROM fcm_project WHERE fb_project_id = ?",
+        broadcast.fb_project_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .unwrap_or_default();
+
+    loop {
+        let progress = sqlx::query!(
+            "SELECT last_token, enqueued, canary_status FROM fcm_broadcast WHERE id = ? AND status = 'running'",
+            broadcast_id
+        )
+        .fetch_optional(&pool)
+        .await;
+
+        let progress = match progress {
+            Ok(Some(progress)) => progress,
+            Ok(None) => {
+                info!(broadcast_id, "Broadcast stopped");
+                return;
+            }
+            Err(e) => {
+                error!(broadcast_id, error = ?e, "Error loading broadcast progress");
+                return;
+            }
+        };
+        let last_token = progress.last_token.unwrap_or_default();
+
+        // only the canary is queued until its error rate is checked
+        let mut batch_size = *BROADCAST_BATCH_SIZE;
+        if let (Some(canary_size), None) = (broadcast.canary_size, &progress.canary_status) {
+            if progress.enqueued >= canary_size {
+                let result = check_canary(
+                    &pool,
+                    broadcast_id,
+                    broadcast.canary_delay_seconds,
+                    broadcast.canary_max_error_rate,
+                )
                 .await;
 
-            match response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        debug!(project_id = ?project_id, message_id=?message.id, "Successfully sent request")
-                    } else {
-                        let resp = response.text().await;
-                        warn!(project_id = ?project_id, message_id=?message.id, response=?resp, "Error sending request");
+                match result {
+                    Ok(true) => continue,
+                    Ok(false) => return,
+                    Err(e) => {
+                        error!(broadcast_id, error = ?e, "Error checking broadcast canary");
+                        return;
                     }
                 }
-                Err(e) => {
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error sending request");
-                    continue;
+            }
+            batch_size = batch_size.min(canary_size - progress.enqueued);
+        }
+
+        let tokens = sqlx::query_scalar!(
+            "SELECT DISTINCT push_token FROM fcm_schedule
+            WHERE fb_project_id = ? AND disabled_reason IS NULL AND push_token > ?
+            ORDER BY push_token LIMIT ?",
+            broadcast.fb_project_id,
+            last_token,
+            batch_size
+        )
+        .fetch_all(&pool)
+        .await;
+
+        let tokens = match tokens {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                error!(broadcast_id, error = ?e, "Error loading broadcast tokens");
+                return;
+            }
+        };
+
+        let current_time = Utc::now().naive_utc();
+
+        let last_token = match tokens.last() {
+            Some(token) => token.to_owned(),
+            None => {
+                let result = sqlx::query!(
+                    "UPDATE fcm_broadcast SET status = 'completed', updated_at = ? WHERE id = ? AND status = 'running'",
+                    current_time,
+                    broadcast_id
+                )
+                .execute(&pool)
+                .await;
+
+                match result {
+                    Ok(_) => info!(broadcast_id, "Broadcast queued for every token"),
+                    Err(e) => error!(broadcast_id, error = ?e, "Error completing broadcast"),
                 }
+                return;
+            }
+        };
+
+        let mut tx = match pool.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                error!(broadcast_id, error = ?e, "Error starting transaction");
+                return;
             }
+        };
 
-            // Update the next execution time
-            let next = match parse(&message.cron_pattern.to_owned(), &Utc::now()) {
-                Ok(next) => next.naive_utc(),
+        for token in &tokens {
+            let firebase_message =
+                build_payload_message(token, &broadcast.payload, project.as_ref());
+            let payload = match serde_json::to_value(&firebase_message) {
+                Ok(payload) => payload,
                 Err(e) => {
-                    println!("Error parsing cron pattern: {}", e);
-                    error!(project_id = ?project_id, message_id=?message.id, error=?e, "Error parsing cron pattern");
-                    continue;
+                    error!(broadcast_id, error = ?e, "Error serializing message");
+                    return;
                 }
             };
 
-            // Update database
+            let result = outbox::enqueue(
+                &mut *tx,
+                outbox::NewMessage {
+                    channel: Channel::Fcm,
+                    target: token,
+                    project_id: &broadcast.fb_project_id,
+                    schedule_id: None,
+                    fb_user_id: None,
+                    payload,
+                    dry_run: *DRY_RUN,
+                    timeout_seconds: None,
+                    priority,
+                    broadcast_id: Some(broadcast_id),
+                    scheduled_at: None,
+                    execution_key: None,
+                },
+            )
+            .await;
+
+            if let Err(e) = result {
+                error!(broadcast_id, error = ?e, "Error enqueueing broadcast message");
+                return;
+            }
+        }
+
+        // the batch is only kept if the broadcast wasn't aborted in the meantime
+        let count = tokens.len() as i64;
+        let result = sqlx::query!(
+            "UPDATE fcm_broadcast SET enqueued = enqueued + ?, last_token = ?, updated_at = ? WHERE id = ? AND status = 'running'",
+            count,
+            last_token,
+            current_time,
+            broadcast_id
+        )
+        .execute(&mut *tx)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                info!(broadcast_id, "Broadcast aborted");
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!(broadcast_id, error = ?e, "Error updating broadcast progress");
+                return;
+            }
+        }
+
+        match tx.commit().await {
+            Ok(_) => info!(broadcast_id, count, "Queued broadcast batch"),
+            Err(e) => {
+                error!(broadcast_id, error = ?e, "Error enqueueing broadcast batch");
+                return;
+            }
+        }
+
+        // give the delivery lanes a head start before queueing the next batch
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Wait for the canary of a broadcast to settle and compare its error rate to the
+/// allowed one. Aborts the broadcast when the canary failed, returns whether to continue.
+async fn check_canary(
+    pool: &SqlitePool,
+    broadcast_id: i64,
+    delay_seconds: i64,
+    max_error_rate: i64,
+) -> Result<bool, sqlx::Error> {
+    info!(broadcast_id, delay_seconds, "Waiting for broadcast canary");
+    sleep(Duration::from_secs(delay_seconds as u64)).await;
+
+    // messages still being retried (or held back by a pause) are waited for
+    let outcome = loop {
+        let outcome = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(CASE WHEN status = 'delivered' THEN 1 ELSE 0 END), 0) as "delivered!: i64",
+                COALESCE(SUM(CASE WHEN status = 'failed' THEN 1 ELSE 0 END), 0) as "failed!: i64",
+                COALESCE(SUM(CASE WHEN status IN ('pending', 'sending') THEN 1 ELSE 0 END), 0) as "unsettled!: i64"
+            FROM outbox WHERE broadcast_id = ?"#,
+            broadcast_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        if outcome.unsettled == 0 {
+            break outcome;
+        }
+
+        let running = sqlx::query_scalar!(
+            "SELECT id FROM fcm_broadcast WHERE id = ? AND status = 'running'",
+            broadcast_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        if running.is_none() {
+            info!(broadcast_id, "Broadcast stopped");
+            return Ok(false);
+        }
+
+        sleep(Duration::from_secs(5)).await;
+    };
+
+    let sent = outcome.delivered + outcome.failed;
+    let error_rate = if sent > 0 {
+        outcome.failed * 100 / sent
+    } else {
+        0
+    };
+    let passed = error_rate <= max_error_rate;
+    let current_time = Utc::now().naive_utc();
+
+    if passed {
+        sqlx::query!(
+            "UPDATE fcm_broadcast SET canary_status = 'passed', updated_at = ? WHERE id = ? AND status = 'running'",
+            current_time,
+            broadcast_id
+        )
+        .execute(pool)
+        .await?;
+
+        info!(
+            broadcast_id,
+            error_rate, "Broadcast canary passed, sending to the remaining tokens"
+        );
+    } else {
+        sqlx::query!(
+            "UPDATE fcm_broadcast SET canary_status = 'failed', status = 'aborted', updated_at = ? WHERE id = ? AND status = 'running'",
+            current_time,
+            broadcast_id
+        )
+        .execute(pool)
+        .await?;
+
+        warn!(
+            broadcast_id,
+            error_rate, max_error_rate, "Broadcast canary failed, aborting the broadcast"
+        );
+    }
+
+    Ok(passed)
+}
+
+/// Deliver messages of a single priority lane, sending up to `concurrency` messages at once
+pub async fn deliver_outbox(
+    service_accounts: ServiceAccounts,
+    pool: SqlitePool,
+    priority: Priority,
+    concurrency: i64,
+) {
+    loop {
+        let messages = outbox::claim(&pool, Channel::Fcm, priority, concurrency)
+            .await
+            .unwrap_or_else(|e| {
+                error!(error = ?e, priority = ?priority, "Error claiming outbox messages");
+                vec![]
+            });
+
+        let claimed = messages.len() as i64;
+        let mut sends = JoinSet::new();
+        for message in messages {
+            let service_accounts = service_accounts.clone();
+            let pool = pool.clone();
+            sends.spawn(async move { deliver(&service_accounts, &pool, message).await });
+        }
+        while sends.join_next().await.is_some() {}
+
+        // keep draining a busy lane, otherwise wait for new messages
+        if claimed < concurrency {
+            sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
+
+async fn deliver(service_accounts: &ServiceAccounts, pool: &SqlitePool, message: OutboxMessage) {
+    let started = Instant::now();
+    let result = if message.dry_run {
+        info!(project_id = ?message.project_id, outbox_id = message.id, target = %message.target, message = ?message.payload, "Dry run, skipping send");
+        Ok(String::new())
+    } else {
+        let timeout = message
+            .timeout_seconds
+            .map(|secs| secs as u64)
+            .unwrap_or(*SEND_TIMEOUT_SECS);
+
+        match serde_json::from_value::<FCM>(message.payload.clone()) {
+            Ok(firebase_message) => {
+                let send = send_message(service_accounts, &message.project_id, &firebase_message);
+                match time::timeout(Duration::from_secs(timeout), send).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        warn!(outbox_id = message.id, timeout, "Send timed out");
+                        Err(SendError::Timeout(timeout))
+                    }
+                }
+            }
+            Err(e) => Err(SendError::Rejected(
+                ErrorCode::InvalidArgument,
+                format!("Invalid message: {}", e),
+            )),
+        }
+    };
+
+    let latency_ms = (!message.dry_run).then(|| started.elapsed().as_millis() as i64);
+
+    let result = match &result {
+        Ok(_) => outbox::mark_delivered(pool, message.id)
+            .await
+            .map(|_| false),
+        Err(SendError::Transient(_, e)) => outbox::retry_later(pool, &message, e).await,
+        Err(SendError::Rejected(code, e)) => {
+            handle_rejection(pool, &message, *code).await;
+            outbox::mark_failed(pool, message.id, e)
+                .await
+                .map(|_| false)
+        }
+        // the message may have been delivered, retrying could send it twice
+        Err(e @ SendError::Timeout(_)) => outbox::mark_failed(pool, message.id, &e.to_string())
+            .await
+            .map(|_| false),
+    }
+    .map(|retrying| (result, retrying));
+
+    match result {
+        // the final outcome is recorded once no more attempts will be made
+        Ok((result, false)) => {
+            if !message.dry_run {
+                metrics::record_delivery(
+                    Channel::Fcm,
+                    message.scheduled_at,
+                    latency_ms.map(|ms| Duration::from_millis(ms as u64)),
+                    result.is_ok(),
+                );
+            }
+            record_execution(pool, &message, &result, latency_ms, false).await
+        }
+        Ok((result, true)) => record_execution(pool, &message, &result, latency_ms, true).await,
+        Err(e) => {
+            error!(outbox_id = message.id, error = ?e, "Error updating outbox message")
+        }
+    }
+}
+
+/// React to errors that won't go away by retrying the same message
+async fn handle_rejection(pool: &SqlitePool, message: &OutboxMessage, code: ErrorCode) {
+    let current_time = Utc::now().naive_utc();
+    let result = match code {
+        // stop sending to a token FCM no longer recognises, every schedule using it would fail too
+        ErrorCode::InvalidToken => {
+            warn!(outbox_id = message.id, target = %message.target, "Disabling schedules of an invalid push token");
             let result = sqlx::query!(
-                r#"UPDATE fcm_schedule SET next_execution = ?, last_execution = ?, updated_at = ? WHERE id = ?"#,
-                next,
+                "UPDATE fcm_schedule SET disabled_reason = ?, version = version + 1, updated_at = ? WHERE push_token = ? AND target_type = 'token' AND disabled_reason IS NULL",
+                "invalid_token",
                 current_time,
+                message.target
+            )
+            .execute(pool)
+            .await;
+
+            // additional devices are dropped from their schedules, the other devices keep receiving them
+            let removed = sqlx::query!(
+                "DELETE FROM fcm_schedule_tokens WHERE push_token = ?",
+                message.target
+            )
+            .execute(pool)
+            .await;
+            if let Err(e) = removed {
+                error!(outbox_id = message.id, error = ?e, "Error removing invalid push token");
+            }
+
+            // hints belong to a user, messages of broadcasts have none
+            if let Some(fb_user_id) = &message.fb_user_id {
+                let hint = tokens::handle_unregistered(pool, fb_user_id, &message.target).await;
+                if let Err(e) = hint {
+                    error!(outbox_id = message.id, error = ?e, "Error recording token hint");
+                }
+            }
+
+            result
+        }
+        // the payload has to be changed by the owner before it can be delivered
+        ErrorCode::PayloadTooBig => {
+            let schedule_id = match message.schedule_id {
+                Some(schedule_id) => schedule_id,
+                None => return,
+            };
+            warn!(
+                outbox_id = message.id,
+                schedule_id, "Disabling schedule with an oversized payload"
+            );
+            sqlx::query!(
+                "UPDATE fcm_schedule SET disabled_reason = ?, version = version + 1, updated_at = ? WHERE id = ?",
+                "payload_too_big",
                 current_time,
-                message.id,
-            ).execute(pool).await;
+                schedule_id
+            )
+            .execute(pool)
+            .await
+        }
+        ErrorCode::AuthError => {
+            error!(project_id = %message.project_id, outbox_id = message.id, "FCM rejected the project credentials, check the service account");
+            return;
+        }
+        _ => return,
+    };
 
-            match result {
-                Ok(data) => println!(
-                    "Successfully updated next execution time: {:?}, rows affected: {}",
-                    next,
-                    data.rows_affected()
-                ),
-                Err(e) => println!("Error updating next execution time: {}", e),
+    if let Err(e) = result {
+        error!(outbox_id = message.id, error = ?e, "Error disabling schedule");
+    }
+}
+
+async fn record_execution(
+    pool: &SqlitePool,
+    message: &OutboxMessage,
+    result: &Result<String, SendError>,
+    latency_ms: Option<i64>,
+    retrying: bool,
+) {
+    let (schedule_id, fb_user_id) = match (message.schedule_id, &message.fb_user_id) {
+        (Some(schedule_id), Some(fb_user_id)) => (schedule_id, fb_user_id),
+        _ => return,
+    };
+
+    let current_time = Utc::now().naive_utc();
+    let (status, error, error_code) = match result {
+        Ok(_) => ("success", None, None),
+        Err(e) if retrying => ("retrying", Some(e.to_string()), Some(e.code().as_str())),
+        Err(e @ SendError::Timeout(_)) => ("timeout", Some(e.to_string()), Some(e.code().as_str())),
+        Err(e) => ("failure", Some(e.to_string()), Some(e.code().as_str())),
+    };
+    let message_id = result.as_ref().ok().filter(|name| !name.is_empty());
+    // transient errors are only final once the message ran out of attempts
+    let exhausted = !retrying && matches!(result, Err(SendError::Transient(..)));
+
+    let result = sqlx::query!(
+        "INSERT INTO fcm_execution_log (schedule_id, fb_user_id, push_token, status, error, error_code, dry_run, latency_ms, message_id, attempts, execution_key, executed_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        schedule_id,
+        fb_user_id,
+        message.target,
+        status,
+        error,
+        error_code,
+        message.dry_run,
+        latency_ms,
+        message_id,
+        message.attempts,
+        message.execution_key,
+        current_time
+    )
+    .execute(pool)
+    .await;
+
+    let log_id = match result {
+        Ok(result) => Some(result.last_insert_rowid()),
+        Err(e) => {
+            error!(outbox_id = message.id, error = ?e, "Error recording execution");
+            None
+        }
+    };
+
+    if retrying {
+        return;
+    }
+
+    // schedules whose delivery still failed after every retry are flagged until an
+    // execution gets through to all of their devices
+    if exhausted {
+        let result = sqlx::query!(
+            "UPDATE fcm_schedule SET errored_at = ? WHERE id = ?",
+            current_time,
+            schedule_id
+        )
+        .execute(pool)
+        .await;
+
+        if let Err(e) = result {
+            error!(schedule_id, error = ?e, "Error flagging schedule");
+        }
+    }
+
+    if let Some(log_id) = log_id {
+        let result = count_execution(pool, message, schedule_id, log_id, status == "success").await;
+        if let Err(e) = result {
+            error!(schedule_id, error = ?e, "Error updating schedule failures");
+        }
+    }
+
+    let event = ExecutionEvent {
+        schedule_id,
+        fb_user_id,
+        status,
+        error_code,
+        message_id: message_id.map(String::as_str),
+        latency_ms,
+        attempts: message.attempts,
+        dry_run: message.dry_run,
+        executed_at: current_time,
+    };
+    followups::run(pool, &message.target, &event).await;
+
+    // deliveries of a batch are reported together once the whole batch is done
+    let batched =
+        match batches::record(pool, message.id, status, error_code, error.as_deref()).await {
+            Ok(batched) => batched,
+            Err(e) => {
+                error!(outbox_id = message.id, error = %e, "Error recording batch outcome");
+                false
+            }
+        };
+
+    // deliveries that failed for good are posted to the schedule's callback
+    if status != "success" && !message.dry_run && !batched {
+        let failure = ExecutionFailure {
+            execution: &event,
+            error: error.as_deref(),
+        };
+        if let Err(e) = callbacks::enqueue(pool, schedule_id, "execution.failed", &failure).await {
+            error!(schedule_id, error = %e, "Error queueing webhook event");
+        }
+    }
+
+    events::publish("execution.completed", event);
+}
+
+/// Count the execution a final delivery belongs to towards the failures in a row of
+/// its schedule and disable schedules that keep failing. An execution sent to several
+/// devices counts once, when the last of its deliveries is final, and only succeeds when
+/// every device got the message.
+async fn count_execution(
+    pool: &SqlitePool,
+    message: &OutboxMessage,
+    schedule_id: i64,
+    log_id: i64,
+    success: bool,
+) -> Result<(), sqlx::Error> {
+    let success = match &message.execution_key {
+        Some(execution_key) => {
+            let execution = sqlx::query!(
+                r#"SELECT
+                    (SELECT COUNT(*) FROM outbox WHERE execution_key = ?1) as "messages!: i64",
+                    COUNT(*) as "finished!: i64",
+                    COALESCE(SUM(status = 'success'), 0) as "successes!: i64",
+                    MAX(id) as "last_id: i64"
+                FROM fcm_execution_log WHERE execution_key = ?1 AND status != 'retrying'"#,
+                execution_key
+            )
+            .fetch_one(pool)
+            .await?;
+
+            // deliveries finishing at the same time all see each other, only the one
+            // logged last counts the execution
+            if execution.finished < execution.messages || execution.last_id != Some(log_id) {
+                return Ok(());
+            }
+            execution.successes == execution.messages
+        }
+        None => success,
+    };
+
+    if success {
+        sqlx::query!(
+            "UPDATE fcm_schedule SET failed_count = 0, errored_at = NULL WHERE id = ?",
+            schedule_id
+        )
+        .execute(pool)
+        .await?;
+    } else {
+        sqlx::query!(
+            "UPDATE fcm_schedule SET failed_count = failed_count + 1,
+                disabled_reason = CASE WHEN disabled_reason IS NULL AND ?1 > 0 AND failed_count + 1 >= ?1 THEN 'failed' ELSE disabled_reason END,
+                version = CASE WHEN disabled_reason IS NULL AND ?1 > 0 AND failed_count + 1 >= ?1 THEN version + 1 ELSE version END
+            WHERE id = ?2",
+            *MAX_CONSECUTIVE_FAILURES,
+            schedule_id
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// each user is purged in a transaction of its own, a failure leaves the others purged
+async fn purge_user(pool: &SqlitePool, fb_user_id: &str) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let deleted_schedules = utils::purge_user(&mut tx, fb_user_id).await?;
+    tx.commit().await?;
+    Ok(deleted_schedules)
+}
+
+pub async fn purge_inactive_anonymous_users(pool: &SqlitePool) {
+    if *ANONYMOUS_RETENTION_DAYS <= 0 {
+        info!("Anonymous user cleanup is disabled");
+        return;
+    }
+
+    loop {
+        let cutoff = format!("-{} days", *ANONYMOUS_RETENTION_DAYS);
+
+        let users = sqlx::query_scalar!(
+            "SELECT fb_user_id FROM fcm_user WHERE anonymous = 1 AND last_seen_at < datetime('now', ?)",
+            cutoff
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap_or_else(|e| {
+            error!(error = ?e, "Error finding inactive anonymous users");
+            vec![]
+        });
+
+        let mut purged = 0;
+        for fb_user_id in users {
+            match purge_user(pool, &fb_user_id).await {
+                Ok(deleted_schedules) => {
+                    debug!(uid = %fb_user_id, deleted_schedules, "Purged inactive anonymous user");
+                    purged += 1;
+                }
+                Err(e) => {
+                    error!(uid = %fb_user_id, error = ?e, "Error purging inactive anonymous user")
+                }
             }
         }
+        info!(purged, "Purged inactive anonymous users");
 
-        // Sleep for 1 minute
-        sleep(Duration::from_secs(60)).await;
+        // Sleep for 1 hour
+        sleep(Duration::from_secs(60 * 60)).await;
     }
 }
@@ -0,0 +1,189 @@
+use super::model::{ExecutionsSensor, OutboxSensor, SchedulesSensor};
+use crate::outbox;
+use crate::utils::{ApiTags, JsonError, ResponseObject};
+use chrono::{Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use poem::{web::Data, Request};
+use poem_openapi::{payload::Json, OpenApi};
+use sqlx::SqlitePool;
+use std::env;
+
+lazy_static! {
+    // read only token for dashboards, so the API key doesn't have to be stored on them
+    static ref HOMEASSISTANT_TOKEN: Option<String> = env::var("HOMEASSISTANT_TOKEN").ok();
+}
+
+fn verify_token(req: &Request) -> Result<(), String> {
+    let token = match HOMEASSISTANT_TOKEN.as_ref() {
+        Some(token) => token,
+        None => {
+            return Err("HOMEASSISTANT_TOKEN is not configured".to_string());
+        }
+    };
+
+    match req
+        .header("Authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+    {
+        Some(bearer) if bearer == token => Ok(()),
+        Some(_) => Err("Invalid token".to_string()),
+        None => Err("Authorization header is missing".to_string()),
+    }
+}
+
+pub struct HomeAssistant;
+
+/// Flat JSON documents meant for Home Assistant REST sensors, read the state with
+/// `value_template: "{{ value_json.state }}"` and the rest through `json_attributes`
+#[OpenApi(
+    prefix_path = "/homeassistant/",
+    request_header(
+        name = "Authorization",
+        ty = "String",
+        description = "HOMEASSISTANT_TOKEN of the server (example: <code>Bearer {token}</code>)"
+    ),
+    tag = "ApiTags::HomeAssistant"
+)]
+impl HomeAssistant {
+    /// number of active schedules
+    #[oai(
+        path = "/schedules",
+        method = "get",
+        operation_id = "homeassistant::get_schedules"
+    )]
+    async fn get_schedules(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<Json<SchedulesSensor>, JsonError<String>> {
+        if let Err(e) = verify_token(req) {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let sensor = sqlx::query_as!(
+            SchedulesSensor,
+            r#"SELECT
+                COALESCE(SUM(CASE WHEN disabled_reason IS NULL THEN 1 ELSE 0 END), 0) as "state!: i64",
+                COALESCE(SUM(CASE WHEN disabled_reason IS NOT NULL THEN 1 ELSE 0 END), 0) as "disabled!: i64",
+                COALESCE(SUM(CASE WHEN disabled_reason = 'pending_approval' THEN 1 ELSE 0 END), 0) as "pending_approval!: i64",
+                COUNT(DISTINCT fb_user_id) as "users!: i64",
+                MIN(CASE WHEN disabled_reason IS NULL THEN next_execution END) as "next_execution: NaiveDateTime"
+            FROM fcm_schedule"#
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        match sensor {
+            Ok(sensor) => Ok(Json(sensor)),
+            Err(e) => Err(ResponseObject::internal_server_error(e)),
+        }
+    }
+
+    /// success rate of the sends in the last 24 hours
+    #[oai(
+        path = "/executions",
+        method = "get",
+        operation_id = "homeassistant::get_executions"
+    )]
+    async fn get_executions(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<Json<ExecutionsSensor>, JsonError<String>> {
+        if let Err(e) = verify_token(req) {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let since = Utc::now().naive_utc() - Duration::hours(24);
+        let totals = sqlx::query!(
+            r#"SELECT COUNT(*) as "sends!: i64",
+                COALESCE(SUM(CASE WHEN status = 'success' THEN 1 ELSE 0 END), 0) as "successes!: i64"
+            FROM fcm_execution_log WHERE executed_at >= ? AND status NOT IN ('retrying', 'skipped') AND dry_run = 0"#,
+            since
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let totals = match totals {
+            Ok(totals) => totals,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let last_execution = sqlx::query_scalar!(
+            r#"SELECT MAX(executed_at) as "executed_at: NaiveDateTime" FROM fcm_execution_log WHERE dry_run = 0"#
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let last_execution = match last_execution {
+            Ok(last_execution) => last_execution,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let state = if totals.sends == 0 {
+            100.0
+        } else {
+            (totals.successes as f64 * 1000.0 / totals.sends as f64).round() / 10.0
+        };
+
+        Ok(Json(ExecutionsSensor {
+            state,
+            sends: totals.sends,
+            successes: totals.successes,
+            failures: totals.sends - totals.successes,
+            last_execution,
+        }))
+    }
+
+    /// whether outbound sends are paused and how many messages are waiting
+    #[oai(
+        path = "/outbox",
+        method = "get",
+        operation_id = "homeassistant::get_outbox"
+    )]
+    async fn get_outbox(
+        &self,
+        req: &Request,
+        pool: Data<&SqlitePool>,
+    ) -> Result<Json<OutboxSensor>, JsonError<String>> {
+        if let Err(e) = verify_token(req) {
+            return Err(ResponseObject::unauthorized(e));
+        }
+
+        let since = Utc::now().naive_utc() - Duration::hours(24);
+        let counts = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(CASE WHEN status = 'pending' THEN 1 ELSE 0 END), 0) as "pending!: i64",
+                COALESCE(SUM(CASE WHEN status = 'failed' AND updated_at >= ? THEN 1 ELSE 0 END), 0) as "failed!: i64",
+                MIN(CASE WHEN status = 'pending' THEN created_at END) as "oldest_pending: NaiveDateTime"
+            FROM outbox"#,
+            since
+        )
+        .fetch_one(pool.0)
+        .await;
+
+        let counts = match counts {
+            Ok(counts) => counts,
+            Err(e) => {
+                return Err(ResponseObject::internal_server_error(e));
+            }
+        };
+
+        let state = if outbox::is_paused() {
+            "paused"
+        } else {
+            "running"
+        };
+
+        Ok(Json(OutboxSensor {
+            state: state.to_string(),
+            pending: counts.pending,
+            failed: counts.failed,
+            oldest_pending: counts.oldest_pending,
+        }))
+    }
+}
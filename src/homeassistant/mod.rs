@@ -0,0 +1,6 @@
+mod handler;
+mod model;
+
+pub async fn homeassistant() -> handler::HomeAssistant {
+    handler::HomeAssistant
+}
@@ -0,0 +1,46 @@
+use chrono::NaiveDateTime;
+use poem_openapi::Object;
+use serde::Serialize;
+
+/// Schedules sensor, the state is the number of active schedules
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct SchedulesSensor {
+    /// Number of active schedules
+    pub state: i64,
+    /// Schedules disabled for any reason
+    pub disabled: i64,
+    /// Schedules waiting for a project manager's approval
+    pub pending_approval: i64,
+    /// Users with at least one schedule
+    pub users: i64,
+    /// Next time a schedule is due
+    pub next_execution: Option<NaiveDateTime>,
+}
+
+/// Executions sensor, the state is the success rate of the last 24 hours in percent
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct ExecutionsSensor {
+    /// Successful sends in percent, 100 when nothing was sent
+    pub state: f64,
+    /// Sends in the last 24 hours
+    pub sends: i64,
+    /// Successful sends in the last 24 hours
+    pub successes: i64,
+    /// Failed or timed out sends in the last 24 hours
+    pub failures: i64,
+    /// Time of the last send
+    pub last_execution: Option<NaiveDateTime>,
+}
+
+/// Outbox sensor, the state is `paused` or `running`
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct OutboxSensor {
+    /// Whether outbound sends are paused or running
+    pub state: String,
+    /// Messages waiting to be sent
+    pub pending: i64,
+    /// Messages that failed in the last 24 hours
+    pub failed: i64,
+    /// Creation time of the oldest message waiting to be sent
+    pub oldest_pending: Option<NaiveDateTime>,
+}
@@ -0,0 +1,212 @@
+use lazy_static::lazy_static;
+use reqwest::{redirect::Policy, Certificate, Client, Proxy, RequestBuilder, Response};
+use std::{env, fs, net::IpAddr, time::Duration};
+use tokio::{net::lookup_host, time::sleep};
+use tracing::{info, warn};
+use url::{Host, Url};
+
+lazy_static! {
+    /// Shared client for all outbound HTTP requests
+    pub static ref CLIENT: Client = build_client(Policy::default());
+    /// Client for requests to URLs given by users, it doesn't follow redirects to
+    /// addresses `validate_public_url` rejects
+    pub static ref PUBLIC_CLIENT: Client = build_client(Policy::custom(|attempt| {
+        if attempt.previous().len() >= 10 {
+            attempt.error("too many redirects")
+        } else if let Err(e) = validate_public_url(attempt.url().as_str()) {
+            attempt.error(e)
+        } else {
+            attempt.follow()
+        }
+    }));
+    static ref MAX_RETRIES: u32 = env_var("HTTP_MAX_RETRIES", 2);
+    static ref RETRY_BACKOFF_MS: u64 = env_var("HTTP_RETRY_BACKOFF_MS", 500);
+}
+
+fn env_var<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn build_client(redirect: Policy) -> Client {
+    let mut builder = Client::builder()
+        .redirect(redirect)
+        .connect_timeout(Duration::from_secs(env_var(
+            "HTTP_CONNECT_TIMEOUT_SECS",
+            10,
+        )))
+        .timeout(Duration::from_secs(env_var("HTTP_TIMEOUT_SECS", 300)));
+
+    // HTTP_PROXY / HTTPS_PROXY / NO_PROXY are picked up by reqwest itself,
+    // HTTP_PROXY_URL forces a proxy for every request regardless of scheme
+    if let Ok(proxy) = env::var("HTTP_PROXY_URL") {
+        match Proxy::all(&proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!(error = ?e, "Ignoring invalid HTTP_PROXY_URL"),
+        }
+    }
+
+    if let Ok(path) = env::var("HTTP_CA_BUNDLE") {
+        let certificates = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| Certificate::from_pem_bundle(&pem).map_err(|e| e.to_string()));
+
+        match certificates {
+            Ok(certificates) => {
+                info!(path = %path, count = certificates.len(), "Loaded custom CA bundle");
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            Err(e) => warn!(path = %path, error = %e, "Failed to load HTTP_CA_BUNDLE"),
+        }
+    }
+
+    builder.build().expect("failed to build HTTP client")
+}
+
+/// Send the request, retrying connection failures and 5xx responses with
+/// exponential backoff. Requests with streaming bodies are sent only once.
+pub async fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    let mut attempt = 0;
+
+    loop {
+        let retry = match request.try_clone() {
+            Some(retry) if attempt < *MAX_RETRIES => retry,
+            _ => return request.send().await,
+        };
+
+        match retry.send().await {
+            Ok(response) if !response.status().is_server_error() => return Ok(response),
+            Ok(response) => {
+                warn!(status = %response.status(), url = %response.url(), attempt, "Retrying request");
+            }
+            Err(e) if e.is_connect() || e.is_timeout() => {
+                warn!(error = ?e, attempt, "Retrying request");
+            }
+            Err(e) => return Err(e),
+        }
+
+        sleep(Duration::from_millis(*RETRY_BACKOFF_MS * 2u64.pow(attempt))).await;
+        attempt += 1;
+    }
+}
+
+/// Check a URL given by a user is an absolute http(s) URL whose host isn't a loopback,
+/// link-local or private address
+pub fn validate_public_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|e| e.to_string())?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err("only http and https are supported".to_string());
+    }
+
+    let public = match url.host() {
+        Some(Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        Some(Host::Domain(domain)) => domain != "localhost" && !domain.ends_with(".localhost"),
+        None => return Err("the url has no host".to_string()),
+    };
+    match public {
+        true => Ok(url),
+        false => Err(format!(
+            "{} is not a public address",
+            url.host_str().unwrap_or_default()
+        )),
+    }
+}
+
+/// Check a URL like `validate_public_url` and that every address its host resolves to
+/// is public. Run before each request, a host may resolve to other addresses than when
+/// the URL was saved
+pub async fn check_public_url(url: &str) -> Result<Url, String> {
+    let url = validate_public_url(url)?;
+    if let Some(Host::Domain(domain)) = url.host() {
+        let port = url.port_or_known_default().unwrap_or_default();
+        let addresses = lookup_host((domain, port))
+            .await
+            .map_err(|e| format!("{} can't be resolved: {}", domain, e))?;
+        for address in addresses {
+            if !is_public(address.ip()) {
+                return Err(format!(
+                    "{} resolves to {}, not a public address",
+                    domain,
+                    address.ip()
+                ));
+            }
+        }
+    }
+    Ok(url)
+}
+
+/// Whether the address is reachable over the internet, loopback, link-local, private and
+/// other special purpose addresses aren't
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // "this network" and the shared address space of carrier-grade NAT
+                || first == 0
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_private_address_literals() {
+        for url in [
+            "http://127.0.0.1/",
+            "http://10.1.2.3/",
+            "http://172.16.0.1/",
+            "http://192.168.1.1/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://localhost:8080/",
+            "http://api.localhost/",
+        ] {
+            assert!(validate_public_url(url).is_err(), "{} was accepted", url);
+        }
+    }
+
+    #[test]
+    fn accepts_public_urls() {
+        for url in [
+            "https://example.com/calendar.ics",
+            "http://93.184.216.34/",
+            "https://[2606:2800:220:1:248:1893:25c8:1946]/",
+        ] {
+            assert!(validate_public_url(url).is_ok(), "{} was rejected", url);
+        }
+        assert!(validate_public_url("ftp://example.com/").is_err());
+        assert!(validate_public_url("example.com").is_err());
+    }
+}
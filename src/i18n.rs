@@ -0,0 +1,129 @@
+use lazy_static::lazy_static;
+use poem::{
+    http::header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE},
+    Endpoint, IntoResponse, Middleware, Request, Response, Result as PoemResult,
+};
+use serde_json::Value;
+use std::{collections::HashMap, env};
+
+const FALLBACK_LOCALE: &str = "en";
+
+lazy_static! {
+    // translations of the english error messages, keyed by the original message
+    static ref CATALOGS: HashMap<&'static str, HashMap<String, String>> = [
+        ("de", include_str!("../locales/de.json")),
+        ("es", include_str!("../locales/es.json")),
+        ("fr", include_str!("../locales/fr.json")),
+    ]
+    .into_iter()
+    .map(|(locale, catalog)| (locale, serde_json::from_str(catalog).expect("invalid locale catalog")))
+    .collect();
+    // locale used when the client doesn't ask for a supported one
+    static ref DEFAULT_LOCALE: String = env::var("DEFAULT_LOCALE")
+        .ok()
+        .filter(|locale| is_supported(locale))
+        .unwrap_or(FALLBACK_LOCALE.to_string());
+}
+
+fn is_supported(locale: &str) -> bool {
+    locale == FALLBACK_LOCALE || CATALOGS.contains_key(locale)
+}
+
+/// Pick the supported locale the client prefers the most from an Accept-Language header
+fn negotiate(accept_language: Option<&str>) -> String {
+    let mut preferences: Vec<(String, f32)> = accept_language
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.trim().split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            // only the primary language is translated, e.g. es-AR uses es
+            let language = tag.split('-').next()?.to_string();
+            Some((language, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    preferences.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    preferences
+        .into_iter()
+        .map(|(language, _)| language)
+        .find(|language| is_supported(language))
+        .unwrap_or(DEFAULT_LOCALE.to_string())
+}
+
+/// Translates the `error` of JSON error responses to the locale negotiated from Accept-Language.
+/// Messages without a translation are returned in english.
+pub struct Localize;
+
+impl<E: Endpoint> Middleware<E> for Localize {
+    type Output = LocalizeEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        LocalizeEndpoint { ep }
+    }
+}
+
+pub struct LocalizeEndpoint<E> {
+    ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for LocalizeEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> PoemResult<Self::Output> {
+        let locale = negotiate(
+            req.headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|v| v.to_str().ok()),
+        );
+
+        let mut response = match self.ep.call(req).await {
+            Ok(response) => response.into_response(),
+            Err(e) => e.into_response(),
+        };
+
+        let catalog = match CATALOGS.get(locale.as_str()) {
+            Some(catalog)
+                if response.status().is_client_error() || response.status().is_server_error() =>
+            {
+                catalog
+            }
+            _ => return Ok(response),
+        };
+
+        let is_json = response
+            .content_type()
+            .is_some_and(|content_type| content_type.starts_with("application/json"));
+        if !is_json {
+            return Ok(response);
+        }
+
+        let body = response.take_body().into_bytes().await?;
+        let mut value: Value = match serde_json::from_slice(&body) {
+            Ok(value) => value,
+            Err(_) => {
+                response.set_body(body);
+                return Ok(response);
+            }
+        };
+
+        if let Some(Value::String(error)) = value.get_mut("error") {
+            if let Some(translated) = catalog.get(error.as_str()) {
+                *error = translated.to_owned();
+                response.headers_mut().insert(
+                    CONTENT_LANGUAGE,
+                    locale.parse().expect("locale is a valid header value"),
+                );
+            }
+        }
+
+        response.set_body(value.to_string());
+        Ok(response)
+    }
+}
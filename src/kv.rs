@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+// Optional Redis store shared by every instance of a deployment. It's used when
+// the server is built with the `redis` feature and REDIS_URL is set, callers
+// keep their in-memory state otherwise. Redis errors never fail a request: reads
+// miss, writes are dropped and reservations don't wait.
+
+#[cfg(feature = "redis")]
+mod backend {
+    use lazy_static::lazy_static;
+    use redis::{aio::ConnectionManager, Client, Script};
+    use std::{env, time::Duration};
+    use tokio::sync::OnceCell;
+    use tracing::{error, info, warn};
+
+    lazy_static! {
+        static ref REDIS_URL: Option<String> = env::var("REDIS_URL").ok();
+        // every key is prefixed so several deployments can share a server
+        static ref PREFIX: String = env::var("REDIS_PREFIX").unwrap_or("toolkit:".to_string());
+        // reserve the next slot of a paced key and return how long to wait for it,
+        // the server clock is used so instances with drifting clocks agree
+        static ref RESERVE: Script = Script::new(
+            r"
+            local time = redis.call('TIME')
+            local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+            local at = math.max(now, tonumber(redis.call('GET', KEYS[1]) or 0))
+            local next = at + tonumber(ARGV[1])
+            redis.call('SET', KEYS[1], next, 'PX', next - now)
+            return at - now
+            ",
+        );
+        // count a hit on a key that expires a fixed time after its first hit
+        static ref INCREMENT: Script = Script::new(
+            r"
+            local count = redis.call('INCR', KEYS[1])
+            if count == 1 then
+                redis.call('PEXPIRE', KEYS[1], ARGV[1])
+            end
+            return count
+            ",
+        );
+    }
+
+    static CONNECTION: OnceCell<Option<ConnectionManager>> = OnceCell::const_new();
+
+    pub fn enabled() -> bool {
+        REDIS_URL.is_some()
+    }
+
+    async fn connection() -> Option<ConnectionManager> {
+        CONNECTION
+            .get_or_init(|| async {
+                let url = REDIS_URL.as_ref()?;
+                let client = match Client::open(url.as_str()) {
+                    Ok(client) => client,
+                    Err(e) => {
+                        error!(error = ?e, "Invalid REDIS_URL");
+                        return None;
+                    }
+                };
+                // the manager reconnects on its own once the first connection is made
+                match ConnectionManager::new(client).await {
+                    Ok(connection) => {
+                        info!("Connected to redis");
+                        Some(connection)
+                    }
+                    Err(e) => {
+                        error!(error = ?e, "Failed to connect to redis");
+                        None
+                    }
+                }
+            })
+            .await
+            .clone()
+    }
+
+    pub async fn get(key: &str) -> Option<String> {
+        let mut connection = connection().await?;
+        match redis::cmd("GET")
+            .arg(format!("{}{}", *PREFIX, key))
+            .query_async::<_, Option<String>>(&mut connection)
+            .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                warn!(key = %key, error = ?e, "Failed to read from redis");
+                None
+            }
+        }
+    }
+
+    pub async fn set(key: &str, value: &str, ttl: Duration) {
+        let mut connection = match connection().await {
+            Some(connection) => connection,
+            None => return,
+        };
+        let result = redis::cmd("SET")
+            .arg(format!("{}{}", *PREFIX, key))
+            .arg(value)
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async::<_, ()>(&mut connection)
+            .await;
+        if let Err(e) = result {
+            warn!(key = %key, error = ?e, "Failed to write to redis");
+        }
+    }
+
+    pub async fn reserve(key: &str, interval: Duration) -> Option<Duration> {
+        let mut connection = connection().await?;
+        match RESERVE
+            .key(format!("{}{}", *PREFIX, key))
+            .arg(interval.as_millis().max(1) as u64)
+            .invoke_async::<_, i64>(&mut connection)
+            .await
+        {
+            Ok(wait_ms) => Some(Duration::from_millis(wait_ms.max(0) as u64)),
+            Err(e) => {
+                warn!(key = %key, error = ?e, "Failed to reserve a slot in redis");
+                Some(Duration::ZERO)
+            }
+        }
+    }
+
+    pub async fn increment(key: &str, ttl: Duration) -> Option<u64> {
+        let mut connection = connection().await?;
+        match INCREMENT
+            .key(format!("{}{}", *PREFIX, key))
+            .arg(ttl.as_millis().max(1) as u64)
+            .invoke_async::<_, u64>(&mut connection)
+            .await
+        {
+            Ok(count) => Some(count),
+            Err(e) => {
+                warn!(key = %key, error = ?e, "Failed to increment a counter in redis");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+mod backend {
+    use std::time::Duration;
+
+    pub fn enabled() -> bool {
+        false
+    }
+
+    pub async fn get(_key: &str) -> Option<String> {
+        None
+    }
+
+    pub async fn set(_key: &str, _value: &str, _ttl: Duration) {}
+
+    pub async fn reserve(_key: &str, _interval: Duration) -> Option<Duration> {
+        None
+    }
+
+    pub async fn increment(_key: &str, _ttl: Duration) -> Option<u64> {
+        None
+    }
+}
+
+/// Whether state is shared through redis
+pub fn enabled() -> bool {
+    backend::enabled()
+}
+
+/// Value of the key, None when it's missing or expired
+pub async fn get(key: &str) -> Option<String> {
+    backend::get(key).await
+}
+
+/// Store the value of the key for the ttl
+pub async fn set(key: &str, value: &str, ttl: Duration) {
+    backend::set(key, value, ttl).await
+}
+
+/// Reserve the next slot of a key whose slots are `interval` apart and return
+/// how long to wait for it, None when redis isn't configured
+pub async fn reserve(key: &str, interval: Duration) -> Option<Duration> {
+    backend::reserve(key, interval).await
+}
+
+/// Add one to the counter of a key and return its new value, the key expires `ttl`
+/// after its first increment. None when redis isn't configured or can't be reached
+pub async fn increment(key: &str, ttl: Duration) -> Option<u64> {
+    backend::increment(key, ttl).await
+}
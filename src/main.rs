@@ -11,9 +11,22 @@ use tokio::time::Duration;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use utils::get_db_pool;
 
+mod access_log;
+mod admin;
 mod browser;
+mod config;
+mod events;
 mod fcm;
 mod health;
+mod homeassistant;
+mod http;
+mod i18n;
+mod kv;
+mod metrics;
+mod outbox;
+mod pdf;
+mod quota;
+mod ratelimit;
 mod utils;
 mod yt_dlp;
 
@@ -21,21 +34,34 @@ mod yt_dlp;
 async fn main() -> Result<(), std::io::Error> {
     dotenv().ok(); // This line loads the environment variables from the ".env" file.
     tracing_subscriber::registry()
-        .with(fmt::layer())
-        .with(EnvFilter::from_env("RUST_LOG"))
+        .with(fmt::layer().with_filter(EnvFilter::from_env("RUST_LOG")))
+        .with(access_log::sql_layer())
         .init();
     
     let pool = get_db_pool().await;
     let hostname = utils::get_host();
     let port = utils::get_port();
 
+    events::start();
     let fcm_api = fcm_api(pool.clone()).await;
     let health_api = health::health_checks(pool.clone()).await;
     let (browser_api, driver) = browser::selenium().await;
     let yt_dlp_api = yt_dlp::yt_dlp().await;
+    let admin_api = admin::admin_api(pool.clone()).await;
+    let pdf_api = pdf::pdf().await;
+    let homeassistant_api = homeassistant::homeassistant().await;
+    tokio::spawn(config::reload_on_sighup());
 
     let api_service = OpenApiService::new(
-        (fcm_api, browser_api, health_api, yt_dlp_api),
+        (
+            fcm_api,
+            browser_api,
+            health_api,
+            yt_dlp_api,
+            admin_api,
+            pdf_api,
+            homeassistant_api,
+        ),
         "ToolKit",
         "1.0",
     )
@@ -49,8 +75,16 @@ async fn main() -> Result<(), std::io::Error> {
         .nest("/api/v1", api_service)
         .nest("/swagger", ui)
         .nest("/swagger/spec", spec)
-        .with(Cors::new())
+        .with_if(*utils::READ_ONLY, utils::ReadOnly)
+        .with(i18n::Localize)
+        .with(Cors::new().expose_headers([
+            "X-RateLimit-Limit",
+            "X-RateLimit-Remaining",
+            "X-RateLimit-Reset",
+        ]))
         .with(Tracing)
+        .with(ratelimit::UsageHeaders)
+        .with(access_log::AccessLog)
         .data(pool.clone());
 
     Server::new(TcpListener::bind(format!("0.0.0.0:{}", port)))
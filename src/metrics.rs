@@ -0,0 +1,320 @@
+use crate::http;
+use crate::outbox::Channel;
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use poem_openapi::Object;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+// Delivery metrics are kept in memory by the instance running the delivery
+// workers and start empty on every restart.
+
+lazy_static! {
+    // share of deliveries that have to complete within SLO_TARGET_SECS of their scheduled time
+    static ref SLO_OBJECTIVE: f64 = env::var("SLO_OBJECTIVE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| (0.0..1.0).contains(v))
+        .unwrap_or(0.99);
+    // due schedules are picked up by a scheduler polling once a minute, so a target
+    // under a minute mostly measures where in the minute the poll happens to run
+    static ref SLO_TARGET_SECS: u64 = env::var("SLO_TARGET_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(90);
+    // alert when both windows spend the error budget this many times faster than
+    // sustainable, 14.4 spends 2% of a 30 day budget in an hour
+    static ref SLO_BURN_RATE_THRESHOLD: f64 = env::var("SLO_BURN_RATE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(14.4);
+    // operator webhook alerts are posted to (e.g. a Slack incoming webhook), alerts are only logged when unset
+    static ref SLO_ALERT_WEBHOOK_URL: Option<String> = env::var("SLO_ALERT_WEBHOOK_URL").ok();
+    static ref METRICS: Mutex<BTreeMap<&'static str, ChannelMetrics>> = Mutex::new(BTreeMap::new());
+}
+
+// upper bounds of the histogram buckets in milliseconds, the last bucket is unbounded
+const BUCKETS_MS: [u64; 12] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000, 60_000, 300_000, 900_000,
+];
+// the long window catches sustained burns, the short one stops alerting soon after a recovery
+const LONG_WINDOW_MINUTES: i64 = 60;
+const SHORT_WINDOW_MINUTES: i64 = 5;
+
+#[derive(Default)]
+struct Histogram {
+    counts: [u64; BUCKETS_MS.len() + 1],
+    count: u64,
+    sum_ms: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let ms = value.as_millis() as u64;
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+
+    fn report(&self) -> HistogramReport {
+        let mut cumulative = 0;
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(index, count)| {
+                cumulative += count;
+                HistogramBucket {
+                    le_ms: BUCKETS_MS.get(index).copied(),
+                    count: cumulative,
+                }
+            })
+            .collect();
+
+        HistogramReport {
+            count: self.count,
+            sum_ms: self.sum_ms,
+            buckets,
+        }
+    }
+}
+
+/// Deliveries completed during a minute
+struct MinuteCount {
+    minute: i64,
+    total: u64,
+    late: u64,
+}
+
+#[derive(Default)]
+struct ChannelMetrics {
+    send_latency: Histogram,
+    delay: Histogram,
+    minutes: VecDeque<MinuteCount>,
+    burning: bool,
+    lag_seconds: i64,
+}
+
+impl ChannelMetrics {
+    /// How many times faster than sustainable the error budget was spent over the last minutes
+    fn burn_rate(&self, now: i64, window: i64) -> f64 {
+        let (total, late) = self
+            .minutes
+            .iter()
+            .filter(|count| count.minute > now - window)
+            .fold((0, 0), |(total, late), count| {
+                (total + count.total, late + count.late)
+            });
+
+        if total == 0 {
+            return 0.0;
+        }
+        (late as f64 / total as f64) / (1.0 - *SLO_OBJECTIVE)
+    }
+}
+
+/// Cumulative count of observations up to a bound
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct HistogramBucket {
+    /// upper bound of the bucket in milliseconds, absent for the unbounded bucket
+    pub le_ms: Option<u64>,
+    /// number of observations less than or equal to the bound
+    pub count: u64,
+}
+
+/// Distribution of durations since the server started
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct HistogramReport {
+    /// number of observations
+    pub count: u64,
+    /// sum of the observations in milliseconds
+    pub sum_ms: u64,
+    pub buckets: Vec<HistogramBucket>,
+}
+
+/// Delivery metrics of a channel
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct ChannelReport {
+    /// delivery channel, e.g. fcm
+    pub channel: String,
+    /// time taken by the external service to accept a message
+    pub send_latency: HistogramReport,
+    /// time from the scheduled time of a message until its final delivery attempt
+    pub delay: HistogramReport,
+    /// error budget burn rate over the last hour, 1 spends the budget exactly
+    pub burn_rate_1h: f64,
+    /// error budget burn rate over the last five minutes
+    pub burn_rate_5m: f64,
+    /// whether the burn rate alert is firing
+    pub burning: bool,
+    /// seconds the oldest due message had been waiting for a delivery worker at the last check
+    pub lag_seconds: i64,
+}
+
+/// Delivery metrics and the SLO they are measured against
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct MetricsReport {
+    /// share of deliveries that have to complete within the target
+    pub objective: f64,
+    /// seconds after the scheduled time a delivery has to complete in
+    pub target_seconds: u64,
+    /// burn rate both windows have to exceed to alert
+    pub burn_rate_threshold: f64,
+    pub channels: Vec<ChannelReport>,
+}
+
+/// Record the final outcome of a delivery, failed deliveries count against the SLO
+pub fn record_delivery(
+    channel: Channel,
+    scheduled_at: NaiveDateTime,
+    latency: Option<Duration>,
+    delivered: bool,
+) {
+    let now = Utc::now();
+    let delay = (now.naive_utc() - scheduled_at)
+        .to_std()
+        .unwrap_or_default();
+    let late = !delivered || delay > Duration::from_secs(*SLO_TARGET_SECS);
+    let minute = now.timestamp() / 60;
+
+    let mut metrics = METRICS.lock().unwrap();
+    let metrics = metrics.entry(channel.as_str()).or_default();
+
+    if let Some(latency) = latency {
+        metrics.send_latency.observe(latency);
+    }
+    metrics.delay.observe(delay);
+
+    match metrics.minutes.back_mut() {
+        Some(count) if count.minute == minute => {
+            count.total += 1;
+            count.late += late as u64;
+        }
+        _ => metrics.minutes.push_back(MinuteCount {
+            minute,
+            total: 1,
+            late: late as u64,
+        }),
+    }
+    while metrics
+        .minutes
+        .front()
+        .is_some_and(|count| count.minute <= minute - LONG_WINDOW_MINUTES)
+    {
+        metrics.minutes.pop_front();
+    }
+}
+
+/// Record how long the oldest due message of the channel has been waiting
+pub fn record_lag(channel: Channel, seconds: i64) {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics.entry(channel.as_str()).or_default().lag_seconds = seconds;
+}
+
+/// Snapshot of the metrics of every channel
+pub fn report() -> MetricsReport {
+    let now = Utc::now().timestamp() / 60;
+    let channels = METRICS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(channel, metrics)| ChannelReport {
+            channel: channel.to_string(),
+            send_latency: metrics.send_latency.report(),
+            delay: metrics.delay.report(),
+            burn_rate_1h: metrics.burn_rate(now, LONG_WINDOW_MINUTES),
+            burn_rate_5m: metrics.burn_rate(now, SHORT_WINDOW_MINUTES),
+            burning: metrics.burning,
+            lag_seconds: metrics.lag_seconds,
+        })
+        .collect();
+
+    MetricsReport {
+        objective: *SLO_OBJECTIVE,
+        target_seconds: *SLO_TARGET_SECS,
+        burn_rate_threshold: *SLO_BURN_RATE_THRESHOLD,
+        channels,
+    }
+}
+
+/// Check the burn rate of every channel each minute and alert the operator when
+/// the error budget is burning too fast, and again once it recovers
+pub async fn watch_burn_rate() {
+    loop {
+        sleep(Duration::from_secs(60)).await;
+
+        let now = Utc::now().timestamp() / 60;
+        let mut changes = Vec::new();
+        for (channel, metrics) in METRICS.lock().unwrap().iter_mut() {
+            let long = metrics.burn_rate(now, LONG_WINDOW_MINUTES);
+            let short = metrics.burn_rate(now, SHORT_WINDOW_MINUTES);
+            let burning = long > *SLO_BURN_RATE_THRESHOLD && short > *SLO_BURN_RATE_THRESHOLD;
+
+            if burning != metrics.burning {
+                metrics.burning = burning;
+                changes.push((*channel, burning, long, short));
+            }
+        }
+
+        for (channel, burning, long, short) in changes {
+            alert(channel, burning, long, short).await;
+        }
+    }
+}
+
+async fn alert(channel: &str, burning: bool, burn_rate_1h: f64, burn_rate_5m: f64) {
+    let text = if burning {
+        format!(
+            "{} deliveries are burning the SLO error budget {:.1}x too fast ({}% within {}s)",
+            channel,
+            burn_rate_1h,
+            *SLO_OBJECTIVE * 100.0,
+            *SLO_TARGET_SECS
+        )
+    } else {
+        format!(
+            "{} deliveries are back within the SLO error budget",
+            channel
+        )
+    };
+
+    if burning {
+        warn!(channel, burn_rate_1h, burn_rate_5m, "{}", text);
+    } else {
+        info!(channel, burn_rate_1h, burn_rate_5m, "{}", text);
+    }
+
+    let url = match SLO_ALERT_WEBHOOK_URL.as_ref() {
+        Some(url) => url,
+        None => return,
+    };
+
+    let body = json!({
+        "text": text,
+        "channel": channel,
+        "state": if burning { "burning" } else { "recovered" },
+        "burn_rate_1h": burn_rate_1h,
+        "burn_rate_5m": burn_rate_5m,
+        "objective": *SLO_OBJECTIVE,
+        "target_seconds": *SLO_TARGET_SECS,
+    });
+
+    let result = http::send(http::CLIENT.post(url).json(&body))
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        error!(channel, error = ?e, "Failed to send SLO alert");
+    }
+}
@@ -0,0 +1,418 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use poem_openapi::Enum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Executor, Sqlite, SqlitePool};
+use std::{
+    env,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+lazy_static! {
+    // give up on a message after this many delivery attempts
+    static ref MAX_ATTEMPTS: i64 = env::var("OUTBOX_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|attempts| *attempts > 0)
+        .unwrap_or(5);
+    // delay before the first retry, doubled on every attempt
+    static ref RETRY_BACKOFF_SECS: i64 = env::var("OUTBOX_RETRY_BACKOFF_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+}
+
+// mirrors scheduler_state.paused so the delivery lanes don't hit the database to check it
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+// error of messages that were being sent when the process stopped
+const INTERRUPTED: &str = "Interrupted by a restart while sending, it may have been delivered";
+
+/// Delivery channels backed by the outbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Fcm,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Fcm => "fcm",
+        }
+    }
+}
+
+/// Delivery priority, every priority is delivered by its own worker lane
+///
+/// - `high`: time critical alerts, requires the `high_priority` feature
+/// - `normal`: regular schedules (default)
+/// - `low`: bulk campaigns that may wait behind everything else
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Enum, Serialize, Deserialize, sqlx::Type,
+)]
+#[oai(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Priority {
+    /// time critical alerts
+    High,
+    #[default]
+    /// regular schedules
+    Normal,
+    /// bulk campaigns
+    Low,
+}
+
+impl From<String> for Priority {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "high" => Priority::High,
+            "low" => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// Message waiting to be delivered to an external service.
+///
+/// Messages move from `pending` to `sending` when claimed by a delivery worker
+/// and end up `delivered` or `failed`, or `aborted` when their broadcast is cancelled.
+/// Messages are delivered in order per target and priority, and at most once: a message
+/// interrupted while sending is failed.
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub target: String,
+    pub project_id: String,
+    pub schedule_id: Option<i64>,
+    pub fb_user_id: Option<String>,
+    pub payload: Value,
+    pub attempts: i64,
+    pub dry_run: bool,
+    pub timeout_seconds: Option<i64>,
+    /// time the message was due, lateness of the delivery is measured from it
+    pub scheduled_at: NaiveDateTime,
+    /// execution of the schedule the message belongs to
+    pub execution_key: Option<String>,
+}
+
+/// Message to be added to the outbox
+pub struct NewMessage<'a> {
+    pub channel: Channel,
+    pub target: &'a str,
+    pub project_id: &'a str,
+    pub schedule_id: Option<i64>,
+    pub fb_user_id: Option<&'a str>,
+    pub payload: Value,
+    pub dry_run: bool,
+    pub timeout_seconds: Option<i64>,
+    pub priority: Priority,
+    pub broadcast_id: Option<i64>,
+    /// time the message is due, it isn't sent earlier, defaults to when it's enqueued
+    pub scheduled_at: Option<NaiveDateTime>,
+    /// execution of the schedule the message belongs to, shared by the messages to its devices
+    pub execution_key: Option<&'a str>,
+}
+
+/// Add a message to the outbox, use a transaction executor to enqueue
+/// atomically with the change that produced the message
+pub async fn enqueue<'e, E>(executor: E, message: NewMessage<'_>) -> Result<i64, sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let current_time = Utc::now().naive_utc();
+    let channel = message.channel.as_str();
+    let scheduled_at = message.scheduled_at.unwrap_or(current_time);
+    let next_attempt_at = scheduled_at.max(current_time);
+
+    let result = sqlx::query!(
+        "INSERT INTO outbox (channel, target, project_id, schedule_id, fb_user_id, payload, dry_run, timeout_seconds, priority, broadcast_id, scheduled_at, execution_key, next_attempt_at, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        channel,
+        message.target,
+        message.project_id,
+        message.schedule_id,
+        message.fb_user_id,
+        message.payload,
+        message.dry_run,
+        message.timeout_seconds,
+        message.priority,
+        message.broadcast_id,
+        scheduled_at,
+        message.execution_key,
+        next_attempt_at,
+        current_time,
+        current_time
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Whether outbound sends are halted by an operator
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Halt or resume every outbound send. Messages keep waiting in the outbox while paused.
+pub async fn set_paused(
+    pool: &SqlitePool,
+    paused: bool,
+    reason: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+
+    sqlx::query!(
+        "UPDATE scheduler_state SET paused = ?, reason = ?, updated_at = ? WHERE id = 1",
+        paused,
+        reason,
+        current_time
+    )
+    .execute(pool)
+    .await?;
+
+    PAUSED.store(paused, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Load the persisted pause switch, must run before the delivery lanes start
+pub async fn restore_paused(pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+    let paused =
+        sqlx::query_scalar!(r#"SELECT paused as "paused: bool" FROM scheduler_state WHERE id = 1"#)
+            .fetch_optional(pool)
+            .await?
+            .unwrap_or(false);
+
+    PAUSED.store(paused, Ordering::SeqCst);
+
+    Ok(paused)
+}
+
+/// Claim due messages of the channel and priority for delivery. Only the oldest due
+/// message of each target is claimed so messages to a target stay ordered within the
+/// priority, messages waiting for a retry or their time don't hold back the others.
+/// Nothing is claimed while sends are paused.
+pub async fn claim(
+    pool: &SqlitePool,
+    channel: Channel,
+    priority: Priority,
+    limit: i64,
+) -> Result<Vec<OutboxMessage>, sqlx::Error> {
+    if is_paused() {
+        return Ok(vec![]);
+    }
+
+    let current_time = Utc::now().naive_utc();
+    let channel = channel.as_str();
+
+    sqlx::query_as!(
+        OutboxMessage,
+        r#"UPDATE outbox SET status = 'sending', attempts = attempts + 1, updated_at = ?1
+        WHERE id IN (
+            SELECT o.id FROM outbox o
+            WHERE o.channel = ?2 AND o.priority = ?4 AND o.status = 'pending' AND o.next_attempt_at <= ?1
+            AND NOT EXISTS (
+                SELECT 1 FROM outbox p
+                WHERE p.target = o.target AND p.channel = o.channel AND p.priority = o.priority
+                AND (p.status = 'sending' OR (p.status = 'pending' AND p.next_attempt_at <= ?1))
+                AND p.id < o.id
+            )
+            ORDER BY o.id
+            LIMIT ?3
+        )
+        RETURNING id as "id!", target, project_id, schedule_id, fb_user_id,
+            payload as "payload: Value", attempts, dry_run as "dry_run: bool", timeout_seconds,
+            COALESCE(scheduled_at, created_at) as "scheduled_at!: NaiveDateTime", execution_key"#,
+        current_time,
+        channel,
+        limit,
+        priority
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Mark the message as delivered
+pub async fn mark_delivered(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+
+    sqlx::query!(
+        "UPDATE outbox SET status = 'delivered', last_error = NULL, updated_at = ? WHERE id = ?",
+        current_time,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Mark the message as permanently failed
+pub async fn mark_failed(pool: &SqlitePool, id: i64, error: &str) -> Result<(), sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+
+    sqlx::query!(
+        "UPDATE outbox SET status = 'failed', last_error = ?, updated_at = ? WHERE id = ?",
+        error,
+        current_time,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Schedule another delivery attempt with exponential backoff, or fail the
+/// message when it ran out of attempts. Returns whether it will be retried.
+pub async fn retry_later(
+    pool: &SqlitePool,
+    message: &OutboxMessage,
+    error: &str,
+) -> Result<bool, sqlx::Error> {
+    if message.attempts >= *MAX_ATTEMPTS {
+        mark_failed(pool, message.id, error).await?;
+        return Ok(false);
+    }
+
+    let current_time = Utc::now().naive_utc();
+    let backoff = RETRY_BACKOFF_SECS
+        .saturating_mul(2i64.saturating_pow(message.attempts.saturating_sub(1) as u32));
+    let next_attempt_at = current_time + Duration::seconds(backoff);
+
+    sqlx::query!(
+        "UPDATE outbox SET status = 'pending', last_error = ?, next_attempt_at = ?, updated_at = ? WHERE id = ?",
+        error,
+        next_attempt_at,
+        current_time,
+        message.id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(true)
+}
+
+/// Seconds the oldest due message of the channel has been waiting for a delivery
+/// worker, 0 when the queue is drained
+pub async fn lag(pool: &SqlitePool, channel: Channel) -> Result<i64, sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+    let channel = channel.as_str();
+
+    let oldest = sqlx::query_scalar!(
+        r#"SELECT MIN(next_attempt_at) as "oldest: NaiveDateTime" FROM outbox
+        WHERE channel = ? AND status = 'pending' AND next_attempt_at <= ?"#,
+        channel,
+        current_time
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(oldest.map_or(0, |oldest| (current_time - oldest).num_seconds().max(0)))
+}
+
+/// Fail messages claimed by a previous process. They may have reached the service
+/// before it stopped, sending them again could deliver them twice.
+pub async fn fail_interrupted(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let current_time = Utc::now().naive_utc();
+
+    let result = sqlx::query!(
+        "UPDATE outbox SET status = 'failed', last_error = ?, updated_at = ? WHERE status = 'sending'",
+        INTERRUPTED,
+        current_time
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn pool() -> SqlitePool {
+        // a single connection keeps the in-memory database for the whole test
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        pool
+    }
+
+    fn message(target: &str, priority: Priority) -> NewMessage<'_> {
+        NewMessage {
+            channel: Channel::Fcm,
+            target,
+            project_id: "project",
+            schedule_id: None,
+            fb_user_id: None,
+            payload: Value::Null,
+            dry_run: false,
+            timeout_seconds: None,
+            priority,
+            broadcast_id: None,
+            scheduled_at: None,
+            execution_key: None,
+        }
+    }
+
+    async fn claim_ids(pool: &SqlitePool, priority: Priority) -> Vec<i64> {
+        let claimed = claim(pool, Channel::Fcm, priority, 10).await.unwrap();
+        claimed.iter().map(|message| message.id).collect()
+    }
+
+    #[tokio::test]
+    async fn claim_keeps_messages_to_a_target_in_order() {
+        let pool = pool().await;
+        let first = enqueue(&pool, message("a", Priority::Normal))
+            .await
+            .unwrap();
+        let second = enqueue(&pool, message("a", Priority::Normal))
+            .await
+            .unwrap();
+        let other = enqueue(&pool, message("b", Priority::Normal))
+            .await
+            .unwrap();
+
+        assert_eq!(claim_ids(&pool, Priority::Normal).await, vec![first, other]);
+        assert!(claim_ids(&pool, Priority::Normal).await.is_empty());
+        mark_delivered(&pool, first).await.unwrap();
+        assert_eq!(claim_ids(&pool, Priority::Normal).await, vec![second]);
+    }
+
+    #[tokio::test]
+    async fn claim_orders_messages_within_their_priority() {
+        let pool = pool().await;
+        let low = enqueue(&pool, message("a", Priority::Low)).await.unwrap();
+        let high = enqueue(&pool, message("a", Priority::High)).await.unwrap();
+
+        assert_eq!(claim_ids(&pool, Priority::High).await, vec![high]);
+        assert_eq!(claim_ids(&pool, Priority::Low).await, vec![low]);
+    }
+
+    #[tokio::test]
+    async fn claim_passes_messages_waiting_for_a_retry() {
+        let pool = pool().await;
+        enqueue(&pool, message("a", Priority::Normal))
+            .await
+            .unwrap();
+        let second = enqueue(&pool, message("a", Priority::Normal))
+            .await
+            .unwrap();
+
+        let claimed = claim(&pool, Channel::Fcm, Priority::Normal, 10)
+            .await
+            .unwrap();
+        assert!(retry_later(&pool, &claimed[0], "unavailable")
+            .await
+            .unwrap());
+        assert_eq!(claim_ids(&pool, Priority::Normal).await, vec![second]);
+    }
+}
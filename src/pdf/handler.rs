@@ -0,0 +1,94 @@
+use super::{
+    model::{PdfContent, PdfDocument},
+    utils::{extract, PDF_MAX_BYTES},
+};
+use crate::http;
+use crate::utils::{verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
+use poem::Request;
+use poem_openapi::OpenApi;
+use tempfile::tempdir;
+use tracing::error;
+
+#[derive(Default)]
+pub struct Pdf;
+
+#[OpenApi(
+    prefix_path = "/pdf/",
+    request_header(name = "API-Key", ty = "String", description = "Private API Key"),
+    tag = "ApiTags::Pdf"
+)]
+impl Pdf {
+    /// extract the text of each page and the metadata of a PDF
+    #[oai(path = "/extract", method = "post", operation_id = "pdf::extract")]
+    async fn extract(
+        &self,
+        req: &Request,
+        document: PdfDocument,
+    ) -> Result<JsonSuccess<PdfContent>, JsonError<String>> {
+        verify_apikey(req)
+            .await
+            .map_err(ResponseObject::unauthorized)?;
+
+        let bytes = match (document.file, document.url) {
+            (Some(file), _) => file.into_vec().await.map_err(|e| {
+                error!(error = ?e, "Failed to read the uploaded PDF");
+                ResponseObject::bad_request("Failed to read the uploaded file".to_string())
+            })?,
+            (None, Some(url)) => {
+                let response = http::send(http::CLIENT.get(&url)).await.map_err(|e| {
+                    error!(url = %url, error = ?e, "Failed to download PDF");
+                    ResponseObject::bad_request("Failed to download the PDF".to_string())
+                })?;
+                if !response.status().is_success() {
+                    return Err(ResponseObject::bad_request(format!(
+                        "Failed to download the PDF: {}",
+                        response.status()
+                    )));
+                }
+                if response
+                    .content_length()
+                    .is_some_and(|length| length as usize > *PDF_MAX_BYTES)
+                {
+                    return Err(ResponseObject::bad_request(
+                        "The PDF is too large".to_string(),
+                    ));
+                }
+                response.bytes().await.map(|b| b.to_vec()).map_err(|e| {
+                    error!(url = %url, error = ?e, "Failed to download PDF");
+                    ResponseObject::bad_request("Failed to download the PDF".to_string())
+                })?
+            }
+            (None, None) => {
+                return Err(ResponseObject::bad_request(
+                    "Either a file or a url is required".to_string(),
+                ));
+            }
+        };
+
+        if bytes.len() > *PDF_MAX_BYTES {
+            return Err(ResponseObject::bad_request(
+                "The PDF is too large".to_string(),
+            ));
+        }
+        if !bytes.starts_with(b"%PDF") {
+            return Err(ResponseObject::bad_request(
+                "The file is not a PDF".to_string(),
+            ));
+        }
+
+        let dir = tempdir().map_err(|e| {
+            error!(error = ?e, "Failed to create temporary directory");
+            ResponseObject::internal_server_error("Failed to store the PDF".to_string())
+        })?;
+        let path = dir.path().join("document.pdf");
+        tokio::fs::write(&path, &bytes).await.map_err(|e| {
+            error!(error = ?e, "Failed to write the PDF");
+            ResponseObject::internal_server_error("Failed to store the PDF".to_string())
+        })?;
+
+        match extract(&path).await {
+            Ok(content) => Ok(ResponseObject::ok(content)),
+            Err(e) => Err(ResponseObject::bad_request(e)),
+        }
+    }
+}
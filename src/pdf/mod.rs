@@ -0,0 +1,7 @@
+mod handler;
+mod model;
+mod utils;
+
+pub async fn pdf() -> handler::Pdf {
+    handler::Pdf
+}
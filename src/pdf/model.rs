@@ -0,0 +1,30 @@
+use poem_openapi::{types::multipart::Upload, Multipart, Object};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// PDF to extract, either uploaded or fetched from a url
+#[derive(Debug, Multipart)]
+pub struct PdfDocument {
+    /// The PDF file
+    pub file: Option<Upload>,
+    /// Url to download the PDF from when no file is uploaded
+    pub url: Option<String>,
+}
+
+/// Text of a single page
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct PdfPage {
+    /// Page number, starting from 1
+    pub number: i64,
+    /// Text of the page with its layout preserved
+    pub text: String,
+}
+
+/// Text and metadata extracted from a PDF
+#[derive(Debug, Object, Clone, Serialize)]
+pub struct PdfContent {
+    /// Document information such as Title, Author, Creator and CreationDate
+    pub metadata: HashMap<String, String>,
+    /// Pages of the document in order
+    pub pages: Vec<PdfPage>,
+}
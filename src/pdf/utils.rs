@@ -0,0 +1,65 @@
+use super::model::{PdfContent, PdfPage};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, env, path::Path};
+use tokio::process::Command;
+use tracing::error;
+
+lazy_static! {
+    // largest PDF accepted, uploaded or downloaded
+    pub static ref PDF_MAX_BYTES: usize = env::var("PDF_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(20 * 1024 * 1024);
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| {
+            error!(program = %program, error = ?e, "Failed to start poppler");
+            "PDF extraction is not available".to_string()
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!(program = %program, status = %output.status, stderr = %stderr, "poppler failed");
+        return Err("Failed to read the PDF".to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Extract the text of each page and the document information of a PDF file
+pub async fn extract(path: &Path) -> Result<PdfContent, String> {
+    let path = path.to_string_lossy();
+
+    let metadata: HashMap<String, String> = run("pdfinfo", &[&path])
+        .await?
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+
+    // pages are separated by a form feed, the last one is followed by one too
+    let text = run("pdftotext", &["-layout", "-enc", "UTF-8", &path, "-"]).await?;
+    let mut pages: Vec<&str> = text.split('\u{c}').collect();
+    if pages.last().is_some_and(|page| page.trim().is_empty()) {
+        pages.pop();
+    }
+
+    Ok(PdfContent {
+        metadata,
+        pages: pages
+            .into_iter()
+            .enumerate()
+            .map(|(idx, text)| PdfPage {
+                number: idx as i64 + 1,
+                text: text.to_string(),
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,101 @@
+use crate::events;
+use crate::fcm;
+use chrono::Utc;
+use poem_openapi::Object;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use tracing::{error, info};
+
+// Quotas warn before they're enforced: once a user has used 80% of a quota, the
+// user's devices get a notification and a `quota.warning` event is published the
+// first time in the quota's period, and responses that count against the quota
+// carry a warning.
+
+// share of a quota, in percent, at which users are warned
+const WARNING_PERCENT: i64 = 80;
+
+/// Usage of a quota
+#[derive(Debug, Object, Clone, Eq, PartialEq, Serialize)]
+pub struct QuotaStatus {
+    /// quota name, e.g. schedules or browser_tasks
+    pub name: String,
+    /// amount of the quota used
+    pub used: i64,
+    /// amount allowed, absent when unlimited
+    pub limit: Option<i64>,
+    /// period the quota resets after, e.g. 2026-10 for monthly quotas, absent when it never resets
+    pub period: Option<String>,
+    /// whether at least 80% of the quota is used
+    pub warning: bool,
+}
+
+impl QuotaStatus {
+    pub fn new(name: &str, used: i64, limit: Option<i64>, period: Option<String>) -> Self {
+        let warning = limit.is_some_and(|limit| limit > 0 && used * 100 >= limit * WARNING_PERCENT);
+        QuotaStatus {
+            name: name.to_string(),
+            used,
+            limit,
+            period,
+            warning,
+        }
+    }
+
+    /// Warning to show the user while the quota is nearly used up
+    pub fn message(&self) -> Option<String> {
+        let limit = self.limit.filter(|_| self.warning)?;
+        Some(format!(
+            "{} of the {} {} quota used",
+            self.used, limit, self.name
+        ))
+    }
+}
+
+/// Notify the user's devices and publish a `quota.warning` event when the quota is
+/// nearly used up, once per quota and period
+pub async fn warn_once(pool: &SqlitePool, user_id: &str, quota: &QuotaStatus) {
+    if !quota.warning {
+        return;
+    }
+
+    let current_time = Utc::now().naive_utc();
+    let period = quota.period.as_deref().unwrap_or_default();
+    let result = sqlx::query!(
+        "INSERT OR IGNORE INTO quota_warning (user_id, quota, period, created_at) VALUES (?, ?, ?, ?)",
+        user_id,
+        quota.name,
+        period,
+        current_time
+    )
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(result) if result.rows_affected() > 0 => {
+            info!(user_id = %user_id, quota = %quota.name, used = quota.used, limit = ?quota.limit, "Quota nearly used up");
+            events::publish("quota.warning", QuotaWarningEvent { user_id, quota });
+
+            let payload = json!({
+                "title": "Quota nearly used up",
+                "body": quota.message().unwrap_or_default(),
+                "quota": quota.name,
+            });
+            if let Err(e) = fcm::notify_user(pool, user_id, &payload).await {
+                error!(user_id = %user_id, quota = %quota.name, error = %e, "Failed to send quota warning");
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!(user_id = %user_id, quota = %quota.name, error = ?e, "Failed to record quota warning")
+        }
+    }
+}
+
+/// Data of the `quota.warning` event
+#[derive(Debug, Serialize)]
+struct QuotaWarningEvent<'a> {
+    user_id: &'a str,
+    #[serde(flatten)]
+    quota: &'a QuotaStatus,
+}
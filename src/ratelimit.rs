@@ -0,0 +1,108 @@
+use crate::access_log;
+use crate::kv;
+use lazy_static::lazy_static;
+use poem::{Endpoint, IntoResponse, Middleware, Request, Response, Result as PoemResult};
+use std::{
+    collections::HashMap,
+    env,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+lazy_static! {
+    // requests a user is expected to make per window, exceeding it is only reported
+    static ref RATE_LIMIT_REQUESTS: u64 = env::var("RATE_LIMIT_REQUESTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|requests| *requests > 0)
+        .unwrap_or(120);
+    static ref RATE_LIMIT_WINDOW_SECS: u64 = env::var("RATE_LIMIT_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(60);
+    // user id -> (start of the window in unix seconds, requests made in it), used
+    // when usage isn't shared through redis
+    static ref USAGE: Mutex<HashMap<String, (u64, u64)>> = Mutex::new(HashMap::new());
+}
+
+// users tracked before windows that already ended are dropped
+const MAX_TRACKED_USERS: usize = 10_000;
+
+/// Count the request against the user's window and return the requests made in it
+/// and the seconds until it resets. Every instance counts in the same window when
+/// redis is configured
+async fn record(user_id: &str) -> (u64, u64) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let window = *RATE_LIMIT_WINDOW_SECS;
+    let window_start = now - now % window;
+    let reset = window_start + window - now;
+
+    let key = format!("ratelimit:{}:{}", user_id, window_start);
+    if let Some(count) = kv::increment(&key, Duration::from_secs(window)).await {
+        return (count, reset);
+    }
+
+    let mut usage = USAGE.lock().unwrap();
+    if usage.len() >= MAX_TRACKED_USERS && !usage.contains_key(user_id) {
+        usage.retain(|_, (start, _)| *start == window_start);
+    }
+
+    let (start, count) = usage
+        .entry(user_id.to_string())
+        .or_insert((window_start, 0));
+    if *start != window_start {
+        *start = window_start;
+        *count = 0;
+    }
+    *count += 1;
+
+    (*count, reset)
+}
+
+/// Adds `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` (seconds
+/// until the window resets) to the responses of authenticated requests. Requests
+/// over the limit are still served, so clients can back off before limits are enforced.
+pub struct UsageHeaders;
+
+impl<E: Endpoint> Middleware<E> for UsageHeaders {
+    type Output = UsageHeadersEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        UsageHeadersEndpoint { ep }
+    }
+}
+
+pub struct UsageHeadersEndpoint<E> {
+    ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for UsageHeadersEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> PoemResult<Self::Output> {
+        let mut response = match self.ep.call(req).await {
+            Ok(response) => response.into_response(),
+            Err(e) => e.into_response(),
+        };
+
+        // the user is only known once the endpoint authenticated the request
+        let user_id = match access_log::current_user() {
+            Some(user_id) => user_id,
+            None => return Ok(response),
+        };
+
+        let (used, reset) = record(&user_id).await;
+        let limit = *RATE_LIMIT_REQUESTS;
+        let headers = response.headers_mut();
+        headers.insert("X-RateLimit-Limit", limit.into());
+        headers.insert("X-RateLimit-Remaining", limit.saturating_sub(used).into());
+        headers.insert("X-RateLimit-Reset", reset.into());
+
+        Ok(response)
+    }
+}
@@ -1,6 +1,6 @@
 use lazy_static::lazy_static;
 use poem::{
-    http::StatusCode,
+    http::{Method, StatusCode},
     web::headers::authorization::Basic,
     web::headers::{self, HeaderMapExt},
     Endpoint, Error as PoemError, Middleware, Request, Response, Result as PoemResult,
@@ -9,18 +9,51 @@ use poem_openapi::{
     error::ParseRequestPayloadError,
     payload::Json,
     types::{ParseFromJSON, ToJSON},
-    {ApiResponse, Object, Tags},
+    {ApiResponse, Enum, Object, Tags},
 };
+use serde::Serialize;
+use serde_json::Value;
 use sqlx::{sqlite::SqliteConnectOptions, Error, SqlitePool};
-use std::env;
+use std::{env, fs};
 use std::{future::Future, path::Path};
 
 lazy_static! {
     static ref API_KEY: String = env::var("API_KEY").expect("API_KEY must be set");
     pub static ref OPENAI_API_KEY: String =
         env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set");
-    pub static ref CHROME_DRIVER_ENDPOINT: String =
-        env::var("CHROME_DRIVER_ENDPOINT").expect("CHROME_DRIVER_ENDPOINT must be set");
+    // chromedriver binary to launch and keep running, leave unset to connect to an external driver
+    pub static ref CHROMEDRIVER_PATH: Option<String> = env::var("CHROMEDRIVER_PATH").ok();
+    pub static ref CHROME_DRIVER_ENDPOINT: String = env::var("CHROME_DRIVER_ENDPOINT")
+        .ok()
+        .or_else(|| CHROMEDRIVER_PATH.as_ref().map(|_| "http://localhost:9515".to_string()))
+        .expect("CHROME_DRIVER_ENDPOINT must be set");
+    // serve GET endpoints from a read-only database without running any background jobs
+    pub static ref READ_ONLY: bool = env::var("READ_ONLY").map(|v| v == "true").unwrap_or(false);
+    // log what would be sent instead of calling external services
+    pub static ref DRY_RUN: bool = env::var("DRY_RUN").map(|v| v == "true").unwrap_or(false);
+    // seconds a single send may take before it is cancelled
+    pub static ref SEND_TIMEOUT_SECS: u64 = env::var("SEND_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    // number of messages sent concurrently by each priority lane
+    pub static ref OUTBOX_CONCURRENCY_HIGH: i64 = env::var("OUTBOX_CONCURRENCY_HIGH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8);
+    pub static ref OUTBOX_CONCURRENCY_NORMAL: i64 = env::var("OUTBOX_CONCURRENCY_NORMAL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(4);
+    pub static ref OUTBOX_CONCURRENCY_LOW: i64 = env::var("OUTBOX_CONCURRENCY_LOW")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1);
+    // number of tokens queued per batch while fanning out a broadcast
+    pub static ref BROADCAST_BATCH_SIZE: i64 = env::var("FCM_BROADCAST_BATCH_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(500);
 }
 
 #[derive(Tags)]
@@ -33,6 +66,12 @@ pub enum ApiTags {
     Selenium,
     /// Youtube-dl service
     YoutubeDL,
+    /// Operator endpoints
+    Admin,
+    /// PDF tools
+    Pdf,
+    /// Home Assistant REST sensors
+    HomeAssistant,
 }
 
 async fn connect(filename: impl AsRef<Path>) -> impl Future<Output = Result<SqlitePool, Error>> {
@@ -42,17 +81,52 @@ async fn connect(filename: impl AsRef<Path>) -> impl Future<Output = Result<Sqli
         .unwrap()
         .trim_start_matches("sqlite:");
 
-    let options = SqliteConnectOptions::new()
+    let mut options = SqliteConnectOptions::new()
         .filename(filename)
-        .create_if_missing(true);
+        .create_if_missing(!*READ_ONLY)
+        .read_only(*READ_ONLY);
+
+    // the key has to be the first statement on every connection, sqlx applies it before other pragmas
+    if let Some(key) = database_key() {
+        options = options.pragma("key", format!("'{}'", key.replace('\'', "''")));
+    }
 
     SqlitePool::connect_with(options)
 }
 
+// SQLCipher passphrase from DATABASE_KEY or the file in DATABASE_KEY_FILE
+fn database_key() -> Option<String> {
+    if let Ok(key) = env::var("DATABASE_KEY") {
+        return Some(key);
+    }
+
+    env::var("DATABASE_KEY_FILE").ok().map(|path| {
+        fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read DATABASE_KEY_FILE {}: {}", path, e))
+            .trim()
+            .to_string()
+    })
+}
+
 pub async fn get_db_pool() -> SqlitePool {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let pool = connect(database_url).await.await.unwrap();
-    sqlx::migrate!().run(&pool).await.unwrap();
+
+    // without SQLCipher the key pragma is silently ignored and the data would be stored in plain text
+    if database_key().is_some() {
+        let cipher_version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+            .fetch_optional(&pool)
+            .await
+            .unwrap();
+        if cipher_version.is_none() {
+            panic!("DATABASE_KEY is set but the binary was built without the sqlcipher feature");
+        }
+    }
+
+    // a read-only replica relies on the primary instance to migrate the database
+    if !*READ_ONLY {
+        sqlx::migrate!().run(&pool).await.unwrap();
+    }
     return pool;
 }
 
@@ -70,6 +144,32 @@ pub fn get_port() -> String {
 pub struct ResponseObject<T: ParseFromJSON + ToJSON + Send + Sync> {
     data: Option<T>,
     error: Option<String>,
+    /// non-fatal issues detected while processing the request
+    #[oai(skip_serializing_if_is_none)]
+    warnings: Option<Vec<String>>,
+    /// position of a page of results in the whole list
+    #[oai(skip_serializing_if_is_none)]
+    meta: Option<PageMeta>,
+}
+
+/// Direction of a listing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Enum)]
+#[oai(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Pagination details of a listing
+#[derive(Debug, Object, Clone, Eq, PartialEq)]
+pub struct PageMeta {
+    /// number of items matching the filters across every page
+    pub total: i64,
+    /// maximum number of items in the page, absent when every item is returned
+    pub limit: Option<i64>,
+    /// number of items skipped before the page
+    pub offset: i64,
 }
 
 impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
@@ -77,6 +177,17 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
         JsonSuccess::Ok(Json(ResponseObject {
             data: Some(data),
             error: None,
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    pub fn ok_with_meta(data: T, meta: PageMeta) -> JsonSuccess<T> {
+        JsonSuccess::Ok(Json(ResponseObject {
+            data: Some(data),
+            error: None,
+            warnings: None,
+            meta: Some(meta),
         }))
     }
 
@@ -84,13 +195,58 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
         JsonSuccess::Created(Json(ResponseObject {
             data: Some(data),
             error: None,
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    pub fn ok_with_warnings(data: T, warnings: Vec<String>) -> JsonSuccess<T> {
+        JsonSuccess::Ok(Json(ResponseObject {
+            data: Some(data),
+            error: None,
+            warnings: (!warnings.is_empty()).then_some(warnings),
+            meta: None,
+        }))
+    }
+
+    pub fn created_with_warnings(data: T, warnings: Vec<String>) -> JsonSuccess<T> {
+        JsonSuccess::Created(Json(ResponseObject {
+            data: Some(data),
+            error: None,
+            warnings: (!warnings.is_empty()).then_some(warnings),
+            meta: None,
         }))
     }
 
+    /// Response carrying the version of the resource as its ETag
+    pub fn ok_with_etag(data: T, etag: String, warnings: Vec<String>) -> JsonTagged<T> {
+        JsonTagged::Ok(
+            Json(ResponseObject {
+                data: Some(data),
+                error: None,
+                warnings: (!warnings.is_empty()).then_some(warnings),
+                meta: None,
+            }),
+            etag,
+        )
+    }
+
     pub fn bad_request(error: impl ToString) -> JsonError<T> {
         JsonError::BadRequest(Json(ResponseObject {
             data: None,
             error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    /// Bad request that still explains itself in `data`, e.g. which items of a batch were invalid
+    pub fn bad_request_with_data(data: T, error: impl ToString) -> JsonError<T> {
+        JsonError::BadRequest(Json(ResponseObject {
+            data: Some(data),
+            error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
         }))
     }
 
@@ -98,6 +254,17 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
         JsonError::Unauthorized(Json(ResponseObject {
             data: None,
             error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    pub fn forbidden(error: impl ToString) -> JsonError<T> {
+        JsonError::Forbidden(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
         }))
     }
 
@@ -105,6 +272,35 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
         JsonError::NotFound(Json(ResponseObject {
             data: None,
             error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    pub fn conflict(error: impl ToString) -> JsonError<T> {
+        JsonError::Conflict(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    pub fn precondition_failed(error: impl ToString) -> JsonError<T> {
+        JsonError::PreconditionFailed(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
+        }))
+    }
+
+    pub fn precondition_required(error: impl ToString) -> JsonError<T> {
+        JsonError::PreconditionRequired(Json(ResponseObject {
+            data: None,
+            error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
         }))
     }
 
@@ -112,27 +308,59 @@ impl<T: ParseFromJSON + ToJSON + Send + Sync> ResponseObject<T> {
         JsonError::InternalServerError(Json(ResponseObject {
             data: None,
             error: Some(error.to_string()),
+            warnings: None,
+            meta: None,
         }))
     }
 }
 
 #[derive(ApiResponse)]
 pub enum JsonSuccess<T: ParseFromJSON + ToJSON + Send + Sync> {
+    /// Request succeeded
     #[oai(status = 200)]
     Ok(Json<ResponseObject<T>>),
+    /// Resource created
     #[oai(status = 201)]
     Created(Json<ResponseObject<T>>),
 }
 
+#[derive(ApiResponse)]
+pub enum JsonTagged<T: ParseFromJSON + ToJSON + Send + Sync> {
+    /// Request succeeded
+    #[oai(status = 200)]
+    Ok(
+        Json<ResponseObject<T>>,
+        /// version of the resource, send it back in `If-Match` to update the resource
+        #[oai(header = "ETag")]
+        String,
+    ),
+}
+
 #[derive(ApiResponse)]
 #[oai(bad_request_handler = "bad_request_handler")]
 pub enum JsonError<T: ParseFromJSON + ToJSON + Send + Sync> {
+    /// Request body or parameters failed validation
     #[oai(status = 400)]
     BadRequest(Json<ResponseObject<T>>),
+    /// Missing or invalid credentials (firebase-auth token or API-Key)
     #[oai(status = 401)]
     Unauthorized(Json<ResponseObject<T>>),
+    /// Credentials are valid but the account isn't allowed to perform the action
+    #[oai(status = 403)]
+    Forbidden(Json<ResponseObject<T>>),
+    /// Resource doesn't exist or belongs to another account
     #[oai(status = 404)]
     NotFound(Json<ResponseObject<T>>),
+    /// Resource belongs to another account
+    #[oai(status = 409)]
+    Conflict(Json<ResponseObject<T>>),
+    /// Resource was changed since the version in `If-Match`
+    #[oai(status = 412)]
+    PreconditionFailed(Json<ResponseObject<T>>),
+    /// Request has to say which version of the resource it changes in `If-Match`
+    #[oai(status = 428)]
+    PreconditionRequired(Json<ResponseObject<T>>),
+    /// Unexpected server side failure
     #[oai(status = 500)]
     InternalServerError(Json<ResponseObject<T>>),
 }
@@ -142,6 +370,8 @@ impl From<anyhow::Error> for JsonError<String> {
         JsonError::InternalServerError(Json(ResponseObject {
             data: None,
             error: Some(err.to_string()),
+            warnings: None,
+            meta: None,
         }))
     }
 }
@@ -151,11 +381,15 @@ fn bad_request_handler<T: ParseFromJSON + ToJSON + Send + Sync>(err: PoemError)
         JsonError::BadRequest(Json(ResponseObject {
             data: None,
             error: Some(err.to_string()),
+            warnings: None,
+            meta: None,
         }))
     } else {
         JsonError::InternalServerError(Json(ResponseObject {
             data: None,
             error: Some(err.to_string()),
+            warnings: None,
+            meta: None,
         }))
     }
 }
@@ -213,6 +447,90 @@ impl<E: Endpoint> Endpoint for BasicAuthEndpoint<E> {
     }
 }
 
+/// Refuses every request that could mutate data while running in read-only mode
+pub struct ReadOnly;
+
+impl<E: Endpoint> Middleware<E> for ReadOnly {
+    type Output = ReadOnlyEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        ReadOnlyEndpoint { ep }
+    }
+}
+
+pub struct ReadOnlyEndpoint<E> {
+    ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ReadOnlyEndpoint<E> {
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> PoemResult<Self::Output> {
+        if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+            return self.ep.call(req).await;
+        }
+
+        let body = serde_json::json!({
+            "data": null,
+            "error": "This instance is read-only",
+        });
+        let res = Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .content_type("application/json; charset=utf-8")
+            .body(body.to_string());
+
+        Err(PoemError::from_response(res))
+    }
+}
+
+/// Keep only the requested comma separated `fields` of each item, all fields are kept when
+/// no fields are requested
+pub fn select_fields<T: Serialize>(
+    items: Vec<T>,
+    fields: Option<&str>,
+) -> Result<Vec<Value>, String> {
+    let items = items
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let fields: Vec<&str> = match fields {
+        Some(fields) => fields
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .collect(),
+        None => return Ok(items),
+    };
+    if fields.is_empty() {
+        return Ok(items);
+    }
+
+    if let Some(Value::Object(first)) = items.first() {
+        let unknown: Vec<&str> = fields
+            .iter()
+            .filter(|f| !first.contains_key(**f))
+            .copied()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(format!("Unknown fields: {}", unknown.join(", ")));
+        }
+    }
+
+    Ok(items
+        .into_iter()
+        .map(|item| match item {
+            Value::Object(mut map) => {
+                map.retain(|key, _| fields.contains(&key.as_str()));
+                Value::Object(map)
+            }
+            item => item,
+        })
+        .collect())
+}
+
 pub async fn verify_apikey(req: &Request) -> Result<(), String> {
     // extract user id from token
     let api_key = match req.header("API-Key") {
@@ -2,6 +2,7 @@ use super::{
     model::Metadata,
     utils::{download_instgram_video, is_instagram_url},
 };
+use crate::http;
 use crate::utils::{self, verify_apikey, ApiTags, JsonError, JsonSuccess, ResponseObject};
 use poem::Request;
 use poem_openapi::{
@@ -185,16 +186,6 @@ impl YoutubeDL {
             }
         };
 
-        let client = match reqwest::Client::builder().build() {
-            Ok(client) => client,
-            Err(error) => {
-                error!(url = %url.0, error = %error, "Failed to build reqwest client");
-                return Err(ResponseObject::internal_server_error(
-                    "Failed to transcribe audio".to_string(),
-                ));
-            }
-        };
-
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             "Authorization",
@@ -210,7 +201,7 @@ impl YoutubeDL {
             )
             .text("model", "whisper-1");
 
-        let request = client
+        let request = http::CLIENT
             .request(
                 reqwest::Method::POST,
                 "https://api.openai.com/v1/audio/transcriptions",
@@ -218,7 +209,7 @@ impl YoutubeDL {
             .headers(headers)
             .multipart(form);
 
-        let response = match request.send().await {
+        let response = match http::send(request).await {
             Ok(response) => response,
             Err(error) => {
                 error!(url = %url.0, error = %error, "Failed to send request to OpenAI");
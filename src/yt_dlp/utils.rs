@@ -1,9 +1,7 @@
 use anyhow::Context;
 use regex::Regex;
-use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    Client,
-};
+use crate::http;
+use reqwest::header::{HeaderMap, HeaderValue};
 use serde_json::Value;
 use tracing::debug;
 use std::{fs::DirEntry, io, path::Path};
@@ -47,13 +45,11 @@ pub async fn download_instgram_video(
 
     dbg!(&url_encoded_string);
 
-    let client = Client::new();
-    let response = client
+    let request = http::CLIENT
         .post("https://www.instagram.com/api/graphql")
         .headers(headers)
-        .body(url_encoded_string)
-        .send()
-        .await?;
+        .body(url_encoded_string);
+    let response = http::send(request).await?;
 
     if response.status().is_success() {
         let response_data: Value = response.json().await?;
@@ -72,7 +68,7 @@ pub async fn download_instgram_video(
 
         debug!(url=%video_url, "Downloading video");
 
-        let video_response = client.get(video_url).send().await?;
+        let video_response = http::send(http::CLIENT.get(video_url)).await?;
         let video_bytes = video_response.bytes().await?;
         let mut video_file = std::fs::File::create(dir_path.join(format!("{}.mp4", post_id)))?;
         io::copy(&mut video_bytes.as_ref(), &mut video_file)?;